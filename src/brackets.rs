@@ -0,0 +1,332 @@
+// Copyright 2018 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An index of the brackets in a text, for finding the bracket matching
+//! the one next to a cursor.
+//!
+//! Like `Breaks`, the brackets are stored in a tree measured in the base
+//! units of the text, so matching walks over the brackets between a pair
+//! rather than over the text, in either direction, and an edit only
+//! rebuilds the part of the tree it touches. Brackets in strings and
+//! comments aren't known to the index; the caller skips them, using the
+//! syntax scopes.
+
+use std::cmp::min;
+use std::mem;
+
+use rope::interval::Interval;
+use rope::rope::{Rope, RopeDelta};
+use rope::tree::{Cursor, Leaf, Node, NodeInfo, TreeBuilder};
+
+/// The opening and closing brackets that are matched.
+pub const BRACKET_PAIRS: &[(u8, u8)] = &[(b'(', b')'), (b'[', b']'), (b'{', b'}')];
+
+/// The most brackets `find_match` walks over looking for a match; a bracket
+/// whose match is further away is treated as unmatched.
+const MAX_MATCH_DISTANCE: usize = 10_000;
+
+const MIN_LEAF: usize = 32;
+const MAX_LEAF: usize = 64;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct BracketsLeaf {
+    len: usize,  // measured in base units
+    brackets: Vec<(usize, u8)>,  // offset relative to start of leaf, and bracket; sorted
+}
+
+#[derive(Clone)]
+struct BracketsInfo(usize);  // number of brackets
+
+impl Leaf for BracketsLeaf {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn is_ok_child(&self) -> bool {
+        self.brackets.len() >= MIN_LEAF
+    }
+
+    fn push_maybe_split(&mut self, other: &BracketsLeaf, iv: Interval) -> Option<BracketsLeaf> {
+        let (start, end) = iv.start_end();
+        for &(offset, c) in &other.brackets {
+            if iv.contains(offset) {
+                self.brackets.push((offset - start + self.len, c));
+            }
+        }
+        self.len += min(end, other.len()) - start;
+
+        if self.brackets.len() <= MAX_LEAF {
+            None
+        } else {
+            let splitpoint = self.brackets.len() / 2;  // number of brackets
+            let splitpoint_units = self.brackets[splitpoint].0;
+            let mut new = self.brackets.split_off(splitpoint);
+            for bracket in &mut new {
+                bracket.0 -= splitpoint_units;
+            }
+            let new_len = self.len - splitpoint_units;
+            self.len = splitpoint_units;
+            Some(BracketsLeaf {
+                len: new_len,
+                brackets: new,
+            })
+        }
+    }
+}
+
+impl NodeInfo for BracketsInfo {
+    type L = BracketsLeaf;
+
+    fn accumulate(&mut self, other: &Self) {
+        self.0 += other.0;
+    }
+
+    fn compute_info(l: &BracketsLeaf) -> BracketsInfo {
+        BracketsInfo(l.brackets.len())
+    }
+}
+
+/// The brackets of a text, in order.
+#[derive(Default)]
+pub struct BracketIndex {
+    brackets: Node<BracketsInfo>,
+}
+
+impl BracketIndex {
+    /// Creates the index for `text`.
+    pub fn new(text: &Rope) -> BracketIndex {
+        BracketIndex { brackets: scan(text, 0, text.len()) }
+    }
+
+    /// Updates the index for an edit, where `text` is the result of
+    /// applying `delta` to the indexed text.
+    pub fn after_edit(&mut self, text: &Rope, delta: &RopeDelta) {
+        let (iv, new_len) = delta.summary();
+        let (start, end) = iv.start_end();
+        self.brackets.edit(Interval::new_closed_open(start, end),
+                           scan(text, start, start + new_len));
+    }
+
+    /// The offset of the bracket matching the one at `offset`, or `None` if
+    /// there is no bracket there, or it's unmatched. Brackets for which
+    /// `ignore` returns `true` are skipped, as if they weren't there.
+    pub fn find_match<F>(&self, offset: usize, ignore: F) -> Option<usize>
+        where F: Fn(usize) -> bool
+    {
+        let (at, bracket) = self.iter_from(offset, true).next()?;
+        if at != offset || ignore(offset) {
+            return None;
+        }
+        if let Some(&(open, close)) = BRACKET_PAIRS.iter().find(|pair| pair.0 == bracket) {
+            find_closing(self.iter_from(offset + 1, true), open, close, ignore)
+        } else {
+            let &(open, close) = BRACKET_PAIRS.iter().find(|pair| pair.1 == bracket)?;
+            find_closing(self.iter_from(offset, false), close, open, ignore)
+        }
+    }
+
+    /// The offsets of the pairs of matching brackets, in order of their
+    /// closing brackets. Brackets are matched as by `find_match`, except
+    /// that there's no limit to how far apart a pair may be.
+    pub fn pairs<F>(&self, ignore: F) -> Vec<(usize, usize)>
+        where F: Fn(usize) -> bool
+    {
+        let mut open_brackets = vec![Vec::new(); BRACKET_PAIRS.len()];
+        let mut pairs = Vec::new();
+        for (offset, c) in self.iter_from(0, true) {
+            if ignore(offset) {
+                continue;
+            }
+            for (i, &(open, close)) in BRACKET_PAIRS.iter().enumerate() {
+                if c == open {
+                    open_brackets[i].push(offset);
+                } else if c == close {
+                    if let Some(open_offset) = open_brackets[i].pop() {
+                        pairs.push((open_offset, offset));
+                    }
+                }
+            }
+        }
+        pairs
+    }
+
+    /// Iterates over the brackets at or after `offset` if `forward`, and
+    /// otherwise over those before it, nearest first.
+    fn iter_from(&self, offset: usize, forward: bool) -> BracketIter {
+        let cursor = Cursor::new(&self.brackets, offset);
+        let (leaf, pos) = cursor.get_leaf().unwrap();
+        let ix = leaf.brackets.iter().take_while(|bracket| bracket.0 < pos).count();
+        BracketIter {
+            leaf_start: offset - pos,
+            cursor,
+            ix,
+            forward,
+        }
+    }
+}
+
+/// An iterator over the offsets and characters of the brackets on one
+/// side of a position, nearest first.
+struct BracketIter<'a> {
+    cursor: Cursor<'a, BracketsInfo>,
+    leaf_start: usize,
+    // the index in the leaf of the next bracket if going forward, and of
+    // the one after it otherwise
+    ix: usize,
+    forward: bool,
+}
+
+impl<'a> Iterator for BracketIter<'a> {
+    type Item = (usize, u8);
+
+    fn next(&mut self) -> Option<(usize, u8)> {
+        loop {
+            let (leaf, _) = self.cursor.get_leaf()?;
+            if self.forward {
+                if let Some(&(offset, c)) = leaf.brackets.get(self.ix) {
+                    self.ix += 1;
+                    return Some((self.leaf_start + offset, c));
+                }
+                self.cursor.next_leaf()?;
+                self.ix = 0;
+            } else {
+                if self.ix > 0 {
+                    self.ix -= 1;
+                    let (offset, c) = leaf.brackets[self.ix];
+                    return Some((self.leaf_start + offset, c));
+                }
+                let (leaf, _) = self.cursor.prev_leaf()?;
+                self.ix = leaf.brackets.len();
+            }
+            self.leaf_start = self.cursor.pos();
+        }
+    }
+}
+
+/// The offset of the first `close` in `brackets` that isn't matched by an
+/// `open` before it, looking at no more than `MAX_MATCH_DISTANCE` brackets.
+fn find_closing<I, F>(brackets: I, open: u8, close: u8, ignore: F) -> Option<usize>
+    where I: Iterator<Item=(usize, u8)>,
+          F: Fn(usize) -> bool
+{
+    let mut depth = 0usize;
+    for (offset, c) in brackets.take(MAX_MATCH_DISTANCE) {
+        if (c == open || c == close) && !ignore(offset) {
+            if c == open {
+                depth += 1;
+            } else if depth == 0 {
+                return Some(offset);
+            } else {
+                depth -= 1;
+            }
+        }
+    }
+    None
+}
+
+/// The brackets between `start` and `end`, as a tree of length `end - start`.
+fn scan(text: &Rope, start: usize, end: usize) -> Node<BracketsInfo> {
+    let mut b = TreeBuilder::new();
+    let mut leaf = BracketsLeaf::default();
+    let mut leaf_start = start;
+    let mut offset = start;
+    for chunk in text.iter_chunks(start, end) {
+        for (i, &c) in chunk.as_bytes().iter().enumerate() {
+            if BRACKET_PAIRS.iter().any(|pair| pair.0 == c || pair.1 == c) {
+                if leaf.brackets.len() == MAX_LEAF {
+                    leaf.len = offset + i - leaf_start;
+                    leaf_start = offset + i;
+                    b.push_leaf(mem::take(&mut leaf));
+                }
+                leaf.brackets.push((offset + i - leaf_start, c));
+            }
+        }
+        offset += chunk.len();
+    }
+    leaf.len = end - leaf_start;
+    b.push_leaf(leaf);
+    b.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rope::delta::Delta;
+    use rope::interval::Interval;
+
+    fn brackets(index: &BracketIndex) -> Vec<(usize, u8)> {
+        index.iter_from(0, true).collect()
+    }
+
+    #[test]
+    fn find_match() {
+        let text = Rope::from("f(a[0], (b)) { \"}\" }");
+        let index = BracketIndex::new(&text);
+        let never = |_| false;
+        assert_eq!(Some(11), index.find_match(1, never));
+        assert_eq!(Some(1), index.find_match(11, never));
+        assert_eq!(Some(5), index.find_match(3, never));
+        assert_eq!(Some(10), index.find_match(8, never));
+        assert_eq!(None, index.find_match(0, never));
+        // the bracket in the string matches, unless it's ignored
+        assert_eq!(Some(16), index.find_match(13, never));
+        assert_eq!(Some(19), index.find_match(13, |offset| offset == 16));
+        assert_eq!(None, index.find_match(16, |offset| offset == 16));
+        assert_eq!(vec![(3, 5), (8, 10), (1, 11), (13, 19)],
+                   index.pairs(|offset| offset == 16));
+    }
+
+    #[test]
+    fn find_match_across_leaves() {
+        let inner = "[x] ".repeat(200);
+        let text = Rope::from(format!("{{ {} }} ({})", inner, inner));
+        let index = BracketIndex::new(&text);
+        let never = |_| false;
+        let close = inner.len() + 3;
+        assert_eq!(Some(close), index.find_match(0, never));
+        assert_eq!(Some(0), index.find_match(close, never));
+        assert_eq!(Some(text.len() - 1), index.find_match(close + 2, never));
+        assert_eq!(Some(close + 2), index.find_match(text.len() - 1, never));
+        assert_eq!(402, index.pairs(never).len());
+    }
+
+    #[test]
+    fn find_match_bounded() {
+        // the closing bracket is the last one looked at
+        let inner = "()".repeat(MAX_MATCH_DISTANCE / 2 - 1);
+        let text = Rope::from(format!("[{}]", inner));
+        let index = BracketIndex::new(&text);
+        assert_eq!(Some(text.len() - 1), index.find_match(0, |_| false));
+        assert_eq!(Some(0), index.find_match(text.len() - 1, |_| false));
+
+        let text = Rope::from(format!("[(){}]", inner));
+        let index = BracketIndex::new(&text);
+        assert_eq!(None, index.find_match(0, |_| false));
+        assert_eq!(None, index.find_match(text.len() - 1, |_| false));
+    }
+
+    #[test]
+    fn after_edit() {
+        let mut text = Rope::from("(a) [b]");
+        let mut index = BracketIndex::new(&text);
+        let delta = Delta::simple_edit(Interval::new_closed_open(2, 5), Rope::from("(c)) ("),
+                                       text.len());
+        text = delta.apply(&text);
+        assert_eq!("(a(c)) (b]", String::from(&text));
+        index.after_edit(&text, &delta);
+        assert_eq!(brackets(&BracketIndex::new(&text)), brackets(&index));
+        assert_eq!(Some(5), index.find_match(0, |_| false));
+        assert_eq!(None, index.find_match(9, |_| false));
+    }
+}
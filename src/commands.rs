@@ -0,0 +1,212 @@
+// Copyright 2018 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The typed command API for driving an `Editor`.
+//!
+//! These types are independent of any wire protocol; a front-end that embeds
+//! xi-core as a library constructs an `EditorCommand` and passes it to
+//! `Editor::execute`.
+
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+use rope::find::CaseMatching;
+use movement::Movement;
+use undo_tree::UndoStep;
+
+/// How a movement or click interacts with the existing selection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SelectionModifier {
+    /// Regions collapse to carets at the destination.
+    #[default]
+    None,
+    /// The active end of each region moves, extending the selection
+    /// (usually bound to the shift key).
+    Extend,
+    /// The selection becomes a rectangular block, from the corner where it
+    /// was started to the destination (usually bound to the alt key). With
+    /// `Move`, only the `Up`, `Down`, `Left` and `Right` movements keep the
+    /// column past the end of a line.
+    Block,
+}
+
+/// An enum representing touch and mouse gestures applied to the text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GestureType {
+    ToggleSel,
+}
+
+/// A single operation on an `Editor`.
+///
+/// Lines and columns are zero-based; columns are currently measured in
+/// UTF-8 code units. Line ranges are half-open.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditorCommand {
+    /// Inserts text at each selection region. If the text has one line for
+    /// each region, as when it was copied from a block selection of the
+    /// same height, each region gets one line.
+    Insert(String),
+    /// Inserts the lines of the text as a rectangle: the first line at each
+    /// region, and each following line on the next line of the buffer, at
+    /// the same visual column, padding short lines with spaces.
+    InsertBlock(String),
+    DeleteForward,
+    DeleteBackward,
+    DeleteWordForward,
+    DeleteWordBackward,
+    DeleteToEndOfParagraph,
+    DeleteToBeginningOfLine,
+    InsertNewline,
+    InsertTab,
+    /// Indents every line touched by a selection region by one level.
+    Indent,
+    /// Removes one level of indentation from every line touched by a
+    /// selection region.
+    Outdent,
+    /// Duplicates the line of each caret, or the text of each other
+    /// selection region, and moves the selection to the copy.
+    DuplicateLine,
+    /// Swaps the lines touched by the selection with the line above them.
+    MoveLinesUp,
+    /// Swaps the lines touched by the selection with the line below them.
+    MoveLinesDown,
+    /// Deletes every line touched by the selection.
+    DeleteLine,
+    /// Joins the lines touched by each selection region, or each caret's
+    /// line with the next one, collapsing the whitespace between them.
+    JoinLines,
+    /// Comments out the lines touched by the selection, or uncomments them
+    /// if they are all commented.
+    ToggleLineComment,
+    /// Wraps the text of each selection region, or each caret's line, in a
+    /// block comment, or unwraps it if it is one.
+    ToggleBlockComment,
+    /// Folds the lines touched by each selection region spanning several
+    /// lines, or else the innermost foldable region around it.
+    Fold,
+    /// Unfolds the folds shown on the lines touched by the selection.
+    Unfold,
+    /// Folds every outermost foldable region.
+    FoldAll,
+    /// Unfolds every fold.
+    UnfoldAll,
+    /// Applies a movement to every selection region.
+    Move(Movement, SelectionModifier),
+    SelectAll,
+    /// Adds a new caret for each region, one visible line above it.
+    AddSelectionAbove,
+    /// Adds a new caret for each region, one visible line below it.
+    AddSelectionBelow,
+    /// Adds the next occurrence of the selected text as a new region,
+    /// wrapping around the end of the document. If all regions are carets,
+    /// selects the word around each of them instead; occurrences added
+    /// after that must be whole words.
+    AddNextOccurrence,
+    /// Replaces the region most recently added by `AddNextOccurrence` with
+    /// the next occurrence, skipping it.
+    SkipOccurrence,
+    /// Selects every occurrence of the selected text, or of the word
+    /// around the caret.
+    SelectAllOccurrences,
+    /// Informs the editor of the visible line range.
+    Scroll(Range<usize>),
+    /// Sets the cursor to the start of the given line.
+    GotoLine(usize),
+    /// Requests that the given lines be rendered, even if they are not
+    /// currently visible.
+    RequestLines(Range<usize>),
+    /// Inserts the contents of the kill ring, like `Insert`.
+    Yank,
+    Transpose,
+    Click { line: usize, col: usize, modifier: SelectionModifier, click_count: usize },
+    Drag { line: usize, col: usize },
+    Gesture { line: usize, col: usize, ty: GestureType },
+    Undo,
+    Redo,
+    /// Moves back through the undo history in the order the edits were
+    /// made, across branches of the undo tree (like vim's `:earlier`).
+    UndoEarlier(UndoStep),
+    /// Moves forward through the undo history in the order the edits were
+    /// made (like vim's `:later`).
+    UndoLater(UndoStep),
+    /// Restores the state after the given undo group; see
+    /// `Editor::undo_tree` for the groups that exist.
+    GotoUndoState(usize),
+    FindNext { wrap_around: bool, allow_same: bool },
+    FindPrevious { wrap_around: bool },
+    /// Replaces the next occurrence of the active search with the given
+    /// text, and selects the one after it. For a regex search, `$1` or
+    /// `${name}` refer to capture groups.
+    ReplaceNext(String),
+    /// Replaces every occurrence of the active search, as a single edit.
+    ReplaceAll(String),
+    /// Replaces the occurrences of the active search within the selection,
+    /// as a single edit.
+    ReplaceInSelection(String),
+    DebugRewrap,
+    /// Prints the style spans present in the active selection.
+    DebugPrintSpans,
+    CancelOperation,
+    /// Cuts the active selection, returning its contents, or `None` if
+    /// the selection was empty.
+    Cut,
+    /// Copies the active selection, returning its contents, or `None` if
+    /// the selection was empty. The regions are joined with newlines; for a
+    /// block selection, lines that don't reach the block are included as
+    /// empty lines, so the text has one line per line of the block.
+    Copy,
+    /// Searches the document for `chars`, if present, falling back on
+    /// the last selection region if `chars` is `None`.
+    ///
+    /// If `whole_words` is set, only occurrences that neither start nor
+    /// end in the middle of a word are found. If `regex` is set, `chars` is
+    /// a regular expression; a search string taken from the selection is
+    /// always matched literally.
+    ///
+    /// Returns the string used for the search, or `None` if there was
+    /// nothing to search for.
+    Find { chars: Option<String>, case_matching: CaseMatching, whole_words: bool, regex: bool },
+}
+
+/// The errors that can occur when executing an `EditorCommand`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandError {
+    /// A line range whose end precedes its start.
+    InvalidLineRange(Range<usize>),
+    /// A click with a `click_count` of zero.
+    InvalidClickCount,
+    /// An undo group that isn't in the undo tree.
+    UnknownUndoGroup(usize),
+    /// A `Find` whose regular expression doesn't compile.
+    InvalidRegex(String),
+}
+
+/// The result of `Editor::execute`. Commands that produce text (such as
+/// `Copy`) return it as `Some`; all others return `None`.
+pub type CommandResult = Result<Option<String>, CommandError>;
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::CommandError::*;
+        match *self {
+            InvalidLineRange(ref r) => write!(f, "invalid line range: {}..{}", r.start, r.end),
+            InvalidClickCount => write!(f, "click count must be at least 1"),
+            UnknownUndoGroup(group) => write!(f, "unknown undo group: {}", group),
+            InvalidRegex(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for CommandError {}
@@ -23,29 +23,42 @@ use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
 use toml;
+use toml::Value;
 
 use syntax::SyntaxDefinition;
 use tabs::ViewIdentifier;
+use word_boundaries::WordBoundaryMode;
+use folding::FoldingMode;
+
+/// A table of config keys and values, as parsed from a TOML document.
+pub type Table = toml::value::Table;
 
 /// Namespace for various default settings.
 #[allow(unused)]
 mod defaults {
     use super::*;
-    pub const BASE: &'static str = include_str!("../assets/defaults.toml");
-    pub const WINDOWS: &'static str = include_str!("../assets/windows.toml");
-    pub const YAML: &'static str = include_str!("../assets/yaml.toml");
-    pub const MAKEFILE: &'static str = include_str!("../assets/makefile.toml");
-/*
+    pub const BASE: &str = include_str!("../assets/defaults.toml");
+    pub const WINDOWS: &str = include_str!("../assets/windows.toml");
+    pub const YAML: &str = include_str!("../assets/yaml.toml");
+    pub const MAKEFILE: &str = include_str!("../assets/makefile.toml");
+    pub const PYTHON: &str = include_str!("../assets/python.toml");
+    pub const SHELL: &str = include_str!("../assets/shell.toml");
+    pub const RUBY: &str = include_str!("../assets/ruby.toml");
+    pub const PERL: &str = include_str!("../assets/perl.toml");
+    pub const TOML: &str = include_str!("../assets/toml.toml");
+    pub const MARKDOWN: &str = include_str!("../assets/markdown.toml");
+    pub const JSON: &str = include_str!("../assets/json.toml");
+    pub const PLAINTEXT: &str = include_str!("../assets/plaintext.toml");
+
     /// A cache of loaded defaults.
     lazy_static! {
         static ref LOADED: Mutex<HashMap<ConfigDomain, Option<Table>>> = {
             Mutex::new(HashMap::new())
         };
     }
-*/
 
     /// config keys that are legal in most config files
-    pub const GENERAL_KEYS: &'static [&'static str] = &[
+    pub const GENERAL_KEYS: &[&str] = &[
         "tab_size",
         "line_ending",
         "translate_tabs_to_spaces",
@@ -55,12 +68,21 @@ mod defaults {
         "auto_indent",
         "scroll_past_end",
         "wrap_width",
+        "word_boundaries",
+        "increase_indent_after",
+        "decrease_indent_before",
+        "decrease_indent_after",
+        "auto_close_pairs",
+        "line_comment",
+        "block_comment",
+        "folding",
     ];
     /// config keys that are only legal at the top level
-    pub const TOP_LEVEL_KEYS: &'static [&'static str] = &[
+    pub const TOP_LEVEL_KEYS: &[&str] = &[
         "plugin_search_path",
+        "search_ignore_globs",
     ];
-/*
+
     /// Given a domain, returns the default config for that domain,
     /// if it exists.
     pub fn defaults_for_domain<D>(domain: D) -> Option<Table>
@@ -73,7 +95,7 @@ mod defaults {
     }
 
     fn load_for_domain(domain: ConfigDomain) -> Option<Table> {
-        match domain.into() {
+        match domain {
             ConfigDomain::General => {
                 let mut base = load(BASE);
                 if let Some(mut overrides) = platform_overrides() {
@@ -87,6 +109,22 @@ mod defaults {
                 Some(load(YAML)),
             ConfigDomain::Syntax(SyntaxDefinition::Makefile) =>
                 Some(load(MAKEFILE)),
+            ConfigDomain::Syntax(SyntaxDefinition::Python) =>
+                Some(load(PYTHON)),
+            ConfigDomain::Syntax(SyntaxDefinition::Shell) =>
+                Some(load(SHELL)),
+            ConfigDomain::Syntax(SyntaxDefinition::Ruby) =>
+                Some(load(RUBY)),
+            ConfigDomain::Syntax(SyntaxDefinition::Perl) =>
+                Some(load(PERL)),
+            ConfigDomain::Syntax(SyntaxDefinition::Toml) =>
+                Some(load(TOML)),
+            ConfigDomain::Syntax(SyntaxDefinition::Markdown) =>
+                Some(load(MARKDOWN)),
+            ConfigDomain::Syntax(SyntaxDefinition::Json) =>
+                Some(load(JSON)),
+            ConfigDomain::Syntax(SyntaxDefinition::Plaintext) =>
+                Some(load(PLAINTEXT)),
             _ => None,
         }
     }
@@ -101,7 +139,6 @@ mod defaults {
         table_from_toml_str(default)
             .expect("default configs must load")
     }
-*/
 }
 
 /// A `ConfigDomain` describes a level or category of user settings.
@@ -124,6 +161,8 @@ pub enum ConfigError {
     UnknownDomain(String),
     /// A file-based config could not be loaded or parsed.
     Parse(PathBuf, toml::de::Error),
+    /// A config value was missing, or had the wrong type.
+    UnexpectedItem(String),
     /// An Io Error
     Io(io::Error),
 }
@@ -132,7 +171,6 @@ pub enum ConfigError {
 /// user settings.
 #[derive(Debug)]
 pub struct ConfigPair {
-/*
     /// A static default configuration, which will never change.
     base: Option<Table>,
     /// A variable, user provided configuration. Items here take
@@ -140,7 +178,6 @@ pub struct ConfigPair {
     user: Option<Table>,
     /// A snapshot of base + user.
     cache: Arc<Table>,
-*/
 }
 
 #[derive(Debug)]
@@ -156,19 +193,17 @@ pub struct ConfigManager {
     /// as plugins and themes.
     extras_dir: Option<PathBuf>,
 }
-/*
 /// A collection of config tables representing a hierarchy, with each
 /// table's keys superseding keys in preceding tables.
 #[derive(Debug, Clone, Default)]
 struct TableStack(Vec<Arc<Table>>);
-*/
 
 /// A frozen collection of settings, and their sources.
 #[derive(Debug, Clone)]
 pub struct Config<T> {
     /// The underlying set of config tables that contributed to this
     /// `Config` instance. Used for diffing.
-    // source: TableStack,
+    source: TableStack,
     /// The settings themselves, deserialized into some concrete type.
     pub items: T,
 }
@@ -185,6 +220,27 @@ pub struct BufferItems {
     pub auto_indent: bool,
     pub scroll_past_end: bool,
     pub wrap_width: usize,
+    pub word_boundaries: WordBoundaryMode,
+    /// A new line is indented one level deeper than the previous one if
+    /// the text before the cursor ends with one of these.
+    pub increase_indent_after: Vec<String>,
+    /// A new line is indented one level less if the text after the cursor
+    /// starts with one of these, e.g. a closing bracket.
+    pub decrease_indent_before: Vec<String>,
+    /// A new line is indented one level less if the previous line starts
+    /// with one of these words, e.g. `return`.
+    pub decrease_indent_after: Vec<String>,
+    /// Opening and closing characters, such as brackets and quotes, whose
+    /// closer is inserted along with the opener.
+    pub auto_close_pairs: Vec<(char, char)>,
+    /// The string starting a line comment, or an empty string if the
+    /// syntax has no line comments.
+    pub line_comment: String,
+    /// The strings starting and ending a block comment, if the syntax has
+    /// block comments.
+    pub block_comment: Option<(String, String)>,
+    /// How the regions that can be folded are found.
+    pub folding: FoldingMode,
 }
 
 pub type BufferConfig = Config<BufferItems>;
@@ -192,36 +248,28 @@ pub type BufferConfig = Config<BufferItems>;
 impl ConfigPair {
     /// Creates a new `ConfigPair` suitable for the provided domain.
     pub fn for_domain<D: Into<ConfigDomain>>(domain: D) -> Self {
-        /*
         let domain = domain.into();
         let base = defaults::defaults_for_domain(domain);
         let user = None;
-        let cache = Arc::new(base.clone().unwrap_or_default());*/
-        ConfigPair { /* base, user, cache */ }
+        let cache = Arc::new(base.clone().unwrap_or_default());
+        ConfigPair { base, user, cache }
     }
-/*
+
     fn set_table(&mut self, user: Table) {
         self.user = Some(user);
         self.rebuild();
     }
-*/
-/*
+
     fn update_table(&mut self, changes: Table) {
         {
             let conf = self.user.get_or_insert(Table::new());
             for (k, v) in changes {
-                //TODO: test/document passing null values to unset keys
-                if v.is_null() {
-                    conf.remove(&k);
-                } else {
-                    conf.insert(k.to_owned(), v.to_owned());
-                }
+                conf.insert(k, v);
             }
         }
         self.rebuild();
     }
-*/
-/*
+
     pub fn rebuild(&mut self) {
         let mut cache = self.base.clone().unwrap_or_default();
         if let Some(ref user) = self.user {
@@ -231,7 +279,6 @@ impl ConfigPair {
         }
         self.cache = Arc::new(cache);
     }
-*/
 }
 
 impl ConfigManager {
@@ -242,7 +289,7 @@ impl ConfigManager {
     pub fn set_extras_dir<P: AsRef<Path>>(&mut self, path: P) {
         self.extras_dir = Some(path.as_ref().to_owned())
     }
-/*
+
     // NOTE: search paths don't really fit the general config model;
     // they're never exposed to the client, they can't be overridden on a
     // per-buffer basis, and they can be appended to from a number of sources.
@@ -252,8 +299,12 @@ impl ConfigManager {
     /// Returns the plugin_search_path.
     pub fn plugin_search_path(&self) -> Vec<PathBuf> {
         let val = self.get("plugin_search_path", ConfigDomain::General).unwrap();
-        let mut search_path: Vec<PathBuf> = serde_json::from_value(val.clone())
-            .unwrap();
+        let mut search_path: Vec<PathBuf> = val.as_array()
+            .expect("plugin_search_path must be an array")
+            .iter()
+            .flat_map(Value::as_str)
+            .map(PathBuf::from)
+            .collect();
 
         // if there is user config dir, add plugins subdir to search path
         if let Some(ref config_dir) = self.config_dir {
@@ -267,6 +318,17 @@ impl ConfigManager {
         search_path
     }
 
+    /// Returns the globs of files and directories skipped by project search.
+    pub fn search_ignore_globs(&self) -> Vec<String> {
+        let val = self.get("search_ignore_globs", ConfigDomain::General).unwrap();
+        val.as_array()
+            .expect("search_ignore_globs must be an array")
+            .iter()
+            .flat_map(Value::as_str)
+            .map(String::from)
+            .collect()
+    }
+
     /// Sets the config for the given domain, removing any existing config.
     pub fn set_user_config<P>(&mut self, domain: ConfigDomain,
                               new_config: Table, path: P)
@@ -274,7 +336,7 @@ impl ConfigManager {
         where P: Into<Option<PathBuf>>,
     {
         self.check_table(&new_config)?;
-        self.configs.entry(domain.into())
+        self.configs.entry(domain)
             .or_insert_with(|| { ConfigPair::for_domain(domain) })
             .set_table(new_config);
         path.into().map(|p| self.sources.insert(p, domain));
@@ -282,13 +344,12 @@ impl ConfigManager {
     }
 
     /// Updates the config for the given domain. Existing keys which are
-    /// not in `changes` are untouched; existing keys for which `changes`
-    /// contains `Value::Null` are removed.
+    /// not in `changes` are untouched.
     pub fn update_user_config(&mut self, domain: ConfigDomain, changes: Table)
                           -> Result<(), ConfigError>
     {
         self.check_table(&changes)?;
-        let conf = self.configs.entry(domain.into())
+        let conf = self.configs.entry(domain)
             .or_insert_with(|| { ConfigPair::for_domain(domain) });
         conf.update_table(changes);
         Ok(())
@@ -301,7 +362,7 @@ impl ConfigManager {
                 .expect("Empty table is always valid");
         }
     }
-*/
+
     /// Checks whether a given file should be loaded, i.e. whether it is a
     /// config file and whether it is in an expected location.
     pub fn should_load_file<P: AsRef<Path>>(&self, path: P) -> bool {
@@ -314,21 +375,17 @@ impl ConfigManager {
             .unwrap_or(false)
     }
 
-/*
     fn check_table(&self, table: &Table) -> Result<(), ConfigError> {
         // verify that this table is well formed
         let mut defaults = defaults::defaults_for_domain(ConfigDomain::General)
             .expect("general domain must have defaults");
         for (k, v) in table.iter() {
-            // changes can include 'null', which means clear field
-            if v.is_null() { continue }
             defaults.insert(k.to_owned(), v.to_owned());
         }
-        let _: BufferItems = serde_json::from_value(defaults.into())?;
+        BufferItems::from_table(&defaults)?;
         Ok(())
     }
-*/
-/*
+
     /// Generates a snapshot of the current configuration for a particular
     /// view.
     pub fn get_buffer_config<S, V>(&self, syntax: S, view_id: V) -> BufferConfig
@@ -340,9 +397,9 @@ impl ConfigManager {
         let mut configs = Vec::new();
 
         configs.push(self.configs.get(&ConfigDomain::General));
-        syntax.map(|s| configs.push(self.configs.get(&s.into())));
-        view_id.map(|v| configs.push(self.configs.get(&ConfigDomain::SysOverride(v))));
-        view_id.map(|v| configs.push(self.configs.get(&ConfigDomain::UserOverride(v))));
+        if let Some(s) = syntax { configs.push(self.configs.get(&s.into())) }
+        if let Some(v) = view_id { configs.push(self.configs.get(&ConfigDomain::SysOverride(v))) }
+        if let Some(v) = view_id { configs.push(self.configs.get(&ConfigDomain::UserOverride(v))) }
 
         let configs = configs.iter().flat_map(Option::iter)
             .map(|c| c.cache.clone())
@@ -352,11 +409,10 @@ impl ConfigManager {
         let stack = TableStack(configs);
         stack.into_config()
     }
-*/
+
     pub fn default_buffer_config(&self) -> BufferConfig {
         self.get_buffer_config(None, None)
     }
-/*
     /// Return the value for `key` in the `ConfigDomain` `domain`.
     fn get<D>(&self, key: &str, domain: D) -> Option<&Value>
         where D: Into<ConfigDomain>,
@@ -364,18 +420,23 @@ impl ConfigManager {
         self.configs.get(&domain.into())
             .and_then(|c| c.cache.get(key))
     }
-*/
 }
 
 impl Default for ConfigManager {
     fn default() -> ConfigManager {
         // the domains for which we include defaults (platform defaults are
         // rolled into `General` at runtime)
-        let defaults = vec![
-            ConfigDomain::General,
+        let defaults = [ConfigDomain::General,
             ConfigDomain::Syntax(SyntaxDefinition::Yaml),
-            ConfigDomain::Syntax(SyntaxDefinition::Makefile)
-        ].iter()
+            ConfigDomain::Syntax(SyntaxDefinition::Makefile),
+            ConfigDomain::Syntax(SyntaxDefinition::Python),
+            ConfigDomain::Syntax(SyntaxDefinition::Shell),
+            ConfigDomain::Syntax(SyntaxDefinition::Ruby),
+            ConfigDomain::Syntax(SyntaxDefinition::Perl),
+            ConfigDomain::Syntax(SyntaxDefinition::Toml),
+            ConfigDomain::Syntax(SyntaxDefinition::Markdown),
+            ConfigDomain::Syntax(SyntaxDefinition::Json),
+            ConfigDomain::Syntax(SyntaxDefinition::Plaintext)].iter()
         .map(|d| (*d, ConfigPair::for_domain(*d)))
         .collect::<HashMap<_, _>>();
 
@@ -388,7 +449,6 @@ impl Default for ConfigManager {
     }
 }
 
-/*
 impl TableStack {
     /// Create a single table representing the final config values.
     fn collate(&self) -> Table {
@@ -408,11 +468,10 @@ impl TableStack {
     }
 
     /// Converts the underlying tables into a static `Config` instance.
-    fn into_config<T>(self) -> Config<T>
-        where for<'de> T: Deserialize<'de>
-    {
+    fn into_config(self) -> BufferConfig {
         let out = self.collate();
-        let items: T = serde_json::from_value(out.into()).unwrap();
+        let items = BufferItems::from_table(&out)
+            .expect("collated config must be well formed");
         let source = self;
         Config { source, items }
     }
@@ -443,14 +502,122 @@ impl TableStack {
     }
 
 }
-*/
-/*
+
 impl<T> Config<T> {
     pub fn to_table(&self) -> Table {
         self.source.collate()
     }
+
+    /// Returns the items which differ between `self` and `other`, or
+    /// `None` if the two configs are identical.
+    pub fn changes_from(&self, other: Option<&Config<T>>) -> Option<Table> {
+        match other {
+            Some(other) => self.source.diff(&other.source),
+            None => Some(self.source.collate()),
+        }
+    }
+}
+
+impl BufferItems {
+    /// Reads the buffer settings out of a collated config table.
+    pub fn from_table(table: &Table) -> Result<BufferItems, ConfigError> {
+        fn get<'a>(table: &'a Table, key: &str) -> Result<&'a Value, ConfigError> {
+            table.get(key).ok_or_else(|| ConfigError::UnexpectedItem(
+                format!("missing key '{}'", key)))
+        }
+
+        fn wrong_type(key: &str, expected: &str) -> ConfigError {
+            ConfigError::UnexpectedItem(format!("'{}' must be {}", key, expected))
+        }
+
+        fn get_str(table: &Table, key: &str) -> Result<String, ConfigError> {
+            get(table, key)?.as_str().map(str::to_owned)
+                .ok_or_else(|| wrong_type(key, "a string"))
+        }
+
+        fn get_bool(table: &Table, key: &str) -> Result<bool, ConfigError> {
+            get(table, key)?.as_bool().ok_or_else(|| wrong_type(key, "a boolean"))
+        }
+
+        fn get_usize(table: &Table, key: &str) -> Result<usize, ConfigError> {
+            get(table, key)?.as_integer()
+                .and_then(|i| if i >= 0 { Some(i as usize) } else { None })
+                .ok_or_else(|| wrong_type(key, "a non-negative integer"))
+        }
+
+        fn get_f32(table: &Table, key: &str) -> Result<f32, ConfigError> {
+            let val = get(table, key)?;
+            val.as_float().or_else(|| val.as_integer().map(|i| i as f64))
+                .map(|f| f as f32)
+                .ok_or_else(|| wrong_type(key, "a number"))
+        }
+
+        fn get_word_boundaries(table: &Table, key: &str)
+            -> Result<WordBoundaryMode, ConfigError>
+        {
+            get_str(table, key).ok()
+                .and_then(|name| WordBoundaryMode::try_from_name(&name))
+                .ok_or_else(|| wrong_type(key, "\"code\" or \"unicode\""))
+        }
+
+        fn get_folding(table: &Table, key: &str) -> Result<FoldingMode, ConfigError> {
+            get_str(table, key).ok()
+                .and_then(|name| FoldingMode::try_from_name(&name))
+                .ok_or_else(|| wrong_type(key, "\"brackets\" or \"indentation\""))
+        }
+
+        fn get_str_list(table: &Table, key: &str) -> Result<Vec<String>, ConfigError> {
+            get(table, key)?.as_array()
+                .and_then(|arr| arr.iter()
+                          .map(|v| v.as_str().map(str::to_owned))
+                          .collect::<Option<Vec<_>>>())
+                .ok_or_else(|| wrong_type(key, "an array of strings"))
+        }
+
+        fn get_char_pairs(table: &Table, key: &str) -> Result<Vec<(char, char)>, ConfigError> {
+            get_str_list(table, key)?.iter()
+                .map(|pair| {
+                    let mut chars = pair.chars();
+                    match (chars.next(), chars.next(), chars.next()) {
+                        (Some(open), Some(close), None) => Some((open, close)),
+                        _ => None,
+                    }
+                })
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| wrong_type(key, "an array of two-character strings"))
+        }
+
+        fn get_str_pair(table: &Table, key: &str)
+            -> Result<Option<(String, String)>, ConfigError>
+        {
+            match get_str_list(table, key)?.as_slice() {
+                [] => Ok(None),
+                [open, close] => Ok(Some((open.to_owned(), close.to_owned()))),
+                _ => Err(wrong_type(key, "an array of two strings, or an empty array")),
+            }
+        }
+
+        Ok(BufferItems {
+            line_ending: get_str(table, "line_ending")?,
+            tab_size: get_usize(table, "tab_size")?,
+            translate_tabs_to_spaces: get_bool(table, "translate_tabs_to_spaces")?,
+            use_tab_stops: get_bool(table, "use_tab_stops")?,
+            font_face: get_str(table, "font_face")?,
+            font_size: get_f32(table, "font_size")?,
+            auto_indent: get_bool(table, "auto_indent")?,
+            scroll_past_end: get_bool(table, "scroll_past_end")?,
+            wrap_width: get_usize(table, "wrap_width")?,
+            word_boundaries: get_word_boundaries(table, "word_boundaries")?,
+            increase_indent_after: get_str_list(table, "increase_indent_after")?,
+            decrease_indent_before: get_str_list(table, "decrease_indent_before")?,
+            decrease_indent_after: get_str_list(table, "decrease_indent_after")?,
+            auto_close_pairs: get_char_pairs(table, "auto_close_pairs")?,
+            line_comment: get_str(table, "line_comment")?,
+            block_comment: get_str_pair(table, "block_comment")?,
+            folding: get_folding(table, "folding")?,
+        })
+    }
 }
-*/
 
 impl<T: PartialEq> PartialEq for Config<T> {
     fn eq(&self, other: &Config<T>) -> bool {
@@ -489,23 +656,15 @@ impl fmt::Display for ConfigError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use self::ConfigError::*;
         match *self {
-            UnknownDomain(ref s) => write!(f, "{}: {}", self.description(), s),
-            Parse(ref p, ref e) => write!(f, "{} ({:?}), {:?}", self.description(), p, e),
+            UnknownDomain(ref s) => write!(f, "unknown domain: {}", s),
+            Parse(ref p, ref e) => write!(f, "{} ({:?}), {:?}", e, p, e),
+            UnexpectedItem(ref s) => write!(f, "invalid config item: {}", s),
             Io(ref e) => write!(f, "error loading config: {:?}", e),
         }
     }
 }
 
-impl Error for ConfigError {
-    fn description(&self) -> &str {
-        use self::ConfigError::*;
-        match *self {
-            UnknownDomain( .. ) => "unknown domain",
-            Parse( _, ref e ) => e.description(),
-            Io( ref e ) => e.description(),
-        }
-    }
-}
+impl Error for ConfigError {}
 
 impl From<io::Error> for ConfigError {
     fn from(src: io::Error) -> ConfigError {
@@ -517,7 +676,7 @@ impl From<io::Error> for ConfigError {
 pub fn init_config_dir(dir: &Path) -> io::Result<()> {
     let builder = fs::DirBuilder::new();
     builder.create(dir)?;
-    Ok(builder.create(dir.join("plugins"))?)
+    builder.create(dir.join("plugins"))
 }
 
 pub fn iter_config_files(dir: &Path) -> io::Result<Box<Iterator<Item=PathBuf>>> {
@@ -529,12 +688,11 @@ pub fn iter_config_files(dir: &Path) -> io::Result<Box<Iterator<Item=PathBuf>>>
         });
     Ok(Box::new(iter))
 }
-/*
 /// Attempts to load a config from a file. The config's domain is determined
 /// by the file name.
 pub fn try_load_from_file(path: &Path) -> Result<(ConfigDomain, Table), ConfigError> {
     let domain = ConfigDomain::try_from_path(path)?;
-    let mut file = fs::File::open(&path)?;
+    let mut file = fs::File::open(path)?;
     let mut contents = String::new();
     file.read_to_string(&mut contents)?;
     let table = table_from_toml_str(&contents)
@@ -542,16 +700,10 @@ pub fn try_load_from_file(path: &Path) -> Result<(ConfigDomain, Table), ConfigEr
 
     Ok((domain, table))
 }
-*/
-/*
+
 fn table_from_toml_str(s: &str) -> Result<Table, toml::de::Error> {
-    let table = toml::from_str(&s)?;
-    let table = from_toml_value(table).as_object()
-        .unwrap()
-        .to_owned();
-    Ok(table)
+    toml::from_str(s)
 }
-*/
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -568,11 +720,15 @@ mod tests {
     #[test]
     fn test_loading_defaults() {
         let manager = ConfigManager::default();
-        assert_eq!(manager.configs.len(), 3);
+        assert_eq!(manager.configs.len(), 11);
         let key = SyntaxDefinition::Yaml.into();
         assert!(manager.configs.contains_key(&key));
         let yaml = manager.configs.get(&key).unwrap();
-        assert_eq!(yaml.cache.get("tab_size"), Some(&json!(2)));
+        assert_eq!(yaml.cache.get("tab_size"), Some(&Value::Integer(2)));
+        // plain text has no comment markers
+        let plaintext = manager.get_buffer_config(SyntaxDefinition::Plaintext, None);
+        assert_eq!("", plaintext.items.line_comment);
+        assert_eq!(None, plaintext.items.block_comment);
     }
 
     #[test]
@@ -587,9 +743,9 @@ mod tests {
         manager.set_user_config(ConfigDomain::General, user_config, None)
             .unwrap();
 
-        let view_id = "view-id-1".into();
+        let view_id = ViewIdentifier::new(1);
         // system override
-        let changes = json!({"tab_size": 67}).as_object().unwrap().to_owned();
+        let changes = table_from_toml_str("tab_size = 67").unwrap();
         manager.update_user_config(ConfigDomain::SysOverride(view_id), changes).unwrap();
 
         let config = manager.default_buffer_config();
@@ -609,24 +765,20 @@ mod tests {
         assert_eq!(config.items.tab_size, 67);
 
         // user override trumps everything
-        let changes = json!({"tab_size": 85}).as_object().unwrap().to_owned();
+        let changes = table_from_toml_str("tab_size = 85").unwrap();
         manager.update_user_config(ConfigDomain::UserOverride(view_id), changes).unwrap();
         let config = manager.get_buffer_config(SyntaxDefinition::Rust, view_id);
         assert_eq!(config.items.tab_size, 85);
     }
 
     #[test]
-    fn test_config_domain_serde() {
+    fn test_config_domain_from_path() {
         assert!(ConfigDomain::try_from_path(Path::new("hi/python.xiconfig")).is_ok());
         assert!(ConfigDomain::try_from_path(Path::new("hi/preferences.xiconfig")).is_ok());
         assert!(ConfigDomain::try_from_path(Path::new("hi/rust.xiconfig")).is_ok());
         assert!(ConfigDomain::try_from_path(Path::new("hi/unknown.xiconfig")).is_err());
-
-        assert_eq!(serde_json::to_string(&ConfigDomain::General).unwrap(), "\"general\"");
-        let d = ConfigDomain::UserOverride(ViewIdentifier::from("view-id-1"));
-        assert_eq!(serde_json::to_string(&d).unwrap(), "{\"user_override\":\"view-id-1\"}");
-        let d = ConfigDomain::Syntax(SyntaxDefinition::Swift);
-        assert_eq!(serde_json::to_string(&d).unwrap(), "{\"syntax\":\"swift\"}");
+        assert_eq!(ConfigDomain::try_from_path(Path::new("hi/swift.xiconfig")).unwrap(),
+                   ConfigDomain::Syntax(SyntaxDefinition::Swift));
     }
 
     #[test]
@@ -634,10 +786,10 @@ mod tests {
         let mut manager = ConfigManager::default();
         let config_dir = PathBuf::from("/home/config/xi");
         manager.set_config_dir(&config_dir);
-        assert!(manager.should_load_file(&config_dir.join("preferences.xiconfig")));
-        assert!(manager.should_load_file(&config_dir.join("rust.xiconfig")));
-        assert!(!manager.should_load_file(&config_dir.join("fake?.xiconfig")));
-        assert!(!manager.should_load_file(&config_dir.join("preferences.toml")));
+        assert!(manager.should_load_file(config_dir.join("preferences.xiconfig")));
+        assert!(manager.should_load_file(config_dir.join("rust.xiconfig")));
+        assert!(!manager.should_load_file(config_dir.join("fake?.xiconfig")));
+        assert!(!manager.should_load_file(config_dir.join("preferences.toml")));
         assert!(!manager.should_load_file(Path::new("/home/rust.xiconfig")));
         assert!(!manager.should_load_file(Path::new("/home/config/xi/subdir/rust.xiconfig")));
     }
@@ -660,29 +812,50 @@ translate_tabs_to_spaces = true
         let stack2 = TableStack(vec![Arc::new(conf2)]);
         let diff = stack1.diff(&stack2).unwrap();
         assert!(diff.len() == 1);
-        assert_eq!(diff.get("tab_size"), Some(&42.into()));
+        assert_eq!(diff.get("tab_size"), Some(&Value::Integer(42)));
     }
 
     #[test]
     fn test_updating_in_place() {
         let mut manager = ConfigManager::default();
         assert_eq!(manager.default_buffer_config().items.font_size, 14.);
-        let changes = json!({"font_size": 69, "font_face": "nice"})
-            .as_object().unwrap().to_owned();
+        let changes = table_from_toml_str(r#"
+font_size = 69
+font_face = "nice"
+"#).unwrap();
         manager.update_user_config(ConfigDomain::General, changes).unwrap();
         assert_eq!(manager.default_buffer_config().items.font_size, 69.);
 
-        // null values in updates removes keys
-        let changes = json!({"font_size": Value::Null})
-            .as_object().unwrap().to_owned();
+        // keys missing from an update are untouched
+        let changes = table_from_toml_str("font_size = 12.5").unwrap();
         manager.update_user_config(ConfigDomain::General, changes).unwrap();
-        assert_eq!(manager.default_buffer_config().items.font_size, 14.);
+        assert_eq!(manager.default_buffer_config().items.font_size, 12.5);
         assert_eq!(manager.default_buffer_config().items.font_face, "nice");
 
-        let changes = json!({"font_face": "Roboto"})
-            .as_object().unwrap().to_owned();
+        // malformed values are rejected
+        let changes = table_from_toml_str(r#"tab_size = "four""#).unwrap();
+        assert!(manager.update_user_config(ConfigDomain::General, changes).is_err());
+
+        let changes = table_from_toml_str(r#"font_face = "Roboto""#).unwrap();
         manager.update_user_config(SyntaxDefinition::Dart.into(), changes).unwrap();
         let config = manager.get_buffer_config(SyntaxDefinition::Dart, None);
         assert_eq!(config.items.font_face, "Roboto");
     }
+
+    #[test]
+    fn test_word_boundaries() {
+        let mut manager = ConfigManager::default();
+        assert_eq!(manager.default_buffer_config().items.word_boundaries,
+                   WordBoundaryMode::Code);
+
+        let changes = table_from_toml_str(r#"word_boundaries = "unicode""#).unwrap();
+        manager.update_user_config(SyntaxDefinition::Markdown.into(), changes).unwrap();
+        let config = manager.get_buffer_config(SyntaxDefinition::Markdown, None);
+        assert_eq!(config.items.word_boundaries, WordBoundaryMode::Unicode);
+        let config = manager.get_buffer_config(SyntaxDefinition::Rust, None);
+        assert_eq!(config.items.word_boundaries, WordBoundaryMode::Code);
+
+        let changes = table_from_toml_str(r#"word_boundaries = "prose""#).unwrap();
+        assert!(manager.update_user_config(ConfigDomain::General, changes).is_err());
+    }
 }
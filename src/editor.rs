@@ -13,44 +13,47 @@
 // limitations under the License.
 
 use std::borrow::Cow;
-use std::cmp::{min, max};
+use std::cmp::{max, min, Reverse};
+use std::collections::BTreeSet;
 use std::fs::File;
 use std::path::{Path, PathBuf};
-use std::io::Write;
-use std::collections::BTreeSet;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
 
 use rope::rope::{LinesMetric, Rope, RopeInfo};
 use rope::interval::Interval;
 use rope::delta::{self, Delta, Transformer};
+use rope::tree::Cursor;
 use rope::engine::{Engine, RevId, RevToken};
-use rope::spans::SpansBuilder;
+use rope::history::HistoryError;
+use rope::find::{CaseMatching, RegexMatcher};
 
-use view::View;
+use view::{View, advance_col};
+use linewrap::{ColumnMeasurer, WidthMeasurer};
+use brackets::BracketIndex;
+use folding::{self, FoldingMode};
 use word_boundaries::WordCursor;
 use movement::{Movement, region_movement};
-use selection::{Affinity, Selection, SelRegion};
+use selection::{Affinity, BlockSelection, Selection, SelRegion};
+use undo_tree::{UndoStep, UndoTree};
 
 use tabs::{BufferIdentifier, ViewIdentifier, DocumentCtx};
-use rpc::{self, GestureType};
+use commands::{CommandError, CommandResult, EditorCommand, GestureType, SelectionModifier};
 use syntax::SyntaxDefinition;
 use layers::Scopes;
-use config::{BufferConfig};
-
-
-#[cfg(not(feature = "ledger"))]
-pub struct SyncStore;
-#[cfg(feature = "ledger")]
-use fuchsia::sync::SyncStore;
-
-const FLAG_SELECT: u64 = 2;
-
-// TODO This could go much higher without issue but while developing it is
-// better to keep it low to expose bugs in the GC during casual testing.
-const MAX_UNDOS: usize = 20;
+use config::{BufferConfig, Table};
 
 // Maximum returned result from plugin get_data RPC.
 const MAX_SIZE_LIMIT: usize = 1024 * 1024;
 
+// Priority of the indentation added by `auto_indent`, which is lower than
+// that of the newline it follows.
+const INDENT_PRIORITY: usize = 0x8000;
+
+// The number of undo groups above which the oldest undone branches are
+// gc'ed, taking the tree back to three quarters of this.
+const MAX_UNDO_GROUPS: usize = 1000;
+
 pub struct Editor {
     pub text: Rope,
     pub path: Option<PathBuf>,
@@ -61,10 +64,8 @@ pub struct Editor {
     pub last_rev_id: RevId,
     pub pristine_rev_id: RevId,
     pub undo_group_id: usize,
-    pub live_undos: Vec<usize>, //  undo groups that may still be toggled
-    pub cur_undo: usize, // index to live_undos, ones after this are undone
-    pub undos: BTreeSet<usize>, // undo groups that are undone
-    pub gc_undos: BTreeSet<usize>, // undo groups that are no longer live and should be gc'ed
+    pub undo_tree: UndoTree,
+    pub gc_undos: BTreeSet<usize>, // undo groups pruned from the tree that should be gc'ed
     pub this_edit_type: EditType,
     pub last_edit_type: EditType,
     pub scroll_to: Option<usize>,
@@ -72,6 +73,21 @@ pub struct Editor {
     pub doc_ctx: DocumentCtx,
     pub config: BufferConfig,
     pub revs_in_flight: usize,
+    /// The offsets of closing characters inserted by `auto_close_pairs`,
+    /// which typing the same character steps over.
+    pub auto_closed: Vec<usize>,
+    /// Closers inserted by the edit not yet committed, in the coordinates of
+    /// the edited text, which are added to `auto_closed` once it is.
+    pub pending_auto_closed: Vec<usize>,
+    /// The selection to set once the edit not yet committed is, in the
+    /// coordinates of the edited text, in place of moving the current
+    /// selection through the edit. See `add_delta_with_selection`.
+    pub pending_selection: Option<Selection>,
+    /// The brackets of the text, for bracket matching.
+    pub brackets: BracketIndex,
+    /// The regions that can be folded, if supplied by a plugin, as the
+    /// offsets they start and end at. See `set_fold_regions`.
+    pub plugin_fold_regions: Option<Vec<(usize, usize)>>,
 }
 
 #[derive(PartialEq, Eq, Clone, Copy)]
@@ -87,9 +103,9 @@ pub struct RendereredSelection {
     pub selections: Vec<(usize, usize)>,
 }
 
-impl Into<&'static str> for EditType {
-    fn into(self) -> &'static str {
-        match self {
+impl From<EditType> for &'static str {
+    fn from(val: EditType) -> Self {
+        match val {
             EditType::InsertChars => "insert",
             EditType::Delete => "delete",
             EditType::Undo => "undo",
@@ -119,30 +135,34 @@ impl Editor {
 
         let mut editor = Editor {
             text: buffer,
-            buffer_id: buffer_id,
+            buffer_id,
             path: None,
             syntax: SyntaxDefinition::default(),
             view: View::new(initial_view_id),
-            engine: engine,
-            last_rev_id: last_rev_id,
+            engine,
+            last_rev_id,
             pristine_rev_id: last_rev_id,
             undo_group_id: 1,
-            // GC only works on undone edits or prefixes of the visible edits,
-            // but initial file loading can create an edit with undo group 0,
-            // so we want to collect that as part of the prefix.
-            live_undos: vec![0],
-            cur_undo: 1,
-            undos: BTreeSet::new(),
+            // initial file loading creates an edit with undo group 0, which
+            // is the root of the tree.
+            undo_tree: UndoTree::new(),
             gc_undos: BTreeSet::new(),
             last_edit_type: EditType::Other,
             this_edit_type: EditType::Other,
             scroll_to: Some(0),
             styles: Scopes::default(),
-            doc_ctx: doc_ctx,
-            config: config,
+            doc_ctx,
+            config,
             revs_in_flight: 0,
+            auto_closed: Vec::new(),
+            pending_auto_closed: Vec::new(),
+            pending_selection: None,
+            brackets: BracketIndex::default(),
+            plugin_fold_regions: None,
         };
-        editor.view.rewrap(&editor.text, editor.config.items.wrap_width);
+        editor.brackets = BracketIndex::new(&editor.text);
+        editor.rewrap();
+        editor.view.word_boundaries = editor.config.items.word_boundaries;
         editor.view.set_dirty(&editor.text);
         editor
     }
@@ -158,29 +178,52 @@ impl Editor {
 
     /// If this `Editor`'s buffer has been saved, Returns its path.
     pub fn get_path(&self) -> Option<&Path> {
-        match self.path {
-            Some(ref p) => Some(p),
-            None => None,
-        }
+        self.path.as_ref().map(|x| x as _)
     }
-/*
+
     /// Sets the config for this buffer. If the new config differs
     /// from the existing config, returns the modified items.
     pub fn set_config(&mut self, conf: BufferConfig) -> Option<Table> {
         if let Some(changes) = conf.changes_from(Some(&self.config)) {
             self.config = conf;
-            if changes.contains_key("wrap_width") {
-                self.view.rewrap(&self.text, self.config.items.wrap_width);;
+            let word_boundaries = self.config.items.word_boundaries;
+            self.view.set_word_boundaries(&self.text, word_boundaries);
+            if changes.contains_key("wrap_width") || changes.contains_key("tab_size") {
+                self.rewrap();
                 self.view.set_dirty(&self.text);
                 self.render();
             }
-            self.doc_ctx.config_changed(&self.view.view_id, &changes);
+            self.doc_ctx.config_changed(self.view.view_id, &self.config);
             Some(changes)
         } else {
             None
         }
     }
-*/
+
+    /// Wraps lines at the `wrap_width` of the config.
+    fn rewrap(&mut self) {
+        let measurer = self.width_measurer();
+        self.view.rewrap(&self.text, self.config.items.wrap_width as f64, measurer);
+    }
+
+    /// The measurer lines are wrapped with: the front-end's, if it has
+    /// one, or else one counting columns.
+    fn width_measurer(&self) -> Arc<WidthMeasurer> {
+        self.doc_ctx.width_measurer.clone().unwrap_or_else(||
+            Arc::new(ColumnMeasurer { tab_size: self.config.items.tab_size }))
+    }
+
+    /// Sets the front-end's measurer for line wrapping, and wraps lines
+    /// again with it.
+    pub fn set_width_measurer(&mut self, measurer: Arc<WidthMeasurer>) {
+        self.doc_ctx.width_measurer = Some(measurer);
+        if self.config.items.wrap_width > 0 {
+            self.rewrap();
+            self.view.set_dirty(&self.text);
+            self.render();
+        }
+    }
+
     pub fn get_config(&self) -> &BufferConfig {
         &self.config
     }
@@ -223,13 +266,12 @@ impl Editor {
                               config)
     }
 */
-/*
+
     /// Send initial config state to the client.
     pub fn send_config_init(&self) {
-        let config = self.config.to_table();
-        self.doc_ctx.config_changed(&self.view.view_id, &config);
+        self.doc_ctx.config_changed(self.view.view_id, &self.config);
     }
-*/
+
     pub fn insert(&mut self, s: &str) {
         let rope = Rope::from(s);
         let mut builder = delta::Builder::new(self.text.len());
@@ -240,9 +282,8 @@ impl Editor {
         self.add_delta(builder.build());
     }
 
-    /// Sets the position of the cursor to `offset`, as part of an edit operation.
-
     // TODO: add affinity.
+    /// Sets the position of the cursor to `offset`, as part of an edit operation.
     pub fn set_cursor(&mut self, offset: usize) {
         self.set_sel_single_region(SelRegion {
             start: offset,
@@ -277,21 +318,17 @@ impl Editor {
 
         if self.this_edit_type == self.last_edit_type &&
             self.this_edit_type != EditType::Other &&
-            !self.live_undos.is_empty() {
+            self.undo_tree.parent(self.undo_tree.current()).is_some() {
 
-            undo_group = *self.live_undos.last().unwrap();
+            undo_group = self.undo_tree.current();
         } else {
+            // Undone groups are kept, so that the new group starts a branch
+            // rather than discarding the redo history.
             undo_group = self.undo_group_id;
-            self.gc_undos.extend(&self.live_undos[self.cur_undo..]);
-            self.live_undos.truncate(self.cur_undo);
-            self.live_undos.push(undo_group);
-            if self.live_undos.len() <= MAX_UNDOS {
-                self.cur_undo += 1;
-            } else {
-                self.gc_undos.insert(self.live_undos.remove(0));
-            }
             self.undo_group_id += 1;
         }
+        self.undo_tree.record_edit(undo_group);
+        self.prune_undos();
         self.last_edit_type = self.this_edit_type;
         let priority = 0x10000;
         self.engine.edit_rev(priority, undo_group, head_rev_id.token(), delta);
@@ -322,20 +359,47 @@ impl Editor {
     }
 */
     fn update_undos(&mut self) {
-        self.engine.undo(self.undos.clone());
+        self.engine.undo(self.undo_tree.undone_groups());
         self.text = self.engine.get_head().clone();
         self.update_after_revision(None);
     }
 
-    fn update_after_revision(&mut self, author: Option<&str>) {
+    fn update_after_revision(&mut self, _author: Option<&str>) {
         let last_token = self.last_rev_id.token();
         let delta = self.engine.delta_rev_head(last_token);
         let is_pristine = self.is_pristine();
         // TODO (performance): it's probably quicker to stash last_text rather than
         // resynthesize it.
         let last_text = self.engine.get_rev(last_token).expect("last_rev not found");
+        let block = self.view.block;
         self.scroll_to = self.view.after_edit(&self.text, &last_text, &delta, is_pristine);
+        if let Some(sel) = self.pending_selection.take() {
+            self.scroll_to = self.view.set_selection(&self.text, sel);
+        } else if let Some(block) = block {
+            self.view.restore_block(&self.text, block, self.config.items.tab_size);
+        }
         let (iv, new_len) = delta.summary();
+        self.brackets.after_edit(&self.text, &delta);
+
+        if !self.auto_closed.is_empty() {
+            let mut transformer = Transformer::new(&delta);
+            self.auto_closed = self.auto_closed.iter().filter_map(|&offset| {
+                let start = transformer.transform(offset, true);
+                // drop closers that were deleted
+                if transformer.transform(offset + 1, false) > start { Some(start) } else { None }
+            }).collect();
+        }
+        if !self.pending_auto_closed.is_empty() {
+            self.auto_closed.append(&mut self.pending_auto_closed);
+            self.auto_closed.sort();
+        }
+        if let Some(ref mut regions) = self.plugin_fold_regions {
+            let mut transformer = Transformer::new(&delta);
+            for region in regions.iter_mut() {
+                *region = (transformer.transform(region.0, false),
+                           transformer.transform(region.1, true));
+            }
+        }
 
         // TODO: perhaps use different semantics for spans that enclose the
         // edited region. Currently it breaks any such span in half and applies
@@ -368,31 +432,33 @@ impl Editor {
                 self.this_edit_type.json_string().to_owned(),
                 author.to_owned());
 
-            let undo_group = *self.live_undos.last().unwrap_or(&0);
+            let undo_group = self.undo_tree.current();
             let view_id = self.view.view_id;
             self.doc_ctx.update_plugins(view_id, update, undo_group);
         }
 */
 
         self.last_rev_id = self.engine.get_head_rev_id();
-        self.sync_state_changed();
+        // No plugin updates are sent, so the revision is acknowledged here.
+        self.dec_revs_in_flight();
+    }
+
+    // Drops the oldest undone branches, and then the oldest edits, once the
+    // undo tree grows too large.
+    fn prune_undos(&mut self) {
+        if self.undo_tree.groups().len() > MAX_UNDO_GROUPS {
+            let pruned = self.undo_tree.prune(MAX_UNDO_GROUPS * 3 / 4);
+            self.gc_undos.extend(pruned);
+        }
     }
 
-    #[cfg(not(target_os = "fuchsia"))]
     fn gc_undos(&mut self) {
         if self.revs_in_flight == 0 && !self.gc_undos.is_empty() {
             self.engine.gc(&self.gc_undos);
-            self.undos = &self.undos - &self.gc_undos;
             self.gc_undos.clear();
         }
     }
 
-    #[cfg(target_os = "fuchsia")]
-    fn gc_undos(&mut self) {
-        // Never run GC on Fuchsia so that peers don't invalidate our
-        // last_rev_id and so that merge will work.
-    }
-
     fn is_pristine(&self) -> bool {
         self.engine.is_equivalent_revision(self.pristine_rev_id, self.engine.get_head_rev_id())
     }
@@ -412,41 +478,39 @@ impl Editor {
         self.text = self.engine.get_head().clone();
         // TODO: better undo semantics. This only implements separate undo histories for low concurrency.
         self.undo_group_id = self.engine.max_undo_group_id() + 1;
-        self.last_synced_rev = self.engine.get_head_rev_id();
         self.commit_delta(None);
         self.render();
     }
 
-    /// See `Engine::set_session_id` only useful when using Fuchsia sync functionality.
-    pub fn set_session_id(&mut self, session: (u64,u32)) {
-        self.engine.set_session_id(session);
+    /// Saves the undo history of this buffer; see `Engine::write_history`.
+    pub fn write_history<W: Write>(&self, writer: W) -> io::Result<()> {
+        self.engine.write_history(writer)
     }
 
-    #[cfg(feature = "ledger")]
-    pub fn set_sync_store(&mut self, sync_store: SyncStore) {
-        self.sync_store = Some(sync_store);
-    }
+    /// Replaces the history of this buffer with one saved by `write_history`,
+    /// so that edits made before the file was last closed can be undone. The
+    /// history is only attached if it was saved for the current text.
+    pub fn read_history<R: Read>(&mut self, reader: R) -> Result<(), HistoryError> {
+        let engine = Engine::read_history(reader, &self.text)?;
 
-    #[cfg(not(feature = "ledger"))]
-    pub fn sync_state_changed(&mut self) {
-    }
+        let undo_tree = UndoTree::from_groups(&engine.edit_undo_groups(),
+                                              engine.undone_groups());
 
-    #[cfg(feature = "ledger")]
-    pub fn sync_state_changed(&mut self) {
-        if let Some(sync_store) = self.sync_store.as_mut() {
-            // we don't want to sync right after recieving a new merge
-            if self.last_synced_rev != self.engine.get_head_rev_id() {
-                self.last_synced_rev = self.engine.get_head_rev_id();
-                sync_store.state_changed();
-            }
-        }
+        self.undo_group_id = engine.max_undo_group_id() + 1;
+        self.last_rev_id = engine.get_head_rev_id();
+        self.pristine_rev_id = self.last_rev_id;
+        self.engine = engine;
+        self.undo_tree = undo_tree;
+        self.gc_undos.clear();
+        self.prune_undos();
+        self.gc_undos();
+        self.last_edit_type = EditType::Other;
+        Ok(())
     }
 
-    #[cfg(feature = "ledger")]
-    pub fn transaction_ready(&mut self) {
-        if let Some(sync_store) = self.sync_store.as_mut() {
-            sync_store.commit_transaction(&self.engine);
-        }
+    /// See `Engine::set_session_id` only useful when using Fuchsia sync functionality.
+    pub fn set_session_id(&mut self, session: (u64,u32)) {
+        self.engine.set_session_id(session);
     }
 
     pub fn delete_word_forward(&mut self) {
@@ -470,8 +534,12 @@ impl Editor {
         // could be improved by implementing a "backspace" movement instead.
         let mut builder = delta::Builder::new(self.text.len());
         for region in self.view.sel_regions() {
+            let mut end = region.max();
             let start = if !region.is_caret() {
                 region.min()
+            } else if let Some((open, close)) = self.empty_pair_around(region.end) {
+                end = close;
+                open
             } else {
                 // backspace deletes max(1, tab_size) contiguous spaces
                 let (_, c) = self.view.offset_to_line_col(&self.text,
@@ -480,19 +548,18 @@ impl Editor {
                 let use_tab_stops = self.config.items.use_tab_stops;
                 let tab_size = self.config.items.tab_size;
                 let tab_size = if c % tab_size == 0 { tab_size } else { c % tab_size };
-                let preceded_by_spaces = self.text.len() > 0 &&
+                let preceded_by_spaces = !self.text.is_empty() &&
                     (region.start.saturating_sub(tab_size)..region.start)
                     .all(|i| self.text.byte_at(i) == b' ');
                if preceded_by_spaces && use_spaces && use_tab_stops {
                    region.start - tab_size
                } else {
-                   // TODO: implement complex emoji logic
-                    self.text.prev_codepoint_offset(region.end)
+                    self.text.prev_grapheme_offset(region.end)
                         .unwrap_or(region.end)
                }
             };
 
-            let iv = Interval::new_closed_open(start, region.max());
+            let iv = Interval::new_closed_open(start, end);
             if !iv.is_empty() {
                 builder.delete(iv);
             }
@@ -523,7 +590,7 @@ impl Editor {
             }
         }
         if save {
-            let saved = self.extract_sel_regions(&deletions).unwrap_or(String::new());
+            let saved = self.extract_sel_regions(&deletions).unwrap_or_default();
             self.doc_ctx.set_kill_ring(Rope::from(saved));
         }
         self.delete_sel_regions(&deletions);
@@ -566,7 +633,98 @@ impl Editor {
     pub fn insert_newline(&mut self) {
         self.this_edit_type = EditType::InsertChars;
         let text = self.config.items.line_ending.clone();
+        let indents = if self.config.items.auto_indent {
+            self.view.sel_regions().iter()
+                .map(|region| (region.min(), region.max(), self.newline_indent(region)))
+                .collect::<Vec<_>>()
+        } else {
+            Vec::new()
+        };
         self.insert(&text);
+        if indents.iter().all(|(_, _, (indent, closer))| indent.is_empty() && closer.is_none()) {
+            return;
+        }
+
+        // The indentation is a separate edit in the same undo group, so
+        // that it can be told apart from the newline. A closer split off
+        // from its opener goes on a line of its own after the caret.
+        let mut builder = delta::Builder::new(self.text.len());
+        let mut sel = Selection::new();
+        let (mut added, mut removed, mut indented) = (0, 0, 0);
+        let mut split = false;
+        for (start, end, (indent, closer)) in indents {
+            added += text.len();
+            let offset = start + added - removed;
+            removed += end - start;
+            let caret = offset + indented + indent.len();
+            let mut inserted = indent;
+            if let Some(closer_indent) = closer {
+                inserted.push_str(&text);
+                inserted.push_str(&closer_indent);
+                split = true;
+            }
+            indented += inserted.len();
+            sel.add_region(SelRegion {
+                start: caret,
+                end: caret,
+                horiz: None,
+                affinity: Affinity::default(),
+            });
+            if !inserted.is_empty() {
+                builder.replace(Interval::new_closed_open(offset, offset), Rope::from(inserted));
+            }
+        }
+        let head_rev_id = self.engine.get_head_rev_id();
+        let undo_group = self.undo_tree.current();
+        self.engine.edit_rev(INDENT_PRIORITY, undo_group, head_rev_id.token(), builder.build());
+        self.text = self.engine.get_head().clone();
+        if split {
+            self.pending_selection = Some(sel);
+        }
+    }
+
+    /// The indentation of the line started by a newline replacing `region`:
+    /// that of the line it was split from, changed by one level according
+    /// to the indentation rules of the buffer's syntax. If the newline goes
+    /// between an opener and a closer, the closer goes on a line of its own
+    /// at the old indentation, returned as the second value.
+    fn newline_indent(&self, region: &SelRegion) -> (String, Option<String>) {
+        let items = &self.config.items;
+        let line = self.text.line_of_offset(region.min());
+        let before = self.text.slice_to_string(self.text.offset_of_line(line), region.min());
+        let end_line = self.text.line_of_offset(region.max());
+        let after = self.text.slice_to_string(region.max(), self.text.offset_of_line(end_line + 1));
+
+        let content = before.trim_start();
+        let indent = &before[..before.len() - content.len()];
+        let first_word = content.split(|c: char| !c.is_alphanumeric() && c != '_')
+            .next().unwrap_or("");
+        let increase = items.increase_indent_after.iter()
+            .any(|s| !s.is_empty() && content.trim_end().ends_with(s.as_str()));
+        let closer_after = items.decrease_indent_before.iter()
+            .any(|s| !s.is_empty() && after.trim_start().starts_with(s.as_str()));
+        let decrease = closer_after || items.decrease_indent_after.iter().any(|s| s == first_word);
+
+        let width = indent_width(indent, items.tab_size);
+        match (increase, decrease) {
+            (true, false) => (self.make_indent(width + items.tab_size), None),
+            (false, true) => (self.make_indent(width.saturating_sub(items.tab_size)), None),
+            (true, true) if closer_after => {
+                (self.make_indent(width + items.tab_size), Some(indent.to_owned()))
+            }
+            _ => (indent.to_owned(), None),
+        }
+    }
+
+    /// Whitespace spanning `width` columns, made of tabs or spaces as the
+    /// buffer config requires.
+    fn make_indent(&self, width: usize) -> String {
+        let tab_size = self.config.items.tab_size;
+        if self.config.items.translate_tabs_to_spaces || tab_size == 0 {
+            " ".repeat(width)
+        } else {
+            "\t".repeat(width / tab_size) + &" ".repeat(width % tab_size)
+        }
     }
 
     pub fn insert_tab(&mut self) {
@@ -585,90 +743,623 @@ impl Editor {
         }
         self.this_edit_type = EditType::InsertChars;
         self.add_delta(builder.build());
+    }
+
+    /// Indents every line touched by a selection region by one level, as a
+    /// single edit. Empty lines are skipped when there are several lines.
+    pub fn indent_lines(&mut self) {
+        self.modify_indent(true);
+    }
+
+    /// Removes one level of indentation from every line touched by a
+    /// selection region, as a single edit.
+    pub fn outdent_lines(&mut self) {
+        self.modify_indent(false);
+    }
+
+    fn modify_indent(&mut self, increase: bool) {
+        let tab_size = max(self.config.items.tab_size, 1);
+        let use_tab_stops = self.config.items.use_tab_stops;
+        let lines = self.selected_lines();
+        let mut builder = delta::Builder::new(self.text.len());
+        for &line in &lines {
+            let start = self.text.offset_of_line(line);
+            let line_text = self.text.slice_to_string(start, self.text.offset_of_line(line + 1));
+            if lines.len() > 1 && line_text.trim_end_matches(&['\r', '\n'][..]).is_empty() {
+                continue;
+            }
+            let content = line_text.trim_start_matches(&[' ', '\t'][..]);
+            let old = &line_text[..line_text.len() - content.len()];
+            let width = indent_width(old, tab_size);
+            let new_width = match (increase, use_tab_stops) {
+                (true, true) => width + tab_size - width % tab_size,
+                (true, false) => width + tab_size,
+                (false, true) => (width.saturating_sub(1) / tab_size) * tab_size,
+                (false, false) => width.saturating_sub(tab_size),
+            };
+            if new_width == width {
+                continue;
+            }
+
+            // Only the end of the indentation is touched where possible, so
+            // that selections in it keep their place.
+            let new = self.make_indent(new_width);
+            let end = start + old.len();
+            if let Some(added) = new.strip_prefix(old) {
+                builder.replace(Interval::new_closed_open(end, end), Rope::from(added));
+            } else if old.starts_with(new.as_str()) {
+                builder.delete(Interval::new_closed_open(start + new.len(), end));
+            } else {
+                builder.replace(Interval::new_closed_open(start, end), Rope::from(new));
+            }
+        }
+        if !builder.is_empty() {
+            self.this_edit_type = EditType::Other;
+            self.add_delta(builder.build());
+        }
+    }
+
+    /// The lines touched by any selection region, in order. A selection
+    /// ending at the start of a line doesn't touch that line.
+    fn selected_lines(&self) -> Vec<usize> {
+        let mut lines: Vec<usize> = Vec::new();
+        for region in self.view.sel_regions() {
+            let first = self.text.line_of_offset(region.min());
+            let mut last = self.text.line_of_offset(region.max());
+            if last > first && self.text.offset_of_line(last) == region.max() {
+                last -= 1;
+            }
+            let first = lines.last().map_or(first, |&line| max(line + 1, first));
+            lines.extend(first..=last);
+        }
+        lines
+    }
+
+    /// The lines touched by any selection region, as runs of consecutive
+    /// lines given by their first and last line.
+    fn selected_line_runs(&self) -> Vec<(usize, usize)> {
+        let mut runs: Vec<(usize, usize)> = Vec::new();
+        for line in self.selected_lines() {
+            match runs.last_mut() {
+                Some(run) if run.1 + 1 == line => run.1 = line,
+                _ => runs.push((line, line)),
+            }
+        }
+        runs
+    }
+
+    /// The offset of the start of `line`, and its text without the line break.
+    fn line_content(&self, line: usize) -> (usize, String) {
+        let start = self.text.offset_of_line(line);
+        let mut text = self.text.slice_to_string(start, self.text.offset_of_line(line + 1));
+        let len = text.len() - line_break_len(&text);
+        text.truncate(len);
+        (start, text)
+    }
+
+    /// Applies `delta` as a single edit, and has the selection set to `sel`,
+    /// which is in the coordinates of the new text, when the edit is
+    /// committed, instead of moving the current selection through the delta.
+    fn add_delta_with_selection(&mut self, delta: Delta<RopeInfo>, sel: Selection) {
+        self.add_delta(delta);
+        self.pending_selection = Some(sel);
+    }
+
+    /// Duplicates the line of each caret, moving the caret to the copy
+    /// below, and the text of each other region, selecting the copy.
+    pub fn duplicate_line(&mut self) {
+        // (offset, inserted text, offset of the copy within the inserted text)
+        let mut edits: Vec<(usize, String, usize)> = Vec::new();
+        // (index of the edit, region, offset of the region in the copied text)
+        let mut copies = Vec::new();
+        let mut last_line = None;
+        for region in self.view.sel_regions() {
+            if region.is_caret() {
+                let line = self.text.line_of_offset(region.end);
+                let start = self.text.offset_of_line(line);
+                if last_line != Some(line) {
+                    let end = self.text.offset_of_line(line + 1);
+                    let text = self.text.slice_to_string(start, end);
+                    if line_break_len(&text) == 0 {
+                        let line_ending = self.config.items.line_ending.clone();
+                        edits.push((end, line_ending.clone() + &text, line_ending.len()));
+                    } else {
+                        edits.push((end, text, 0));
+                    }
+                    last_line = Some(line);
+                }
+                copies.push((edits.len() - 1, region.clone(), start));
+            } else {
+                let text = self.text.slice_to_string(region.min(), region.max());
+                edits.push((region.max(), text, 0));
+                last_line = None;
+                copies.push((edits.len() - 1, region.clone(), region.min()));
+            }
+        }
+        if edits.is_empty() {
+            return;
+        }
+
+        // a copied line can come after a later region on the same line
+        let mut order = (0..edits.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| edits[i].0);
+        let mut builder = delta::Builder::new(self.text.len());
+        let mut copy_starts = vec![0; edits.len()];
+        let mut inserted = 0;
+        for i in order {
+            let (offset, ref text, copy_offset) = edits[i];
+            builder.replace(Interval::new_closed_open(offset, offset), Rope::from(text.as_str()));
+            copy_starts[i] = offset + inserted + copy_offset;
+            inserted += text.len();
+        }
+
+        let mut sel = Selection::new();
+        for (i, region, copied_from) in copies {
+            sel.add_region(SelRegion {
+                start: copy_starts[i] + region.start - copied_from,
+                end: copy_starts[i] + region.end - copied_from,
+                horiz: None,
+                affinity: region.affinity,
+            });
+        }
+        self.this_edit_type = EditType::Other;
+        self.add_delta_with_selection(builder.build(), sel);
+    }
+
+    /// Swaps each run of lines touched by the selection with the line
+    /// above it, or the line below it if `down` is set, keeping the
+    /// selection on the moved lines. Does nothing if a run can't move.
+    pub fn move_lines(&mut self, down: bool) {
+        let runs = self.selected_line_runs();
+        let stuck = |&(first, last): &(usize, usize)| if down {
+            self.text.offset_of_line(last + 1) == self.text.len()
+        } else {
+            first == 0
+        };
+        if runs.is_empty() || runs.iter().any(stuck) {
+            return;
+        }
+
+        let mut builder = delta::Builder::new(self.text.len());
+        // (start, end, new start) of each run
+        let mut moved = Vec::new();
+        for (first, last) in runs {
+            let block_start = self.text.offset_of_line(first);
+            let block_end = self.text.offset_of_line(last + 1);
+            let block = self.text.slice_to_string(block_start, block_end);
+            let (start, end, swapped, new_start) = if down {
+                let end = self.text.offset_of_line(last + 2);
+                let below = self.text.slice_to_string(block_end, end);
+                // the line below moves the line break of the block up if it had none
+                let moved_break = if line_break_len(&below) == 0 { line_break_len(&block) } else { 0 };
+                let new_start = block_start + below.len() + moved_break;
+                (block_start, end, swap_lines(&below, &block), new_start)
+            } else {
+                let start = self.text.offset_of_line(first - 1);
+                let above = self.text.slice_to_string(start, block_start);
+                (start, block_end, swap_lines(&block, &above), start)
+            };
+            builder.replace(Interval::new_closed_open(start, end), Rope::from(swapped));
+            moved.push((block_start, block_end, new_start));
+        }
+
+        let mut sel = Selection::new();
+        for region in self.view.sel_regions() {
+            let &(start, _, new_start) = moved.iter()
+                .find(|&&(start, end, _)| region.min() >= start && region.max() <= end)
+                .expect("region outside of the moved lines");
+            sel.add_region(SelRegion {
+                start: new_start + region.start - start,
+                end: new_start + region.end - start,
+                horiz: None,
+                affinity: region.affinity,
+            });
+        }
+        self.this_edit_type = EditType::Other;
+        self.add_delta_with_selection(builder.build(), sel);
+    }
+
+    /// Deletes every line touched by a selection region, with its line break.
+    pub fn delete_lines(&mut self) {
+        let mut builder = delta::Builder::new(self.text.len());
+        for (first, last) in self.selected_line_runs() {
+            let mut start = self.text.offset_of_line(first);
+            let end = self.text.offset_of_line(last + 1);
+            // the last line has no line break, so delete the one before it
+            if end == self.text.len() && first > 0 &&
+                line_break_len(&self.text.slice_to_string(start, end)) == 0 {
+                let prev = self.text.slice_to_string(self.text.offset_of_line(first - 1), start);
+                start -= line_break_len(&prev);
+            }
+            builder.delete(Interval::new_closed_open(start, end));
+        }
+        if !builder.is_empty() {
+            self.this_edit_type = EditType::Other;
+            self.add_delta(builder.build());
+        }
+    }
+
+    /// Joins each run of lines touched by the selection into a single line,
+    /// or a line with the next one if the run is a single line. The
+    /// whitespace around each line break is collapsed into a single space,
+    /// and blank lines are dropped.
+    pub fn join_lines(&mut self) {
+        let last_line = self.text.measure::<LinesMetric>();
+        let mut builder = delta::Builder::new(self.text.len());
+        for (first, last) in self.selected_line_runs() {
+            let last = if first == last { last + 1 } else { last };
+            if last > last_line {
+                continue;
+            }
+            let (start, content) = self.line_content(first);
+            // where the separator goes, and whether there is text before it
+            let mut join_at = start + content.trim_end_matches(&[' ', '\t'][..]).len();
+            let mut joined_text = join_at > start;
+            for line in first + 1..=last {
+                let (start, content) = self.line_content(line);
+                let trimmed = content.trim_start_matches(&[' ', '\t'][..]);
+                if trimmed.is_empty() && line < last {
+                    continue;
+                }
+                let sep = if joined_text && !trimmed.is_empty() { " " } else { "" };
+                let text_start = start + content.len() - trimmed.len();
+                builder.replace(Interval::new_closed_open(join_at, text_start), Rope::from(sep));
+                join_at = start + content.trim_end_matches(&[' ', '\t'][..]).len();
+                joined_text |= !trimmed.is_empty();
+            }
+        }
+        if !builder.is_empty() {
+            self.this_edit_type = EditType::Other;
+            self.add_delta(builder.build());
+        }
+    }
+
+    /// Comments out every line touched by a selection region, or uncomments
+    /// them if they are all commented. The comment markers are aligned to
+    /// the smallest indentation of the lines, and blank lines are left
+    /// alone. Syntaxes without line comments get block comments instead.
+    pub fn toggle_line_comment(&mut self) {
+        let token = self.config.items.line_comment.clone();
+        if token.is_empty() {
+            if self.config.items.block_comment.is_some() {
+                self.toggle_block_comment();
+            }
+            return;
+        }
+        let indent = |content: &str| content.len() - content.trim_start_matches(&[' ', '\t'][..]).len();
+        let mut lines = self.selected_lines().into_iter()
+            .map(|line| self.line_content(line))
+            .collect::<Vec<_>>();
+        // blank lines are only commented if there is nothing else
+        if lines.iter().any(|(_, content)| indent(content) < content.len()) {
+            lines.retain(|(_, content)| indent(content) < content.len());
+        }
+        let uncomment = lines.iter()
+            .all(|(_, content)| content[indent(content)..].starts_with(token.as_str()));
+
+        let mut builder = delta::Builder::new(self.text.len());
+        if uncomment {
+            for &(start, ref content) in &lines {
+                let marker = indent(content);
+                let mut end = marker + token.len();
+                if content[end..].starts_with(' ') {
+                    end += 1;
+                }
+                builder.delete(Interval::new_closed_open(start + marker, start + end));
+            }
+        } else {
+            let column = lines.iter().map(|(_, content)| indent(content)).min().unwrap_or(0);
+            let marker = format!("{} ", token);
+            for &(start, _) in &lines {
+                let offset = start + column;
+                builder.replace(Interval::new_closed_open(offset, offset), Rope::from(marker.as_str()));
+            }
+        }
+        if !builder.is_empty() {
+            self.this_edit_type = EditType::Other;
+            self.add_delta(builder.build());
+        }
+    }
 
-        // What follows is old indent code, retained because it will be useful for
-        // indent action (Sublime no longer does indent on non-caret selections).
-        /*
-            let (first_line, _) = self.view.offset_to_line_col(&self.text, self.view.sel_min());
-            let (last_line, last_col) =
-                self.view.offset_to_line_col(&self.text, self.view.sel_max());
-            let last_line = if last_col == 0 && last_line > first_line {
-                last_line
+    /// Wraps the text of each selection region in a block comment, or the
+    /// text of its line for a caret, or unwraps it if it already is, or is
+    /// right inside, a block comment. The selection stays on the wrapped
+    /// text. Syntaxes without block comments get line comments instead.
+    pub fn toggle_block_comment(&mut self) {
+        let (open, close) = match self.config.items.block_comment.clone() {
+            Some(tokens) => tokens,
+            None => {
+                if !self.config.items.line_comment.is_empty() {
+                    self.toggle_line_comment();
+                }
+                return;
+            }
+        };
+        let whitespace = &[' ', '\t', '\r', '\n'][..];
+        let mut builder = delta::Builder::new(self.text.len());
+        // each region, and the end of the text it comments
+        let mut regions = Vec::new();
+        let mut last_end = None;
+        for region in self.view.sel_regions() {
+            let (start, end) = if region.is_caret() {
+                let (start, content) = self.line_content(self.text.line_of_offset(region.end));
+                (start, start + content.len())
             } else {
-                last_line + 1
+                (region.min(), region.max())
+            };
+            let text = self.text.slice_to_string(start, end);
+            let trimmed = text.trim_start_matches(whitespace);
+            let start = start + text.len() - trimmed.len();
+            let trimmed = trimmed.trim_end_matches(whitespace);
+            let end = start + trimmed.len();
+            regions.push((region.clone(), end));
+            if trimmed.is_empty() {
+                continue;
+            }
+
+            // the markers of a comment in the text, or right around it
+            let line_start = self.text.offset_of_line(self.text.line_of_offset(start));
+            let before = self.text.slice_to_string(line_start, start);
+            let (end_line_start, end_line) = self.line_content(self.text.line_of_offset(end));
+            let after = &end_line[end - end_line_start..];
+            let markers = match trimmed.strip_prefix(open.as_str())
+                .and_then(|t| t.strip_suffix(close.as_str()))
+            {
+                Some(inner) => {
+                    let lead = if inner.starts_with(' ') { 1 } else { 0 };
+                    let trail = if inner.len() > lead && inner.ends_with(' ') { 1 } else { 0 };
+                    Some((start, start + open.len() + lead, end - close.len() - trail, end))
+                }
+                None => {
+                    let before_open = before.strip_suffix(' ')
+                        .filter(|b| b.ends_with(open.as_str())).unwrap_or(before.as_str());
+                    let after_close = after.strip_prefix(' ')
+                        .filter(|a| a.starts_with(close.as_str())).unwrap_or(after);
+                    if before_open.ends_with(open.as_str()) && after_close.starts_with(close.as_str()) {
+                        let close_end = end + after.len() - after_close.len() + close.len();
+                        Some((line_start + before_open.len() - open.len(), start, end, close_end))
+                    } else {
+                        None
+                    }
+                }
             };
-            for line in first_line..last_line {
-                let offset = self.view.line_col_to_offset(&self.text, line, 0);
-                let iv = Interval::new_closed_open(offset, offset);
-                self.add_simple_edit(iv, Rope::from(n_spaces(TAB_SIZE)));
+
+            // a caret's line can overlap with a neighbouring region
+            let (first, last) = markers.map_or((start, end), |m| (m.0, m.3));
+            if last_end.is_some_and(|last_end| first <= last_end) {
+                continue;
+            }
+            last_end = Some(last);
+            match markers {
+                Some((open_start, open_end, close_start, close_end)) => {
+                    builder.delete(Interval::new_closed_open(open_start, open_end));
+                    builder.delete(Interval::new_closed_open(close_start, close_end));
+                }
+                None => {
+                    builder.replace(Interval::new_closed_open(start, start),
+                                    Rope::from(format!("{} ", open)));
+                    builder.replace(Interval::new_closed_open(end, end),
+                                    Rope::from(format!(" {}", close)));
+                }
+            }
+        }
+        if builder.is_empty() {
+            return;
+        }
+
+        let delta = builder.build();
+        let mut sel = Selection::new();
+        {
+            let mut transformer = Transformer::new(&delta);
+            for (region, end) in regions {
+                // keep the selection inside the comment markers
+                let (start_after, end_after) = if region.is_caret() {
+                    (region.end < end, region.end < end)
+                } else {
+                    (region.start == region.min(), region.end == region.min())
+                };
+                sel.add_region(SelRegion {
+                    start: transformer.transform(region.start, start_after),
+                    end: transformer.transform(region.end, end_after),
+                    horiz: None,
+                    affinity: region.affinity,
+                });
+            }
+        }
+        self.this_edit_type = EditType::Other;
+        self.add_delta_with_selection(delta, sel);
+    }
+
+    /// Sets the regions that can be folded, as supplied by a plugin, in
+    /// place of those found with the `folding` config key, or goes back to
+    /// those if `regions` is `None`. A region is given by the offsets it
+    /// starts and ends at; folding it hides the lines after the one it
+    /// starts on, up to the one it ends on.
+    pub fn set_fold_regions(&mut self, regions: Option<Vec<(usize, usize)>>) {
+        self.plugin_fold_regions = regions;
+    }
+
+    /// The regions that can be folded, as the text line shown in place of
+    /// each and the last text line it hides, in order.
+    pub fn fold_regions(&self) -> Vec<(usize, usize)> {
+        if let Some(ref regions) = self.plugin_fold_regions {
+            let mut regions = regions.iter()
+                .map(|&(start, end)| (self.text.line_of_offset(start),
+                                      self.text.line_of_offset(end)))
+                .filter(|&(header, last)| last > header)
+                .collect::<Vec<_>>();
+            regions.sort();
+            return regions;
+        }
+        match self.config.items.folding {
+            FoldingMode::Brackets => {
+                let ignore = |offset| self.styles.has_scope_at(offset, &["string", "comment"]);
+                folding::bracket_regions(&self.text, &self.brackets, ignore)
+            }
+            FoldingMode::Indentation =>
+                folding::indentation_regions(&self.text, self.config.items.tab_size),
+        }
+    }
+
+    /// Folds the lines touched by each selection region spanning more than
+    /// one line, and the innermost region around each other region's line
+    /// that isn't folded yet.
+    fn fold(&mut self) {
+        let regions = self.fold_regions();
+        let folded = self.view.folded_lines(&self.text);
+        let mut folds = Vec::new();
+        for region in self.view.sel_regions() {
+            let first = self.text.line_of_offset(region.min());
+            let mut last = self.text.line_of_offset(region.max());
+            if last > first && self.text.offset_of_line(last) == region.max() {
+                last -= 1;
+            }
+            if last > first {
+                folds.push((first, last));
+                continue;
+            }
+            let innermost = regions.iter()
+                .filter(|&&(header, last)| header <= first && first <= last)
+                .filter(|&&(header, _)| !folded.iter().any(|fold| fold.0 == header))
+                .max_by_key(|&&(header, last)| (header, Reverse(last)));
+            folds.extend(innermost);
+        }
+        self.view.fold_lines(&self.text, &folds);
+    }
+
+    /// Unfolds the folds shown on the lines touched by the selection.
+    fn unfold(&mut self) {
+        let runs = self.selected_line_runs();
+        let text = &self.text;
+        self.view.unfold(text, |start, _| {
+            let line = text.line_of_offset(start);
+            runs.iter().any(|&(first, last)| first <= line && line <= last)
+        });
+    }
+
+    /// Folds every region that isn't inside another one.
+    fn fold_all(&mut self) {
+        let mut folds: Vec<(usize, usize)> = Vec::new();
+        for (header, last) in self.fold_regions() {
+            if folds.last().is_none_or(|&(_, end)| header > end) {
+                folds.push((header, last));
             }
-        */
+        }
+        self.view.fold_lines(&self.text, &folds);
     }
 
     /// Apply a movement, also setting the scroll to the point requested by
     /// the movement.
-    ///
-    /// The type of the `flags` parameter is a convenience to old-style
-    /// movement methods.
-    pub fn do_move(&mut self, movement: Movement, flags: u64) {
-        self.scroll_to = self.view.do_move(&self.text, movement,
-            (flags & FLAG_SELECT) != 0);
+    pub fn do_move(&mut self, movement: Movement, modifier: SelectionModifier) {
+        if movement == Movement::MatchingBracket {
+            self.move_to_matching_bracket(modifier != SelectionModifier::None);
+            return;
+        }
+        self.scroll_to = if modifier == SelectionModifier::Block {
+            self.view.do_block_move(&self.text, movement, self.config.items.tab_size)
+        } else {
+            self.view.do_move(&self.text, movement, modifier == SelectionModifier::Extend)
+        };
+    }
+
+    /// The bracket next to `offset`, after it or else before it, and the
+    /// bracket matching it. Brackets in strings and comments are ignored.
+    pub fn matching_bracket(&self, offset: usize) -> Option<(usize, usize)> {
+        let ignore = |offset| self.styles.has_scope_at(offset, &["string", "comment"]);
+        if let Some(other) = self.brackets.find_match(offset, ignore) {
+            return Some((offset, other));
+        }
+        let before = offset.checked_sub(1)?;
+        self.brackets.find_match(before, ignore).map(|other| (before, other))
+    }
+
+    /// Moves each region's cursor to the bracket matching the one next to
+    /// it, on the same side of it: before the match if the cursor was
+    /// before its bracket, and after it otherwise.
+    fn move_to_matching_bracket(&mut self, extend: bool) {
+        let mut sel = Selection::new();
+        for region in self.view.sel_regions() {
+            let end = match self.matching_bracket(region.end) {
+                Some((bracket, other)) if bracket == region.end => other,
+                Some((_, other)) => other + 1,
+                None => region.end,
+            };
+            sel.add_region(SelRegion {
+                start: if extend { region.start } else { end },
+                end,
+                horiz: None,
+                affinity: Affinity::default(),
+            });
+        }
+        self.scroll_to = self.view.set_selection(&self.text, sel);
     }
 
-    pub fn move_up(&mut self, flags: u64) {
-        self.do_move(Movement::Up, flags);
+    /// Highlights the bracket next to each caret, and the one matching it.
+    fn update_bracket_highlights(&mut self) {
+        let mut brackets = self.view.sel_regions().iter()
+            .filter(|region| region.is_caret())
+            .filter_map(|region| self.matching_bracket(region.end))
+            .flat_map(|(bracket, other)| vec![bracket, other])
+            .collect::<Vec<_>>();
+        brackets.sort();
+        brackets.dedup();
+        self.view.set_bracket_highlights(&self.text, brackets);
     }
 
-    pub fn move_down(&mut self, flags: u64) {
-        self.do_move(Movement::Down, flags);
+    pub fn move_up(&mut self, modifier: SelectionModifier) {
+        self.do_move(Movement::Up, modifier);
     }
 
-    pub fn move_left(&mut self, flags: u64) {
-        self.do_move(Movement::Left, flags);
+    pub fn move_down(&mut self, modifier: SelectionModifier) {
+        self.do_move(Movement::Down, modifier);
     }
 
-    pub fn move_word_left(&mut self, flags: u64) {
-        self.do_move(Movement::LeftWord, flags);
+    pub fn move_left(&mut self, modifier: SelectionModifier) {
+        self.do_move(Movement::Left, modifier);
     }
 
-    pub fn move_to_left_end_of_line(&mut self, flags: u64) {
-        self.do_move(Movement::LeftOfLine, flags);
+    pub fn move_word_left(&mut self, modifier: SelectionModifier) {
+        self.do_move(Movement::LeftWord, modifier);
     }
 
-    pub fn move_right(&mut self, flags: u64) {
-        self.do_move(Movement::Right, flags);
+    pub fn move_to_left_end_of_line(&mut self, modifier: SelectionModifier) {
+        self.do_move(Movement::LeftOfLine, modifier);
     }
 
-    pub fn move_word_right(&mut self, flags: u64) {
-        self.do_move(Movement::RightWord, flags);
+    pub fn move_right(&mut self, modifier: SelectionModifier) {
+        self.do_move(Movement::Right, modifier);
     }
 
-    pub fn move_to_right_end_of_line(&mut self, flags: u64) {
-        self.do_move(Movement::RightOfLine, flags);
+    pub fn move_word_right(&mut self, modifier: SelectionModifier) {
+        self.do_move(Movement::RightWord, modifier);
     }
 
-    pub fn move_to_beginning_of_paragraph(&mut self, flags: u64) {
-        self.do_move(Movement::StartOfParagraph, flags);
+    pub fn move_to_right_end_of_line(&mut self, modifier: SelectionModifier) {
+        self.do_move(Movement::RightOfLine, modifier);
     }
 
-    pub fn move_to_end_of_paragraph(&mut self, flags: u64) {
-        self.do_move(Movement::EndOfParagraph, flags);
+    pub fn move_to_beginning_of_paragraph(&mut self, modifier: SelectionModifier) {
+        self.do_move(Movement::StartOfParagraph, modifier);
     }
 
-    pub fn move_to_beginning_of_document(&mut self, flags: u64) {
-        self.do_move(Movement::StartOfDocument, flags);
+    pub fn move_to_end_of_paragraph(&mut self, modifier: SelectionModifier) {
+        self.do_move(Movement::EndOfParagraph, modifier);
     }
 
-    pub fn move_to_end_of_document(&mut self, flags: u64) {
-        self.do_move(Movement::EndOfDocument, flags);
+    pub fn move_to_beginning_of_document(&mut self, modifier: SelectionModifier) {
+        self.do_move(Movement::StartOfDocument, modifier);
     }
 
-    pub fn scroll_page_up(&mut self, flags: u64) {
-        self.do_move(Movement::UpPage, flags);
+    pub fn move_to_end_of_document(&mut self, modifier: SelectionModifier) {
+        self.do_move(Movement::EndOfDocument, modifier);
     }
 
-    pub fn scroll_page_down(&mut self, flags: u64) {
-        self.do_move(Movement::DownPage, flags);
+    pub fn scroll_page_up(&mut self, modifier: SelectionModifier) {
+        self.do_move(Movement::UpPage, modifier);
+    }
+
+    pub fn scroll_page_down(&mut self, modifier: SelectionModifier) {
+        self.do_move(Movement::DownPage, modifier);
     }
 
     pub fn select_all(&mut self) {
@@ -689,7 +1380,170 @@ impl Editor {
     // but paste should.
     pub fn do_insert(&mut self, chars: &str) {
         self.this_edit_type = EditType::InsertChars;
-        self.insert(chars);
+        let mut iter = chars.chars();
+        match (iter.next(), iter.next()) {
+            (Some(c), None) if self.config.items.auto_close_pairs.iter()
+                .any(|&(open, close)| c == open || c == close) => self.insert_auto_closed(c),
+            _ => self.insert_distributed(chars),
+        }
+    }
+
+    /// Inserts `c` at each region, handling `auto_close_pairs`: an opener
+    /// typed at a caret is inserted along with its closer, and around the
+    /// text of other regions, and a closer typed just before one inserted
+    /// this way steps over it.
+    fn insert_auto_closed(&mut self, c: char) {
+        let pairs = self.config.items.auto_close_pairs.clone();
+        let closer = pairs.iter().find(|pair| pair.0 == c).map(|pair| pair.1);
+        let is_closer = |c: Option<char>| c.is_some_and(|c| pairs.iter().any(|pair| pair.1 == c));
+        let c_len = c.len_utf8();
+
+        let mut builder = delta::Builder::new(self.text.len());
+        let mut sel = Selection::new();
+        let mut stepped_over = Vec::new();
+        let mut new_closers = Vec::new();
+        let (mut added, mut removed) = (0, 0);
+        for region in self.view.sel_regions() {
+            let (start, end) = (region.min(), region.max());
+            let new_start = start + added - removed;
+            let next = Cursor::new(&self.text, end).next_codepoint();
+            let prev = self.char_before(start);
+            let mut new_region = SelRegion {
+                start: new_start + c_len,
+                end: new_start + c_len,
+                horiz: None,
+                affinity: Affinity::default(),
+            };
+            let inserted = match closer {
+                _ if region.is_caret() && next == Some(c) && is_closer(next)
+                    && self.auto_closed.contains(&end) => {
+                    stepped_over.push(end);
+                    String::new()
+                }
+                Some(close) if !region.is_caret() => {
+                    // keep the surrounded text selected
+                    new_region.start = new_start + region.start - start + c_len;
+                    new_region.end = new_start + region.end - start + c_len;
+                    new_region.affinity = region.affinity;
+                    builder.replace(Interval::new_closed_open(start, start), Rope::from(c.to_string()));
+                    builder.replace(Interval::new_closed_open(end, end), Rope::from(close.to_string()));
+                    added += c_len + close.len_utf8();
+                    sel.add_region(new_region);
+                    continue;
+                }
+                // a quote is only closed if it isn't in or right after a word
+                Some(close) if (next.is_none_or(char::is_whitespace) || is_closer(next))
+                    && (close != c || !prev.is_some_and(char::is_alphanumeric)) => {
+                    new_closers.push(new_start + c_len);
+                    format!("{}{}", c, close)
+                }
+                _ => c.to_string(),
+            };
+            if !inserted.is_empty() || start != end {
+                builder.replace(Interval::new_closed_open(start, end), Rope::from(inserted.as_str()));
+            }
+            added += inserted.len();
+            removed += end - start;
+            sel.add_region(new_region);
+        }
+
+        self.auto_closed.retain(|offset| !stepped_over.contains(offset));
+        if builder.is_empty() {
+            self.scroll_to = self.view.set_selection(&self.text, sel);
+        } else {
+            self.pending_auto_closed = new_closers;
+            self.add_delta_with_selection(builder.build(), sel);
+        }
+    }
+
+    fn char_before(&self, offset: usize) -> Option<char> {
+        if offset == 0 {
+            None
+        } else {
+            Cursor::new(&self.text, offset).prev_codepoint()
+        }
+    }
+
+    /// If `offset` is between an opener and a closer inserted along with it
+    /// by `auto_close_pairs`, the offsets before and after the pair.
+    fn empty_pair_around(&self, offset: usize) -> Option<(usize, usize)> {
+        if !self.auto_closed.contains(&offset) {
+            return None;
+        }
+        let open = self.char_before(offset)?;
+        let close = Cursor::new(&self.text, offset).next_codepoint()?;
+        if self.config.items.auto_close_pairs.contains(&(open, close)) {
+            Some((offset - open.len_utf8(), offset + close.len_utf8()))
+        } else {
+            None
+        }
+    }
+
+    /// Inserts `s` at each selection region, or one line of it into each
+    /// region if it has as many lines as there are regions.
+    fn insert_distributed(&mut self, s: &str) {
+        let lines = split_lines(s);
+        if lines.len() < 2 || lines.len() != self.view.sel_regions().len() {
+            self.insert(s);
+            return;
+        }
+        let mut builder = delta::Builder::new(self.text.len());
+        for (region, line) in self.view.sel_regions().iter().zip(lines) {
+            let iv = Interval::new_closed_open(region.min(), region.max());
+            builder.replace(iv, Rope::from(line));
+        }
+        self.add_delta(builder.build());
+    }
+
+    /// Inserts the lines of `s` as a rectangle at each selection region;
+    /// see `EditorCommand::InsertBlock`. Regions on lines already covered
+    /// by the rectangle of a previous region are skipped.
+    pub fn do_insert_block(&mut self, s: &str) {
+        let lines = split_lines(s);
+        if lines.len() < 2 || lines.len() == self.view.sel_regions().len() {
+            self.insert_distributed(s);
+            return;
+        }
+        let tab_size = self.config.items.tab_size;
+        let last_line = self.view.line_of_offset(&self.text, self.text.len());
+        // (start, end, replacement), in order
+        let mut edits: Vec<(usize, usize, String)> = Vec::new();
+        let mut appended = String::new();
+        let mut next_free_line = 0;
+        for region in self.view.sel_regions() {
+            let (first_line, col) = self.view.offset_to_visual_col(&self.text, region.min(),
+                                                                   tab_size);
+            if !edits.is_empty() && first_line < next_free_line {
+                continue;
+            }
+            let line = self.view.line_of_offset(&self.text, region.max());
+            edits.push((region.min(), region.max(), lines[0].to_owned()));
+            for (i, text) in lines[1..].iter().enumerate() {
+                let target = line + 1 + i;
+                if target > last_line {
+                    appended.push_str(&self.config.items.line_ending);
+                    appended.push_str(&" ".repeat(col));
+                    appended.push_str(text);
+                } else {
+                    let (offset, reached) = self.view.visual_col_to_offset(&self.text, target,
+                                                                           col, tab_size, false);
+                    edits.push((offset, offset, " ".repeat(col - reached) + text));
+                }
+            }
+            next_free_line = line + lines.len();
+        }
+        if !appended.is_empty() {
+            let len = self.text.len();
+            match edits.last_mut() {
+                Some(edit) if edit.1 == len => edit.2.push_str(&appended),
+                _ => edits.push((len, len, appended)),
+            }
+        }
+        let mut builder = delta::Builder::new(self.text.len());
+        for (start, end, text) in edits {
+            builder.replace(Interval::new_closed_open(start, end), Rope::from(text));
+        }
+        self.add_delta(builder.build());
     }
 
     pub fn do_save<P: AsRef<Path>>(&mut self, path: P) {
@@ -711,26 +1565,38 @@ impl Editor {
         self.render();
     }
 
-    pub fn do_scroll(&mut self, first: i64, last: i64) {
-        let first = max(first, 0) as usize;
-        let last = last as usize;
+    pub fn do_scroll(&mut self, first: usize, last: usize) {
         self.view.set_scroll(first, last);
     }
 
     /// Sets the cursor and scrolls to the beginning of the given line.
-    pub fn do_goto_line(&mut self, line: u64) {
-        let line = self.view.line_col_to_offset(&self.text, line as usize, 0);
+    pub fn do_goto_line(&mut self, line: usize) {
+        let line = self.view.line_col_to_offset(&self.text, line, 0);
         self.set_cursor(line);
     }
 
-    pub fn do_request_lines(&mut self, first: i64, last: i64) {
-        self.view.request_lines(&self.text, &self.doc_ctx, self.styles.get_merged(), first as usize, last as usize);
+    pub fn do_request_lines(&mut self, first: usize, last: usize) {
+        self.view.request_lines(&self.text, &self.doc_ctx, self.styles.get_merged(), first, last);
     }
 
-    pub fn do_click(&mut self, line: u64, col: u64, flags: u64, click_count: u64) {
+    pub fn do_click(&mut self, line: usize, col: usize, modifier: SelectionModifier,
+                    click_count: usize) {
         // TODO: calculate affinity
-        let offset = self.view.line_col_to_offset(&self.text, line as usize, col as usize);
-        if (flags & FLAG_SELECT) != 0 {
+        if modifier == SelectionModifier::Block {
+            let tab_size = self.config.items.tab_size;
+            let (line, col) = self.view.line_col_to_visual_col(&self.text, line, col, tab_size);
+            let block = BlockSelection {
+                anchor_line: line,
+                anchor_col: col,
+                active_line: line,
+                active_col: col,
+            };
+            self.view.drag_state = None;
+            self.scroll_to = self.view.set_block_selection(&self.text, block, tab_size);
+            return;
+        }
+        let offset = self.view.line_col_to_offset(&self.text, line, col);
+        if modifier == SelectionModifier::Extend {
             if !self.view.is_point_in_selection(offset) {
                 let sel = {
                     let (last, rest) = self.view.sel_regions().split_last().unwrap();
@@ -755,23 +1621,24 @@ impl Editor {
             }
         } else if click_count == 2 {
             let (start, end) = {
-                let mut word_cursor = WordCursor::new(&self.text, offset);
+                let mut word_cursor = WordCursor::with_mode(&self.text, offset,
+                    self.config.items.word_boundaries);
                 word_cursor.select_word()
             };
             self.set_sel_single_region(SelRegion{
-                start: start,
-                end: end,
+                start,
+                end,
                 horiz: None,
                 affinity: Affinity::default(),
             });
             self.view.start_drag(offset, start, end);
             return;
         } else if click_count == 3 {
-            let start = self.view.line_col_to_offset(&self.text, line as usize, 0);
-            let end = self.view.line_col_to_offset(&self.text, line as usize + 1, 0);
+            let start = self.view.line_col_to_offset(&self.text, line, 0);
+            let end = self.view.line_col_to_offset(&self.text, line + 1, 0);
             self.set_sel_single_region(SelRegion{
-                start: start,
-                end: end,
+                start,
+                end,
                 horiz: None,
                 affinity: Affinity::default(),
             });
@@ -782,20 +1649,31 @@ impl Editor {
         self.set_cursor(offset);
     }
 
-    pub fn do_drag(&mut self, line: u64, col: u64, _flags: u64) {
-        let offset = self.view.line_col_to_offset(&self.text, line as usize, col as usize);
+    /// Extends the selection to the given point, as a block if the drag
+    /// started with a block selection click.
+    pub fn do_drag(&mut self, line: usize, col: usize) {
+        if let Some(mut block) = self.view.block {
+            let tab_size = self.config.items.tab_size;
+            let (line, col) = self.view.line_col_to_visual_col(&self.text, line, col, tab_size);
+            block.active_line = line;
+            block.active_col = col;
+            self.scroll_to = self.view.set_block_selection(&self.text, block, tab_size);
+            return;
+        }
+        let offset = self.view.line_col_to_offset(&self.text, line, col);
         self.scroll_to = self.view.do_drag(&self.text, offset, Affinity::default());
     }
 
-    pub fn do_gesture(&mut self, line: u64, col: u64, ty: GestureType) {
-        let offset = self.view.line_col_to_offset(&self.text, line as usize, col as usize);
+    pub fn do_gesture(&mut self, line: usize, col: usize, ty: GestureType) {
+        let offset = self.view.line_col_to_offset(&self.text, line, col);
         match ty {
             GestureType::ToggleSel => self.view.toggle_sel(&self.text, offset),
         }
     }
 
     pub fn debug_rewrap(&mut self) {
-        self.view.rewrap(&self.text, 72);
+        let measurer = self.width_measurer();
+        self.view.rewrap(&self.text, 72.0, measurer);
         self.view.set_dirty(&self.text);
     }
 
@@ -815,27 +1693,59 @@ impl Editor {
     }
 
     pub fn do_copy(&self) -> Option<String> {
-        self.extract_sel_regions(self.view.sel_regions())
+        let regions = self.view.sel_regions();
+        if self.view.block.is_some() && regions.iter().any(|region| !region.is_caret()) {
+            // keep one line per line of the block, even where it's empty
+            let lines = regions.iter()
+                .map(|region| self.text.slice_to_string(region.min(), region.max()))
+                .collect::<Vec<_>>();
+            Some(lines.join("\n"))
+        } else {
+            self.extract_sel_regions(regions)
+        }
     }
 
     pub fn do_undo(&mut self) {
-        if self.cur_undo > 0 {
-            self.cur_undo -= 1;
-            assert!(self.undos.insert(self.live_undos[self.cur_undo]));
+        if self.undo_tree.undo() {
             self.this_edit_type = EditType::Undo;
             self.update_undos();
         }
     }
 
     pub fn do_redo(&mut self) {
-        if self.cur_undo < self.live_undos.len() {
-            assert!(self.undos.remove(&self.live_undos[self.cur_undo]));
-            self.cur_undo += 1;
+        if self.undo_tree.redo() {
+            self.this_edit_type = EditType::Redo;
+            self.update_undos();
+        }
+    }
+
+    /// Moves back through the undo history in chronological order, across
+    /// branches; see `UndoTree::earlier`.
+    pub fn do_undo_earlier(&mut self, step: UndoStep) {
+        if self.undo_tree.earlier(step) {
+            self.this_edit_type = EditType::Undo;
+            self.update_undos();
+        }
+    }
+
+    /// Moves forward through the undo history in chronological order, across
+    /// branches; see `UndoTree::later`.
+    pub fn do_undo_later(&mut self, step: UndoStep) {
+        if self.undo_tree.later(step) {
             self.this_edit_type = EditType::Redo;
             self.update_undos();
         }
     }
 
+    /// Restores the state after the edits in `undo_group`, on any branch
+    /// of the undo tree.
+    pub fn do_goto_undo_state(&mut self, undo_group: usize) {
+        if self.undo_tree.jump_to(undo_group) {
+            self.this_edit_type = EditType::Undo;
+            self.update_undos();
+        }
+    }
+
     pub fn do_transpose(&mut self) {
         let mut builder = delta::Builder::new(self.text.len());
         let mut last = 0;
@@ -868,14 +1778,13 @@ impl Editor {
     }
 
     pub fn yank(&mut self) {
-        // TODO: if there are multiple cursors and the number of newlines
-        // is one less than the number of cursors, split and distribute one
-        // line per cursor.
         let kill_ring_string = self.doc_ctx.get_kill_ring();
-        self.insert(&*String::from(kill_ring_string));
+        self.insert_distributed(&String::from(kill_ring_string));
     }
 
-    pub fn do_find(&mut self, chars: Option<String>, case_sensitive: bool) -> Option<String> {
+    pub fn do_find(&mut self, chars: Option<String>, case_matching: CaseMatching,
+                   whole_words: bool, regex: Option<RegexMatcher>) -> Option<String>
+    {
         let mut from_sel = false;
         let search_string = if chars.is_some() {
             chars
@@ -896,16 +1805,79 @@ impl Editor {
         }
 
         let search_string = search_string.unwrap();
-        if search_string.len() == 0 {
+        if search_string.is_empty() {
             self.view.unset_find(&self.text);
             return None;
         }
 
-        self.view.set_find(&self.text, &search_string, case_sensitive);
+        // a search string taken from the selection is always literal
+        let regex = if from_sel { None } else { regex };
+        self.view.set_find(&self.text, &search_string, case_matching, whole_words, regex);
 
         Some(search_string.to_string())
     }
 
+    /// Replaces the next occurrence of the active search, at or after the start of the last
+    /// selection region, with `chars`, then selects the occurrence after it. Both searches wrap
+    /// around the end of the document.
+    pub fn do_replace_next(&mut self, chars: &str) {
+        let from = self.view.sel_regions().last().map_or(0, |region| region.min());
+        let len = self.text.len();
+        let mut found = self.view.find_replacements(&self.text, from, len, chars, 1);
+        if found.is_empty() {
+            found = self.view.find_replacements(&self.text, 0, len, chars, 1);
+        }
+        let iv = match found.first() {
+            Some(&(iv, _)) => iv,
+            None => return,
+        };
+        // the occurrence after this one is selected before replacing it, so
+        // that the selection is carried through the edit when it's committed
+        self.set_cursor(iv.end());
+        self.do_find_next(false, true, false);
+        let sel = self.view.sel_regions().last().map(|region| (region.min(), region.max()));
+        if sel == Some((iv.start(), iv.end())) {
+            // it's the only occurrence
+            self.set_cursor(iv.end());
+        }
+        self.replace_occurrences(found);
+    }
+
+    /// Replaces every occurrence of the active search with `chars`.
+    pub fn do_replace_all(&mut self, chars: &str) {
+        let len = self.text.len();
+        let found = self.view.find_replacements(&self.text, 0, len, chars, usize::MAX);
+        self.replace_occurrences(found);
+    }
+
+    /// Replaces the occurrences of the active search that lie entirely within a selection
+    /// region with `chars`.
+    pub fn do_replace_in_selection(&mut self, chars: &str) {
+        let regions = self.view.sel_regions().iter()
+            .filter(|region| !region.is_caret())
+            .map(|region| (region.min(), region.max()))
+            .collect::<Vec<_>>();
+        let mut found = Vec::new();
+        for (start, end) in regions {
+            found.extend(self.view.find_replacements(&self.text, start, end, chars,
+                                                     usize::MAX));
+        }
+        self.replace_occurrences(found);
+    }
+
+    /// Replaces each interval with its string, as a single edit in its own undo group.
+    fn replace_occurrences(&mut self, occurrences: Vec<(Interval, String)>) {
+        if occurrences.is_empty() {
+            return;
+        }
+        let mut builder = delta::Builder::new(self.text.len());
+        for (iv, replaced) in occurrences {
+            builder.replace(iv, Rope::from(replaced));
+        }
+        self.this_edit_type = EditType::Other;
+        self.add_delta(builder.build());
+    }
+
     pub fn do_find_next(&mut self, reverse: bool, wrap_around: bool, allow_same: bool) {
         self.scroll_to = self.view.select_next_occurrence(&self.text, reverse, false, true, allow_same);
 
@@ -915,6 +1887,68 @@ impl Editor {
         }
     }
 
+    /// Adds the next occurrence of the text of the last selection region as
+    /// a new region. If all regions are carets, each one selects the word
+    /// it's in instead, and later calls add whole-word occurrences of that
+    /// word. With `skip`, the region added last is replaced by the next one.
+    pub fn do_add_next_occurrence(&mut self, skip: bool) {
+        if self.view.sel_regions().iter().all(SelRegion::is_caret) {
+            self.select_words();
+        } else {
+            self.find_selection();
+            self.scroll_to = self.view.add_next_occurrence(&self.text, skip);
+        }
+    }
+
+    /// Selects every occurrence of the text of the last selection region,
+    /// or of the word under the caret.
+    pub fn do_select_all_occurrences(&mut self) {
+        if self.view.sel_regions().iter().all(SelRegion::is_caret) && !self.select_words() {
+            return;
+        }
+        self.find_selection();
+        self.scroll_to = self.view.select_all_occurrences(&self.text);
+    }
+
+    /// Selects the word around each caret, and searches for the last one as
+    /// a whole word. Returns `false`, leaving the selection alone, if the
+    /// last caret isn't in a word.
+    fn select_words(&mut self) -> bool {
+        let word_boundaries = self.config.items.word_boundaries;
+        let mut sel = Selection::new();
+        for region in self.view.sel_regions() {
+            let (start, end) = WordCursor::with_mode(&self.text, region.end, word_boundaries)
+                .select_word();
+            sel.add_region(SelRegion {
+                start,
+                end,
+                horiz: None,
+                affinity: Affinity::default(),
+            });
+        }
+        let last = sel.last().unwrap().clone();
+        if last.is_caret() {
+            return false;
+        }
+        let word = self.text.slice_to_string(last.min(), last.max());
+        self.view.set_find(&self.text, &word, CaseMatching::Exact, true, None);
+        self.scroll_to = self.view.set_selection(&self.text, sel);
+        true
+    }
+
+    /// Makes the text of the region added last (or of the last region) the
+    /// active search, matched exactly. A whole-word search for the same
+    /// text, as started by `select_words`, is kept.
+    fn find_selection(&mut self) {
+        let last = self.view.last_added_occurrence.clone()
+            .unwrap_or_else(|| self.view.sel_regions().last().unwrap().clone());
+        let chars = self.text.slice_to_string(last.min(), last.max());
+        let whole_words = self.view.whole_words
+            && self.view.search_string.as_ref() == Some(&chars)
+            && self.view.search_regex.is_none();
+        self.view.set_find(&self.text, &chars, CaseMatching::Exact, whole_words, None);
+    }
+
     pub fn do_cancel_operation(&mut self) {
         self.view.unset_find(&self.text);
         self.view.collapse_selections(&self.text);
@@ -927,97 +1961,109 @@ impl Editor {
     pub fn cmd_postlude(&mut self) {
         // TODO: could defer this until input quiesces - will this help?
         self.commit_delta(None);
+        self.update_bracket_highlights();
         self.render();
         self.last_edit_type = self.this_edit_type;
     }
 
-    pub fn handle_notification(&mut self, _view_id: ViewIdentifier,
-                               cmd: rpc::EditNotification) {
-        use rpc::EditNotification::*;
-        use rpc::{LineRange, MouseAction};
-        self.cmd_prelude();
+    /// Executes a single command against this editor, then commits any
+    /// resulting edit and renders.
+    ///
+    /// Commands which produce text (`Cut`, `Copy` and `Find`) return it in
+    /// the `Ok` value; all other commands return `Ok(None)`.
+    pub fn execute(&mut self, cmd: EditorCommand) -> CommandResult {
+        use commands::EditorCommand::*;
 
+        // validate before touching any state, so a bad command is a no-op.
+        let mut search_regex = None;
         match cmd {
-            Insert { chars } => self.do_insert(&chars),
-            DeleteForward => self.delete_forward(),
-            DeleteBackward => self.delete_backward(),
-            DeleteWordForward => self.delete_word_forward(),
-            DeleteWordBackward => self.delete_word_backward(),
-            DeleteToEndOfParagraph => self.delete_to_end_of_paragraph(),
-            DeleteToBeginningOfLine => self.delete_to_beginning_of_line(),
-            InsertNewline => self.insert_newline(),
-            InsertTab => self.insert_tab(),
-            MoveUp => self.move_up(0),
-            MoveUpAndModifySelection => self.move_up(FLAG_SELECT),
-            MoveDown => self.move_down(0),
-            MoveDownAndModifySelection => self.move_down(FLAG_SELECT),
-            MoveLeft | MoveBackward => self.move_left(0),
-            MoveLeftAndModifySelection => self.move_left(FLAG_SELECT),
-            MoveRight | MoveForward => self.move_right(0),
-            MoveRightAndModifySelection => self.move_right(FLAG_SELECT),
-            MoveWordLeft => self.move_word_left(0),
-            MoveWordLeftAndModifySelection => self.move_word_left(FLAG_SELECT),
-            MoveWordRight => self.move_word_right(0),
-            MoveWordRightAndModifySelection => self.move_word_right(FLAG_SELECT),
-            MoveToBeginningOfParagraph => self.move_to_beginning_of_paragraph(0),
-            MoveToEndOfParagraph => self.move_to_end_of_paragraph(0),
-            MoveToLeftEndOfLine => self.move_to_left_end_of_line(0),
-            MoveToLeftEndOfLineAndModifySelection => self.move_to_left_end_of_line(FLAG_SELECT),
-            MoveToRightEndOfLine => self.move_to_right_end_of_line(0),
-            MoveToRightEndOfLineAndModifySelection => self.move_to_right_end_of_line(FLAG_SELECT),
-            MoveToBeginningOfDocument => self.move_to_beginning_of_document(0),
-            MoveToBeginningOfDocumentAndModifySelection => self.move_to_beginning_of_document(FLAG_SELECT),
-            MoveToEndOfDocument => self.move_to_end_of_document(0),
-            MoveToEndOfDocumentAndModifySelection => self.move_to_end_of_document(FLAG_SELECT),
-            ScrollPageUp => self.scroll_page_up(0),
-            PageUpAndModifySelection => self.scroll_page_up(FLAG_SELECT),
-            ScrollPageDown => self.scroll_page_down(0),
-            PageDownAndModifySelection => {
-                self.scroll_page_down(FLAG_SELECT)
+            Scroll(ref range) | RequestLines(ref range) if range.end < range.start => {
+                return Err(CommandError::InvalidLineRange(range.clone()));
             }
-            SelectAll => self.select_all(),
-            AddSelectionAbove => self.add_selection_by_movement(Movement::Up),
-            AddSelectionBelow => self.add_selection_by_movement(Movement::Down),
-            Scroll(LineRange { first, last }) => self.do_scroll(first, last),
-            GotoLine { line } => self.do_goto_line(line),
-            RequestLines(LineRange { first, last }) => self.do_request_lines(first, last),
-            Yank => self.yank(),
-            Transpose => self.do_transpose(),
-            Click(MouseAction {line, column, flags, click_count} ) => {
-                self.do_click(line, column, flags, click_count.unwrap())
+            Click { click_count: 0, .. } => return Err(CommandError::InvalidClickCount),
+            GotoUndoState(group) if !self.undo_tree.contains(group) => {
+                return Err(CommandError::UnknownUndoGroup(group));
             }
-            Drag (MouseAction {line, column, flags, ..}) => {
-                self.do_drag(line, column, flags);
+            Find { chars: Some(ref chars), case_matching, regex: true, .. } => {
+                match RegexMatcher::new(chars, case_matching) {
+                    Ok(matcher) => search_regex = Some(matcher),
+                    Err(err) => return Err(CommandError::InvalidRegex(err.to_string())),
+                }
             }
-            Gesture { line, col, ty } => self.do_gesture(line, col, ty),
-            Undo => self.do_undo(),
-            Redo => self.do_redo(),
-            FindNext { wrap_around, allow_same } => self.do_find_next(false, wrap_around.unwrap_or(false), allow_same.unwrap_or(false)),
-            FindPrevious { wrap_around } => self.do_find_next(true, wrap_around.unwrap_or(false), true),
-            DebugRewrap => self.debug_rewrap(),
-            DebugPrintSpans => self.debug_print_spans(),
-            CancelOperation => self.do_cancel_operation(),
-        };
-
-        self.cmd_postlude();
-    }
+            _ => (),
+        }
 
-/*
-    pub fn handle_request(&mut self, _view_id: ViewIdentifier,
-                          cmd: rpc::EditRequest) -> Result<Value, RemoteError> {
-        use rpc::EditRequest::*;
         self.cmd_prelude();
 
         let result = match cmd {
+            Insert(chars) => { self.do_insert(&chars); None }
+            InsertBlock(chars) => { self.do_insert_block(&chars); None }
+            DeleteForward => { self.delete_forward(); None }
+            DeleteBackward => { self.delete_backward(); None }
+            DeleteWordForward => { self.delete_word_forward(); None }
+            DeleteWordBackward => { self.delete_word_backward(); None }
+            DeleteToEndOfParagraph => { self.delete_to_end_of_paragraph(); None }
+            DeleteToBeginningOfLine => { self.delete_to_beginning_of_line(); None }
+            InsertNewline => { self.insert_newline(); None }
+            InsertTab => { self.insert_tab(); None }
+            Indent => { self.indent_lines(); None }
+            Outdent => { self.outdent_lines(); None }
+            DuplicateLine => { self.duplicate_line(); None }
+            MoveLinesUp => { self.move_lines(false); None }
+            MoveLinesDown => { self.move_lines(true); None }
+            DeleteLine => { self.delete_lines(); None }
+            JoinLines => { self.join_lines(); None }
+            ToggleLineComment => { self.toggle_line_comment(); None }
+            ToggleBlockComment => { self.toggle_block_comment(); None }
+            Fold => { self.fold(); None }
+            Unfold => { self.unfold(); None }
+            FoldAll => { self.fold_all(); None }
+            UnfoldAll => { self.view.unfold(&self.text, |_, _| true); None }
+            Move(movement, modifier) => { self.do_move(movement, modifier); None }
+            SelectAll => { self.select_all(); None }
+            AddSelectionAbove => { self.add_selection_by_movement(Movement::Up); None }
+            AddSelectionBelow => { self.add_selection_by_movement(Movement::Down); None }
+            AddNextOccurrence => { self.do_add_next_occurrence(false); None }
+            SkipOccurrence => { self.do_add_next_occurrence(true); None }
+            SelectAllOccurrences => { self.do_select_all_occurrences(); None }
+            Scroll(range) => { self.do_scroll(range.start, range.end); None }
+            GotoLine(line) => { self.do_goto_line(line); None }
+            RequestLines(range) => { self.do_request_lines(range.start, range.end); None }
+            Yank => { self.yank(); None }
+            Transpose => { self.do_transpose(); None }
+            Click { line, col, modifier, click_count } => {
+                self.do_click(line, col, modifier, click_count);
+                None
+            }
+            Drag { line, col } => { self.do_drag(line, col); None }
+            Gesture { line, col, ty } => { self.do_gesture(line, col, ty); None }
+            Undo => { self.do_undo(); None }
+            Redo => { self.do_redo(); None }
+            UndoEarlier(step) => { self.do_undo_earlier(step); None }
+            UndoLater(step) => { self.do_undo_later(step); None }
+            GotoUndoState(group) => { self.do_goto_undo_state(group); None }
+            FindNext { wrap_around, allow_same } => {
+                self.do_find_next(false, wrap_around, allow_same);
+                None
+            }
+            FindPrevious { wrap_around } => { self.do_find_next(true, wrap_around, true); None }
+            ReplaceNext(chars) => { self.do_replace_next(&chars); None }
+            ReplaceAll(chars) => { self.do_replace_all(&chars); None }
+            ReplaceInSelection(chars) => { self.do_replace_in_selection(&chars); None }
+            DebugRewrap => { self.debug_rewrap(); None }
+            DebugPrintSpans => { self.debug_print_spans(); None }
+            CancelOperation => { self.do_cancel_operation(); None }
             Cut => self.do_cut(),
             Copy => self.do_copy(),
-            Find { chars, case_sensitive } => self.do_find(chars, case_sensitive),
+            Find { chars, case_matching, whole_words, .. } => {
+                self.do_find(chars, case_matching, whole_words, search_regex)
+            }
         };
 
         self.cmd_postlude();
         Ok(result)
     }
-*/
+
     pub fn theme_changed(&mut self) {
         self.styles.theme_changed(&self.doc_ctx);
         self.view.set_dirty(&self.text);
@@ -1153,6 +2199,40 @@ impl Editor {
 */
 }
 
+/// The lines of `s`, without their line endings. A final line ending doesn't
+/// start another line.
+fn split_lines(s: &str) -> Vec<&str> {
+    let s = s.strip_suffix('\n').unwrap_or(s);
+    s.split('\n').map(|line| line.trim_end_matches('\r')).collect()
+}
+
+/// The length of the line break at the end of `line`, if any.
+fn line_break_len(line: &str) -> usize {
+    if line.ends_with("\r\n") {
+        2
+    } else if line.ends_with('\n') {
+        1
+    } else {
+        0
+    }
+}
+
+/// The text of two lines put one after the other, where `first` may be the
+/// last line of the text, without a line break.
+fn swap_lines(first: &str, second: &str) -> String {
+    if line_break_len(first) > 0 {
+        first.to_owned() + second
+    } else {
+        let content_len = second.len() - line_break_len(second);
+        first.to_owned() + &second[content_len..] + &second[..content_len]
+    }
+}
+
+/// The number of columns spanned by the whitespace `indent`.
+fn indent_width(indent: &str, tab_size: usize) -> usize {
+    indent.chars().fold(0, |col, c| advance_col(col, c, tab_size))
+}
+
 fn n_spaces(n: usize) -> &'static str {
     let spaces = "                                ";
     assert!(n <= spaces.len());
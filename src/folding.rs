@@ -0,0 +1,113 @@
+// Copyright 2018 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Finding the regions of a text that can be folded.
+//!
+//! A region is given as a pair of text lines: the line that stays visible
+//! when the region is folded, and the last line hidden by the fold.
+
+use brackets::BracketIndex;
+use rope::rope::Rope;
+use view::advance_col;
+
+/// How the foldable regions of a buffer are found. Set with the `folding`
+/// config key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FoldingMode {
+    /// A region spans the lines between a pair of brackets.
+    #[default]
+    Brackets,
+    /// A region spans the lines indented deeper than the line before them.
+    Indentation,
+}
+
+impl FoldingMode {
+    /// Returns the mode with the given config name, "brackets" or
+    /// "indentation".
+    pub fn try_from_name(name: &str) -> Option<Self> {
+        match name {
+            "brackets" => Some(FoldingMode::Brackets),
+            "indentation" => Some(FoldingMode::Indentation),
+            _ => None,
+        }
+    }
+}
+
+/// The regions opened by a bracket and closed by its match on a later
+/// line. The line of the opening bracket is shown in place of the lines
+/// after it, up to the one before the closing bracket. Brackets for which
+/// `ignore` returns `true` are skipped.
+pub fn bracket_regions<F>(text: &Rope, brackets: &BracketIndex, ignore: F)
+    -> Vec<(usize, usize)>
+    where F: Fn(usize) -> bool
+{
+    let mut regions = brackets.pairs(ignore).into_iter()
+        .map(|(open, close)| (text.line_of_offset(open), text.line_of_offset(close)))
+        .filter(|&(header, close_line)| close_line > header + 1)
+        .map(|(header, close_line)| (header, close_line - 1))
+        .collect::<Vec<_>>();
+    regions.sort();
+    regions.dedup();
+    regions
+}
+
+/// The regions made up of the lines indented deeper than the line before
+/// them. Blank lines are part of the region around them, but don't end
+/// one.
+pub fn indentation_regions(text: &Rope, tab_size: usize) -> Vec<(usize, usize)> {
+    let mut regions = Vec::new();
+    // the lines which may start a region, with their indentation
+    let mut headers: Vec<(usize, usize)> = Vec::new();
+    let mut last_non_blank = 0;
+    for (line, line_str) in text.lines(0, text.len()).enumerate() {
+        let content = line_str.trim_start();
+        if content.is_empty() {
+            continue;
+        }
+        let indent = line_str[..line_str.len() - content.len()].chars()
+            .fold(0, |col, c| advance_col(col, c, tab_size));
+        while let Some(&(header, header_indent)) = headers.last() {
+            if header_indent < indent {
+                break;
+            }
+            if last_non_blank > header {
+                regions.push((header, last_non_blank));
+            }
+            headers.pop();
+        }
+        headers.push((line, indent));
+        last_non_blank = line;
+    }
+    for (header, _) in headers {
+        if last_non_blank > header {
+            regions.push((header, last_non_blank));
+        }
+    }
+    regions.sort();
+    regions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions() {
+        let text = Rope::from("fn a() {\n    b(c,\n      d);\n}\nfn e() { f() }\n");
+        let index = BracketIndex::new(&text);
+        assert_eq!(vec![(0, 2)], bracket_regions(&text, &index, |_| false));
+        let text = Rope::from("if a:\n    b\n\n    if c:\n        d\ne\n  f\n");
+        assert_eq!(vec![(0, 4), (3, 4), (5, 6)], indentation_regions(&text, 4));
+    }
+}
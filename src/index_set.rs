@@ -38,6 +38,12 @@ pub fn remove_n_at<T: Clone>(v: &mut Vec<T>, index: usize, n: usize) {
     }
 }
 
+impl Default for IndexSet {
+    fn default() -> IndexSet {
+        IndexSet::new()
+    }
+}
+
 impl IndexSet {
     /// Create a new, empty set.
     pub fn new() -> IndexSet {
@@ -118,9 +124,9 @@ impl IndexSet {
             ranges = &ranges[1..];
         }
         MinusIter {
-            ranges: ranges,
-            start: start,
-            end: end,
+            ranges,
+            start,
+            end,
         }
     }
 
@@ -137,7 +143,7 @@ impl IndexSet {
             if new_range.0 == new_range.1 {
                 continue; // remove collapsed regions
             }
-            if ranges.len() > 0 {
+            if !ranges.is_empty() {
                 let ix = ranges.len() - 1;
                 if ranges[ix].1 == new_range.0 {
                     ranges[ix] = (ranges[ix].0, new_range.1);
@@ -146,7 +152,7 @@ impl IndexSet {
             }
             ranges.push(new_range);
         }
-        IndexSet { ranges: ranges }
+        IndexSet { ranges }
     }
 
     #[cfg(test)]
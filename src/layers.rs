@@ -17,11 +17,22 @@
 //! Plugins provide syntax highlighting information in the form of 'scopes'.
 //! Scope information originating from any number of plugins can be resolved
 //! into styles using a theme, augmented with additional style definitions.
+//!
+//! Without the `syntect` feature there is no theme, and scopes resolve to
+//! the default style.
 
-use std::collections::{BTreeMap, HashMap};
+use std::collections::BTreeMap;
+#[cfg(feature = "syntect")]
+use std::collections::HashMap;
+#[cfg(feature = "syntect")]
 use syntect::parsing::Scope;
+#[cfg(feature = "syntect")]
 use syntect::highlighting::StyleModifier;
 
+/// Without syntect, scopes are kept by name.
+#[cfg(not(feature = "syntect"))]
+type Scope = String;
+
 use rope::interval::Interval;
 use rope::spans::{Spans, SpansBuilder};
 
@@ -44,13 +55,19 @@ pub struct Scopes {
 }
 
 /// A collection of scope spans from a single source.
+#[derive(Default)]
 pub struct ScopeLayer {
     stack_lookup: Vec<Vec<Scope>>,
+    /// The names of the scopes of each stack in `stack_lookup`, so they can
+    /// be matched without building them.
+    #[cfg(feature = "syntect")]
+    name_lookup: Vec<Vec<String>>,
     style_lookup: Vec<Style>,
     // TODO: this might be efficient (in memory at least) if we use
     // a prefix tree.
     /// style state of existing scope spans, so we can more efficiently
     /// compute styles of child spans.
+    #[cfg(feature = "syntect")]
     style_cache: HashMap<Vec<Scope>, StyleModifier>,
     /// Human readable scope names, for debugging
     scope_spans: Spans<u32>,
@@ -134,6 +151,23 @@ impl Scopes {
         self.merged.edit(iv, resolved);
     }
 
+    /// Whether the text at `offset` has a scope named by one of `names`, or
+    /// a child of one (`"string"` matches `string.quoted.double`), in any
+    /// layer.
+    pub fn has_scope_at(&self, offset: usize, names: &[&str]) -> bool {
+        self.layers.values().any(|layer| {
+            if offset >= layer.scope_spans.len() {
+                return false;
+            }
+            layer.scope_spans.data_at(offset).any(|&id| {
+                layer.stack_names(id).iter().any(|scope| {
+                    names.iter().any(|name| scope == *name ||
+                                     scope.starts_with(name) && scope[name.len()..].starts_with('.'))
+                })
+            })
+        })
+    }
+
     /// Prints scopes and style information for the given `Interval`.
     pub fn debug_print_spans(&self, iv: Interval) {
         for (id, layer) in self.layers.iter() {
@@ -160,24 +194,15 @@ impl Scopes {
     }
 }
 
-impl Default for ScopeLayer {
-    fn default() -> Self {
-        ScopeLayer {
-            stack_lookup: Vec::new(),
-            style_lookup: Vec::new(),
-            style_cache: HashMap::new(),
-            scope_spans: Spans::default(),
-            style_spans: Spans::default(),
-        }
-    }
-}
-
 impl ScopeLayer {
 
     pub fn new(len: usize) -> Self {
         ScopeLayer {
             stack_lookup: Vec::new(),
+            #[cfg(feature = "syntect")]
+            name_lookup: Vec::new(),
             style_lookup: Vec::new(),
+            #[cfg(feature = "syntect")]
             style_cache: HashMap::new(),
             scope_spans: SpansBuilder::new(len).build(),
             style_spans: SpansBuilder::new(len).build(),
@@ -199,8 +224,13 @@ impl ScopeLayer {
     fn add_scopes(&mut self, scopes: Vec<Vec<String>>,
                                 doc_ctx: &DocumentCtx) {
         let mut stacks = Vec::with_capacity(scopes.len());
+        #[cfg(feature = "syntect")]
+        let mut names = Vec::with_capacity(scopes.len());
         for stack in scopes {
-            let scopes = stack.iter().map(|s| Scope::new(&s))
+            #[cfg(not(feature = "syntect"))]
+            let scopes = stack;
+            #[cfg(feature = "syntect")]
+            let scopes = stack.iter().map(|s| Scope::new(s))
                 .filter(|result| match *result {
                     Err(ref err) => {
                         eprintln!("failed to resolve scope {}\nErr: {:?}",
@@ -212,14 +242,36 @@ impl ScopeLayer {
                 })
                 .map(|s| s.unwrap())
                 .collect::<Vec<_>>();
+            #[cfg(feature = "syntect")]
+            names.push(stack);
             stacks.push(scopes);
         }
 
         let mut new_styles = self.styles_for_stacks(stacks.as_slice(), doc_ctx);
         self.stack_lookup.append(&mut stacks);
+        #[cfg(feature = "syntect")]
+        self.name_lookup.append(&mut names);
         self.style_lookup.append(&mut new_styles);
     }
 
+    /// The names of the scopes of the stack `id`.
+    #[cfg(feature = "syntect")]
+    fn stack_names(&self, id: u32) -> &[String] {
+        &self.name_lookup[id as usize]
+    }
+
+    #[cfg(not(feature = "syntect"))]
+    fn stack_names(&self, id: u32) -> &[String] {
+        &self.stack_lookup[id as usize]
+    }
+
+    #[cfg(not(feature = "syntect"))]
+    fn styles_for_stacks(&mut self, stacks: &[Vec<Scope>],
+                         _doc_ctx: &DocumentCtx) -> Vec<Style> {
+        vec![Style::default(); stacks.len()]
+    }
+
+    #[cfg(feature = "syntect")]
     fn styles_for_stacks(&mut self, stacks: &[Vec<Scope>],
                          doc_ctx: &DocumentCtx) -> Vec<Style> {
         let style_map = doc_ctx.get_style_map().lock().unwrap();
@@ -228,7 +280,7 @@ impl ScopeLayer {
 
         for stack in stacks {
             let mut last_style: Option<StyleModifier> = None;
-            let mut upper_bound_of_last = stack.len() as usize;
+            let mut upper_bound_of_last = stack.len();
 
             // walk backwards through stack to see if we have an existing
             // style for any child stacks.
@@ -244,7 +296,7 @@ impl ScopeLayer {
 
             // apply the stack, generating children as needed.
             for i in upper_bound_of_last..stack.len() {
-                let style_mod = highlighter.get_style(&stack[0..i+1]);
+                let style_mod = highlighter.style_mod_for_stack(&stack[0..i+1]);
                 base_style_mod = base_style_mod.apply(style_mod);
             }
 
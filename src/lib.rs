@@ -13,43 +13,95 @@
 // limitations under the License.
 
 //! The main library for xi-core.
+//!
+//! With default features disabled, only the `rope` module (the rope data
+//! structure and its CRDT `Engine`) is built. The `editor` feature adds the
+//! editor, views and config handling; `syntect` and `notify` add theme based
+//! highlighting and config file watching respectively. `rpc` adds the types
+//! of the xi JSON-RPC protocol, for embedders that speak it.
+
+// This crate predates `dyn` and the 2018 lifetime idioms.
+#![allow(bare_trait_objects, mismatched_lifetime_syntaxes)]
 
-#[macro_use]
-extern crate lazy_static;
 extern crate bytecount;
 extern crate memchr;
-extern crate time;
-extern crate syntect;
+extern crate regex_automata;
+#[cfg(feature = "editor")]
+#[macro_use]
+extern crate lazy_static;
+#[cfg(feature = "editor")]
 extern crate toml;
+#[cfg(feature = "syntect")]
+extern crate syntect;
 #[cfg(feature = "notify")]
 extern crate notify;
+#[cfg(test)]
+extern crate proptest;
 
-pub mod editor;
 pub mod rope;
+pub mod unicode;
+
+#[cfg(feature = "editor")]
+pub mod editor;
+#[cfg(feature = "editor")]
+pub mod commands;
 
 /// Internal data structures and logic.
 ///
 /// These internals are not part of the public API (for the purpose of binding to
 /// a front-end), but are exposed here, largely so they appear in documentation.
+#[cfg(feature = "editor")]
 pub mod tabs;
+#[cfg(feature = "editor")]
 pub mod view;
+#[cfg(feature = "editor")]
 pub mod linewrap;
-// pub mod plugins;
+#[cfg(feature = "editor")]
 pub mod styles;
+#[cfg(feature = "editor")]
 pub mod word_boundaries;
+#[cfg(feature = "editor")]
 pub mod index_set;
+#[cfg(feature = "editor")]
 pub mod selection;
+#[cfg(feature = "editor")]
 pub mod movement;
+#[cfg(feature = "editor")]
 pub mod syntax;
+#[cfg(feature = "editor")]
 pub mod layers;
+#[cfg(feature = "editor")]
 pub mod config;
 #[cfg(feature = "notify")]
 pub mod watcher;
+#[cfg(feature = "editor")]
 pub mod line_cache_shadow;
-pub mod unicode;
+#[cfg(feature = "editor")]
+pub mod observer;
+#[cfg(feature = "editor")]
+pub mod undo_tree;
+#[cfg(feature = "rpc")]
 pub mod rpc;
+#[cfg(feature = "editor")]
+pub mod search;
+#[cfg(feature = "editor")]
+pub mod brackets;
+#[cfg(feature = "editor")]
+pub mod folding;
 
+#[cfg(feature = "editor")]
 pub use syntax::SyntaxDefinition;
+#[cfg(feature = "editor")]
 pub use config::{BufferItems as BufferConfig};
-
-use tabs::{Documents, ViewIdentifier, BufferContainerRef};
\ No newline at end of file
+#[cfg(feature = "editor")]
+pub use commands::{EditorCommand, CommandError, CommandResult, SelectionModifier};
+#[cfg(feature = "editor")]
+pub use undo_tree::{UndoStep, UndoTree};
+#[cfg(feature = "editor")]
+pub use search::{SearchHandle, SearchMatch, SearchQuery, SearchScope, SearchSource};
+#[cfg(feature = "editor")]
+pub use tabs::{Documents, ViewIdentifier, BufferIdentifier};
+#[cfg(feature = "editor")]
+pub use observer::{ViewObserver, ViewEvent, ChannelObserver};
+#[cfg(feature = "editor")]
+pub use linewrap::WidthMeasurer;
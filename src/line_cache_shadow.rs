@@ -88,6 +88,12 @@ pub struct PlanSegment {
     pub tactic: RenderTactic,
 }
 
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Builder {
     pub fn new() -> Builder {
         Builder { spans: Vec::new(), dirty: false }
@@ -13,14 +13,43 @@
 // limitations under the License.
 
 //! Compute line wrapping breaks for text.
+//!
+//! Lines are broken at the break opportunities of UAX #14, and the text
+//! between them is measured by a `WidthMeasurer`, so that wrapping works
+//! for proportional fonts as well as in columns.
 
-use time;
+use std::time::{Duration, Instant};
 
 use rope::rope::{Rope, RopeInfo};
 use rope::tree::Cursor;
 use rope::interval::Interval;
 use rope::breaks::{Breaks, BreakBuilder, BreaksBaseMetric};
 use unicode::LineBreakLeafIter;
+use view::advance_col;
+
+/// Measures the width of text as laid out by the front-end, in the units
+/// it wraps lines in, such as pixels or points. Implemented by embedders
+/// using proportional fonts.
+pub trait WidthMeasurer: Send + Sync {
+    /// The width that `s`, which doesn't contain line breaks, adds to a
+    /// line that is `start_width` wide before it, so that a tab can extend
+    /// to the next tab stop. Lines are measured a word at a time, and a word
+    /// may be measured in several pieces.
+    fn measure_width(&self, start_width: f64, s: &str) -> f64;
+}
+
+/// Measures text in the columns of a monospace font: wide characters take
+/// up two columns, and a tab extends to the next multiple of `tab_size`.
+pub struct ColumnMeasurer {
+    pub tab_size: usize,
+}
+
+impl WidthMeasurer for ColumnMeasurer {
+    fn measure_width(&self, start_width: f64, s: &str) -> f64 {
+        let start = start_width as usize;
+        (s.chars().fold(start, |col, c| advance_col(col, c, self.tab_size)) - start) as f64
+    }
+}
 
 struct LineBreakCursor<'a> {
     inner: Cursor<'a, RopeInfo>,
@@ -37,8 +66,8 @@ impl<'a> LineBreakCursor<'a> {
             _ => LineBreakLeafIter::default()
         };
         LineBreakCursor {
-            inner: inner,
-            lb_iter: lb_iter,
+            inner,
+            lb_iter,
             last_byte: 0,
         }
     }
@@ -65,43 +94,74 @@ impl<'a> LineBreakCursor<'a> {
     }
 }
 
-pub fn linewrap(text: &Rope, cols: usize) -> Breaks {
-    let start_time = time::now();
+fn duration_ms(d: Duration) -> f64 {
+    d.as_secs() as f64 * 1e3 + d.subsec_nanos() as f64 * 1e-6
+}
+
+/// The width the text from `start` to `end`, without its line ending, adds
+/// to a line `line_width` wide, and its width without trailing whitespace,
+/// which may hang past the wrap width.
+fn word_widths(text: &Rope, start: usize, end: usize, line_width: f64,
+               measurer: &WidthMeasurer) -> (f64, f64) {
+    // a word is nearly always within one leaf, and only copied if it isn't
+    let first = text.iter_chunks(start, end).next().unwrap_or("");
+    let copy;
+    let word = if first.len() == end - start {
+        first
+    } else {
+        copy = text.slice_to_string(start, end);
+        &copy
+    };
+    let word = word.trim_end_matches(&['\n', '\r'][..]);
+    let visible = word.trim_end();
+    let visible_width = measurer.measure_width(line_width, visible);
+    if visible.len() == word.len() {
+        (visible_width, visible_width)
+    } else {
+        (visible_width + measurer.measure_width(line_width + visible_width,
+                                                &word[visible.len()..]), visible_width)
+    }
+}
+
+/// Computes the breaks of `text` wrapped at `max_width`, as measured by
+/// `measurer`.
+pub fn linewrap(text: &Rope, max_width: f64, measurer: &WidthMeasurer) -> Breaks {
+    let start_time = Instant::now();
     let mut lb_cursor = LineBreakCursor::new(text, 0);
     let mut builder = BreakBuilder::new();
     let mut last_pos = 0;
     let mut last_break_pos = 0;
-    let mut width = 0;
+    let mut width = 0.0;
     loop {
         let (pos, hard) = lb_cursor.next();
-        let word_width = pos - last_pos;
-        if width > 0 && width + word_width > cols {
-            builder.add_break(width);
-            //eprintln!("soft break {}", width);
-            last_break_pos += width;
-            width = 0;
+        let (mut word_width, visible_width) = word_widths(text, last_pos, pos, width, measurer);
+        if last_pos > last_break_pos && width + visible_width > max_width {
+            builder.add_break(last_pos - last_break_pos);
+            last_break_pos = last_pos;
+            width = 0.0;
+            word_width = word_widths(text, last_pos, pos, width, measurer).0;
         }
         width += word_width;
         if hard {
-            builder.add_break(width);
-            //eprintln!("hard break {}", width);
-            last_break_pos += width;
-            width = 0;
+            builder.add_break(pos - last_break_pos);
+            last_break_pos = pos;
+            width = 0.0;
         }
         last_pos = pos;
         if pos == text.len() { break; }
     }
     builder.add_no_break(text.len() - last_break_pos);
     let result = builder.build();
-    let time_ms = (time::now() - start_time).num_nanoseconds().unwrap() as f64 * 1e-6;
+    let time_ms = duration_ms(start_time.elapsed());
     eprintln!("time to wrap {} bytes: {:.2}ms", text.len(), time_ms);
     result
 }
 
 // `text` is string _after_ editing.
-pub fn rewrap(breaks: &mut Breaks, text: &Rope, iv: Interval, newsize: usize, cols: usize) {
+pub fn rewrap(breaks: &mut Breaks, text: &Rope, iv: Interval, newsize: usize, max_width: f64,
+              measurer: &WidthMeasurer) {
     let (edit_iv, new_breaks) = {
-        let start_time = time::now();
+        let start_time = Instant::now();
         let (start, end) = iv.start_end();
         let mut bk_cursor = Cursor::new(breaks, start);
         // start of range to invalidate
@@ -118,14 +178,16 @@ pub fn rewrap(breaks: &mut Breaks, text: &Rope, iv: Interval, newsize: usize, co
         let mut builder = BreakBuilder::new();
         let mut last_pos = inval_start;
         let mut last_break_pos = inval_start;
-        let mut width = 0;
+        let mut width = 0.0;
         loop {
             let (pos, hard) = lb_cursor.next();
-            let word_width = pos - last_pos;
-            if width > 0 && width + word_width > cols {
-                builder.add_break(width);
-                last_break_pos += width;
-                width = 0;
+            let (mut word_width, visible_width) =
+                word_widths(text, last_pos, pos, width, measurer);
+            if last_pos > last_break_pos && width + visible_width > max_width {
+                builder.add_break(last_pos - last_break_pos);
+                last_break_pos = last_pos;
+                width = 0.0;
+                word_width = word_widths(text, last_pos, pos, width, measurer).0;
                 while last_break_pos > inval_end {
                     inval_end = bk_cursor.next::<BreaksBaseMetric>().map_or(text.len(), |pos|
                         pos - (end - start) + newsize);
@@ -137,9 +199,9 @@ pub fn rewrap(breaks: &mut Breaks, text: &Rope, iv: Interval, newsize: usize, co
             width += word_width;
             if hard {
                 // TODO: DRY
-                builder.add_break(width);
-                last_break_pos += width;
-                width = 0;
+                builder.add_break(pos - last_break_pos);
+                last_break_pos = pos;
+                width = 0.0;
                 while last_break_pos > inval_end {
                     inval_end = bk_cursor.next::<BreaksBaseMetric>().map_or(text.len(), |pos|
                         pos - (end - start) + newsize);
@@ -154,10 +216,68 @@ pub fn rewrap(breaks: &mut Breaks, text: &Rope, iv: Interval, newsize: usize, co
             }
         }
         builder.add_no_break(inval_end - last_break_pos);
-        let time_ms = (time::now() - start_time).num_nanoseconds().unwrap() as f64 * 1e-6;
+        let time_ms = duration_ms(start_time.elapsed());
         eprintln!("time to wrap {} bytes: {:.2}ms (not counting build+edit)",
             inval_end - inval_start, time_ms);
         (Interval::new_open_closed(inval_start, inval_end + (end - start) - newsize), builder.build())
     };
     breaks.edit(edit_iv, new_breaks);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rope::breaks::BreaksMetric;
+    use rope::delta::Delta;
+
+    /// Measures text as if each narrow letter were half as wide as others.
+    struct Proportional;
+
+    impl WidthMeasurer for Proportional {
+        fn measure_width(&self, _: f64, s: &str) -> f64 {
+            s.chars().map(|c| if "ilt ".contains(c) { 0.5 } else { 1.0 }).sum()
+        }
+    }
+
+    fn line_starts(text: &Rope, breaks: &Breaks) -> Vec<usize> {
+        (0..breaks.measure::<BreaksMetric>() + 1)
+            .map(|line| breaks.convert_metrics::<BreaksMetric, BreaksBaseMetric>(line))
+            .take_while(|&offset| offset < text.len())
+            .collect()
+    }
+
+    #[test]
+    fn wrap_columns() {
+        // wide characters count twice, and trailing spaces may hang
+        let text = Rope::from("ab cd \u{65e5}\u{672c} ef\ngh");
+        let breaks = linewrap(&text, 5.0, &ColumnMeasurer { tab_size: 4 });
+        assert_eq!(vec![0, 6, 13, 16], line_starts(&text, &breaks));
+    }
+
+    #[test]
+    fn wrap_tabs() {
+        // the tab takes "a de" to column 8, so "fgh" doesn't fit before 9
+        let text = Rope::from("a de\tfgh xyz");
+        let breaks = linewrap(&text, 9.0, &ColumnMeasurer { tab_size: 4 });
+        assert_eq!(vec![0, 5], line_starts(&text, &breaks));
+        // a tab in a word moved to the next line is measured there
+        let text = Rope::from("abcd ef\tgh");
+        let breaks = linewrap(&text, 5.0, &ColumnMeasurer { tab_size: 4 });
+        assert_eq!(vec![0, 5, 8], line_starts(&text, &breaks));
+    }
+
+    #[test]
+    fn wrap_proportional() {
+        let mut text = Rope::from("little tilt wide mow");
+        let mut breaks = linewrap(&text, 6.0, &Proportional);
+        assert_eq!(vec![0, 12, 17], line_starts(&text, &breaks));
+
+        let iv = Interval::new_closed_open(0, 0);
+        let delta = Delta::simple_edit(iv, Rope::from("mm "), text.len());
+        text = delta.apply(&text);
+        rewrap(&mut breaks, &text, iv, 3, 6.0, &Proportional);
+        assert_eq!(line_starts(&text, &linewrap(&text, 6.0, &Proportional)),
+                   line_starts(&text, &breaks));
+        assert_eq!(vec![0, 10, 20], line_starts(&text, &breaks));
+    }
+}
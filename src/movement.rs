@@ -23,7 +23,7 @@ use rope::rope::{LinesMetric, Rope};
 use rope::tree::Cursor;
 
 /// The specification of a movement.
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Movement {
     /// Move to the left by one grapheme cluster.
     Left,
@@ -55,6 +55,10 @@ pub enum Movement {
     StartOfDocument,
     /// Move to the end of the document
     EndOfDocument,
+    /// Move to the bracket matching the one next to the cursor. This needs
+    /// the editor's bracket index and syntax scopes, so it's handled by
+    /// `Editor::do_move`, and leaves regions unchanged here.
+    MatchingBracket,
 }
 
 /// Compute movement based on vertical motion by the given number of lines.
@@ -134,12 +138,12 @@ pub fn region_movement(m: Movement, r: &SelRegion, view: &View, text: &Rope, mod
             }
         }
         Movement::LeftWord => {
-            let mut word_cursor = WordCursor::new(text, r.end);
+            let mut word_cursor = WordCursor::with_mode(text, r.end, view.word_boundaries);
             let offset = word_cursor.prev_boundary().unwrap_or(0);
             (offset, None)
         }
         Movement::RightWord => {
-            let mut word_cursor = WordCursor::new(text, r.end);
+            let mut word_cursor = WordCursor::with_mode(text, r.end, view.word_boundaries);
             let offset = word_cursor.next_boundary().unwrap_or_else(|| text.len());
             (offset, None)
         }
@@ -165,14 +169,14 @@ pub fn region_movement(m: Movement, r: &SelRegion, view: &View, text: &Rope, mod
         Movement::Down => vertical_motion(r, view, text, 1, modify),
         Movement::StartOfParagraph => {
             // Note: TextEdit would start at modify ? r.end : r.min()
-            let mut cursor = Cursor::new(&text, r.end);
+            let mut cursor = Cursor::new(text, r.end);
             let offset = cursor.prev::<LinesMetric>().unwrap_or(0);
             (offset, None)
         }
         Movement::EndOfParagraph => {
             // Note: TextEdit would start at modify ? r.end : r.max()
             let mut offset = r.end;
-            let mut cursor = Cursor::new(&text, offset);
+            let mut cursor = Cursor::new(text, offset);
             if let Some(next_para_offset) = cursor.next::<LinesMetric>() {
                 if cursor.is_boundary::<LinesMetric>() {
                     if let Some(eol) = text.prev_grapheme_offset(next_para_offset) {
@@ -187,7 +191,7 @@ pub fn region_movement(m: Movement, r: &SelRegion, view: &View, text: &Rope, mod
         Movement::EndOfParagraphKill => {
             // Note: TextEdit would start at modify ? r.end : r.max()
             let mut offset = r.end;
-            let mut cursor = Cursor::new(&text, offset);
+            let mut cursor = Cursor::new(text, offset);
             if let Some(next_para_offset) = cursor.next::<LinesMetric>() {
                 offset = next_para_offset;
                 if cursor.is_boundary::<LinesMetric>() {
@@ -204,11 +208,15 @@ pub fn region_movement(m: Movement, r: &SelRegion, view: &View, text: &Rope, mod
         Movement::DownPage => vertical_motion(r, view, text, scroll_height(view), modify),
         Movement::StartOfDocument => (0, None),
         Movement::EndOfDocument => (text.len(), None),
+        Movement::MatchingBracket => (r.end, r.horiz),
     };
+    // an offset hidden in a fold moves out of it, in the direction of the movement
+    let forward = m == Movement::Right || m == Movement::RightWord;
+    let offset = view.skip_folded(text, offset, forward);
     SelRegion {
         start: if modify { r.start } else { offset },
         end: offset,
-        horiz: horiz,
+        horiz,
         affinity: Affinity::default(),
     }
 }
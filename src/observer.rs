@@ -0,0 +1,135 @@
+// Copyright 2018 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Typed notifications from xi-core to a front-end.
+//!
+//! The core computes a minimal diff against the front-end's line cache
+//! (see `line_cache_shadow`) and reports it, along with scroll requests,
+//! style definitions and alerts, to a `ViewObserver`. Front-ends either
+//! implement the trait directly, or use `ChannelObserver` to receive the
+//! same information as a stream of `ViewEvent`s.
+
+use std::sync::Mutex;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use config::BufferItems;
+use styles::Style;
+use tabs::ViewIdentifier;
+use view::RenderedLine;
+
+/// A single operation on the front-end's line cache.
+///
+/// An update is a sequence of these operations, which together produce the
+/// new cache from the old one. Counts are in visual lines.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LineCacheOp {
+    /// Copy the next `n` lines of the old cache to the new cache.
+    Copy(usize),
+    /// Skip the next `n` lines of the old cache.
+    Skip(usize),
+    /// Append `n` invalid (not yet rendered) lines to the new cache.
+    Invalidate(usize),
+    /// Append the given lines to the new cache.
+    Insert(Vec<RenderedLine>),
+}
+
+/// An update to a view's line cache.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ViewUpdate {
+    pub ops: Vec<LineCacheOp>,
+    /// `true` if the buffer has no unsaved changes.
+    pub pristine: bool,
+}
+
+/// A receiver of notifications from the core.
+///
+/// Only `update` and `scroll_to` are required; the other notifications
+/// default to doing nothing.
+pub trait ViewObserver: Send + Sync {
+    /// Called when the line cache for `view_id` should be updated.
+    fn update(&self, view_id: ViewIdentifier, update: ViewUpdate);
+
+    /// Called when the given position should be scrolled into view.
+    fn scroll_to(&self, view_id: ViewIdentifier, line: usize, col: usize);
+
+    /// Called when the config for the buffer shown in `view_id` changes.
+    fn config_changed(&self, _view_id: ViewIdentifier, _config: &BufferItems) {}
+
+    /// Called the first time a style is referenced by a rendered line. The
+    /// reserved styles, which the front-end styles itself, are never
+    /// defined: 0 is the selection, 1 find highlights and 2 brackets
+    /// matching the one at a caret.
+    fn def_style(&self, _style_id: usize, _style: &Style) {}
+
+    /// Called when a message should be shown to the user.
+    fn alert(&self, _msg: &str) {}
+}
+
+/// The notifications delivered by a `ChannelObserver`. Each variant
+/// corresponds to a method of `ViewObserver`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ViewEvent {
+    Update { view_id: ViewIdentifier, update: ViewUpdate },
+    ScrollTo { view_id: ViewIdentifier, line: usize, col: usize },
+    ConfigChanged { view_id: ViewIdentifier, config: Box<BufferItems> },
+    DefStyle { style_id: usize, style: Style },
+    Alert(String),
+}
+
+/// A `ViewObserver` which forwards every notification into a channel.
+pub struct ChannelObserver(Mutex<Sender<ViewEvent>>);
+
+/// A `ViewObserver` which ignores all notifications.
+pub struct NullObserver;
+
+impl ChannelObserver {
+    /// Creates a new observer, returning it along with the receiving end
+    /// of its channel.
+    pub fn new() -> (ChannelObserver, Receiver<ViewEvent>) {
+        let (tx, rx) = channel();
+        (ChannelObserver(Mutex::new(tx)), rx)
+    }
+
+    fn send(&self, event: ViewEvent) {
+        // if the receiver has hung up there's nobody left to notify.
+        let _ = self.0.lock().unwrap().send(event);
+    }
+}
+
+impl ViewObserver for ChannelObserver {
+    fn update(&self, view_id: ViewIdentifier, update: ViewUpdate) {
+        self.send(ViewEvent::Update { view_id, update });
+    }
+
+    fn scroll_to(&self, view_id: ViewIdentifier, line: usize, col: usize) {
+        self.send(ViewEvent::ScrollTo { view_id, line, col });
+    }
+
+    fn config_changed(&self, view_id: ViewIdentifier, config: &BufferItems) {
+        self.send(ViewEvent::ConfigChanged { view_id, config: Box::new(config.clone()) });
+    }
+
+    fn def_style(&self, style_id: usize, style: &Style) {
+        self.send(ViewEvent::DefStyle { style_id, style: style.clone() });
+    }
+
+    fn alert(&self, msg: &str) {
+        self.send(ViewEvent::Alert(msg.to_owned()));
+    }
+}
+
+impl ViewObserver for NullObserver {
+    fn update(&self, _view_id: ViewIdentifier, _update: ViewUpdate) {}
+    fn scroll_to(&self, _view_id: ViewIdentifier, _line: usize, _col: usize) {}
+}
@@ -193,27 +193,19 @@ impl Breaks {
     // other use cases, use the builder.
     pub fn new_no_break(len: usize) -> Breaks {
         let leaf = BreaksLeaf {
-            len: len,
+            len,
             data: vec![],
         };
         Node::from_leaf(leaf)
     }
 }
 
+#[derive(Default)]
 pub struct BreakBuilder {
     b: TreeBuilder<BreaksInfo>,
     leaf: BreaksLeaf,
 }
 
-impl Default for BreakBuilder {
-    fn default() -> BreakBuilder {
-        BreakBuilder {
-            b: TreeBuilder::new(),
-            leaf: BreaksLeaf::default(),
-        }
-    }
-}
-
 impl BreakBuilder {
     pub fn new() -> BreakBuilder {
         BreakBuilder::default()
@@ -221,7 +213,7 @@ impl BreakBuilder {
 
     pub fn add_break(&mut self, len: usize) {
         if self.leaf.data.len() == 64 {
-            let leaf = mem::replace(&mut self.leaf, BreaksLeaf::default());
+            let leaf = mem::take(&mut self.leaf);
             self.b.push(Node::from_leaf(leaf));
         }
         self.leaf.len += len;
@@ -240,9 +232,9 @@ impl BreakBuilder {
 
 #[cfg(test)]
 mod tests {
-    use breaks::{BreaksLeaf, BreaksInfo, BreaksMetric, BreakBuilder};
-    use tree::{Node, Cursor};
-    use interval::Interval;
+    use rope::breaks::{BreaksLeaf, BreaksInfo, BreaksMetric, BreakBuilder};
+    use rope::tree::{Node, Cursor};
+    use rope::interval::Interval;
 
     fn gen(n: usize) -> Node<BreaksInfo> {
         let mut node = Node::default();
@@ -53,7 +53,7 @@ pub struct InsertDelta<N: NodeInfo>(Delta<N>);
 impl<N: NodeInfo> Delta<N> {
     pub fn simple_edit(interval: Interval, rope: Node<N>, base_len: usize) -> Delta<N> {
         let mut builder = Builder::new(base_len);
-        if rope.len() > 0 {
+        if !rope.is_empty() {
             builder.replace(interval, rope);
         } else {
             builder.delete(interval);
@@ -65,7 +65,7 @@ impl<N: NodeInfo> Delta<N> {
     pub fn as_simple_insert(&self) -> Option<&Node<N>> {
         if self.els.len() <= 3 && self.iter_deletions().count() == 0 {
             for el in &self.els {
-                if let &DeltaElement::Insert(ref n) = el {
+                if let DeltaElement::Insert(n) = el {
                     return Some(n)
                 }
             }
@@ -99,8 +99,8 @@ impl<N: NodeInfo> Delta<N> {
     /// Applying the insert then the delete yields the same result as the original delta:
     ///
     /// ```no_run
-    /// # use rope::rope::{Rope, RopeInfo};
-    /// # use rope::delta::Delta;
+    /// # use xi_core::rope::rope::{Rope, RopeInfo};
+    /// # use xi_core::rope::delta::Delta;
     /// # use std::str::FromStr;
     /// fn test_factor(d : &Delta<RopeInfo>, r : &Rope) {
     ///     let (ins, del) = d.clone().factor();
@@ -150,8 +150,8 @@ impl<N: NodeInfo> Delta<N> {
     /// union string.
     ///
     /// ```no_run
-    /// # use rope::rope::{Rope, RopeInfo};
-    /// # use rope::delta::Delta;
+    /// # use xi_core::rope::rope::{Rope, RopeInfo};
+    /// # use xi_core::rope::delta::Delta;
     /// # use std::str::FromStr;
     /// fn test_synthesize(d : &Delta<RopeInfo>, r : &Rope) {
     ///     let (ins_d, del) = d.clone().factor();
@@ -184,8 +184,7 @@ impl<N: NodeInfo> Delta<N> {
                     last_old = old_ranges.next();
                 }
                 // If we have a range in the old text with the character at beg, then we Copy
-                if last_old.is_some() && last_old.unwrap().0 <= beg {
-                    let (ib, ie) = last_old.unwrap();
+                if let Some((ib, ie)) = last_old.filter(|&(ib, _)| ib <= beg) {
                     let end = min(e, ie);
                     // Try to merge contiguous Copys in the output
                     let xbeg = beg + x - ib;  // "beg - ib + x" better for overflow?
@@ -218,7 +217,115 @@ impl<N: NodeInfo> Delta<N> {
                 }
             }
         }
-        Delta { els: els, base_len: base_len }
+        Delta { els, base_len }
+    }
+
+    /// Compose two consecutive deltas into one. `other` must apply to the
+    /// result of `self`, and the result applies to the base of `self`:
+    ///
+    /// `a.compose(&b).apply(s) == b.apply(&a.apply(s))`
+    pub fn compose(&self, other: &Delta<N>) -> Delta<N> {
+        assert_eq!(self.new_document_len(), other.base_len,
+                   "composed Delta must apply to the result of self");
+        let mut els = Vec::new();
+        let mut i = 0;  // index into self.els
+        let mut pos = 0;  // start of self.els[i] in the result of self
+        for elem in &other.els {
+            let (b, e) = match *elem {
+                DeltaElement::Copy(b, e) => (b, e),
+                DeltaElement::Insert(ref n) => {
+                    els.push(DeltaElement::Insert(n.clone()));
+                    continue;
+                }
+            };
+            // copied ranges are increasing, so we never need to look back
+            while i < self.els.len() && pos < e {
+                let len = Delta::total_element_len(&self.els[i..i + 1]);
+                let (lo, hi) = (b.max(pos) - pos, min(e, pos + len) - pos);
+                if lo < hi {
+                    match self.els[i] {
+                        DeltaElement::Copy(cb, _) => {
+                            push_copy(&mut els, cb + lo, cb + hi);
+                        }
+                        DeltaElement::Insert(ref n) if lo == 0 && hi == len => {
+                            els.push(DeltaElement::Insert(n.clone()));
+                        }
+                        DeltaElement::Insert(ref n) => {
+                            let iv = Interval::new_closed_open(lo, hi);
+                            els.push(DeltaElement::Insert(n.subseq(iv)));
+                        }
+                    }
+                }
+                if pos + len > e {
+                    break;
+                }
+                pos += len;
+                i += 1;
+            }
+        }
+        Delta { els, base_len: self.base_len }
+    }
+
+    /// Produce the delta that undoes this one. `base` is the text this delta
+    /// applies to, which supplies the deleted text:
+    ///
+    /// `d.invert(s).apply(&d.apply(s)) == s`
+    pub fn invert(&self, base: &Node<N>) -> Delta<N> {
+        assert_eq!(base.len(), self.base_len, "must invert Delta against Node of correct length");
+        let mut els = Vec::new();
+        let mut pos = 0;  // position in the result of self
+        let mut last_end = 0;  // end of the last copied range in base
+        for elem in &self.els {
+            match *elem {
+                DeltaElement::Copy(b, e) => {
+                    if b > last_end {
+                        let iv = Interval::new_closed_open(last_end, b);
+                        els.push(DeltaElement::Insert(base.subseq(iv)));
+                    }
+                    push_copy(&mut els, pos, pos + e - b);
+                    pos += e - b;
+                    last_end = e;
+                }
+                DeltaElement::Insert(ref n) => pos += n.len(),
+            }
+        }
+        if last_end < self.base_len {
+            let iv = Interval::new_closed_open(last_end, self.base_len);
+            els.push(DeltaElement::Insert(base.subseq(iv)));
+        }
+        Delta { els, base_len: pos }
+    }
+
+    /// Rebase this delta over a concurrent delta `other` with the same base,
+    /// so that it applies to the result of `other`. Text deleted by both is
+    /// only deleted once, and where both insert at the same position,
+    /// `after` determines whether the insertions of `self` come second.
+    ///
+    /// Transforming each delta over the other gives the same text in either
+    /// order:
+    ///
+    /// `a.transform(&b, true).apply(&b.apply(s)) == b.transform(&a, false).apply(&a.apply(s))`
+    pub fn transform(&self, other: &Delta<N>, after: bool) -> Delta<N> {
+        assert_eq!(self.base_len, other.base_len, "transformed Deltas must have the same base");
+        let (self_ins, self_dels) = self.clone().factor();
+        let (other_ins, other_dels) = other.clone().factor();
+
+        // Both sets of insertions are in the union string; first move our
+        // insertions past the other ones, then drop the text `other` deleted.
+        let other_inserted = other_ins.inserted_subset();
+        let union_ins = self_ins.transform_expand(&other_inserted, after);
+        let self_inserted = union_ins.inserted_subset();
+        let other_dels = other_dels.transform_expand(&other_inserted);
+        let ins = union_ins.transform_shrink(&other_dels);
+
+        let self_dels = self_dels.transform_expand(&other_inserted)
+            .transform_expand(&self_inserted)
+            .transform_shrink(&other_dels.transform_expand(&self_inserted));
+        let mut builder = Builder::new(self_dels.len());
+        for (b, e) in self_dels.range_iter(CountMatcher::NonZero) {
+            builder.delete(Interval::new_closed_open(b, e));
+        }
+        ins.compose(&builder.build())
     }
 
     /// Produce a summary of the delta. Everything outside the returned interval
@@ -295,6 +402,17 @@ impl<N: NodeInfo> Delta<N> {
     }
 }
 
+/// Pushes a `Copy`, merging it into a preceding contiguous one.
+fn push_copy<N: NodeInfo>(els: &mut Vec<DeltaElement<N>>, b: usize, e: usize) {
+    if let Some(&mut DeltaElement::Copy(_, ref mut le)) = els.last_mut() {
+        if *le == b {
+            *le = e;
+            return;
+        }
+    }
+    els.push(DeltaElement::Copy(b, e));
+}
+
 impl<N: NodeInfo> fmt::Debug for Delta<N> where Node<N>: fmt::Debug {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         if f.alternate() {
@@ -391,7 +509,7 @@ impl<N: NodeInfo> InsertDelta<N> {
         if y > b1 {
             els.push(DeltaElement::Copy(b1, y));
         }
-        InsertDelta(Delta { els: els, base_len: l })
+        InsertDelta(Delta { els, base_len: l })
     }
 
     // TODO: it is plausible this method also works on Deltas with deletes
@@ -411,7 +529,7 @@ impl<N: NodeInfo> InsertDelta<N> {
                 }
             }
         }).collect();
-        InsertDelta(Delta { els: els, base_len: xform.len_after_delete()})
+        InsertDelta(Delta { els, base_len: xform.len_after_delete()})
     }
 
     /// Return a Subset containing the inserted ranges.
@@ -444,11 +562,10 @@ impl<N: NodeInfo> Deref for InsertDelta<N> {
     }
 }
 
-/// A mapping from coordinates in the source sequence to coordinates in the sequence after
-/// the delta is applied.
-
 // TODO: this doesn't need the new strings, so it should either be based on a new structure
 // like Delta but missing the strings, or perhaps the two subsets it's synthesized from.
+/// A mapping from coordinates in the source sequence to coordinates in the sequence after
+/// the delta is applied.
 pub struct Transformer<'a, N: NodeInfo + 'a> {
     delta: &'a Delta<N>,
 }
@@ -457,14 +574,13 @@ impl<'a, N: NodeInfo + 'a> Transformer<'a, N> {
     /// Create a new transformer from a delta.
     pub fn new(delta: &'a Delta<N>) -> Self {
         Transformer {
-            delta: delta,
+            delta,
         }
     }
 
+    // TODO: implement a cursor so we're not scanning from the beginning every time.
     /// Transform a single coordinate. The `after` parameter indicates whether it
     /// it should land before or after an inserted region.
-
-    // TODO: implement a cursor so we're not scanning from the beginning every time.
     pub fn transform(&mut self, ix: usize, after: bool) -> usize {
         if ix == 0 && !after {
             return 0;
@@ -486,7 +602,7 @@ impl<'a, N: NodeInfo + 'a> Transformer<'a, N> {
                 }
             }
         }
-        return result;
+        result
     }
 
     /// Determine whether a given interval is untouched by the transformation.
@@ -534,7 +650,7 @@ impl<N: NodeInfo> Builder<N> {
         Builder {
             delta: Delta {
                 els: Vec::new(),
-                base_len: base_len,
+                base_len,
             },
             last_offset: 0,
         }
@@ -555,7 +671,7 @@ impl<N: NodeInfo> Builder<N> {
     /// is not properly sorted.
     pub fn replace(&mut self, interval: Interval, rope: Node<N>) {
         self.delete(interval);
-        if rope.len() > 0 {
+        if !rope.is_empty() {
             self.delta.els.push(DeltaElement::Insert(rope));
         }
     }
@@ -598,13 +714,13 @@ impl<'a, N: NodeInfo> Iterator for InsertsIter<'a, N> {
 
     fn next(&mut self) -> Option<Self::Item> {
         let mut result = None;
-        while let Some(elem) = self.els_iter.next() {
+        for elem in self.els_iter.by_ref() {
             match elem {
                 &DeltaElement::Copy(b, e) => {
                     self.pos += e - b;
                     self.last_end = e;
                 }
-                &DeltaElement::Insert(ref n) => {
+                DeltaElement::Insert(n) => {
                     result = Some(DeltaRegion::new(self.last_end, self.pos, n.len()));
                     self.pos += n.len();
                     self.last_end += n.len();
@@ -628,7 +744,7 @@ impl<'a, N: NodeInfo> Iterator for DeletionsIter<'a, N> {
 
     fn next(&mut self) -> Option<Self::Item> {
         let mut result = None;
-        while let Some(elem) = self.els_iter.next() {
+        for elem in self.els_iter.by_ref() {
             match elem {
                 &DeltaElement::Copy(b, e) => {
                     if b > self.last_end {
@@ -640,7 +756,7 @@ impl<'a, N: NodeInfo> Iterator for DeletionsIter<'a, N> {
                         break;
                     }
                 }
-                &DeltaElement::Insert(ref n) => {
+                DeltaElement::Insert(n) => {
                     self.pos += n.len();
                     self.last_end += n.len();
                 }
@@ -656,13 +772,14 @@ impl<'a, N: NodeInfo> Iterator for DeletionsIter<'a, N> {
 
 #[cfg(test)]
 mod tests {
-    use serde_json;
-    use rope::{Rope, RopeInfo};
-    use delta::{Delta, Builder, DeltaRegion};
-    use interval::Interval;
-    use test_helpers::find_deletions;
+    use proptest::prelude::*;
+    use rope::rope::{Rope, RopeInfo};
+    use rope::delta::{Delta, Builder, DeltaRegion};
+    use rope::interval::Interval;
+    use rope::multiset::CountMatcher;
+    use rope::test_helpers::find_deletions;
 
-    const TEST_STR: &'static str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+    const TEST_STR: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
 
     #[test]
     fn simple() {
@@ -766,15 +883,92 @@ mod tests {
     }
 
     #[test]
-    fn delta_serde() {
-        let d = Delta::simple_edit(Interval::new_closed_open(10, 12),
-                                   Rope::from("+"), TEST_STR.len());
-        let ser = serde_json::to_value(d.clone()).expect("serialize failed");
-        eprintln!("{:?}", &ser);
-        let de: Delta<RopeInfo> = serde_json::from_value(ser)
-        .expect("deserialize failed");
-        assert_eq!(d.apply_to_string(TEST_STR), de.apply_to_string(TEST_STR));
+    fn compose() {
+        let d1 = Delta::simple_edit(Interval::new_closed_open(1, 9), Rope::from("era"), 11);
+        let d2 = Delta::simple_edit(Interval::new_closed_open(0, 2), Rope::from("HE"), 6);
+        assert_eq!("HErald", d1.compose(&d2).apply_to_string("hello world"));
+    }
+
+    #[test]
+    fn invert() {
+        let d = Delta::simple_edit(Interval::new_closed_open(1, 9), Rope::from("era"), 11);
+        let inv = d.invert(&Rope::from("hello world"));
+        assert_eq!("hello world", inv.apply_to_string("herald"));
+    }
 
+    #[test]
+    #[should_panic]
+    fn invert_wrong_base() {
+        let d = Delta::simple_edit(Interval::new_closed_open(1, 9), Rope::from("era"), 11);
+        d.invert(&Rope::from("hello"));
+    }
 
+    #[test]
+    fn transform() {
+        let base = "hello world";
+        let d1 = Delta::simple_edit(Interval::new_closed_open(0, 5), Rope::from("goodbye"), 11);
+        let d2 = Delta::simple_edit(Interval::new_closed_open(11, 11), Rope::from("!"), 11);
+        let d1_rebased = d1.transform(&d2, false);
+        assert_eq!("goodbye world!", d1_rebased.apply_to_string(&d2.apply_to_string(base)));
+
+        // concurrent insertions at the same position are ordered by `after`
+        let d3 = Delta::simple_edit(Interval::new_closed_open(11, 11), Rope::from("?"), 11);
+        assert_eq!("hello world!?", d3.transform(&d2, true).apply_to_string("hello world!"));
+        assert_eq!("hello world?!", d3.transform(&d2, false).apply_to_string("hello world!"));
+
+        // text deleted by both is only deleted once
+        let d4 = Delta::simple_edit(Interval::new_closed_open(3, 8), Rope::from(""), 11);
+        assert_eq!("goodbyerld", d4.transform(&d1, false).apply_to_string("goodbye world"));
+    }
+
+    /// Builds a delta on a base of length `len` from unsorted
+    /// `(offset, deleted length, inserted text)` triples.
+    fn build_delta(len: usize, mut edits: Vec<(usize, usize, String)>) -> Delta<RopeInfo> {
+        edits.sort();
+        let mut builder = Builder::new(len);
+        let mut last = 0;
+        for (offset, del_len, text) in edits {
+            let start = (offset % (len + 1)).max(last);
+            let end = (start + del_len).min(len);
+            builder.replace(Interval::new_closed_open(start, end), Rope::from(text));
+            last = end;
+        }
+        builder.build()
+    }
+
+    fn edits() -> impl Strategy<Value = Vec<(usize, usize, String)>> {
+        prop::collection::vec((0..40usize, 0..6usize, "[A-Z]{0,3}"), 0..6)
+    }
+
+    proptest! {
+        #[test]
+        fn compose_matches_apply(s in "[a-z]{0,30}", e1 in edits(), e2 in edits()) {
+            let d1 = build_delta(s.len(), e1);
+            let s1 = d1.apply_to_string(&s);
+            let d2 = build_delta(s1.len(), e2);
+            prop_assert_eq!(d2.apply_to_string(&s1), d1.compose(&d2).apply_to_string(&s));
+        }
+
+        #[test]
+        fn invert_matches_apply(s in "[a-z]{0,30}", e in edits()) {
+            let d = build_delta(s.len(), e);
+            let inv = d.invert(&Rope::from(&s));
+            prop_assert_eq!(&s, &inv.apply_to_string(&d.apply_to_string(&s)));
+        }
+
+        #[test]
+        fn transform_converges(s in "[a-z]{0,30}", e1 in edits(), e2 in edits()) {
+            let d1 = build_delta(s.len(), e1);
+            let d2 = build_delta(s.len(), e2);
+            let s1 = d2.transform(&d1, false).apply_to_string(&d1.apply_to_string(&s));
+            let s2 = d1.transform(&d2, true).apply_to_string(&d2.apply_to_string(&s));
+            prop_assert_eq!(&s1, &s2);
+            // every insertion survives, and text deleted by either is gone
+            let inserted = s1.bytes().filter(u8::is_ascii_uppercase).count();
+            prop_assert_eq!(d1.inserts_len() + d2.inserts_len(), inserted);
+            let deleted = d1.clone().factor().1.union(&d2.clone().factor().1)
+                .count(CountMatcher::NonZero);
+            prop_assert_eq!(s.len() - deleted, s1.len() - inserted);
+        }
     }
 }
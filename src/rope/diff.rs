@@ -0,0 +1,361 @@
+// Copyright 2018 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Computing a `RopeDelta` between two versions of a text.
+//!
+//! This is used to apply an external change to a buffer (the file changing
+//! on disk, or a formatter rewriting it) as a minimal edit, so that
+//! selections, spans and undo history are preserved outside the changed
+//! regions.
+//!
+//! The texts are first compared line by line, using Myers' O(ND) algorithm
+//! on line hashes, and each changed block of lines is then refined by
+//! running the same algorithm on its characters. As in git, the search
+//! gives up on blocks which would take too many edits to diff exactly, and
+//! splits them where it got furthest instead.
+
+use std::borrow::Cow;
+use std::cmp;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+
+use rope::delta::Builder;
+use rope::interval::Interval;
+use rope::rope::{Rope, RopeDelta, RopeInfo};
+
+/// Changed blocks larger than this (in bytes, counting both sides) are
+/// replaced as a whole, rather than refined character by character.
+const MAX_REFINE_LEN: usize = 16 * 1024;
+
+/// The least number of edits `middle_snake` searches through before giving
+/// up on a block.
+const MIN_MAX_COST: isize = 256;
+
+/// A range of one sequence which is replaced by a range of the other, in
+/// item indices.
+type Hunk = (Range<usize>, Range<usize>);
+
+struct Line<'a> {
+    hash: u64,
+    text: Cow<'a, str>,
+}
+
+impl<'a> PartialEq for Line<'a> {
+    fn eq(&self, other: &Line<'a>) -> bool {
+        self.hash == other.hash && self.text == other.text
+    }
+}
+
+/// The lines of `text`, along with the offset of each line and of the end
+/// of the text.
+fn lines(text: &Rope) -> (Vec<Line>, Vec<usize>) {
+    let mut lines = Vec::new();
+    let mut offsets = vec![0];
+    for line in text.lines_raw(0, text.len()) {
+        let mut hasher = DefaultHasher::new();
+        line.hash(&mut hasher);
+        let end = offsets[lines.len()] + line.len();
+        offsets.push(end);
+        lines.push(Line { hash: hasher.finish(), text: line });
+    }
+    (lines, offsets)
+}
+
+/// Computes a delta which transforms `base` into `target`, touching as
+/// little of `base` as possible.
+///
+/// `compute_delta(base, target).apply(base) == target`
+pub fn compute_delta(base: &Rope, target: &Rope) -> RopeDelta {
+    let (base_lines, base_offsets) = lines(base);
+    let (target_lines, target_offsets) = lines(target);
+    let mut line_hunks = Vec::new();
+    diff(0..base_lines.len(), 0..target_lines.len(),
+         &|i, j| base_lines[i] == target_lines[j], &mut line_hunks);
+
+    let mut builder = Builder::new(base.len());
+    for (a, b) in line_hunks {
+        let a = base_offsets[a.start]..base_offsets[a.end];
+        let b = target_offsets[b.start]..target_offsets[b.end];
+        if a.len() + b.len() > MAX_REFINE_LEN {
+            builder.replace(Interval::new_closed_open(a.start, a.end),
+                            target.slice(b.start, b.end));
+        } else {
+            refine(base, a, target, b, &mut builder);
+        }
+    }
+    builder.build()
+}
+
+/// Adds the edits turning `a` in `base` into `b` in `target`, at character
+/// granularity.
+fn refine(base: &Rope, a: Range<usize>, target: &Rope, b: Range<usize>,
+          builder: &mut Builder<RopeInfo>) {
+    let a_str = base.slice_to_string(a.start, a.end);
+    let b_str = target.slice_to_string(b.start, b.end);
+    let a_chars = a_str.char_indices().collect::<Vec<_>>();
+    let b_chars = b_str.char_indices().collect::<Vec<_>>();
+    let mut char_hunks = Vec::new();
+    diff(0..a_chars.len(), 0..b_chars.len(),
+         &|i, j| a_chars[i].1 == b_chars[j].1, &mut char_hunks);
+
+    let a_offset = |i: usize| a.start + a_chars.get(i).map_or(a_str.len(), |c| c.0);
+    let b_offset = |i: usize| b.start + b_chars.get(i).map_or(b_str.len(), |c| c.0);
+    for (ca, cb) in char_hunks {
+        builder.replace(Interval::new_closed_open(a_offset(ca.start), a_offset(ca.end)),
+                        target.slice(b_offset(cb.start), b_offset(cb.end)));
+    }
+}
+
+/// Appends the hunks turning `a` into `b` to `out`, in order. `eq(i, j)`
+/// compares item `i` of `a` with item `j` of `b`.
+fn diff<F>(mut a: Range<usize>, mut b: Range<usize>, eq: &F, out: &mut Vec<Hunk>)
+    where F: Fn(usize, usize) -> bool
+{
+    while a.start < a.end && b.start < b.end && eq(a.start, b.start) {
+        a.start += 1;
+        b.start += 1;
+    }
+    while a.start < a.end && b.start < b.end && eq(a.end - 1, b.end - 1) {
+        a.end -= 1;
+        b.end -= 1;
+    }
+    if a.start == a.end && b.start == b.end {
+        return;
+    }
+    if a.start == a.end || b.start == b.end {
+        out.push((a, b));
+        return;
+    }
+    match middle_snake(a.clone(), b.clone(), eq) {
+        Some((x, y)) => {
+            diff(a.start..x, b.start..y, eq, out);
+            diff(x..a.end, y..b.end, eq, out);
+        }
+        None => out.push((a, b)),
+    }
+}
+
+/// The number of edits `middle_snake` searches through in a block of `len`
+/// items, counting both sides, before giving up. Like git, this is the
+/// square root of the length, so that the search takes O(N^1.5) time.
+fn max_cost(len: isize) -> isize {
+    cmp::max(MIN_MAX_COST, (len as f64).sqrt() as isize)
+}
+
+/// Finds a point `(x, y)` on a shortest edit path from `a` to `b`, by
+/// searching from both ends at once; see section 4b of Myers' "An O(ND)
+/// Difference Algorithm and Its Variations". If the path would take more
+/// than `max_cost` edits, the point furthest along either search is
+/// returned instead, so that the block is diffed in parts. Returns `None`
+/// if the two sequences have nothing in common, in which case the block is
+/// replaced as a whole.
+fn middle_snake<F>(a: Range<usize>, b: Range<usize>, eq: &F) -> Option<(usize, usize)>
+    where F: Fn(usize, usize) -> bool
+{
+    let n = a.len() as isize;
+    let m = b.len() as isize;
+    let max_d = cmp::min((n + m + 1) / 2, max_cost(n + m));
+    let v_offset = max_d;
+    let v_len = 2 * max_d + 2;
+    // furthest x reached on each diagonal k (as k + v_offset), going
+    // forwards from the start in v1 and backwards from the end in v2
+    let mut v1 = vec![-1isize; v_len as usize];
+    let mut v2 = vec![-1isize; v_len as usize];
+    v1[v_offset as usize + 1] = 0;
+    v2[v_offset as usize + 1] = 0;
+    let delta = n - m;
+    // if the difference in length is odd, the forward path finds the overlap
+    let front = delta % 2 != 0;
+    let (mut k1_start, mut k1_end, mut k2_start, mut k2_end) = (0, 0, 0, 0);
+    let at = |x: isize, y: isize| eq(a.start + x as usize, b.start + y as usize);
+
+    for d in 0..max_d {
+        let mut k1 = -d + k1_start;
+        while k1 <= d - k1_end {
+            let k1_offset = (v_offset + k1) as usize;
+            let mut x1 = if k1 == -d || (k1 != d && v1[k1_offset - 1] < v1[k1_offset + 1]) {
+                v1[k1_offset + 1]
+            } else {
+                v1[k1_offset - 1] + 1
+            };
+            let mut y1 = x1 - k1;
+            while x1 < n && y1 < m && at(x1, y1) {
+                x1 += 1;
+                y1 += 1;
+            }
+            v1[k1_offset] = x1;
+            if x1 > n {
+                k1_end += 2;
+            } else if y1 > m {
+                k1_start += 2;
+            } else if front {
+                let k2_offset = v_offset + delta - k1;
+                if k2_offset >= 0 && k2_offset < v_len && v2[k2_offset as usize] != -1 {
+                    let x2 = n - v2[k2_offset as usize];
+                    if x1 >= x2 {
+                        return Some((a.start + x1 as usize, b.start + y1 as usize));
+                    }
+                }
+            }
+            k1 += 2;
+        }
+
+        let mut k2 = -d + k2_start;
+        while k2 <= d - k2_end {
+            let k2_offset = (v_offset + k2) as usize;
+            let mut x2 = if k2 == -d || (k2 != d && v2[k2_offset - 1] < v2[k2_offset + 1]) {
+                v2[k2_offset + 1]
+            } else {
+                v2[k2_offset - 1] + 1
+            };
+            let mut y2 = x2 - k2;
+            while x2 < n && y2 < m && at(n - x2 - 1, m - y2 - 1) {
+                x2 += 1;
+                y2 += 1;
+            }
+            v2[k2_offset] = x2;
+            if x2 > n {
+                k2_end += 2;
+            } else if y2 > m {
+                k2_start += 2;
+            } else if !front {
+                let k1_offset = v_offset + delta - k2;
+                if k1_offset >= 0 && k1_offset < v_len && v1[k1_offset as usize] != -1 {
+                    let x1 = v1[k1_offset as usize];
+                    let y1 = v_offset + x1 - k1_offset;
+                    if x1 >= n - x2 {
+                        return Some((a.start + x1 as usize, b.start + y1 as usize));
+                    }
+                }
+            }
+            k2 += 2;
+        }
+    }
+    if max_d < (n + m + 1) / 2 {
+        furthest_point(n, m, &v1, &v2, v_offset)
+            .map(|(x, y)| (a.start + x as usize, b.start + y as usize))
+    } else {
+        None
+    }
+}
+
+/// The point furthest from both ends reached by the searches of
+/// `middle_snake`, as a place to split a block it gave up on, as git's
+/// xdiff does. Returns `None` if that is either end of the block.
+fn furthest_point(n: isize, m: isize, v1: &[isize], v2: &[isize], v_offset: isize)
+    -> Option<(isize, isize)>
+{
+    let forward = v1.iter().enumerate()
+        .filter(|&(_, &x)| x != -1)
+        .map(|(k, &x)| (x, x - (k as isize - v_offset)));
+    let backward = v2.iter().enumerate()
+        .filter(|&(_, &x)| x != -1)
+        .map(|(k, &x)| (n - x, m - (x - (k as isize - v_offset))));
+    // how far a point is from the nearer end, counting both sides
+    let progress = |&(x, y): &(isize, isize)| cmp::min(x + y, n + m - x - y);
+    forward.chain(backward)
+        .filter(|&(x, y)| 0 <= x && x <= n && 0 <= y && y <= m)
+        .max_by_key(progress)
+        .filter(|point| progress(point) > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use rope::diff::*;
+
+    fn check(base: &str, target: &str) -> RopeDelta {
+        let delta = compute_delta(&Rope::from(base), &Rope::from(target));
+        assert_eq!(target, String::from(delta.apply(&Rope::from(base))));
+        delta
+    }
+
+    #[test]
+    fn identical() {
+        let delta = check("one\ntwo\n", "one\ntwo\n");
+        assert_eq!(0, delta.iter_inserts().count() + delta.iter_deletions().count());
+        check("", "");
+    }
+
+    #[test]
+    fn single_line_change() {
+        let delta = check("one\ntwo\nthree\n", "one\ntwice\nthree\n");
+        // only the differing characters of the changed line are touched
+        let (iv, new_len) = delta.summary();
+        assert_eq!((6, 7), iv.start_end());
+        assert_eq!(3, new_len);
+    }
+
+    #[test]
+    fn lines_moved() {
+        let delta = check("a\nb\nc\nd\ne\n", "a\nc\nd\nb\ne\n");
+        assert_eq!(2, delta.inserts_len());
+        assert_eq!(2, delta.iter_deletions().map(|d| d.len).sum::<usize>());
+    }
+
+    #[test]
+    fn expensive_block() {
+        let base = (0..2000).map(|i| format!("line {}\n", i)).collect::<String>();
+        let target = (0..2000).rev().map(|i| format!("line {}\n", i)).collect::<String>();
+        check(&base, &target);
+
+        let base = (0..2000).map(|i| format!("{}", i % 7)).collect::<String>();
+        let target = (0..2000).map(|i| format!("{}", i % 5)).collect::<String>();
+        check(&base, &target);
+    }
+
+    #[test]
+    fn multibyte() {
+        check("héllo wörld\n", "hëllo wörld!\n");
+        check("日本語\n", "日本\n語");
+        check("", "🎉\n");
+        check("🎉\n", "");
+    }
+
+    #[test]
+    fn large_text() {
+        let base = (0..2000).map(|i| format!("line {}\n", i)).collect::<String>();
+        let target = base.replace("line 1000\n", "line one thousand\n")
+            .replace("line 5\n", "");
+        let delta = check(&base, &target);
+        assert!(delta.inserts_len() < 20);
+    }
+
+    #[test]
+    fn scattered_changes() {
+        // too many changes to diff exactly, but each is still edited alone
+        let base = (0..20_000).map(|i| format!("line {}\n", i)).collect::<String>();
+        let target = (0..20_000)
+            .map(|i| if i % 50 == 0 { format!("changed {}\n", i) } else { format!("line {}\n", i) })
+            .collect::<String>();
+        let delta = check(&base, &target);
+        // a few characters of each of the 400 changed lines are inserted
+        assert!(delta.inserts_len() < 400 * 10);
+    }
+
+    proptest! {
+        #[test]
+        fn delta_applies(base in "[ab\n]{0,40}", target in "[ab\n]{0,40}") {
+            let delta = compute_delta(&Rope::from(&base), &Rope::from(&target));
+            prop_assert_eq!(target, String::from(delta.apply(&Rope::from(&base))));
+        }
+
+        #[test]
+        fn delta_applies_unicode(base in "[aé🎉\n]{0,20}", target in "[aé🎉\n]{0,20}") {
+            let delta = compute_delta(&Rope::from(&base), &Rope::from(&target));
+            prop_assert_eq!(target, String::from(delta.apply(&Rope::from(&base))));
+        }
+    }
+}
@@ -31,12 +31,14 @@
 use std::borrow::Cow;
 use std::collections::BTreeSet;
 use std::collections::hash_map::DefaultHasher;
+use std::io::{self, Read, Write};
 use std;
 
 use rope::rope::{Rope, RopeInfo};
 use rope::multiset::{Subset, CountMatcher};
 use rope::interval::Interval;
 use rope::delta::{Delta, InsertDelta};
+use rope::history::{self, HistoryError, HistoryReader, HistoryWriter};
 
 /// Represents the current state of a document and all of its history
 #[derive(Debug)]
@@ -174,7 +176,7 @@ impl Engine {
     /// ancestor in order to be mergeable.
     pub fn new(initial_contents: Rope) -> Engine {
         let mut engine = Engine::empty();
-        if initial_contents.len() > 0 {
+        if !initial_contents.is_empty() {
             let first_rev = engine.get_head_rev_id().token();
             let delta = Delta::simple_edit(Interval::new_closed_closed(0,0), initial_contents, 0);
             engine.edit_rev(0, 0, first_rev, delta);
@@ -206,13 +208,13 @@ impl Engine {
 
     fn find_rev(&self, rev_id: RevId) -> Option<usize> {
         self.revs.iter().enumerate().rev()
-            .find(|&(_, ref rev)| rev.rev_id == rev_id)
+            .find(|&(_, rev)| rev.rev_id == rev_id)
             .map(|(i, _)| i)
     }
 
     fn find_rev_token(&self, rev_token: RevToken) -> Option<usize> {
         self.revs.iter().enumerate().rev()
-            .find(|&(_, ref rev)| rev.rev_id.token() == rev_token)
+            .find(|&(_, rev)| rev.rev_id.token() == rev_token)
             .map(|(i, _)| i)
     }
 
@@ -363,7 +365,7 @@ impl Engine {
             max_undo_so_far: std::cmp::max(undo_group, head_rev.max_undo_so_far),
             edit: Edit {
                 priority: new_priority,
-                undo_group: undo_group,
+                undo_group,
                 inserts: new_inserts,
                 deletes: new_deletes,
             }
@@ -404,9 +406,9 @@ impl Engine {
                     return i + 1; // +1 since we know the one we just found doesn't have it
                 }
             }
-            return 0;
+            0
         } else { // no toggled groups, return past end
-            return self.revs.len();
+            self.revs.len()
         }
     }
 
@@ -414,7 +416,7 @@ impl Engine {
     // recompute the prefix up to where the history diverges, but it's not clear that's
     // even worth the code complexity.
     fn compute_undo(&self, groups: &BTreeSet<usize>) -> (Revision, Subset) {
-        let toggled_groups = self.undone_groups.symmetric_difference(&groups).cloned().collect();
+        let toggled_groups = self.undone_groups.symmetric_difference(groups).cloned().collect();
         let first_candidate = self.find_first_undo_candidate_index(&toggled_groups);
         // the `false` below: don't invert undos since our first_candidate is based on the current undo set, not past
         let mut deletes_from_union = self.deletes_from_union_before_index(first_candidate, false).into_owned();
@@ -510,7 +512,7 @@ impl Engine {
             self.tombstones = dels_from_tombstones.delete_from(&self.tombstones);
             self.deletes_from_union = self.deletes_from_union.transform_shrink(&gc_dels);
         }
-        let old_revs = std::mem::replace(&mut self.revs, Vec::new());
+        let old_revs = std::mem::take(&mut self.revs);
         for rev in old_revs.into_iter().rev() {
             match rev.edit {
                 Edit { priority, undo_group, inserts, deletes } => {
@@ -530,10 +532,10 @@ impl Engine {
                             rev_id: rev.rev_id,
                             max_undo_so_far: rev.max_undo_so_far,
                             edit: Edit {
-                                priority: priority,
-                                undo_group: undo_group,
-                                inserts: inserts,
-                                deletes: deletes,
+                                priority,
+                                undo_group,
+                                inserts,
+                                deletes,
                             }
                         });
                     }
@@ -554,7 +556,7 @@ impl Engine {
                             rev_id: rev.rev_id,
                             max_undo_so_far: rev.max_undo_so_far,
                             edit: Undo {
-                                toggled_groups: &toggled_groups - &gc_groups,
+                                toggled_groups: &toggled_groups - gc_groups,
                                 deletes_bitxor: new_deletes_bitxor,
                             }
                         })
@@ -600,10 +602,188 @@ impl Engine {
         assert_eq!(1, self.revs.len(), "Revisions were added to an Engine before set_session_id, these may collide.");
         self.session = session;
     }
+
+    /// The undo groups that are currently undone.
+    pub fn undone_groups(&self) -> &BTreeSet<usize> {
+        &self.undone_groups
+    }
+
+    /// The undo groups of the edits in the history, in the order they were
+    /// first used.
+    pub fn edit_undo_groups(&self) -> Vec<usize> {
+        let mut seen = BTreeSet::new();
+        self.revs.iter()
+            .filter_map(|rev| match rev.edit {
+                Edit { undo_group, .. } => Some(undo_group),
+                Undo { .. } => None,
+            })
+            .filter(|&group| seen.insert(group))
+            .collect()
+    }
+
+    /// Writes the full edit history, so that it can later be restored with
+    /// `read_history`. The text itself is not included, only enough to check
+    /// that it's unchanged; see `history` for a description of the format.
+    pub fn write_history<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut w = HistoryWriter::new(writer);
+        w.write_header()?;
+
+        // most histories only have one or two sessions, so write each once
+        let mut sessions = vec![self.session];
+        for rev in &self.revs {
+            if !sessions.contains(&rev.rev_id.session_id()) {
+                sessions.push(rev.rev_id.session_id());
+            }
+        }
+        w.write_usize(sessions.len())?;
+        for &(session1, session2) in &sessions {
+            w.write_varint(session1)?;
+            w.write_varint(u64::from(session2))?;
+        }
+        w.write_varint(u64::from(self.rev_id_counter))?;
+
+        w.write_usize(self.text.len())?;
+        w.write_u64_fixed(history::text_hash(&self.text))?;
+        w.write_rope(&self.tombstones)?;
+        w.write_subset(&self.deletes_from_union)?;
+        w.write_groups(&self.undone_groups)?;
+
+        w.write_usize(self.revs.len())?;
+        for rev in &self.revs {
+            let session_ix = sessions.iter().position(|&s| s == rev.rev_id.session_id()).unwrap();
+            w.write_usize(session_ix)?;
+            w.write_varint(u64::from(rev.rev_id.num))?;
+            w.write_usize(rev.max_undo_so_far)?;
+            match rev.edit {
+                Edit { priority, undo_group, ref inserts, ref deletes } => {
+                    w.write_u8(0)?;
+                    w.write_usize(priority)?;
+                    w.write_usize(undo_group)?;
+                    w.write_subset(inserts)?;
+                    w.write_subset(deletes)?;
+                }
+                Undo { ref toggled_groups, ref deletes_bitxor } => {
+                    w.write_u8(1)?;
+                    w.write_groups(toggled_groups)?;
+                    w.write_subset(deletes_bitxor)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Restores an engine from a history written by `write_history`, with
+    /// `text` as its current contents. Fails with `TextMismatch` if `text`
+    /// isn't the text the history was saved with, for example because the
+    /// file was changed by another program in the meantime.
+    pub fn read_history<R: Read>(reader: R, text: &Rope) -> Result<Engine, HistoryError> {
+        let mut r = HistoryReader::new(reader);
+        r.read_header()?;
+
+        let n_sessions = r.read_usize()?;
+        let mut sessions = Vec::new();
+        for _ in 0..n_sessions {
+            sessions.push((r.read_varint()?, r.read_u32()?));
+        }
+        let session = *sessions.first().ok_or(HistoryError::Malformed("no session"))?;
+        let rev_id_counter = r.read_u32()?;
+
+        let text_len = r.read_usize()?;
+        let text_hash = r.read_u64_fixed()?;
+        if text_len != text.len() || text_hash != history::text_hash(text) {
+            return Err(HistoryError::TextMismatch);
+        }
+        let tombstones = r.read_rope()?;
+        let deletes_from_union = r.read_subset()?;
+        let undone_groups = r.read_groups()?;
+
+        let union_len = text.len() + tombstones.len();
+        if deletes_from_union.len() != union_len ||
+                deletes_from_union.count(CountMatcher::Zero) != text.len() {
+            return Err(HistoryError::Malformed("deletes don't match the text"));
+        }
+
+        let n_revs = r.read_usize()?;
+        let mut revs = Vec::new();
+        for _ in 0..n_revs {
+            let session_ix = r.read_usize()?;
+            let (session1, session2) = *sessions.get(session_ix)
+                .ok_or(HistoryError::Malformed("unknown session"))?;
+            let rev_id = RevId { session1, session2, num: r.read_u32()? };
+            let max_undo_so_far = r.read_usize()?;
+            let edit = match r.read_u8()? {
+                0 => Edit {
+                    priority: r.read_usize()?,
+                    undo_group: r.read_usize()?,
+                    inserts: r.read_subset()?,
+                    deletes: r.read_subset()?,
+                },
+                1 => Undo {
+                    toggled_groups: r.read_groups()?,
+                    deletes_bitxor: r.read_subset()?,
+                },
+                _ => return Err(HistoryError::Malformed("unknown revision type")),
+            };
+            revs.push(Revision { rev_id, max_undo_so_far, edit });
+        }
+        r.read_end()?;
+
+        if revs.is_empty() {
+            return Err(HistoryError::Malformed("no revisions"));
+        }
+        check_revisions(&revs, union_len, &undone_groups)?;
+
+        Ok(Engine {
+            session,
+            rev_id_counter,
+            text: text.clone(),
+            tombstones,
+            deletes_from_union,
+            undone_groups,
+            revs,
+        })
+    }
 }
 
 // ======== Generic helpers
 
+/// Check that the subsets of each revision in a history read from disk are
+/// relative to the union string as it was after that revision, and that the
+/// undo groups each revision mentions had been created by then, so that a
+/// corrupt history is rejected rather than panicking once it's used.
+fn check_revisions(revs: &[Revision], union_len: usize, undone_groups: &BTreeSet<usize>)
+        -> Result<(), HistoryError> {
+    let max_undo = revs.last().map_or(0, |rev| rev.max_undo_so_far);
+    if undone_groups.iter().any(|&group| group > max_undo) {
+        return Err(HistoryError::Malformed("unknown undo group"));
+    }
+    // the head revision's subsets are relative to the current union string;
+    // walk back from there, removing each revision's inserts
+    let mut len = union_len;
+    for rev in revs.iter().rev() {
+        match rev.edit {
+            Edit { undo_group, ref inserts, ref deletes, .. } => {
+                if inserts.len() != len || deletes.len() != len {
+                    return Err(HistoryError::Malformed("revisions don't match the text"));
+                }
+                if undo_group > rev.max_undo_so_far {
+                    return Err(HistoryError::Malformed("unknown undo group"));
+                }
+                len -= inserts.count(CountMatcher::NonZero);
+            }
+            Undo { ref toggled_groups, ref deletes_bitxor } => {
+                if deletes_bitxor.len() != len {
+                    return Err(HistoryError::Malformed("revisions don't match the text"));
+                }
+                if toggled_groups.iter().any(|&group| group > rev.max_undo_so_far) {
+                    return Err(HistoryError::Malformed("unknown undo group"));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
 /// Move sections from text to tombstones and out of tombstones based on a new and old set of deletions
 fn shuffle_tombstones(text: &Rope, tombstones: &Rope,
         old_deletes_from_union: &Subset, new_deletes_from_union: &Subset) -> Rope {
@@ -630,11 +810,11 @@ fn shuffle(text: &Rope, tombstones: &Rope,
 
 /// Find an index before which everything is the same
 fn find_base_index(a: &[Revision], b: &[Revision]) -> usize {
-    assert!(a.len() > 0 && b.len() > 0);
+    assert!(!a.is_empty() && !b.is_empty());
     assert!(a[0].rev_id == b[0].rev_id);
     // TODO find the maximum base revision.
     // this should have the same behavior, but worse performance
-    return 1;
+    1
 }
 
 /// Find a set of revisions common to both lists
@@ -817,15 +997,16 @@ fn rebase(mut expand_by: Vec<(FullPriority, Subset)>, b_new: Vec<DeltaOp>, mut t
 
 #[cfg(test)]
 mod tests {
-    use engine::*;
-    use rope::{Rope, RopeInfo};
-    use delta::{Builder, Delta};
-    use multiset::Subset;
-    use interval::Interval;
+    use rope::engine::*;
+    use rope::history::HistoryError;
+    use rope::rope::{Rope, RopeInfo};
+    use rope::delta::{Builder, Delta};
+    use rope::multiset::Subset;
+    use rope::interval::Interval;
     use std::collections::BTreeSet;
-    use test_helpers::{parse_subset_list, parse_subset, parse_delta, debug_subsets};
+    use rope::test_helpers::{parse_subset_list, parse_subset, parse_delta, debug_subsets};
 
-    const TEST_STR: &'static str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+    const TEST_STR: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
 
     fn build_delta_1() -> Delta<RopeInfo> {
         let mut d_builder = Builder::new(TEST_STR.len());
@@ -1092,6 +1273,123 @@ mod tests {
         assert_eq!("ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz", String::from(engine.get_head()));
     }
 
+    fn history_roundtrip(engine: &Engine) -> Engine {
+        let mut buf = Vec::new();
+        engine.write_history(&mut buf).unwrap();
+        Engine::read_history(&buf[..], engine.get_head()).unwrap()
+    }
+
+    #[test]
+    fn history_roundtrip_undo() {
+        let mut engine = Engine::new(Rope::from(TEST_STR));
+        let first_rev = engine.get_head_rev_id().token();
+        engine.edit_rev(1, 1, first_rev, build_delta_1());
+        engine.edit_rev(0, 2, first_rev, build_delta_2());
+        engine.undo([2].iter().cloned().collect());
+
+        let mut restored = history_roundtrip(&engine);
+        assert_eq!(engine.get_head(), restored.get_head());
+        assert_eq!(engine.get_head_rev_id(), restored.get_head_rev_id());
+        assert_eq!(engine.undone_groups(), restored.undone_groups());
+        assert_eq!(vec![0, 1, 2], restored.edit_undo_groups());
+
+        // undo and redo work on the restored history
+        for undos in &[vec![1, 2], vec![], vec![1]] {
+            let undos = undos.iter().cloned().collect::<BTreeSet<usize>>();
+            engine.undo(undos.clone());
+            restored.undo(undos);
+            assert_eq!(engine.get_head(), restored.get_head());
+        }
+        restored.undo(BTreeSet::new());
+        assert_eq!("0!3456789abcDEEFGIjklmnopqr888999stuvHIz", String::from(restored.get_head()));
+
+        // and new edits get fresh revision ids
+        let head = restored.get_head_rev_id();
+        let d = Delta::simple_edit(Interval::new_closed_open(0, 0), Rope::from("x"),
+                                   restored.get_head().len());
+        restored.edit_rev(0, 3, head.token(), d);
+        assert!(restored.find_rev(head).is_some());
+        assert!(engine.find_rev(restored.get_head_rev_id()).is_none());
+    }
+
+    #[test]
+    fn history_roundtrip_session_and_gc() {
+        let mut engine = Engine::empty();
+        engine.set_session_id((42, 7));
+        let first_rev = engine.get_head_rev_id().token();
+        let d = Delta::simple_edit(Interval::new_closed_open(0, 0), Rope::from(TEST_STR), 0);
+        engine.edit_rev(0, 0, first_rev, d);
+        let first_rev = engine.get_head_rev_id().token();
+        engine.edit_rev(1, 1, first_rev, build_delta_1());
+        engine.edit_rev(0, 2, first_rev, build_delta_2());
+        engine.undo([1].iter().cloned().collect());
+        engine.gc(&[1].iter().cloned().collect());
+
+        let restored = history_roundtrip(&engine);
+        assert_eq!(format!("{:?}", engine), format!("{:?}", restored));
+    }
+
+    #[test]
+    fn history_text_mismatch() {
+        let mut engine = Engine::new(Rope::from(TEST_STR));
+        let first_rev = engine.get_head_rev_id().token();
+        engine.edit_rev(1, 1, first_rev, build_delta_1());
+        let mut buf = Vec::new();
+        engine.write_history(&mut buf).unwrap();
+
+        let mut changed = String::from(engine.get_head());
+        changed.insert(3, '!');
+        match Engine::read_history(&buf[..], &Rope::from(changed)) {
+            Err(HistoryError::TextMismatch) => (),
+            other => panic!("expected text mismatch, got {:?}", other),
+        }
+        let same_len = "_".to_owned() + &String::from(engine.get_head())[1..];
+        assert!(Engine::read_history(&buf[..], &Rope::from(same_len)).is_err());
+
+        for len in 0..buf.len() {
+            assert!(Engine::read_history(&buf[..len], engine.get_head()).is_err());
+        }
+        assert!(Engine::read_history(&buf[..], engine.get_head()).is_ok());
+    }
+
+    #[test]
+    fn history_malformed_revisions() {
+        fn read_back(engine: &Engine) -> Result<Engine, HistoryError> {
+            let mut buf = Vec::new();
+            engine.write_history(&mut buf).unwrap();
+            Engine::read_history(&buf[..], engine.get_head())
+        }
+
+        fn undone_edit() -> Engine {
+            let mut engine = Engine::new(Rope::from(TEST_STR));
+            let first_rev = engine.get_head_rev_id().token();
+            engine.edit_rev(1, 1, first_rev, build_delta_1());
+            engine.undo([1].iter().cloned().collect());
+            engine
+        }
+        assert!(read_back(&undone_edit()).is_ok());
+
+        // an earlier revision whose subsets are the wrong length
+        let mut bad = undone_edit();
+        if let Edit { ref mut inserts, .. } = bad.revs[1].edit {
+            *inserts = Subset::new(inserts.len() + 1);
+        }
+        match read_back(&bad) {
+            Err(HistoryError::Malformed(_)) => (),
+            other => panic!("expected malformed history, got {:?}", other),
+        }
+
+        // an undo of a group that was never created
+        let mut bad = undone_edit();
+        if let Undo { ref mut toggled_groups, .. } = bad.revs.last_mut().unwrap().edit {
+            toggled_groups.insert(5);
+        }
+        assert!(read_back(&bad).is_err());
+        let mut bad = undone_edit();
+        bad.undone_groups.insert(5);
+        assert!(read_back(&bad).is_err());
+    }
+
     fn basic_rev(i: usize) -> RevId {
         RevId { session1: 1, session2: 0, num: i as u32 }
     }
@@ -1324,7 +1622,7 @@ mod tests {
             match *op {
                 MergeTestOp::Merge(ai, bi) => {
                     let (start, end) = self.peers.split_at_mut(ai);
-                    let (mut a, rest) = end.split_first_mut().unwrap();
+                    let (a, rest) = end.split_first_mut().unwrap();
                     let b = if bi < ai {
                         &mut start[bi]
                     } else {
@@ -1346,7 +1644,7 @@ mod tests {
                     }
                 },
                 MergeTestOp::Edit { ei, p, u, d: ref delta } => {
-                    let mut e = &mut self.peers[ei];
+                    let e = &mut self.peers[ei];
                     let head = e.get_head_rev_id().token();
                     e.edit_rev(p, u, head, delta.clone());
                 },
@@ -15,11 +15,20 @@
 //! Implementation of string finding in ropes.
 
 use std::cmp::min;
+use std::error::Error;
+use std::fmt;
 
 use memchr::{memchr, memchr2, memchr3};
+use regex_automata::{Anchored, Input, MatchKind};
+use regex_automata::hybrid::LazyStateID;
+use regex_automata::hybrid::dfa::{Cache, DFA};
+use regex_automata::meta;
+use regex_automata::nfa::thompson;
+use regex_automata::util::{start, syntax};
 
-use rope::rope::{BaseMetric, RopeInfo};
+use rope::rope::{BaseMetric, LinesMetric, Rope, RopeInfo};
 use rope::tree::Cursor;
+use unicode::{canonical_combining_class, to_nfd};
 
 /// The result of a [`find`][find] operation.
 /// 
@@ -34,10 +43,9 @@ pub enum FindResult {
     TryAgain,
 }
 
-/// A policy for case matching. There may be more choices in the future (for
-/// example, an even more forgiving mode that ignores accents, or possibly
-/// handling Unicode normalization).
-#[derive(Clone, Copy, PartialEq)]
+/// A policy for case matching, and for how forgiving a match is of other
+/// differences in the text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CaseMatching {
     /// Require an exact codepoint-for-codepoint match (implies case sensitivity).
     Exact,
@@ -45,6 +53,13 @@ pub enum CaseMatching {
     /// reasonably well otherwise (it is currently defined in terms of the
     /// `to_lowercase` methods in the Rust standard library).
     CaseInsensitive,
+    /// Case sensitive match of canonically equivalent text, so that for
+    /// example the NFC and NFD forms of "é" match each other.
+    Normalized,
+    /// The most forgiving match: case insensitive, and ignoring accents and
+    /// other combining marks, so that "e" matches "é", "E" and "ë". Like
+    /// `Normalized`, this is insensitive to how the text is normalized.
+    IgnoreAccents,
 }
 
 /// Finds a pattern string in the rope referenced by the cursor, starting at
@@ -57,7 +72,7 @@ pub enum CaseMatching {
 ///
 /// Can panic if `pat` is empty.
 pub fn find(cursor: &mut Cursor<RopeInfo>, cm: CaseMatching, pat: &str) -> Option<usize> {
-    match find_progress(cursor, cm, pat, usize::max_value()) {
+    match find_progress(cursor, cm, pat, usize::MAX) {
         FindResult::Found(pos) => Some(pos),
         FindResult::NotFound => None,
         FindResult::TryAgain => unreachable!("find_progress got stuck"),
@@ -97,7 +112,7 @@ pub fn find_progress(cursor: &mut Cursor<RopeInfo>, cm: CaseMatching, pat: &str,
                 // 0xE2 is first utf-8 byte of u+212A (kelvin sign)
                 let scanner = |s: &str| memchr3(b'k', b'K', 0xE2, s.as_bytes());
                 find_progress_iter(cursor, &pat_lower, &scanner, &matcher, num_steps)
-            } else if b >= b'a' && b <= b'z' {
+            } else if b.is_ascii_lowercase() {
                 let scanner = |s: &str| memchr2(b, b - 0x20, s.as_bytes());
                 find_progress_iter(cursor, &pat_lower, &scanner, &matcher, num_steps)
             } else if b < 0x80 {
@@ -109,6 +124,20 @@ pub fn find_progress(cursor: &mut Cursor<RopeInfo>, cm: CaseMatching, pat: &str,
                 find_progress_iter(cursor, &pat_lower, &scanner, &matcher, num_steps)
             }
         }
+        CaseMatching::Normalized | CaseMatching::IgnoreAccents => {
+            let pat_folded = fold(pat, cm);
+            let c = match pat_folded.chars().next() {
+                Some(c) => c,
+                // the pattern consists only of accents
+                None => return find_progress(cursor, CaseMatching::CaseInsensitive, pat,
+                                             num_steps),
+            };
+            let scanner = |s: &str| scan_folded(c, s, cm);
+            let matcher = |cursor: &mut Cursor<RopeInfo>, pat: &str| {
+                compare_cursor_str_folded(cursor, pat, cm)
+            };
+            find_progress_iter(cursor, &pat_folded, &scanner, &matcher, num_steps)
+        }
     }
 }
 
@@ -217,12 +246,426 @@ fn scan_lowercase(probe: char, s: &str) -> Option<usize> {
     None
 }
 
+/// Converts `s` to the form in which it is compared under `cm`, which is
+/// `Normalized` or `IgnoreAccents`.
+fn fold(s: &str, cm: CaseMatching) -> String {
+    match cm {
+        CaseMatching::IgnoreAccents => {
+            // lowercased a character at a time, like the text it's compared with
+            let lower = s.chars().flat_map(char::to_lowercase).collect::<String>();
+            to_nfd(&lower).chars().filter(|&c| canonical_combining_class(c) == 0).collect()
+        }
+        _ => to_nfd(s),
+    }
+}
+
+/// Scan for a codepoint whose folded form starts with the probe.
+fn scan_folded(probe: char, s: &str, cm: CaseMatching) -> Option<usize> {
+    let mut buf = [0; 4];
+    for (i, c) in s.char_indices() {
+        let found = if c.is_ascii() {
+            let c = if cm == CaseMatching::IgnoreAccents { c.to_ascii_lowercase() } else { c };
+            c == probe
+        } else {
+            fold(c.encode_utf8(&mut buf), cm).starts_with(probe)
+        };
+        if found {
+            return Some(i);
+        }
+    }
+    None
+}
+
+/// Like `compare_cursor_str`, but compares the folded form of the text with
+/// the already folded `pat`. The match can't end inside a sequence of
+/// combining marks, as that would change the last matched character; with
+/// `IgnoreAccents` it is extended over them instead.
+fn compare_cursor_str_folded(cursor: &mut Cursor<RopeInfo>, pat: &str, cm: CaseMatching)
+    -> bool
+{
+    // the text folded so far is `pat[..matched]` followed by `marks`, the
+    // combining marks after the last starter, which later marks may still
+    // be reordered with
+    let mut matched = 0;
+    let mut marks = Vec::new();
+    let mut buf = [0; 4];
+    loop {
+        let end = cursor.pos();
+        let next = cursor.next_codepoint();
+        if pat[matched..].chars().eq(marks.iter().cloned()) {
+            let is_mark = next.is_some_and(|c| canonical_combining_class(c) != 0);
+            if !is_mark {
+                cursor.set(end);
+                return true;
+            }
+            if cm != CaseMatching::IgnoreAccents {
+                return false;
+            }
+        }
+        let c = match next {
+            Some(c) => c,
+            None => return false,
+        };
+        if c.is_ascii() {
+            let c = if cm == CaseMatching::IgnoreAccents { c.to_ascii_lowercase() } else { c };
+            if !push_folded(pat, &mut matched, &mut marks, c) {
+                return false;
+            }
+        } else {
+            for c in fold(c.encode_utf8(&mut buf), cm).chars() {
+                if !push_folded(pat, &mut matched, &mut marks, c) {
+                    return false;
+                }
+            }
+        }
+    }
+}
+
+/// Appends `c` to the folded text of `compare_cursor_str_folded`, keeping
+/// the trailing combining marks in canonical order. Returns `false` once the
+/// text can no longer match `pat`.
+fn push_folded(pat: &str, matched: &mut usize, marks: &mut Vec<char>, c: char) -> bool {
+    let class = canonical_combining_class(c);
+    if class != 0 {
+        let ix = marks.iter().rposition(|&m| canonical_combining_class(m) <= class)
+            .map_or(0, |ix| ix + 1);
+        marks.insert(ix, c);
+        return true;
+    }
+    for c in marks.drain(..).chain(Some(c)) {
+        if !pat[*matched..].starts_with(c) {
+            return false;
+        }
+        *matched += c.len_utf8();
+    }
+    true
+}
+
+/// An error compiling a regular expression.
+#[derive(Debug, Clone)]
+pub struct RegexError(String);
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid regex: {}", self.0)
+    }
+}
+
+impl Error for RegexError {}
+
+/// A forward scan suspended by `find_regex_progress`.
+struct RegexScan {
+    /// Where the scan started; matches can't start before this.
+    start: usize,
+    /// The position of the cursor when the scan was suspended.
+    pos: usize,
+    state: LazyStateID,
+    /// The end of the preferred match seen so far.
+    match_end: Option<usize>,
+}
+
+/// How far a single step of a forward scan got.
+enum ScanStep {
+    Continue,
+    Done,
+    /// The DFA gave up at this position.
+    Quit(usize),
+}
+
+/// A compiled regular expression, for use with [`find_regex`][find_regex].
+///
+/// Matching is done by a lazy DFA which is fed the rope one leaf at a time,
+/// so matches may span leaves and the text is never copied. A second DFA,
+/// run backwards from the end of a match, finds where it starts. `^` and `$`
+/// match at line boundaries.
+///
+/// The DFAs give up on non-ASCII text when the pattern contains a Unicode
+/// word boundary (`\b`). In that case the affected lines are copied and
+/// searched with a slower engine, and matches can't extend past the end of
+/// the last such line.
+///
+/// [find_regex]: fn.find_regex.html
+pub struct RegexMatcher {
+    forward: DFA,
+    reverse: DFA,
+    forward_cache: Cache,
+    reverse_cache: Cache,
+    fallback: meta::Regex,
+    scan: Option<RegexScan>,
+}
+
+impl RegexMatcher {
+    /// Compiles `pattern`. Of the case matching policies, `Normalized` is
+    /// treated as `Exact`, and `IgnoreAccents` as `CaseInsensitive`.
+    pub fn new(pattern: &str, cm: CaseMatching) -> Result<RegexMatcher, RegexError> {
+        let case_insensitive = cm == CaseMatching::CaseInsensitive
+            || cm == CaseMatching::IgnoreAccents;
+        let syntax = syntax::Config::new()
+            .case_insensitive(case_insensitive)
+            .multi_line(true);
+        let fallback = meta::Regex::builder()
+            .syntax(syntax)
+            .build(pattern)
+            .map_err(|e| RegexError(e.to_string()))?;
+        let forward = DFA::builder()
+            .configure(DFA::config().unicode_word_boundary(true).specialize_start_states(true))
+            .syntax(syntax)
+            .build(pattern)
+            .map_err(|e| RegexError(e.to_string()))?;
+        let reverse = DFA::builder()
+            .configure(DFA::config().unicode_word_boundary(true).match_kind(MatchKind::All))
+            .syntax(syntax)
+            .thompson(thompson::Config::new().reverse(true))
+            .build(pattern)
+            .map_err(|e| RegexError(e.to_string()))?;
+        Ok(RegexMatcher {
+            forward_cache: forward.create_cache(),
+            reverse_cache: reverse.create_cache(),
+            forward,
+            reverse,
+            fallback,
+            scan: None,
+        })
+    }
+
+    /// Expands `replacement` for the match at `start..end` of `text`: `$1`,
+    /// `$name` and `${name}` are replaced by the text of the corresponding
+    /// capture group, and `$$` by `$`.
+    pub fn expand(&self, text: &Rope, start: usize, end: usize, replacement: &str) -> String {
+        // the surrounding lines are included for the sake of `^`, `$` and `\b`
+        let line_start = text.offset_of_line(text.line_of_offset(start));
+        let line_end = Cursor::new(text, end).next::<LinesMetric>().unwrap_or(text.len());
+        let haystack = text.slice_to_string(line_start, line_end);
+        let input = Input::new(&haystack)
+            .span(start - line_start..end - line_start)
+            .anchored(Anchored::Yes);
+        let mut caps = self.fallback.create_captures();
+        self.fallback.search_captures(&input, &mut caps);
+        caps.interpolate_string(&haystack, replacement)
+    }
+
+    /// Forgets a search suspended by `find_regex_progress`. This must be
+    /// called if the text changes while a search is suspended.
+    pub fn reset(&mut self) {
+        self.scan = None;
+    }
+
+    /// Whether the search suspended by `find_regex_progress` is part way
+    /// through a possible match, or has found one that may get longer.
+    pub fn is_match_pending(&self) -> bool {
+        self.scan.as_ref().is_some_and(|scan| scan.match_end.is_some() || !scan.state.is_start())
+    }
+
+    fn start_scan(&mut self, pos: usize, text: &Rope) -> Result<RegexScan, usize> {
+        let look_behind = if pos > 0 { Some(text.byte_at(pos - 1)) } else { None };
+        let config = start::Config::new().anchored(Anchored::No).look_behind(look_behind);
+        let state = self.forward.start_state(&mut self.forward_cache, &config)
+            .map_err(|_| pos)?;
+        Ok(RegexScan { start: pos, pos, state, match_end: None })
+    }
+
+    /// Feeds the rest of the cursor's leaf to the forward DFA, and moves the
+    /// cursor to the next leaf.
+    fn scan_leaf(&mut self, cursor: &mut Cursor<RopeInfo>, scan: &mut RegexScan) -> ScanStep {
+        let (leaf, offset) = match cursor.get_leaf() {
+            Some(leaf) => leaf,
+            None => return ScanStep::Done,
+        };
+        let pos = cursor.pos();
+        for (i, &b) in leaf.as_bytes()[offset..].iter().enumerate() {
+            scan.state = match self.forward.next_state(&mut self.forward_cache, scan.state, b) {
+                Ok(state) => state,
+                Err(_) => return ScanStep::Quit(pos + i),
+            };
+            if scan.state.is_tagged() {
+                // matches are reported one byte late, after the DFA has seen
+                // what follows them
+                if scan.state.is_match() {
+                    scan.match_end = Some(pos + i);
+                } else if scan.state.is_dead() {
+                    return ScanStep::Done;
+                } else if scan.state.is_quit() {
+                    return ScanStep::Quit(pos + i);
+                }
+            }
+        }
+        let leaf_end = pos - offset + leaf.len();
+        if leaf_end == cursor.root().len() {
+            scan.state = match self.forward.next_eoi_state(&mut self.forward_cache, scan.state) {
+                Ok(state) => state,
+                Err(_) => return ScanStep::Quit(leaf_end),
+            };
+            if scan.state.is_match() {
+                scan.match_end = Some(leaf_end);
+            }
+            return ScanStep::Done;
+        }
+        let _ = cursor.next_leaf();
+        ScanStep::Continue
+    }
+
+    /// Finds the start of the match ending at `end`, which is the earliest
+    /// one that doesn't start before `bound`.
+    fn match_start(&mut self, text: &Rope, bound: usize, end: usize) -> Result<usize, usize> {
+        let look_behind = if end < text.len() { Some(text.byte_at(end)) } else { None };
+        let config = start::Config::new().anchored(Anchored::Yes).look_behind(look_behind);
+        let mut state = self.reverse.start_state(&mut self.reverse_cache, &config)
+            .map_err(|_| end)?;
+        let mut start = None;
+        let mut cursor = Cursor::new(text, end);
+        let (mut leaf, mut offset) = cursor.get_leaf().ok_or(end)?;
+        let mut leaf_start = end - offset;
+        'leaves: loop {
+            for (i, &b) in leaf.as_bytes()[..offset].iter().enumerate().rev() {
+                if leaf_start + i < bound {
+                    break 'leaves;
+                }
+                state = self.reverse.next_state(&mut self.reverse_cache, state, b)
+                    .map_err(|_| leaf_start + i)?;
+                if state.is_tagged() {
+                    if state.is_match() {
+                        start = Some(leaf_start + i + 1);
+                    } else if state.is_dead() {
+                        return start.ok_or(end);
+                    } else if state.is_quit() {
+                        return Err(leaf_start + i);
+                    }
+                }
+            }
+            if leaf_start <= bound {
+                break;
+            }
+            leaf = cursor.prev_leaf().ok_or(end)?.0;
+            offset = leaf.len();
+            leaf_start -= leaf.len();
+        }
+        state = if bound > 0 {
+            self.reverse.next_state(&mut self.reverse_cache, state, text.byte_at(bound - 1))
+        } else {
+            self.reverse.next_eoi_state(&mut self.reverse_cache, state)
+        }.map_err(|_| bound)?;
+        if state.is_match() {
+            start = Some(bound);
+        }
+        start.ok_or(end)
+    }
+
+    fn finish_scan(&mut self, cursor: &mut Cursor<RopeInfo>, scan: &RegexScan) -> FindResult {
+        let end = match scan.match_end {
+            Some(end) => end,
+            None => return FindResult::NotFound,
+        };
+        match self.match_start(cursor.root(), scan.start, end) {
+            Ok(start) => {
+                cursor.set(end);
+                FindResult::Found(start)
+            }
+            Err(at) => self.find_fallback(cursor, scan.start, at),
+        }
+    }
+
+    /// Searches from `start` to the end of the line containing `at`, where
+    /// the DFAs gave up, with an engine that handles everything.
+    fn find_fallback(&mut self, cursor: &mut Cursor<RopeInfo>, start: usize, at: usize)
+        -> FindResult
+    {
+        let text = cursor.root();
+        let line_start = text.offset_of_line(text.line_of_offset(start));
+        let line_end = Cursor::new(text, at).next::<LinesMetric>().unwrap_or(text.len());
+        let haystack = text.slice_to_string(line_start, line_end);
+        let input = Input::new(&haystack).span(start - line_start..haystack.len());
+        match self.fallback.search(&input) {
+            Some(m) => {
+                cursor.set(line_start + m.end());
+                FindResult::Found(line_start + m.start())
+            }
+            None if line_end == text.len() => FindResult::NotFound,
+            None => {
+                cursor.set(line_end);
+                FindResult::TryAgain
+            }
+        }
+    }
+}
+
+/// Finds the first match of a regular expression in the rope referenced by
+/// the cursor, starting at the current location of the cursor, and returns
+/// its start.
+///
+/// On success, the cursor is updated to immediately follow the match. If
+/// the match is empty, the caller should advance the cursor before searching
+/// again. On failure, the cursor's position is indeterminate.
+pub fn find_regex(cursor: &mut Cursor<RopeInfo>, matcher: &mut RegexMatcher) -> Option<usize> {
+    matcher.reset();
+    loop {
+        match find_regex_progress(cursor, matcher, usize::MAX) {
+            FindResult::Found(pos) => return Some(pos),
+            FindResult::NotFound => return None,
+            FindResult::TryAgain => (),
+        }
+    }
+}
+
+/// A variant of [`find_regex`][find_regex] that gives up once the cursor is
+/// past `limit` with no match pending, returning `None`. A match which is
+/// under way by then is followed to its end, however far that is.
+///
+/// [find_regex]: fn.find_regex.html
+pub fn find_regex_before(cursor: &mut Cursor<RopeInfo>, matcher: &mut RegexMatcher,
+    limit: usize) -> Option<usize>
+{
+    matcher.reset();
+    loop {
+        match find_regex_progress(cursor, matcher, 1) {
+            FindResult::Found(pos) => return Some(pos),
+            FindResult::NotFound => return None,
+            FindResult::TryAgain if cursor.pos() > limit && !matcher.is_match_pending() => {
+                matcher.reset();
+                return None;
+            }
+            FindResult::TryAgain => (),
+        }
+    }
+}
+
+/// A variant of [`find_regex`][find_regex] that makes a bounded amount of
+/// progress, then either returns or suspends (returning `TryAgain`). A step
+/// is scanning one leaf.
+///
+/// The state of a suspended search is kept in the matcher, and picked up if
+/// the cursor is at the position where it was left. A match in progress can
+/// span any number of calls.
+///
+/// [find_regex]: fn.find_regex.html
+pub fn find_regex_progress(cursor: &mut Cursor<RopeInfo>, matcher: &mut RegexMatcher,
+    num_steps: usize) -> FindResult
+{
+    let pos = cursor.pos();
+    let mut scan = match matcher.scan.take() {
+        Some(scan) if scan.pos == pos => scan,
+        _ => match matcher.start_scan(pos, cursor.root()) {
+            Ok(scan) => scan,
+            Err(at) => return matcher.find_fallback(cursor, pos, at),
+        }
+    };
+    for _ in 0..num_steps {
+        match matcher.scan_leaf(cursor, &mut scan) {
+            ScanStep::Continue => (),
+            ScanStep::Done => return matcher.finish_scan(cursor, &scan),
+            ScanStep::Quit(at) => return matcher.find_fallback(cursor, scan.start, at),
+        }
+    }
+    scan.pos = cursor.pos();
+    matcher.scan = Some(scan);
+    FindResult::TryAgain
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use super::CaseMatching::{Exact, CaseInsensitive};
-    use tree::Cursor;
-    use rope::Rope;
+    use super::CaseMatching::{Exact, CaseInsensitive, Normalized, IgnoreAccents};
+    use rope::tree::Cursor;
+    use rope::rope::Rope;
 
     #[test]
     fn find_small() {
@@ -325,6 +768,49 @@ mod tests {
         assert_eq!(find(&mut c, CaseInsensitive, "i"), Some(2));
     }
 
+    #[test]
+    fn find_normalized() {
+        // "café" with a precomposed é, then with e and a combining acute accent
+        let a = Rope::from("caf\u{e9} cafe\u{301} cafe");
+        let mut c = Cursor::new(&a, 0);
+        assert_eq!(find(&mut c, Normalized, "cafe\u{301}"), Some(0));
+        assert_eq!(c.pos(), 5);
+        assert_eq!(find(&mut c, Normalized, "caf\u{e9}"), Some(6));
+        assert_eq!(c.pos(), 12);
+        assert_eq!(find(&mut c, Normalized, "caf\u{e9}"), None);
+        // the accent is part of the last character
+        c.set(0);
+        assert_eq!(find(&mut c, Normalized, "cafe"), Some(13));
+        c.set(0);
+        assert_eq!(find(&mut c, Normalized, "Cafe"), None);
+        // marks are compared in canonical order, whichever order they're in
+        let a = Rope::from("x\u{1ea1}\u{301}y a\u{301}\u{323}");
+        let mut c = Cursor::new(&a, 0);
+        assert_eq!(find(&mut c, Normalized, "a\u{301}\u{323}"), Some(1));
+        assert_eq!(c.pos(), 6);
+        assert_eq!(find(&mut c, Normalized, "a\u{301}\u{323}"), Some(8));
+        assert_eq!(c.pos(), 13);
+    }
+
+    #[test]
+    fn find_ignore_accents() {
+        let a = Rope::from("R\u{e9}sum\u{e9} resume RESUM\u{c9} re\u{301}sume\u{301}s");
+        let mut c = Cursor::new(&a, 0);
+        assert_eq!(find(&mut c, IgnoreAccents, "resume"), Some(0));
+        assert_eq!(c.pos(), 8);
+        assert_eq!(find(&mut c, IgnoreAccents, "resume"), Some(9));
+        assert_eq!(find(&mut c, IgnoreAccents, "resume"), Some(16));
+        assert_eq!(c.pos(), 23);
+        // the match covers the trailing accent
+        assert_eq!(find(&mut c, IgnoreAccents, "resume"), Some(24));
+        assert_eq!(c.pos(), 34);
+        assert_eq!(find(&mut c, IgnoreAccents, "resume"), None);
+        c.set(0);
+        assert_eq!(find(&mut c, IgnoreAccents, "R\u{c9}SUM\u{c9}"), Some(0));
+        c.set(0);
+        assert_eq!(find(&mut c, IgnoreAccents, "\u{301}"), Some(26));
+    }
+
     #[test]
     fn compare_cursor_str_small() {
         let a = Rope::from("Löwe 老虎 Léopard");
@@ -360,4 +846,119 @@ mod tests {
         assert!(compare_cursor_str(&mut c, &s[2000..]));
         assert_eq!(c.pos(), s.len());
     }
+
+    fn regex_matches(text: &Rope, pattern: &str, cm: CaseMatching) -> Vec<(usize, usize)> {
+        let mut matcher = RegexMatcher::new(pattern, cm).unwrap();
+        let mut c = Cursor::new(text, 0);
+        let mut result = Vec::new();
+        while let Some(start) = find_regex(&mut c, &mut matcher) {
+            let end = c.pos();
+            result.push((start, end));
+            if end == text.len() {
+                break;
+            }
+            if start == end {
+                c.set(end + 1);
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn find_regex_small() {
+        let a = Rope::from("Löwe 老虎 Léopard");
+        assert_eq!(regex_matches(&a, "L[^ ]*", Exact), vec![(0, 5), (13, 21)]);
+        assert_eq!(regex_matches(&a, "l.", CaseInsensitive), vec![(0, 3), (13, 16)]);
+        assert_eq!(regex_matches(&a, "老+", Exact), vec![(6, 9)]);
+        assert_eq!(regex_matches(&a, "x", Exact), vec![]);
+        assert!(RegexMatcher::new("(", Exact).is_err());
+    }
+
+    #[test]
+    fn find_regex_lines() {
+        let a = Rope::from("one\ntwo\nthree");
+        assert_eq!(regex_matches(&a, "^t", Exact), vec![(4, 5), (8, 9)]);
+        assert_eq!(regex_matches(&a, "e$", Exact), vec![(2, 3), (12, 13)]);
+        assert_eq!(regex_matches(&a, "o\\nt", Exact), vec![(6, 9)]);
+        // a search starting mid-line sees the text before it
+        let mut matcher = RegexMatcher::new("^.", Exact).unwrap();
+        let mut c = Cursor::new(&a, 1);
+        assert_eq!(find_regex(&mut c, &mut matcher), Some(4));
+    }
+
+    #[test]
+    fn find_regex_across_leaves() {
+        let s = format!("{}needle{}", "a".repeat(2000), "b".repeat(2000));
+        let a = Rope::from(&s);
+        assert_eq!(regex_matches(&a, "a+needleb+", Exact), vec![(0, s.len())]);
+        assert_eq!(regex_matches(&a, "a{3}needle", Exact), vec![(1997, 2006)]);
+        assert_eq!(regex_matches(&a, "ab|ba", Exact), vec![]);
+    }
+
+    #[test]
+    fn find_regex_unicode_word() {
+        // non-ASCII text makes the DFAs give up on \b
+        let a = Rope::from("über uber\nfür\nfoo für");
+        assert_eq!(regex_matches(&a, r"\bf\w+", Exact), vec![(11, 15), (16, 19), (20, 24)]);
+        assert_eq!(regex_matches(&a, r"\bber", Exact), vec![]);
+    }
+
+    #[test]
+    fn find_regex_compare() {
+        let s = (0..500).map(|i| format!("line {} with Ünïcode {}\n", i, i * 7)).collect::<String>();
+        let a = Rope::from(&s);
+        for &pattern in &["\\d+\n", "^line 4\\d*", "[ÜW]", "code \\d+$", "\\bw", "(?s)t.{3}\\d"] {
+            let expected = meta::Regex::new(&format!("(?m){}", pattern)).unwrap()
+                .find_iter(&s)
+                .map(|m| (m.start(), m.end()))
+                .collect::<Vec<_>>();
+            assert_eq!(regex_matches(&a, pattern, Exact), expected, "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn regex_expand() {
+        let a = Rope::from("let x = 1;\nlet y = 2;");
+        let matcher = RegexMatcher::new(r"^let (?P<name>\w+) = (\d+)", Exact).unwrap();
+        assert_eq!("y: 2", matcher.expand(&a, 11, 20, "${name}: $2"));
+        assert_eq!("$x", matcher.expand(&a, 0, 9, "$$$1"));
+    }
+
+    #[test]
+    fn find_regex_resumable() {
+        let s = format!("{}xyz{}", "a".repeat(5000), "a".repeat(1000));
+        let a = Rope::from(&s);
+        let mut matcher = RegexMatcher::new("a+xyz", Exact).unwrap();
+        let mut c = Cursor::new(&a, 0);
+        let mut steps = 0;
+        let start = loop {
+            steps += 1;
+            match find_regex_progress(&mut c, &mut matcher, 1) {
+                FindResult::Found(start) => break start,
+                FindResult::NotFound => panic!("not found"),
+                FindResult::TryAgain => (),
+            }
+        };
+        assert!(steps > 2);
+        assert_eq!(0, start);
+        assert_eq!(5003, c.pos());
+    }
+
+    #[test]
+    fn find_regex_limit() {
+        let s = format!("{}xyz{}", "a".repeat(5000), "b".repeat(5000));
+        let a = Rope::from(&s);
+        // a match under way at the limit is followed to its end
+        let mut matcher = RegexMatcher::new("a+xyz", Exact).unwrap();
+        let mut c = Cursor::new(&a, 0);
+        assert_eq!(Some(0), find_regex_before(&mut c, &mut matcher, 10));
+        assert_eq!(5003, c.pos());
+        // but the search stops soon after the limit if there's none
+        for pattern in &["c+d", r"\bcd"] {
+            let mut matcher = RegexMatcher::new(pattern, Exact).unwrap();
+            let mut c = Cursor::new(&a, 5003);
+            assert_eq!(None, find_regex_before(&mut c, &mut matcher, 5010), "{}", pattern);
+            assert!(c.pos() < 8000, "{}", pattern);
+        }
+    }
 }
@@ -0,0 +1,431 @@
+// Copyright 2018 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A module for representing a set of folds: disjoint ranges of a text
+//! that are collapsed, and hidden from view.
+//!
+//! Like `Breaks`, the folds are stored in a tree measured in the base units
+//! of the text, so they can be carried through the deltas applied to it.
+//! A fold from `start` to `end` hides the positions after `start`, up to
+//! and including `end`. Each fold also records the number of lines it
+//! hides, so that the tree can convert between the lines of the text and
+//! the lines shown.
+
+use std::cmp::min;
+use std::mem;
+
+use rope::delta::{Delta, Transformer};
+use rope::interval::Interval;
+use rope::tree::{Cursor, Leaf, Metric, Node, NodeInfo, TreeBuilder};
+
+const MIN_LEAF: usize = 16;
+const MAX_LEAF: usize = 32;
+
+pub type Folds = Node<FoldsInfo>;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FoldsLeaf {
+    len: usize,  // measured in base units
+    // start and end relative to start of leaf, and lines hidden; sorted and disjoint
+    folds: Vec<(usize, usize, usize)>,
+}
+
+#[derive(Clone)]
+pub struct FoldsInfo {
+    n_folds: usize,
+    hidden_lines: usize,
+}
+
+impl Leaf for FoldsLeaf {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn is_ok_child(&self) -> bool {
+        self.folds.len() >= MIN_LEAF
+    }
+
+    fn push_maybe_split(&mut self, other: &FoldsLeaf, iv: Interval) -> Option<FoldsLeaf> {
+        let (start, end) = iv.start_end();
+        // a fold is never split, so one cut by the interval is dropped
+        for &(fold_start, fold_end, hidden) in &other.folds {
+            if start <= fold_start && fold_end <= end {
+                self.folds.push((fold_start - start + self.len, fold_end - start + self.len,
+                                 hidden));
+            }
+        }
+        self.len += min(end, other.len()) - start;
+
+        if self.folds.len() <= MAX_LEAF {
+            None
+        } else {
+            // split at the start of a fold, so that folds stay in one leaf
+            let splitpoint = self.folds.len() / 2;  // number of folds
+            let splitpoint_units = self.folds[splitpoint].0;
+            let mut new = self.folds.split_off(splitpoint);
+            for fold in &mut new {
+                fold.0 -= splitpoint_units;
+                fold.1 -= splitpoint_units;
+            }
+            let new_len = self.len - splitpoint_units;
+            self.len = splitpoint_units;
+            Some(FoldsLeaf {
+                len: new_len,
+                folds: new,
+            })
+        }
+    }
+}
+
+impl NodeInfo for FoldsInfo {
+    type L = FoldsLeaf;
+
+    fn accumulate(&mut self, other: &Self) {
+        self.n_folds += other.n_folds;
+        self.hidden_lines += other.hidden_lines;
+    }
+
+    fn compute_info(l: &FoldsLeaf) -> FoldsInfo {
+        FoldsInfo {
+            n_folds: l.folds.len(),
+            hidden_lines: l.folds.iter().map(|fold| fold.2).sum(),
+        }
+    }
+}
+
+/// A metric whose boundaries are the starts of the folds.
+#[derive(Copy, Clone)]
+pub struct FoldsMetric(());
+
+impl Metric<FoldsInfo> for FoldsMetric {
+    fn measure(info: &FoldsInfo, _: usize) -> usize {
+        info.n_folds
+    }
+
+    fn to_base_units(l: &FoldsLeaf, in_measured_units: usize) -> usize {
+        if in_measured_units > l.folds.len() {
+            l.len + 1
+        } else if in_measured_units == 0 {
+            0
+        } else {
+            l.folds[in_measured_units - 1].0
+        }
+    }
+
+    fn from_base_units(l: &FoldsLeaf, in_base_units: usize) -> usize {
+        l.folds.iter().take_while(|fold| fold.0 <= in_base_units).count()
+    }
+
+    fn is_boundary(l: &FoldsLeaf, offset: usize) -> bool {
+        l.folds.iter().any(|fold| fold.0 == offset)
+    }
+
+    fn prev(l: &FoldsLeaf, offset: usize) -> Option<usize> {
+        l.folds.iter().rev().map(|fold| fold.0).find(|&start| start < offset)
+    }
+
+    fn next(l: &FoldsLeaf, offset: usize) -> Option<usize> {
+        l.folds.iter().map(|fold| fold.0).find(|&start| start > offset)
+    }
+
+    fn can_fragment() -> bool { false }
+}
+
+/// A metric counting the lines hidden by the folds. All of a fold's lines
+/// are counted just after its start.
+#[derive(Copy, Clone)]
+pub struct HiddenLinesMetric(());
+
+impl Metric<FoldsInfo> for HiddenLinesMetric {
+    fn measure(info: &FoldsInfo, _: usize) -> usize {
+        info.hidden_lines
+    }
+
+    fn to_base_units(l: &FoldsLeaf, in_measured_units: usize) -> usize {
+        if in_measured_units == 0 {
+            return 0;
+        }
+        let mut hidden = 0;
+        for fold in &l.folds {
+            hidden += fold.2;
+            if hidden >= in_measured_units {
+                return fold.0 + 1;
+            }
+        }
+        l.len + 1
+    }
+
+    fn from_base_units(l: &FoldsLeaf, in_base_units: usize) -> usize {
+        l.folds.iter().take_while(|fold| fold.0 < in_base_units).map(|fold| fold.2).sum()
+    }
+
+    fn is_boundary(l: &FoldsLeaf, offset: usize) -> bool {
+        l.folds.iter().any(|fold| fold.0 + 1 == offset)
+    }
+
+    fn prev(l: &FoldsLeaf, offset: usize) -> Option<usize> {
+        l.folds.iter().rev().map(|fold| fold.0 + 1).find(|&boundary| boundary < offset)
+    }
+
+    fn next(l: &FoldsLeaf, offset: usize) -> Option<usize> {
+        l.folds.iter().map(|fold| fold.0 + 1).find(|&boundary| boundary > offset)
+    }
+
+    fn can_fragment() -> bool { false }
+}
+
+#[derive(Copy, Clone)]
+pub struct FoldsBaseMetric(());
+
+impl Metric<FoldsInfo> for FoldsBaseMetric {
+    fn measure(_: &FoldsInfo, len: usize) -> usize {
+        len
+    }
+
+    fn to_base_units(_: &FoldsLeaf, in_measured_units: usize) -> usize {
+        in_measured_units
+    }
+
+    fn from_base_units(_: &FoldsLeaf, in_base_units: usize) -> usize {
+        in_base_units
+    }
+
+    fn is_boundary(l: &FoldsLeaf, offset: usize) -> bool {
+        FoldsMetric::is_boundary(l, offset)
+    }
+
+    fn prev(l: &FoldsLeaf, offset: usize) -> Option<usize> {
+        FoldsMetric::prev(l, offset)
+    }
+
+    fn next(l: &FoldsLeaf, offset: usize) -> Option<usize> {
+        FoldsMetric::next(l, offset)
+    }
+
+    fn can_fragment() -> bool { true }
+}
+
+pub struct FoldsBuilder {
+    b: TreeBuilder<FoldsInfo>,
+    leaf: FoldsLeaf,
+    len: usize,
+    total_len: usize,
+}
+
+impl FoldsBuilder {
+    pub fn new(total_len: usize) -> FoldsBuilder {
+        FoldsBuilder {
+            b: TreeBuilder::new(),
+            leaf: FoldsLeaf::default(),
+            len: 0,
+            total_len,
+        }
+    }
+
+    // Precondition: folds must be added in order, and must not overlap.
+    pub fn add_fold(&mut self, start: usize, end: usize, hidden_lines: usize) {
+        if self.leaf.folds.len() == MAX_LEAF {
+            let mut leaf = mem::take(&mut self.leaf);
+            leaf.len = start - self.len;
+            self.len = start;
+            self.b.push(Node::from_leaf(leaf));
+        }
+        self.leaf.folds.push((start - self.len, end - self.len, hidden_lines));
+    }
+
+    pub fn build(mut self) -> Folds {
+        self.leaf.len = self.total_len - self.len;
+        self.b.push(Node::from_leaf(self.leaf));
+        self.b.build()
+    }
+}
+
+pub struct FoldsIter<'a> {
+    cursor: Cursor<'a, FoldsInfo>,
+    ix: usize,
+}
+
+impl Folds {
+    /// The number of folds.
+    pub fn count(&self) -> usize {
+        self.measure::<FoldsMetric>()
+    }
+
+    /// The number of lines hidden by all of the folds.
+    pub fn hidden_lines(&self) -> usize {
+        self.measure::<HiddenLinesMetric>()
+    }
+
+    /// The number of lines hidden by the folds starting before `offset`.
+    pub fn hidden_lines_before(&self, offset: usize) -> usize {
+        self.convert_metrics::<FoldsBaseMetric, HiddenLinesMetric>(offset)
+    }
+
+    /// Iterates over the start and end of each fold, in order.
+    pub fn iter(&self) -> FoldsIter {
+        self.iter_from(0)
+    }
+
+    /// Iterates over the start and end of each fold starting at or after
+    /// `offset`, in order.
+    pub fn iter_from(&self, offset: usize) -> FoldsIter {
+        let cursor = Cursor::new(self, offset);
+        let ix = cursor.get_leaf().map_or(0, |(leaf, pos)|
+            leaf.folds.iter().take_while(|fold| fold.0 < pos).count());
+        FoldsIter { cursor, ix }
+    }
+
+    /// The fold hiding `offset`, if any: the one starting before it and
+    /// ending at or after it.
+    pub fn fold_containing(&self, offset: usize) -> Option<(usize, usize)> {
+        // a fold is in one leaf, which holds the position before `offset`
+        let position = offset.checked_sub(1)?;
+        let (leaf, pos) = Cursor::new(self, position).get_leaf()?;
+        let leaf_start = position - pos;
+        leaf.folds.iter()
+            .find(|fold| fold.0 <= pos && pos < fold.1)
+            .map(|fold| (fold.0 + leaf_start, fold.1 + leaf_start))
+    }
+
+    /// Carries the folds through an edit. A fold keeps the text inserted at
+    /// either of its ends, and is dropped if all of its text is deleted.
+    /// Only the folds touching the edited region are moved; `f`, called with
+    /// the new start and end of each, returns the lines it hides, or `None`
+    /// if it should be dropped.
+    pub fn transform<N, F>(&self, delta: &Delta<N>, mut f: F) -> Folds
+        where N: NodeInfo,
+              F: FnMut(usize, usize) -> Option<usize>
+    {
+        let (iv, new_len) = delta.summary();
+        let (iv_start, iv_end) = iv.start_end();
+        let touched = self.fold_containing(iv_start).into_iter()
+            .chain(self.iter_from(iv_start).take_while(|&(start, _)| start <= iv_end))
+            .collect::<Vec<_>>();
+        let start = touched.first().map_or(iv_start, |fold| min(fold.0, iv_start));
+        let end = touched.last().map_or(iv_end, |fold| fold.1.max(iv_end));
+        let shift = |offset: usize| offset + new_len - (iv_end - iv_start);
+
+        let mut transformer = Transformer::new(delta);
+        let mut builder = FoldsBuilder::new(shift(end) - start);
+        for (fold_start, fold_end) in touched {
+            let new_start = transformer.transform(fold_start, true);
+            let new_end = transformer.transform(fold_end, true);
+            if new_start < new_end {
+                if let Some(hidden_lines) = f(new_start, new_end) {
+                    builder.add_fold(new_start - start, new_end - start, hidden_lines);
+                }
+            }
+        }
+        let mut folds = self.clone();
+        folds.edit(Interval::new_closed_closed(start, end), builder.build());
+        folds
+    }
+}
+
+impl<'a> Iterator for FoldsIter<'a> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        loop {
+            let (leaf, start_pos) = self.cursor.get_leaf()?;
+            if self.ix < leaf.folds.len() {
+                let leaf_start = self.cursor.pos() - start_pos;
+                let (start, end, _) = leaf.folds[self.ix];
+                self.ix += 1;
+                return Some((start + leaf_start, end + leaf_start));
+            }
+            self.cursor.next_leaf()?;
+            self.ix = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rope::rope::Rope;
+
+    fn build(len: usize, folds: &[(usize, usize)]) -> Folds {
+        let mut builder = FoldsBuilder::new(len);
+        for &(start, end) in folds {
+            builder.add_fold(start, end, 1);
+        }
+        builder.build()
+    }
+
+    #[test]
+    fn iter_and_lookup() {
+        let folds = (0..100).map(|i| (i * 10 + 2, i * 10 + 8)).collect::<Vec<_>>();
+        let tree = build(1000, &folds);
+        assert_eq!(100, tree.count());
+        assert_eq!(folds, tree.iter().collect::<Vec<_>>());
+        assert_eq!(folds[50..], tree.iter_from(502).collect::<Vec<_>>()[..]);
+        assert_eq!(folds[51..], tree.iter_from(512).collect::<Vec<_>>()[..]);
+        assert_eq!(None, tree.fold_containing(502));
+        assert_eq!(Some((502, 508)), tree.fold_containing(503));
+        assert_eq!(Some((502, 508)), tree.fold_containing(508));
+        assert_eq!(None, tree.fold_containing(509));
+        assert_eq!(0, build(10, &[]).iter().count());
+    }
+
+    #[test]
+    fn hidden_lines() {
+        let mut builder = FoldsBuilder::new(1000);
+        for i in 0..100 {
+            builder.add_fold(i * 10 + 2, i * 10 + 8, i);
+        }
+        let tree = builder.build();
+        assert_eq!(4950, tree.hidden_lines());
+        assert_eq!(0, tree.hidden_lines_before(0));
+        assert_eq!(0, tree.hidden_lines_before(2));
+        assert_eq!(0, tree.hidden_lines_before(3));
+        assert_eq!(1, tree.hidden_lines_before(13));
+        assert_eq!(1225, tree.hidden_lines_before(502));
+        assert_eq!(1275, tree.hidden_lines_before(503));
+        assert_eq!(4950, tree.hidden_lines_before(1000));
+    }
+
+    #[test]
+    fn transform() {
+        let text = Rope::from("a {\n  b\n}\nc {\n  d\n}\n");
+        let tree = build(text.len(), &[(3, 7), (13, 17)]);
+        // typing at the start of a fold, inside the first one, and
+        // deleting all of the second
+        let mut builder = ::rope::delta::Builder::new(text.len());
+        builder.replace(Interval::new_closed_open(3, 3), Rope::from(" x"));
+        builder.replace(Interval::new_closed_open(6, 6), Rope::from("yz"));
+        builder.delete(Interval::new_closed_open(13, 17));
+        let delta = builder.build();
+        let tree = tree.transform(&delta, |_, _| Some(1));
+        assert_eq!(vec![(5, 11)], tree.iter().collect::<Vec<_>>());
+        assert_eq!(delta.new_document_len(), tree.len());
+    }
+
+    #[test]
+    fn transform_shifts_untouched_folds() {
+        let folds = (0..100).map(|i| (i * 10 + 2, i * 10 + 8)).collect::<Vec<_>>();
+        let tree = build(1000, &folds);
+        let delta = Delta::simple_edit(Interval::new_closed_open(500, 505), Rope::from("x"), 1000);
+        let mut touched = Vec::new();
+        let tree = tree.transform(&delta, |start, end| {
+            touched.push((start, end));
+            Some(1)
+        });
+        assert_eq!(vec![(501, 504)], touched);
+        assert_eq!(996, tree.len());
+        assert_eq!(100, tree.count());
+        assert_eq!(Some((508, 514)), tree.iter_from(505).next());
+        assert_eq!(Some((492, 498)), tree.iter_from(492).next());
+    }
+}
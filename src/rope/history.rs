@@ -0,0 +1,365 @@
+// Copyright 2018 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The building blocks of the binary format used to save an `Engine`'s
+//! edit history with `Engine::write_history`, so that undo survives
+//! closing and reopening a file.
+//!
+//! Integers are written as unsigned LEB128 varints, so the small numbers
+//! that make up most of a history take a single byte. A `Subset` is
+//! written as its number of segments followed by the length and count of
+//! each one, and a set of undo groups as its size followed by the
+//! differences between consecutive (sorted) members.
+
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use rope::rope::Rope;
+use rope::multiset::{Subset, SubsetBuilder};
+
+/// Identifies a file as a saved history.
+pub const MAGIC: &[u8] = b"XIHIST";
+
+/// The version of the format written by this module. Readers reject
+/// any other version.
+pub const VERSION: u8 = 1;
+
+/// An error encountered while reading a saved history.
+#[derive(Debug)]
+pub enum HistoryError {
+    /// The data does not start with the history magic number.
+    BadMagic,
+    /// The history was written in a format version we can't read.
+    UnsupportedVersion(u8),
+    /// The history is truncated or internally inconsistent.
+    Malformed(&'static str),
+    /// The history was saved for different text than the one given.
+    TextMismatch,
+    /// An Io Error
+    Io(io::Error),
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::HistoryError::*;
+        match *self {
+            BadMagic => write!(f, "not a saved history"),
+            UnsupportedVersion(v) => write!(f, "unsupported history version: {}", v),
+            Malformed(s) => write!(f, "malformed history: {}", s),
+            TextMismatch => write!(f, "history does not match the file contents"),
+            Io(ref e) => write!(f, "error reading history: {:?}", e),
+        }
+    }
+}
+
+impl Error for HistoryError {}
+
+impl From<io::Error> for HistoryError {
+    fn from(src: io::Error) -> HistoryError {
+        if src.kind() == io::ErrorKind::UnexpectedEof {
+            HistoryError::Malformed("unexpected end of data")
+        } else {
+            HistoryError::Io(src)
+        }
+    }
+}
+
+/// A stable 64-bit hash (FNV-1a) of the text, used to check that a history
+/// is being attached to the text it was saved with.
+pub fn text_hash(text: &Rope) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for chunk in text.iter_chunks(0, text.len()) {
+        for &b in chunk.as_bytes() {
+            hash ^= u64::from(b);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+pub struct HistoryWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> HistoryWriter<W> {
+    pub fn new(inner: W) -> HistoryWriter<W> {
+        HistoryWriter { inner }
+    }
+
+    pub fn write_header(&mut self) -> io::Result<()> {
+        self.inner.write_all(MAGIC)?;
+        self.write_u8(VERSION)
+    }
+
+    pub fn write_u8(&mut self, val: u8) -> io::Result<()> {
+        self.inner.write_all(&[val])
+    }
+
+    pub fn write_u64_fixed(&mut self, val: u64) -> io::Result<()> {
+        let mut buf = [0u8; 8];
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = (val >> (i * 8)) as u8;
+        }
+        self.inner.write_all(&buf)
+    }
+
+    pub fn write_varint(&mut self, mut val: u64) -> io::Result<()> {
+        loop {
+            let byte = (val & 0x7f) as u8;
+            val >>= 7;
+            if val == 0 {
+                return self.write_u8(byte);
+            }
+            self.write_u8(byte | 0x80)?;
+        }
+    }
+
+    pub fn write_usize(&mut self, val: usize) -> io::Result<()> {
+        self.write_varint(val as u64)
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.write_usize(bytes.len())?;
+        self.inner.write_all(bytes)
+    }
+
+    pub fn write_rope(&mut self, rope: &Rope) -> io::Result<()> {
+        self.write_usize(rope.len())?;
+        for chunk in rope.iter_chunks(0, rope.len()) {
+            self.inner.write_all(chunk.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn write_subset(&mut self, subset: &Subset) -> io::Result<()> {
+        self.write_usize(subset.segments.len())?;
+        for seg in &subset.segments {
+            self.write_usize(seg.len)?;
+            self.write_usize(seg.count)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_groups(&mut self, groups: &BTreeSet<usize>) -> io::Result<()> {
+        self.write_usize(groups.len())?;
+        let mut last = 0;
+        for &group in groups {
+            self.write_usize(group - last)?;
+            last = group;
+        }
+        Ok(())
+    }
+}
+
+pub struct HistoryReader<R: Read> {
+    inner: R,
+}
+
+impl<R: Read> HistoryReader<R> {
+    pub fn new(inner: R) -> HistoryReader<R> {
+        HistoryReader { inner }
+    }
+
+    /// Checks the magic number and version.
+    pub fn read_header(&mut self) -> Result<(), HistoryError> {
+        let mut magic = [0u8; 6];
+        self.inner.read_exact(&mut magic).map_err(|_| HistoryError::BadMagic)?;
+        if magic != MAGIC {
+            return Err(HistoryError::BadMagic);
+        }
+        match self.read_u8()? {
+            VERSION => Ok(()),
+            v => Err(HistoryError::UnsupportedVersion(v)),
+        }
+    }
+
+    /// Checks that all of the data has been consumed.
+    pub fn read_end(&mut self) -> Result<(), HistoryError> {
+        let mut buf = [0u8; 1];
+        match self.inner.read(&mut buf)? {
+            0 => Ok(()),
+            _ => Err(HistoryError::Malformed("trailing data")),
+        }
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, HistoryError> {
+        let mut buf = [0u8; 1];
+        self.inner.read_exact(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    pub fn read_u64_fixed(&mut self) -> Result<u64, HistoryError> {
+        let mut buf = [0u8; 8];
+        self.inner.read_exact(&mut buf)?;
+        Ok(buf.iter().rev().fold(0, |val, &b| (val << 8) | u64::from(b)))
+    }
+
+    pub fn read_varint(&mut self) -> Result<u64, HistoryError> {
+        let mut val = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.read_u8()?;
+            // only the lowest bit of the tenth byte fits in a u64
+            if shift == 63 && byte & 0x7e != 0 {
+                return Err(HistoryError::Malformed("integer out of range"));
+            }
+            val |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(val);
+            }
+        }
+        Err(HistoryError::Malformed("varint too long"))
+    }
+
+    pub fn read_usize(&mut self) -> Result<usize, HistoryError> {
+        let val = self.read_varint()?;
+        if val > usize::MAX as u64 {
+            return Err(HistoryError::Malformed("integer out of range"));
+        }
+        Ok(val as usize)
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, HistoryError> {
+        let val = self.read_varint()?;
+        if val > u64::from(u32::MAX) {
+            return Err(HistoryError::Malformed("integer out of range"));
+        }
+        Ok(val as u32)
+    }
+
+    pub fn read_bytes(&mut self) -> Result<Vec<u8>, HistoryError> {
+        let len = self.read_usize()?;
+        // don't trust `len` for the allocation, the data might be truncated
+        let mut buf = Vec::new();
+        self.inner.by_ref().take(len as u64).read_to_end(&mut buf)?;
+        if buf.len() != len {
+            return Err(HistoryError::Malformed("unexpected end of data"));
+        }
+        Ok(buf)
+    }
+
+    pub fn read_rope(&mut self) -> Result<Rope, HistoryError> {
+        let bytes = self.read_bytes()?;
+        let s = String::from_utf8(bytes)
+            .map_err(|_| HistoryError::Malformed("text is not valid UTF-8"))?;
+        Ok(Rope::from(s))
+    }
+
+    pub fn read_subset(&mut self) -> Result<Subset, HistoryError> {
+        let n_segments = self.read_usize()?;
+        let mut sb = SubsetBuilder::new();
+        for _ in 0..n_segments {
+            let len = self.read_usize()?;
+            let count = self.read_usize()?;
+            if len == 0 {
+                return Err(HistoryError::Malformed("empty subset segment"));
+            }
+            sb.push_segment(len, count);
+        }
+        Ok(sb.build())
+    }
+
+    pub fn read_groups(&mut self) -> Result<BTreeSet<usize>, HistoryError> {
+        let n_groups = self.read_usize()?;
+        let mut groups = BTreeSet::new();
+        let mut last = 0usize;
+        for _ in 0..n_groups {
+            last = last.checked_add(self.read_usize()?)
+                .ok_or(HistoryError::Malformed("integer out of range"))?;
+            groups.insert(last);
+        }
+        Ok(groups)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use rope::history::*;
+    use rope::multiset::SubsetBuilder;
+
+    #[test]
+    fn varint_roundtrip() {
+        let vals = [0, 1, 0x7f, 0x80, 0x3fff, 0x4000, u64::from(u32::MAX),
+                    u64::MAX];
+        let mut buf = Vec::new();
+        {
+            let mut w = HistoryWriter::new(&mut buf);
+            for &v in &vals {
+                w.write_varint(v).unwrap();
+            }
+        }
+        assert_eq!(1, buf.iter().take_while(|&&b| b == 0).count());
+        let mut r = HistoryReader::new(&buf[..]);
+        for &v in &vals {
+            assert_eq!(v, r.read_varint().unwrap());
+        }
+        r.read_end().unwrap();
+
+        // a value that doesn't fit in a u64 is an error
+        let mut r = HistoryReader::new(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+                                          0x03][..]);
+        match r.read_varint() {
+            Err(HistoryError::Malformed(_)) => (),
+            other => panic!("expected malformed error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn subset_and_groups_roundtrip() {
+        let mut sb = SubsetBuilder::new();
+        sb.push_segment(5, 0);
+        sb.push_segment(300, 2);
+        sb.push_segment(1, 0);
+        let subset = sb.build();
+        let groups = [0, 3, 4, 1000].iter().cloned().collect::<BTreeSet<usize>>();
+
+        let mut buf = Vec::new();
+        {
+            let mut w = HistoryWriter::new(&mut buf);
+            w.write_header().unwrap();
+            w.write_subset(&subset).unwrap();
+            w.write_groups(&groups).unwrap();
+        }
+        let mut r = HistoryReader::new(&buf[..]);
+        r.read_header().unwrap();
+        assert_eq!(subset, r.read_subset().unwrap());
+        assert_eq!(groups, r.read_groups().unwrap());
+        r.read_end().unwrap();
+
+        // truncated data is an error, not a panic
+        let mut r = HistoryReader::new(&buf[..buf.len() - 1]);
+        r.read_header().unwrap();
+        r.read_subset().unwrap();
+        match r.read_groups() {
+            Err(HistoryError::Malformed(_)) => (),
+            other => panic!("expected malformed error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn header() {
+        let mut r = HistoryReader::new(&b"XIHIST\x02"[..]);
+        match r.read_header() {
+            Err(HistoryError::UnsupportedVersion(2)) => (),
+            other => panic!("expected unsupported version, got {:?}", other),
+        }
+        let mut r = HistoryReader::new(&b"hello"[..]);
+        match r.read_header() {
+            Err(HistoryError::BadMagic) => (),
+            other => panic!("expected bad magic, got {:?}", other),
+        }
+    }
+}
@@ -115,8 +115,8 @@ pub enum CountMatcher {
 impl CountMatcher {
     fn matches(self, seg: &Segment) -> bool {
         match self {
-            CountMatcher::Zero => (seg.count == 0),
-            CountMatcher::NonZero => (seg.count != 0),
+            CountMatcher::Zero => seg.count == 0,
+            CountMatcher::NonZero => seg.count != 0,
             CountMatcher::All => true
         }
     }
@@ -347,12 +347,12 @@ impl fmt::Debug for Subset {
                 } else if s.count == 1 {
                     '#'
                 } else if s.count <= 9 {
-                    ((s.count as u8) + ('0' as u8)) as char
+                    ((s.count as u8) + b'0') as char
                 } else {
                     '+'
                 };
                 for _ in 0..s.len {
-                    try!(write!(f, "{}", chr));
+                    write!(f, "{}", chr)?;
                 }
             }
             Ok(())
@@ -374,7 +374,7 @@ impl<'a> Iterator for RangeIter<'a> {
     type Item = (usize, usize);
 
     fn next(&mut self) -> Option<(usize, usize)> {
-        while let Some(seg) = self.seg_iter.next() {
+        for seg in self.seg_iter.by_ref() {
             self.consumed += seg.len;
             if self.matcher.matches(seg) {
                 return Some((self.consumed - seg.len, self.consumed));
@@ -466,11 +466,11 @@ impl<'a> Mapper<'a> {
         while i >= self.cur_range.1 {
             self.subset_amount_consumed += self.cur_range.1 - self.cur_range.0;
             self.cur_range = match self.range_iter.next() {
-                Some(range) => range.clone(),
+                Some(range) => range,
                 // past the end of the subset
                 None => {
                     // ensure we don't try to consume any more
-                    self.cur_range = (usize::max_value(), usize::max_value());
+                    self.cur_range = (usize::MAX, usize::MAX);
                     return self.subset_amount_consumed
                 }
             }
@@ -487,10 +487,10 @@ impl<'a> Mapper<'a> {
 
 #[cfg(test)]
 mod tests {
-    use multiset::*;
-    use test_helpers::find_deletions;
+    use rope::multiset::*;
+    use rope::test_helpers::find_deletions;
 
-    const TEST_STR: &'static str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+    const TEST_STR: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
 
     #[test]
     fn test_apply() {
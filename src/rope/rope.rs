@@ -17,6 +17,8 @@
 
 use std::cmp::{min,max};
 use std::borrow::Cow;
+use std::iter::{self, once};
+use std::ops::Add;
 use std::str::FromStr;
 use std::string::ParseError;
 use std::fmt;
@@ -29,12 +31,56 @@ use rope::interval::Interval;
 use bytecount;
 use memchr::memchr;
 
+use unicode::{self, GraphemeBreakState, grapheme_property};
+
 const MIN_LEAF: usize = 511;
 const MAX_LEAF: usize = 1024;
 
 /// The main rope data structure. It is implemented as a b-tree with simply
 /// `String` as the leaf type. The base metric counts UTF-8 code units
 /// (bytes) and has boundaries at code points.
+///
+/// A [rope](https://en.wikipedia.org/wiki/Rope_(data_structure)) is a data structure
+/// for strings, specialized for incremental editing operations. Most operations
+/// (such as insert, delete, substring) are O(log n). Ropes are immutable
+/// (also known as [persistent](https://en.wikipedia.org/wiki/Persistent_data_structure)),
+/// and if there are many copies of similar strings, the common parts are shared.
+///
+/// Also note: in addition to the `From` traits described below, this module
+/// implements `From<Rope> for String` and `From<&Rope> for String`, for easy
+/// conversions in both directions.
+///
+/// # Examples
+///
+/// Create a `Rope` from a `String`:
+///
+/// ```rust
+/// # use xi_core::rope::Rope;
+/// let a = Rope::from("hello ");
+/// let b = Rope::from("world");
+/// assert_eq!("hello world", String::from(a.clone() + b.clone()));
+/// assert!("hello world" == a + b);
+/// ```
+///
+/// Get a slice of a `Rope`:
+///
+/// ```rust
+/// # use xi_core::rope::Rope;
+/// let a = Rope::from("hello world");
+/// let b = a.slice(1, 9);
+/// assert_eq!("ello wor", String::from(&b));
+/// let c = b.slice(1, 7);
+/// assert_eq!("llo wo", String::from(c));
+/// ```
+///
+/// Replace part of a `Rope`:
+///
+/// ```rust
+/// # use xi_core::rope::Rope;
+/// let mut a = Rope::from("hello world");
+/// a.edit_str(1, 9, "era");
+/// assert_eq!("herald", String::from(a));
+/// ```
 pub type Rope = Node<RopeInfo>;
 
 /// Represents a transform from one rope to another.
@@ -71,6 +117,8 @@ impl Leaf for String {
 #[derive(Clone, Copy)]
 pub struct RopeInfo {
     lines: usize,
+    utf16_size: usize,
+    chars: usize,
 }
 
 impl NodeInfo for RopeInfo {
@@ -78,17 +126,23 @@ impl NodeInfo for RopeInfo {
 
     fn accumulate(&mut self, other: &Self) {
         self.lines += other.lines;
+        self.utf16_size += other.utf16_size;
+        self.chars += other.chars;
     }
 
     fn compute_info(s: &String) -> Self {
         RopeInfo {
             lines: count_newlines(s),
+            utf16_size: count_utf16_code_units(s.as_bytes()),
+            chars: count_chars(s.as_bytes()),
         }
     }
 
     fn identity() -> Self {
         RopeInfo {
             lines: 0,
+            utf16_size: 0,
+            chars: 0,
         }
     }
 }
@@ -153,6 +207,7 @@ pub fn len_utf8_from_first_byte(b: u8) -> usize {
 }
 
 #[derive(Clone, Copy)]
+#[allow(dead_code)]
 pub struct LinesMetric(usize);  // number of lines
 
 impl Metric<RopeInfo> for LinesMetric {
@@ -197,12 +252,109 @@ impl Metric<RopeInfo> for LinesMetric {
     fn can_fragment() -> bool { true }
 }
 
+/// Measures a rope in UTF-16 code units, as used by many platform text
+/// APIs. Boundaries are at code points; a code point outside the BMP
+/// counts as two units.
+#[derive(Clone, Copy)]
+pub struct Utf16CodeUnitsMetric(());
+
+impl Metric<RopeInfo> for Utf16CodeUnitsMetric {
+    fn measure(info: &RopeInfo, _: usize) -> usize {
+        info.utf16_size
+    }
+
+    fn is_boundary(s: &String, offset: usize) -> bool {
+        s.is_char_boundary(offset)
+    }
+
+    fn to_base_units(s: &String, in_measured_units: usize) -> usize {
+        let mut cur_len_utf16 = 0;
+        let mut cur_len_utf8 = 0;
+        for c in s.chars() {
+            if cur_len_utf16 >= in_measured_units {
+                break;
+            }
+            cur_len_utf16 += c.len_utf16();
+            cur_len_utf8 += c.len_utf8();
+        }
+        cur_len_utf8
+    }
+
+    fn from_base_units(s: &String, in_base_units: usize) -> usize {
+        count_utf16_code_units(&s.as_bytes()[..in_base_units])
+    }
+
+    fn prev(s: &String, offset: usize) -> Option<usize> {
+        BaseMetric::prev(s, offset)
+    }
+
+    fn next(s: &String, offset: usize) -> Option<usize> {
+        BaseMetric::next(s, offset)
+    }
+
+    fn can_fragment() -> bool {
+        false
+    }
+}
+
+/// Measures a rope in Unicode scalar values (Rust `char`s).
+#[derive(Clone, Copy)]
+pub struct CharsMetric(());
+
+impl Metric<RopeInfo> for CharsMetric {
+    fn measure(info: &RopeInfo, _: usize) -> usize {
+        info.chars
+    }
+
+    fn is_boundary(s: &String, offset: usize) -> bool {
+        s.is_char_boundary(offset)
+    }
+
+    fn to_base_units(s: &String, in_measured_units: usize) -> usize {
+        s.char_indices().nth(in_measured_units)
+            .map(|(offset, _)| offset)
+            .unwrap_or(s.len())
+    }
+
+    fn from_base_units(s: &String, in_base_units: usize) -> usize {
+        count_chars(&s.as_bytes()[..in_base_units])
+    }
+
+    fn prev(s: &String, offset: usize) -> Option<usize> {
+        BaseMetric::prev(s, offset)
+    }
+
+    fn next(s: &String, offset: usize) -> Option<usize> {
+        BaseMetric::next(s, offset)
+    }
+
+    fn can_fragment() -> bool {
+        false
+    }
+}
+
 // Low level functions
 
 fn count_newlines(s: &str) -> usize {
     bytecount::count(s.as_bytes(), b'\n')
 }
 
+/// Counts the UTF-16 code units needed to encode a UTF-8 byte string.
+fn count_utf16_code_units(s: &[u8]) -> usize {
+    let mut utf16_count = 0;
+    for &b in s {
+        // every code point has exactly one non-continuation byte
+        if (b as i8) >= -0x40 { utf16_count += 1; }
+        // and code points outside the BMP need a surrogate pair
+        if b >= 0xf0 { utf16_count += 1; }
+    }
+    utf16_count
+}
+
+fn count_chars(s: &[u8]) -> usize {
+    bytecount::num_chars(s)
+}
+
 fn find_leaf_split_for_bulk(s: &str) -> usize {
     find_leaf_split(s, MIN_LEAF)
 }
@@ -211,7 +363,8 @@ fn find_leaf_split_for_merge(s: &str) -> usize {
     find_leaf_split(s, max(MIN_LEAF, s.len() - MAX_LEAF))
 }
 
-// Try to split at newline boundary (leaning left), if not, then split at codepoint
+// Try to split at newline boundary (leaning left), if not, then split at
+// grapheme cluster boundary, and as a last resort at codepoint
 fn find_leaf_split(s: &str, minsplit: usize) -> usize {
     let mut splitpoint = min(MAX_LEAF, s.len() - MIN_LEAF);
     match s.as_bytes()[minsplit - 1..splitpoint].iter().rposition(|&c| c == b'\n') {
@@ -220,7 +373,13 @@ fn find_leaf_split(s: &str, minsplit: usize) -> usize {
             while !s.is_char_boundary(splitpoint) {
                 splitpoint -= 1;
             }
-            splitpoint
+            if unicode::is_grapheme_boundary(s, splitpoint) {
+                return splitpoint;
+            }
+            match unicode::prev_grapheme_boundary(s, splitpoint) {
+                Some(pos) if pos >= minsplit => pos,
+                _ => splitpoint,
+            }
         }
     }
 }
@@ -237,6 +396,9 @@ impl FromStr for Rope {
 }
 
 impl Rope {
+    /// Edit the string, replacing the byte range [`start`..`end`] with `new`.
+    ///
+    /// Time complexity: O(log n)
     pub fn edit_str(&mut self, start: usize, end: usize, new: &str) {
         let mut b = TreeBuilder::new();
         // TODO: may make this method take the iv directly
@@ -248,6 +410,19 @@ impl Rope {
         *self = b.build();
     }
 
+    /// Returns a new Rope with the contents of the byte range [`start`..`end`].
+    ///
+    /// Time complexity: O(log n)
+    pub fn slice(&self, start: usize, end: usize) -> Rope {
+        self.subseq(Interval::new_closed_open(start, end))
+    }
+
+    /// Append `s` to the string.
+    pub fn push_str(&mut self, s: &str) {
+        let len = self.len();
+        self.edit_str(len, len, s);
+    }
+
     // encourage callers to use Cursor instead?
 
     /// Determine whether `offset` lies on a codepoint boundary.
@@ -268,15 +443,71 @@ impl Rope {
         cursor.next::<BaseMetric>()
     }
 
-    // graphemes should probably be developed as a cursor-based interface
+    /// Determine whether `offset` lies on an extended grapheme cluster
+    /// boundary (UAX #29). The start and end of the rope are boundaries.
+    pub fn is_grapheme_boundary(&self, offset: usize) -> bool {
+        if offset == 0 || offset == self.len() {
+            return true;
+        }
+        if !self.is_codepoint_boundary(offset) {
+            return false;
+        }
+        let mut state = self.grapheme_state_at(offset);
+        let mut cursor = Cursor::new(self, offset);
+        let c = cursor.next_codepoint().unwrap();
+        state.next(grapheme_property(c))
+    }
+
+    /// Return the offset of the grapheme cluster boundary before `offset`.
     pub fn prev_grapheme_offset(&self, offset: usize) -> Option<usize> {
-        // TODO: actual grapheme analysis
-        self.prev_codepoint_offset(offset)
+        // walk back to a boundary that doesn't depend on the text before
+        // it, then find the last boundary before `offset` going forward
+        let mut cursor = Cursor::new(self, offset);
+        let mut next = grapheme_property(cursor.prev_codepoint()?);
+        while let Some(c) = cursor.prev_codepoint() {
+            let prev = grapheme_property(c);
+            if unicode::is_unconditional_grapheme_break(prev, next) {
+                cursor.next_codepoint();
+                break;
+            }
+            next = prev;
+        }
+        let mut pos = cursor.pos();
+        // walking off the start of the rope leaves the cursor invalid
+        cursor.set(pos);
+        let mut state = self.grapheme_state_at(pos);
+        let mut boundary = pos;
+        while pos < offset {
+            let c = cursor.next_codepoint().unwrap();
+            if state.next(grapheme_property(c)) {
+                boundary = pos;
+            }
+            pos += c.len_utf8();
+        }
+        Some(boundary)
     }
 
+    /// Return the offset of the grapheme cluster boundary after `offset`.
     pub fn next_grapheme_offset(&self, offset: usize) -> Option<usize> {
-        // TODO: actual grapheme analysis
-        self.next_codepoint_offset(offset)
+        if offset >= self.len() {
+            return None;
+        }
+        let mut state = self.grapheme_state_at(offset);
+        let mut cursor = Cursor::new(self, offset);
+        let c = cursor.next_codepoint().unwrap();
+        state.next(grapheme_property(c));
+        while let Some(c) = cursor.next_codepoint() {
+            if state.next(grapheme_property(c)) {
+                return Some(cursor.pos() - c.len_utf8());
+            }
+        }
+        Some(self.len())
+    }
+
+    // The grapheme break state at `offset`, looking back as far as needed.
+    fn grapheme_state_at(&self, offset: usize) -> GraphemeBreakState {
+        let mut cursor = Cursor::new(self, offset);
+        GraphemeBreakState::from_preceding(iter::from_fn(|| cursor.prev_codepoint()))
     }
 
     /// Return the line number corresponding to the byte index `offset`.
@@ -289,6 +520,38 @@ impl Rope {
         self.convert_metrics::<BaseMetric, LinesMetric>(offset)
     }
 
+    /// Return the byte offset corresponding to the UTF-16 code unit offset
+    /// `utf16_offset`.
+    ///
+    /// Time complexity: O(log n)
+    pub fn offset_of_utf16(&self, utf16_offset: usize) -> usize {
+        self.convert_metrics::<Utf16CodeUnitsMetric, BaseMetric>(utf16_offset)
+    }
+
+    /// Return the offset in UTF-16 code units corresponding to the byte
+    /// index `offset`.
+    ///
+    /// Time complexity: O(log n)
+    pub fn utf16_of_offset(&self, offset: usize) -> usize {
+        self.convert_metrics::<BaseMetric, Utf16CodeUnitsMetric>(offset)
+    }
+
+    /// Return the byte offset corresponding to the char (Unicode scalar
+    /// value) index `char_offset`.
+    ///
+    /// Time complexity: O(log n)
+    pub fn offset_of_char(&self, char_offset: usize) -> usize {
+        self.convert_metrics::<CharsMetric, BaseMetric>(char_offset)
+    }
+
+    /// Return the char (Unicode scalar value) index corresponding to the
+    /// byte index `offset`.
+    ///
+    /// Time complexity: O(log n)
+    pub fn char_of_offset(&self, offset: usize) -> usize {
+        self.convert_metrics::<BaseMetric, CharsMetric>(offset)
+    }
+
     /// Return the byte offset corresponding to the line number `line`.
     ///
     /// The line number is 0-based.
@@ -315,7 +578,7 @@ impl Rope {
     pub fn iter_chunks(&self, start: usize, end: usize) -> ChunkIter {
         ChunkIter {
             cursor: Cursor::new(self, start),
-            end: end,
+            end,
         }
     }
     /// An iterator over the raw lines. The lines, except the last, include the
@@ -417,12 +680,30 @@ impl From<Rope> for String {
     }
 }
 
-impl<'a> From<&'a Rope> for String {
+impl From<&Rope> for String {
     fn from(r: &Rope) -> String {
         r.slice_to_string(0, r.len())
     }
 }
 
+impl Add<Rope> for Rope {
+    type Output = Rope;
+    fn add(self, rhs: Rope) -> Rope {
+        Rope::concat(self, rhs)
+    }
+}
+
+// Not sure I want to commit to this, it shadows Add<String>, which might be optimized
+// to reuse the string's allocation.
+impl<T: AsRef<str>> Add<T> for Rope {
+    type Output = Rope;
+    fn add(self, rhs: T) -> Rope {
+        let mut lhs = self;
+        lhs.push_str(rhs.as_ref());
+        lhs
+    }
+}
+
 impl fmt::Debug for Rope {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         if f.alternate() {
@@ -535,9 +816,92 @@ impl<'a> Iterator for Lines<'a> {
     }
 }
 
+// Equality and related
+
+fn eq_chunks<'a, T: Iterator<Item=&'a str>, U: Iterator<Item=&'a str>>(mut a: T, mut b: U) -> bool {
+    let mut a_chunk = &b""[..];
+    let mut b_chunk = &b""[..];
+    loop {
+        if a_chunk.is_empty() {
+            if let Some(s) = a.next() { a_chunk = s.as_bytes(); }
+        }
+        if b_chunk.is_empty() {
+            if let Some(s) = b.next() { b_chunk = s.as_bytes(); }
+        }
+        let len = min(a_chunk.len(), b_chunk.len());
+        if len == 0 {
+            return a_chunk.is_empty() && b_chunk.is_empty();
+        }
+        if a_chunk[..len] != b_chunk[..len] {
+            return false;
+        }
+        a_chunk = &a_chunk[len..];
+        b_chunk = &b_chunk[len..];
+    }
+}
+
+impl PartialEq for Rope {
+    fn eq(&self, rhs: &Rope) -> bool {
+        self.len() == rhs.len()
+            && eq_chunks(self.iter_chunks(0, self.len()), rhs.iter_chunks(0, rhs.len()))
+    }
+}
+
+impl Eq for Rope {
+}
+
+impl PartialEq<str> for Rope {
+    fn eq(&self, rhs: &str) -> bool {
+        self.len() == rhs.len() && eq_chunks(self.iter_chunks(0, self.len()), once(rhs))
+    }
+}
+
+impl PartialEq<&str> for Rope {
+    fn eq(&self, rhs: &&str) -> bool {
+        self.len() == rhs.len() && eq_chunks(self.iter_chunks(0, self.len()), once(*rhs))
+    }
+}
+
+impl PartialEq<String> for Rope {
+    fn eq(&self, rhs: &String) -> bool {
+        self.len() == rhs.len()
+            && eq_chunks(self.iter_chunks(0, self.len()), once(rhs.as_str()))
+    }
+}
+
+impl<'a> PartialEq<Cow<'a, str>> for Rope {
+    fn eq(&self, rhs: &Cow<'a, str>) -> bool {
+        self.len() == rhs.len() && eq_chunks(self.iter_chunks(0, self.len()), once(&**rhs))
+    }
+}
+
+impl PartialEq<Rope> for str {
+    fn eq(&self, rhs: &Rope) -> bool {
+        rhs == self
+    }
+}
+
+impl PartialEq<Rope> for &str {
+    fn eq(&self, rhs: &Rope) -> bool {
+        rhs == self
+    }
+}
+
+impl PartialEq<Rope> for String {
+    fn eq(&self, rhs: &Rope) -> bool {
+        rhs == self
+    }
+}
+
+impl<'a> PartialEq<Rope> for Cow<'a, str> {
+    fn eq(&self, rhs: &Rope) -> bool {
+        rhs == self
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use rope::Rope;
+    use rope::rope::*;
 
     #[test]
     fn replace_small() {
@@ -554,13 +918,11 @@ mod tests {
         assert_eq!(Some(1), a.prev_codepoint_offset(3));
         assert_eq!(Some(0), a.prev_codepoint_offset(1));
         assert_eq!(None, a.prev_codepoint_offset(0));
-        /* TODO
         let b = a.slice(1, 10);
         assert_eq!(Some(5), b.prev_codepoint_offset(9));
         assert_eq!(Some(2), b.prev_codepoint_offset(5));
         assert_eq!(Some(0), b.prev_codepoint_offset(2));
         assert_eq!(None, b.prev_codepoint_offset(0));
-        */
     }
 
     #[test]
@@ -571,12 +933,251 @@ mod tests {
         assert_eq!(Some(3), a.next_codepoint_offset(1));
         assert_eq!(Some(1), a.next_codepoint_offset(0));
         assert_eq!(None, a.next_codepoint_offset(10));
-        /* TODO
         let b = a.slice(1, 10);
         assert_eq!(Some(9), b.next_codepoint_offset(5));
         assert_eq!(Some(5), b.next_codepoint_offset(2));
         assert_eq!(Some(2), b.next_codepoint_offset(0));
         assert_eq!(None, b.next_codepoint_offset(9));
-        */
+    }
+
+    #[test]
+    fn line_of_offset_small() {
+        let a = Rope::from("a\nb\nc");
+        assert_eq!(0, a.line_of_offset(0));
+        assert_eq!(0, a.line_of_offset(1));
+        assert_eq!(1, a.line_of_offset(2));
+        assert_eq!(1, a.line_of_offset(3));
+        assert_eq!(2, a.line_of_offset(4));
+        assert_eq!(2, a.line_of_offset(5));
+        let b = a.slice(2, 4);
+        assert_eq!(0, b.line_of_offset(0));
+        assert_eq!(0, b.line_of_offset(1));
+        assert_eq!(1, b.line_of_offset(2));
+    }
+
+    #[test]
+    fn offset_of_line_small() {
+        let a = Rope::from("a\nb\nc");
+        assert_eq!(0, a.offset_of_line(0));
+        assert_eq!(2, a.offset_of_line(1));
+        assert_eq!(4, a.offset_of_line(2));
+        assert_eq!(5, a.offset_of_line(3));
+        let b = a.slice(2, 4);
+        assert_eq!(0, b.offset_of_line(0));
+        assert_eq!(2, b.offset_of_line(1));
+    }
+
+    #[test]
+    fn lines_raw_small() {
+        let a = Rope::from("a\nb\nc");
+        assert_eq!(vec!["a\n", "b\n", "c"], a.lines_raw(0, a.len()).collect::<Vec<_>>());
+
+        let a = Rope::from("a\nb\n");
+        assert_eq!(vec!["a\n", "b\n"], a.lines_raw(0, a.len()).collect::<Vec<_>>());
+
+        let a = Rope::from("\n");
+        assert_eq!(vec!["\n"], a.lines_raw(0, a.len()).collect::<Vec<_>>());
+
+        let a = Rope::from("");
+        assert_eq!(0, a.lines_raw(0, a.len()).count());
+    }
+
+    #[test]
+    fn lines_med() {
+        let mut a = String::new();
+        let mut b = String::new();
+        let line_len = MAX_LEAF + MIN_LEAF - 1;
+        for _ in 0..line_len {
+            a.push('a');
+            b.push('b');
+        }
+        a.push('\n');
+        b.push('\n');
+        let r = Rope::from(&a[..MAX_LEAF]);
+        let r = r + Rope::from(String::from(&a[MAX_LEAF..]) + &b[..MIN_LEAF]);
+        let r = r + Rope::from(&b[MIN_LEAF..]);
+
+        assert_eq!(vec![a.as_str(), b.as_str()], r.lines_raw(0, r.len()).collect::<Vec<_>>());
+        assert_eq!(vec![&a[..line_len], &b[..line_len]], r.lines(0, r.len()).collect::<Vec<_>>());
+        assert_eq!(String::from(&r).lines().collect::<Vec<_>>(),
+                   r.lines(0, r.len()).collect::<Vec<_>>());
+
+        // additional tests for line indexing
+        assert_eq!(a.len(), r.offset_of_line(1));
+        assert_eq!(r.len(), r.offset_of_line(2));
+        assert_eq!(0, r.line_of_offset(a.len() - 1));
+        assert_eq!(1, r.line_of_offset(a.len()));
+        assert_eq!(1, r.line_of_offset(r.len() - 1));
+        assert_eq!(2, r.line_of_offset(r.len()));
+    }
+
+    #[test]
+    fn lines_small() {
+        for s in &["a\nb\nc", "a\nb\n", "\n", "", "a\r\nb\r\nc", "a\rb\rc"] {
+            let a = Rope::from(s);
+            assert_eq!(s.lines().collect::<Vec<_>>(), a.lines(0, a.len()).collect::<Vec<_>>());
+        }
+        let a = Rope::from("a\r\nb\r\nc");
+        assert_eq!(vec!["a", "b", "c"], a.lines(0, a.len()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn append_large() {
+        let mut a = Rope::from("");
+        let mut b = String::new();
+        for i in 0..5_000 {
+            let c = i.to_string() + "\n";
+            b.push_str(&c);
+            a = a + c;
+        }
+        assert_eq!(b, String::from(a));
+    }
+
+    #[test]
+    fn utf16_and_char_metrics_small() {
+        // 1, 2, 3 and 4 byte code points; the last is outside the BMP.
+        let a = Rope::from("a\u{00A1}\u{4E00}\u{1F4A9}");
+        assert_eq!(5, a.measure::<Utf16CodeUnitsMetric>());
+        assert_eq!(4, a.measure::<CharsMetric>());
+
+        let offsets = [0, 1, 3, 6, 10];
+        let utf16 = [0, 1, 2, 3, 5];
+        for i in 0..offsets.len() {
+            assert_eq!(utf16[i], a.utf16_of_offset(offsets[i]));
+            assert_eq!(offsets[i], a.offset_of_utf16(utf16[i]));
+            assert_eq!(i, a.char_of_offset(offsets[i]));
+            assert_eq!(offsets[i], a.offset_of_char(i));
+        }
+    }
+
+    #[test]
+    fn utf16_and_char_metrics_large() {
+        let piece = "a\u{00A1}\u{4E00}\u{1F4A9}\n";
+        let mut s = String::new();
+        for _ in 0..1_000 {
+            s.push_str(piece);
+        }
+        let a = Rope::from(&s);
+        assert_eq!(s.encode_utf16().count(), a.measure::<Utf16CodeUnitsMetric>());
+        assert_eq!(s.chars().count(), a.measure::<CharsMetric>());
+
+        let mut utf16 = 0;
+        for (chars, (offset, c)) in s.char_indices().enumerate() {
+            assert_eq!(utf16, a.utf16_of_offset(offset));
+            assert_eq!(offset, a.offset_of_utf16(utf16));
+            assert_eq!(chars, a.char_of_offset(offset));
+            assert_eq!(offset, a.offset_of_char(chars));
+            utf16 += c.len_utf16();
+        }
+        assert_eq!(s.len(), a.offset_of_utf16(utf16));
+        assert_eq!(utf16, a.utf16_of_offset(s.len()));
+        assert_eq!(s.len(), a.offset_of_char(s.chars().count()));
+    }
+
+    #[test]
+    fn grapheme_offsets_small() {
+        // e + combining acute, a flag, and a family emoji
+        let a = Rope::from("e\u{0301}\u{1F1FA}\u{1F1F8}\u{1F469}\u{200D}\u{1F467}x");
+        assert_eq!(Some(3), a.next_grapheme_offset(0));
+        assert_eq!(Some(11), a.next_grapheme_offset(3));
+        assert_eq!(Some(22), a.next_grapheme_offset(11));
+        assert_eq!(Some(23), a.next_grapheme_offset(22));
+        assert_eq!(None, a.next_grapheme_offset(23));
+
+        assert_eq!(Some(22), a.prev_grapheme_offset(23));
+        assert_eq!(Some(11), a.prev_grapheme_offset(22));
+        assert_eq!(Some(3), a.prev_grapheme_offset(11));
+        assert_eq!(Some(0), a.prev_grapheme_offset(3));
+        assert_eq!(None, a.prev_grapheme_offset(0));
+
+        assert!(!a.is_grapheme_boundary(1));
+        assert!(!a.is_grapheme_boundary(7));
+        assert!(!a.is_grapheme_boundary(15));
+
+        // an odd run of regional indicators pairs up from its start
+        let b = Rope::from("x\u{1F1FA}\u{1F1F8}\u{1F1EB}");
+        assert_eq!(Some(9), b.prev_grapheme_offset(13));
+        assert_eq!(Some(1), b.prev_grapheme_offset(9));
+        assert_eq!(Some(0), b.prev_grapheme_offset(1));
+    }
+
+    #[test]
+    fn grapheme_offsets_across_leaves() {
+        let x = "x".repeat(1000);
+        // combining mark at the start of the second leaf
+        let a = Rope::from(x.clone() + "e") + Rope::from("\u{0301}".to_owned() + &x);
+        assert!(!a.is_grapheme_boundary(1001));
+        assert_eq!(Some(1003), a.next_grapheme_offset(1000));
+        assert_eq!(Some(1000), a.prev_grapheme_offset(1003));
+
+        // regional indicators pair up across the leaf boundary
+        let a = Rope::from(x.clone() + "\u{1F1FA}") + Rope::from("\u{1F1F8}\u{1F1EB}".to_owned() + &x);
+        assert!(a.is_grapheme_boundary(1008));
+        assert_eq!(Some(1008), a.next_grapheme_offset(1000));
+        assert_eq!(Some(1012), a.next_grapheme_offset(1008));
+        assert_eq!(Some(1008), a.prev_grapheme_offset(1012));
+        assert_eq!(Some(1000), a.prev_grapheme_offset(1008));
+
+        // a ZWJ sequence joined across the leaf boundary
+        let a = Rope::from("a".repeat(600) + "\u{1F468}")
+            + Rope::from("\u{200D}\u{1F469}".to_owned() + &"b".repeat(600));
+        assert_eq!(Some(600), a.next_grapheme_offset(599));
+        assert_eq!(Some(611), a.next_grapheme_offset(600));
+        assert_eq!(Some(600), a.prev_grapheme_offset(611));
+        assert!(!a.is_grapheme_boundary(604));
+        assert!(!a.is_grapheme_boundary(607));
+    }
+
+    #[test]
+    fn grapheme_offsets_large() {
+        let family = "\u{1F469}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        let s = family.repeat(200);
+        let a = Rope::from(&s);
+        let mut offset = 0;
+        for i in 1..201 {
+            offset = a.next_grapheme_offset(offset).unwrap();
+            assert_eq!(i * family.len(), offset);
+        }
+        assert_eq!(None, a.next_grapheme_offset(offset));
+        assert_eq!(Some(199 * family.len()), a.prev_grapheme_offset(offset));
+    }
+
+    #[test]
+    fn eq_small() {
+        let a = Rope::from("a");
+        let a2 = Rope::from("a");
+        let b = Rope::from("b");
+        let empty = Rope::from("");
+        assert!(a == a2);
+        assert!(a != b);
+        assert!(a != empty);
+        assert!(empty == empty);
+        assert!(a.slice(0, 0) == empty);
+        assert!(a == "a");
+        assert!("b" == b);
+    }
+
+    #[test]
+    fn eq_med() {
+        let mut a = String::new();
+        let mut b = String::new();
+        let line_len = MAX_LEAF + MIN_LEAF - 1;
+        for _ in 0..line_len {
+            a.push('a');
+            b.push('b');
+        }
+        a.push('\n');
+        b.push('\n');
+        let r = Rope::from(&a[..MAX_LEAF]);
+        let r = r + Rope::from(String::from(&a[MAX_LEAF..]) + &b[..MIN_LEAF]);
+        let r = r + Rope::from(&b[MIN_LEAF..]);
+
+        let a_rope = Rope::from(&a);
+        let b_rope = Rope::from(&b);
+        assert!(r != a_rope);
+        assert!(r.slice(0, a.len()) == a_rope);
+        assert!(r.slice(a.len(), r.len()) == b_rope);
+        assert!(r == a_rope.clone() + b_rope.clone());
+        assert!(r != b_rope + a_rope);
     }
 }
@@ -104,7 +104,7 @@ impl<T: Clone + Default> NodeInfo for SpansInfo<T> {
         }
         SpansInfo {
             n_spans: l.spans.len(),
-            iv: iv,
+            iv,
             phantom: PhantomData,
         }
     }
@@ -123,7 +123,7 @@ impl<T: Clone + Default> SpansBuilder<T> {
             b: TreeBuilder::new(),
             leaf: SpansLeaf::default(),
             len: 0,
-            total_len: total_len,
+            total_len,
         }
     }
 
@@ -131,14 +131,14 @@ impl<T: Clone + Default> SpansBuilder<T> {
     // Maybe take Span struct instead of separate iv, data args?
     pub fn add_span(&mut self, iv: Interval, data: T) {
         if self.leaf.spans.len() == MAX_LEAF {
-            let mut leaf = mem::replace(&mut self.leaf, SpansLeaf::default());
+            let mut leaf = mem::take(&mut self.leaf);
             leaf.len = iv.start() - self.len;
             self.len = iv.start();
             self.b.push(Node::from_leaf(leaf));
         }
         self.leaf.spans.push(Span {
             iv: iv.translate_neg(self.len),
-            data: data,
+            data,
         })
     }
 
@@ -157,12 +157,11 @@ pub struct SpanIter<'a, T: 'a + Clone + Default> {
 }
 
 impl<T: Clone + Default> Spans<T> {
-    /// Perform operational transformation on a spans object intended to be edited into
-    /// a sequence at the given offset.
-
     // Note: this implementation is not efficient for very large Spans objects, as it
     // traverses all spans linearly. A more sophisticated approach would be to traverse
     // the tree, and only delve into subtrees that are transformed.
+    /// Perform operational transformation on a spans object intended to be edited into
+    /// a sequence at the given offset.
     pub fn transform<N: NodeInfo>(&self, base_start: usize, base_end: usize,
             xform: &mut Transformer<N>) -> Self {
         // TODO: maybe should take base as an Interval and figure out "after" from that
@@ -283,6 +282,13 @@ impl<T: Clone + Default> Spans<T> {
         sb.build()
     }
 
+    /// Iterates over the data of the spans containing `offset`, without
+    /// building a subsequence for it.
+    pub fn data_at<'a>(&'a self, offset: usize) -> impl Iterator<Item=&'a T> + 'a {
+        let (leaf, pos) = Cursor::new(self, offset).get_leaf().unwrap();
+        leaf.spans.iter().filter(move |span| span.iv.contains(pos)).map(|span| &span.data)
+    }
+
     // possible future: an iterator that takes an interval, so results are the same as
     // taking a subseq on the spans object. Would require specialized Cursor.
     pub fn iter(&self) -> SpanIter<T> {
@@ -419,4 +425,17 @@ mod tests {
 
         assert!(merged_iter.next().is_none());
     }
+
+    #[test]
+    fn test_data_at() {
+        let mut sb = SpansBuilder::new(200);
+        for i in 0..100 {
+            sb.add_span(Interval::new_closed_open(i * 2, i * 2 + 1), i);
+        }
+        let spans = sb.build();
+        assert_eq!(vec![&0], spans.data_at(0).collect::<Vec<_>>());
+        assert!(spans.data_at(1).next().is_none());
+        assert_eq!(vec![&70], spans.data_at(140).collect::<Vec<_>>());
+        assert!(spans.data_at(199).next().is_none());
+    }
 }
@@ -12,10 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use multiset::{SubsetBuilder, Subset};
-use delta::{Delta, self};
-use rope::{Rope, RopeInfo};
-use interval::Interval;
+use rope::multiset::{SubsetBuilder, Subset};
+use rope::delta::{Delta, self};
+use rope::rope::{Rope, RopeInfo};
+use rope::interval::Interval;
 
 /// Creates a `Subset` of `s` by scanning through `substr` and finding which
 /// characters of `s` are missing from it in order. Returns a `Subset` which
@@ -40,12 +40,6 @@ impl Delta<RopeInfo> {
     }
 }
 
-impl PartialEq for Rope {
-    fn eq(&self, other: &Rope) -> bool {
-        String::from(self) == String::from(other)
-    }
-}
-
 pub fn parse_subset(s: &str) -> Subset {
     let mut sb = SubsetBuilder::new();
 
@@ -41,7 +41,7 @@ pub trait NodeInfo: Clone {
     /// deriving the info from the concatenation of the two leaves. In
     /// mathematical terms, the compute_info method is a monoid
     /// homomorphism.
-    fn compute_info(&Self::L) -> Self;
+    fn compute_info(leaf: &Self::L) -> Self;
 
     /// The identity of the monoid. Need not be implemented because it
     /// can be computed from the leaf default.
@@ -66,6 +66,11 @@ pub trait Leaf: Sized + Clone + Default {
     /// Concretely, for Rust's String type the base unit is the byte.
     fn len(&self) -> usize;
 
+    /// Whether the leaf is empty, measured in base units.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     /// Generally a minimum size requirement for leaves.
     fn is_ok_child(&self) -> bool;
 
@@ -79,7 +84,7 @@ pub trait Leaf: Sized + Clone + Default {
     /// 
     /// - If one or the other input is empty, then no split.
     /// - If either input satisfies is_ok_child, then on return self
-    /// satisfies this, as does the optional split.
+    ///   satisfies this, as does the optional split.
     fn push_maybe_split(&mut self, other: &Self, iv: Interval) -> Option<Self>;
 
     /// same meaning as push_maybe_split starting from an empty
@@ -135,7 +140,7 @@ pub trait Metric<N: NodeInfo> {
     /// Return the number of boundarys in the NodeInfo::Leaf
     /// 
     /// The usize argument is the total size/length of the node, in base units.
-    fn measure(&N, usize) -> usize;
+    fn measure(info: &N, len: usize) -> usize;
 
     /// Returns the smallest offset, in base units, for an offset in measured units.
     ///
@@ -181,8 +186,8 @@ impl<N: NodeInfo> Node<N> {
         Node(Arc::new(
             NodeBody {
             height: 0,
-            len: len,
-            info: info,
+            len,
+            info,
             val: NodeVal::Leaf(l),
         }))
     }
@@ -197,9 +202,9 @@ impl<N: NodeInfo> Node<N> {
         }
         Node(Arc::new(
             NodeBody {
-            height: height,
-            len: len,
-            info: info,
+            height,
+            len,
+            info,
             val: NodeVal::Internal(nodes),
         }))
     }
@@ -208,6 +213,10 @@ impl<N: NodeInfo> Node<N> {
         self.0.len
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     fn height(&self) -> usize {
         self.0.height
     }
@@ -233,11 +242,11 @@ impl<N: NodeInfo> Node<N> {
     fn next_positive_measure_child<M: Metric<N>>(&self, j: usize) -> (Option<usize>, usize) {
         let children = self.get_children();
         let mut offset = 0;
-        for i in j .. children.len() {
-            if children[i].measure::<M>() > 0 {
+        for (i, child) in children.iter().enumerate().skip(j) {
+            if child.measure::<M>() > 0 {
                 return (Some(i), offset);
             } else {
-                offset += children[i].len();
+                offset += child.len();
             }
         }
         (None, offset)
@@ -254,7 +263,7 @@ impl<N: NodeInfo> Node<N> {
     fn is_ok_child(&self) -> bool {
         match self.0.val {
             NodeVal::Leaf(ref l) => l.is_ok_child(),
-            NodeVal::Internal(ref nodes) => (nodes.len() >= MIN_CHILDREN)
+            NodeVal::Internal(ref nodes) => nodes.len() >= MIN_CHILDREN
         }
     }
 
@@ -280,7 +289,7 @@ impl<N: NodeInfo> Node<N> {
         if both_ok {
             return Node::from_nodes(vec![rope1, rope2]);
         }
-        match {
+        let res = {
             let node1 = Arc::make_mut(&mut rope1.0);
             let leaf2 = rope2.get_leaf();
             if let NodeVal::Leaf(ref mut leaf1) = node1.val {
@@ -292,7 +301,7 @@ impl<N: NodeInfo> Node<N> {
             } else {
                 panic!("merge_leaves called on non-leaf");
             }
-        } {
+        }; match res {
             Some(new) => {
                 Node::from_nodes(vec![
                     rope1,
@@ -414,7 +423,6 @@ impl<N: NodeInfo> Node<N> {
                     child.push_subseq(b, rec_iv);
                     offset += child.len();
                 }
-                return;
             }
         }
     }
@@ -469,6 +477,12 @@ impl<N: NodeInfo> Default for Node<N> {
 
 pub struct TreeBuilder<N: NodeInfo>(Option<Node<N>>);
 
+impl<N: NodeInfo> Default for TreeBuilder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<N: NodeInfo> TreeBuilder<N> {
     pub fn new() -> TreeBuilder<N> {
         TreeBuilder(None)
@@ -513,7 +527,7 @@ impl<'a, N: NodeInfo> Cursor<'a, N> {
     pub fn new(n: &'a Node<N>, position: usize) -> Cursor<'a, N> {
         let mut result = Cursor {
             root: n,
-            position: position,
+            position,
             cache: [None; CURSOR_CACHE_SIZE],
             leaf: None,
             offset_of_leaf: 0,
@@ -545,6 +559,11 @@ impl<'a, N: NodeInfo> Cursor<'a, N> {
         self.position
     }
 
+    /// The node this cursor is traversing.
+    pub fn root(&self) -> &'a Node<N> {
+        self.root
+    }
+
     pub fn is_boundary<M: Metric<N>>(&mut self) -> bool {
         if self.leaf.is_none() {
             // not at a valid position
@@ -570,7 +589,7 @@ impl<'a, N: NodeInfo> Cursor<'a, N> {
     /// Moves the cursor to the previous boundary, or to the beginning of the
     /// rope. In the former case, returns the position of the first character
     /// past this boundary. In the latter case, returns `0`.
-    pub fn prev<M: Metric<N>>(&mut self) -> Option<(usize)> {
+    pub fn prev<M: Metric<N>>(&mut self) -> Option<usize> {
         if self.position == 0 || self.leaf.is_none() {
             self.leaf = None;
             return None;
@@ -620,7 +639,8 @@ impl<'a, N: NodeInfo> Cursor<'a, N> {
     /// Moves the cursor to the next boundary, or to the end of the rope. In the
     /// former case, returns the position of the first character past this
     /// boundary. In the latter case, returns the length of the rope.
-    pub fn next<M: Metric<N>>(&mut self) -> Option<(usize)> {
+    #[allow(clippy::should_implement_trait)]
+    pub fn next<M: Metric<N>>(&mut self) -> Option<usize> {
         if self.position >= self.root.len() || self.leaf.is_none() {
             self.leaf = None;
             return None;
@@ -659,7 +679,7 @@ impl<'a, N: NodeInfo> Cursor<'a, N> {
             // the cached subtree, (2) self.position corresponds to the begining
             // of the first leaf after the cached subtree.
             self.descend();
-            return self.next::<M>();
+            self.next::<M>()
         } else {
             panic!("inconsistent, shouldn't get here");
         }
@@ -873,7 +893,7 @@ impl Metric<BytesInfo> for BytesMetric {
 
 #[cfg(test)]
 mod test {
-    use ::rope::*;
+    use rope::rope::*;
     use super::*;
 
     fn build_triangle(n: u32) -> String {
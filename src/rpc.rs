@@ -14,16 +14,28 @@
 
 //! The main RPC protocol, for communication between `xi-core` and the client.
 //!
-//! We rely on [Serde] for serialization and deserialization between
-//! the JSON-RPC protocol and the types here.
-//!
-//! [Serde]: https://serde.rs
+//! These types mirror the xi JSON-RPC protocol, without committing to any
+//! serialization format; embedders that speak the protocol decode into
+//! these types, and convert edit commands into `EditorCommand`s.
 
 
+use std::cmp::max;
 use std::path::PathBuf;
 
 use tabs::ViewIdentifier;
-use config::{ConfigDomain};
+use config::{ConfigDomain, Table};
+use commands::{EditorCommand, SelectionModifier};
+use movement::Movement;
+use rope::find::CaseMatching;
+
+pub use commands::GestureType;
+
+/// The bit in `MouseAction::flags` indicating that the selection should
+/// be extended (i.e. shift is held).
+const FLAG_SELECT: u64 = 2;
+/// The bit in `MouseAction::flags` indicating a block selection (i.e. alt
+/// is held).
+const FLAG_BLOCK: u64 = 8;
 
 // =============================================================================
 //  Command types
@@ -37,51 +49,7 @@ pub struct EmptyStruct {}
 ///
 /// # Note
 ///
-/// For serialization, all identifiers are converted to "snake_case".
-///
-/// # Examples
-///
-/// The `close_view` command:
-///
-/// ```
-/// # extern crate xi_core_lib as xi_core;
-/// extern crate serde_json;
-/// # fn main() {
-/// use xi_core::rpc::CoreNotification;
-///
-/// let json = r#"{
-///     "method": "close_view",
-///     "params": { "view_id": "view-id-1" }
-///     }"#;
-///
-/// let cmd: CoreNotification = serde_json::from_str(&json).unwrap();
-/// match cmd {
-///     CoreNotification::CloseView { .. } => (), // expected
-///     other => panic!("Unexpected variant"),
-/// }
-/// # }
-/// ```
-///
-/// The `client_started` command:
-///
-/// ```
-/// # extern crate xi_core_lib as xi_core;
-/// extern crate serde_json;
-/// # fn main() {
-/// use xi_core::rpc::CoreNotification;
-///
-/// let json = r#"{
-///     "method": "client_started",
-///     "params": {}
-///     }"#;
-///
-/// let cmd: CoreNotification = serde_json::from_str(&json).unwrap();
-/// match cmd {
-///     CoreNotification::ClientStarted { .. }  => (), // expected
-///     other => panic!("Unexpected variant"),
-/// }
-/// # }
-/// ```
+/// In the JSON protocol, all identifiers are converted to "snake_case".
 #[derive(Debug, PartialEq)]
 pub enum CoreNotification {
     /// The 'edit' namespace, for view-specific editor actions.
@@ -100,33 +68,6 @@ pub enum CoreNotification {
     ///
     /// [`EditNotification`]: enum.EditNotification.html
     /// [`EditRequest`]: enum.EditRequest.html
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// # extern crate xi_core_lib as xi_core;
-    /// #[macro_use]
-    /// extern crate serde_json;
-    /// use xi_core::rpc::*;
-    /// # fn main() {
-    /// let edit = EditCommand {
-    ///     view_id: "view-id-1".into(),
-    ///     cmd: EditNotification::Insert { chars: "hello!".into() },
-    /// };
-    /// let rpc = CoreNotification::Edit(edit);
-    /// let expected = json!({
-    ///     "method": "edit",
-    ///     "params": {
-    ///         "method": "insert",
-    ///         "params": {
-    ///             "view_id": "view-id-1",
-    ///             "chars": "hello!",
-    ///         }
-    ///     }
-    /// });
-    /// assert_eq!(serde_json::to_value(&rpc).unwrap(), expected);
-    /// # }
-    /// ```
     Edit(EditCommand<EditNotification>),
     /// The 'plugin' namespace, for interacting with plugins.
     ///
@@ -139,32 +80,6 @@ pub enum CoreNotification {
     ///For the available commands, see [`PluginNotification`].
     ///
     /// [`PluginNotification`]: enum.PluginNotification.html
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// # extern crate xi_core_lib as xi_core;
-    /// #[macro_use]
-    /// extern crate serde_json;
-    /// use xi_core::rpc::*;
-    /// # fn main() {
-    /// let rpc = CoreNotification::Plugin(
-    ///     PluginNotification::Start {
-    ///         view_id: "view-id-1".into(),
-    ///         plugin_name: "syntect".into(),
-    ///     });
-    ///
-    /// let expected = json!({
-    ///     "method": "plugin",
-    ///     "params": {
-    ///         "command": "start",
-    ///         "view_id": "view-id-1",
-    ///         "plugin_name": "syntect",
-    ///     }
-    /// });
-    /// assert_eq!(serde_json::to_value(&rpc).unwrap(), expected);
-    /// # }
-    /// ```
     Plugin(PluginNotification),
     /// Tells `xi-core` to close the specified view.
     CloseView { view_id: ViewIdentifier },
@@ -179,43 +94,20 @@ pub enum CoreNotification {
         /// Path to additional plugins, included by the client.
         client_extras_dir: Option<PathBuf>,
     },
-    // Updates the user's config for the given domain. Where keys in
-    // `changes` are `null`, those keys are cleared in the user config
-    // for that domain; otherwise the config is updated with the new
-    // value.
-    //
-    // Note: If the client is using file-based config, the only valid
-    // domain argument is `ConfigDomain::UserOverride(_)`, which
-    // represents non-persistent view-specific settings, such as when
-    // a user manually changes whitespace settings for a given view.
-    // ModifyUserConfig { domain: ConfigDomain, changes: Table },
+    /// Updates the user's config for the given domain. Keys in `changes`
+    /// replace those in the user config for that domain; other keys are
+    /// left untouched.
+    ///
+    /// Note: If the client is using file-based config, the only valid
+    /// domain argument is `ConfigDomain::UserOverride(_)`, which
+    /// represents non-persistent view-specific settings, such as when
+    /// a user manually changes whitespace settings for a given view.
+    ModifyUserConfig { domain: ConfigDomain, changes: Table },
 }
 
 /// The requests which make up the base of the protocol.
 ///
 /// All requests expect a response.
-///
-/// # Examples
-///
-/// The `new_view` command:
-/// ```
-/// # extern crate xi_core_lib as xi_core;
-/// extern crate serde_json;
-/// # fn main() {
-/// use xi_core::rpc::CoreRequest;
-///
-/// let json = r#"{
-///     "method": "new_view",
-///     "params": { "file_path": "~/my_very_fun_file.rs" }
-///     }"#;
-///
-/// let cmd: CoreRequest = serde_json::from_str(&json).unwrap();
-/// match cmd {
-///     CoreRequest::NewView { .. } => (), // expected
-///     other => panic!("Unexpected variant {:?}", other),
-/// }
-/// # }
-/// ```
 #[derive(Debug, PartialEq)]
 pub enum CoreRequest {
     /// The 'edit' namespace, for view-specific requests.
@@ -235,50 +127,20 @@ pub enum CoreRequest {
 /// requests and notifications.
 ///
 /// Edit requests and notifications have 'method', 'params', and
-/// 'view_id' param members. We use this wrapper, which has custom
-/// `Deserialize` and `Serialize` implementations, to pull out the
+/// 'view_id' param members. We use this wrapper to pull out the
 /// `view_id` field.
-///
-/// # Examples
-///
-/// ```
-/// # extern crate xi_core_lib as xi_core;
-/// extern crate serde_json;
-/// # fn main() {
-/// use xi_core::rpc::*;
-///
-/// let json = r#"{
-///     "view_id": "view-id-1",
-///     "method": "scroll",
-///     "params": [0, 6]
-///     }"#;
-///
-/// let cmd: EditCommand<EditNotification> = serde_json::from_str(&json).unwrap();
-/// match cmd.cmd {
-///     EditNotification::Scroll( .. ) => (), // expected
-///     other => panic!("Unexpected variant {:?}", other),
-/// }
-/// # }
-/// ```
 #[derive(Debug, Clone, PartialEq)]
 pub struct EditCommand<T> {
     pub view_id: ViewIdentifier,
     pub cmd: T,
 }
 
-/// An enum representing touch and mouse gestures applied to the text.
-#[derive(PartialEq, Eq, Debug)]
-pub enum GestureType {
-    ToggleSel,
-}
-
 /// An inclusive range.
 ///
 /// # Note:
 ///
 /// Several core protocol commands use a params array to pass arguments
-/// which are named, internally. this type use custom Serialize /
-/// Deserialize impls to accomodate this.
+/// which are named, internally.
 #[derive(PartialEq, Eq, Debug)]
 pub struct LineRange {
     pub first: i64,
@@ -303,6 +165,8 @@ pub struct MouseAction {
 #[derive(Debug, PartialEq)]
 pub enum EditNotification {
     Insert { chars: String },
+    /// Inserts `chars` as a rectangle; see `EditorCommand::InsertBlock`.
+    InsertBlock { chars: String },
     DeleteForward,
     DeleteBackward,
     DeleteWordForward,
@@ -311,6 +175,19 @@ pub enum EditNotification {
     DeleteToBeginningOfLine,
     InsertNewline,
     InsertTab,
+    Indent,
+    Outdent,
+    DuplicateLine,
+    MoveLinesUp,
+    MoveLinesDown,
+    DeleteLine,
+    JoinLines,
+    ToggleLineComment,
+    ToggleBlockComment,
+    Fold,
+    Unfold,
+    FoldAll,
+    UnfoldAll,
     MoveUp,
     MoveUpAndModifySelection,
     MoveDown,
@@ -319,6 +196,10 @@ pub enum EditNotification {
     // synoynm for `MoveLeft`
     MoveBackward,
     MoveLeftAndModifySelection,
+    MoveUpAndModifyBlockSelection,
+    MoveDownAndModifyBlockSelection,
+    MoveLeftAndModifyBlockSelection,
+    MoveRightAndModifyBlockSelection,
     MoveRight,
     // synoynm for `MoveRight`
     MoveForward,
@@ -337,6 +218,8 @@ pub enum EditNotification {
     MoveToBeginningOfDocumentAndModifySelection,
     MoveToEndOfDocument,
     MoveToEndOfDocumentAndModifySelection,
+    MoveToMatchingBracket,
+    MoveToMatchingBracketAndModifySelection,
     ScrollPageUp,
     PageUpAndModifySelection,
     ScrollPageDown,
@@ -344,6 +227,9 @@ pub enum EditNotification {
     SelectAll,
     AddSelectionAbove,
     AddSelectionBelow,
+    AddNextOccurrence,
+    SkipOccurrence,
+    SelectAllOccurrences,
     Scroll(LineRange),
     GotoLine { line: u64 },
     RequestLines(LineRange),
@@ -356,6 +242,9 @@ pub enum EditNotification {
     Redo,
     FindNext { wrap_around: Option<bool>, allow_same: Option<bool> },
     FindPrevious { wrap_around: Option<bool> },
+    ReplaceNext { chars: String },
+    ReplaceAll { chars: String },
+    ReplaceInSelection { chars: String },
     DebugRewrap,
     /// Prints the style spans present in the active selection.
     DebugPrintSpans,
@@ -376,7 +265,19 @@ pub enum EditRequest {
     ///
     /// If `chars` is `None` and there is an active selection, returns
     /// the string value used for the search, else returns `Null`.
-    Find { chars: Option<String>, case_sensitive: bool },
+    /// `chars` is a regular expression if `regex` is `Some(true)`.
+    ///
+    /// `whole_words` limits the search to whole words. `ignore_accents`
+    /// makes it case insensitive and ignore diacritics, and `normalize`
+    /// makes a case sensitive search match canonically equivalent text.
+    Find {
+        chars: Option<String>,
+        case_sensitive: bool,
+        regex: Option<bool>,
+        whole_words: Option<bool>,
+        ignore_accents: Option<bool>,
+        normalize: Option<bool>,
+    },
 }
 
 
@@ -387,3 +288,159 @@ pub enum PluginNotification {
     Stop { view_id: ViewIdentifier, plugin_name: String },
     /*PluginRpc { view_id: ViewIdentifier, receiver: String, rpc: PlaceholderRpc },*/
 }
+
+impl LineRange {
+    fn to_range(&self) -> ::std::ops::Range<usize> {
+        max(self.first, 0) as usize..max(self.last, 0) as usize
+    }
+}
+
+impl MouseAction {
+    fn modifier(&self) -> SelectionModifier {
+        if (self.flags & FLAG_SELECT) != 0 {
+            SelectionModifier::Extend
+        } else if (self.flags & FLAG_BLOCK) != 0 {
+            SelectionModifier::Block
+        } else {
+            SelectionModifier::None
+        }
+    }
+}
+
+impl From<EditNotification> for EditorCommand {
+    fn from(src: EditNotification) -> EditorCommand {
+        use self::EditNotification::*;
+        use commands::SelectionModifier::{Block, Extend, None as Set};
+
+        match src {
+            Insert { chars } => EditorCommand::Insert(chars),
+            InsertBlock { chars } => EditorCommand::InsertBlock(chars),
+            DeleteForward => EditorCommand::DeleteForward,
+            DeleteBackward => EditorCommand::DeleteBackward,
+            DeleteWordForward => EditorCommand::DeleteWordForward,
+            DeleteWordBackward => EditorCommand::DeleteWordBackward,
+            DeleteToEndOfParagraph => EditorCommand::DeleteToEndOfParagraph,
+            DeleteToBeginningOfLine => EditorCommand::DeleteToBeginningOfLine,
+            InsertNewline => EditorCommand::InsertNewline,
+            InsertTab => EditorCommand::InsertTab,
+            Indent => EditorCommand::Indent,
+            Outdent => EditorCommand::Outdent,
+            DuplicateLine => EditorCommand::DuplicateLine,
+            MoveLinesUp => EditorCommand::MoveLinesUp,
+            MoveLinesDown => EditorCommand::MoveLinesDown,
+            DeleteLine => EditorCommand::DeleteLine,
+            JoinLines => EditorCommand::JoinLines,
+            ToggleLineComment => EditorCommand::ToggleLineComment,
+            ToggleBlockComment => EditorCommand::ToggleBlockComment,
+            Fold => EditorCommand::Fold,
+            Unfold => EditorCommand::Unfold,
+            FoldAll => EditorCommand::FoldAll,
+            UnfoldAll => EditorCommand::UnfoldAll,
+            MoveUp => EditorCommand::Move(Movement::Up, Set),
+            MoveUpAndModifySelection => EditorCommand::Move(Movement::Up, Extend),
+            MoveDown => EditorCommand::Move(Movement::Down, Set),
+            MoveDownAndModifySelection => EditorCommand::Move(Movement::Down, Extend),
+            MoveLeft | MoveBackward => EditorCommand::Move(Movement::Left, Set),
+            MoveLeftAndModifySelection => EditorCommand::Move(Movement::Left, Extend),
+            MoveUpAndModifyBlockSelection => EditorCommand::Move(Movement::Up, Block),
+            MoveDownAndModifyBlockSelection => EditorCommand::Move(Movement::Down, Block),
+            MoveLeftAndModifyBlockSelection => EditorCommand::Move(Movement::Left, Block),
+            MoveRightAndModifyBlockSelection => EditorCommand::Move(Movement::Right, Block),
+            MoveRight | MoveForward => EditorCommand::Move(Movement::Right, Set),
+            MoveRightAndModifySelection => EditorCommand::Move(Movement::Right, Extend),
+            MoveWordLeft => EditorCommand::Move(Movement::LeftWord, Set),
+            MoveWordLeftAndModifySelection => EditorCommand::Move(Movement::LeftWord, Extend),
+            MoveWordRight => EditorCommand::Move(Movement::RightWord, Set),
+            MoveWordRightAndModifySelection => EditorCommand::Move(Movement::RightWord, Extend),
+            MoveToBeginningOfParagraph => EditorCommand::Move(Movement::StartOfParagraph, Set),
+            MoveToEndOfParagraph => EditorCommand::Move(Movement::EndOfParagraph, Set),
+            MoveToLeftEndOfLine => EditorCommand::Move(Movement::LeftOfLine, Set),
+            MoveToLeftEndOfLineAndModifySelection =>
+                EditorCommand::Move(Movement::LeftOfLine, Extend),
+            MoveToRightEndOfLine => EditorCommand::Move(Movement::RightOfLine, Set),
+            MoveToRightEndOfLineAndModifySelection =>
+                EditorCommand::Move(Movement::RightOfLine, Extend),
+            MoveToBeginningOfDocument => EditorCommand::Move(Movement::StartOfDocument, Set),
+            MoveToBeginningOfDocumentAndModifySelection =>
+                EditorCommand::Move(Movement::StartOfDocument, Extend),
+            MoveToEndOfDocument => EditorCommand::Move(Movement::EndOfDocument, Set),
+            MoveToEndOfDocumentAndModifySelection =>
+                EditorCommand::Move(Movement::EndOfDocument, Extend),
+            MoveToMatchingBracket => EditorCommand::Move(Movement::MatchingBracket, Set),
+            MoveToMatchingBracketAndModifySelection =>
+                EditorCommand::Move(Movement::MatchingBracket, Extend),
+            ScrollPageUp => EditorCommand::Move(Movement::UpPage, Set),
+            PageUpAndModifySelection => EditorCommand::Move(Movement::UpPage, Extend),
+            ScrollPageDown => EditorCommand::Move(Movement::DownPage, Set),
+            PageDownAndModifySelection => EditorCommand::Move(Movement::DownPage, Extend),
+            SelectAll => EditorCommand::SelectAll,
+            AddSelectionAbove => EditorCommand::AddSelectionAbove,
+            AddSelectionBelow => EditorCommand::AddSelectionBelow,
+            AddNextOccurrence => EditorCommand::AddNextOccurrence,
+            SkipOccurrence => EditorCommand::SkipOccurrence,
+            SelectAllOccurrences => EditorCommand::SelectAllOccurrences,
+            Scroll(range) => EditorCommand::Scroll(range.to_range()),
+            GotoLine { line } => EditorCommand::GotoLine(line as usize),
+            RequestLines(range) => EditorCommand::RequestLines(range.to_range()),
+            Yank => EditorCommand::Yank,
+            Transpose => EditorCommand::Transpose,
+            Click(action) => EditorCommand::Click {
+                line: action.line as usize,
+                col: action.column as usize,
+                modifier: action.modifier(),
+                click_count: action.click_count.unwrap_or(1) as usize,
+            },
+            Drag(action) => EditorCommand::Drag {
+                line: action.line as usize,
+                col: action.column as usize,
+            },
+            Gesture { line, col, ty } => EditorCommand::Gesture {
+                line: line as usize,
+                col: col as usize,
+                ty,
+            },
+            Undo => EditorCommand::Undo,
+            Redo => EditorCommand::Redo,
+            FindNext { wrap_around, allow_same } => EditorCommand::FindNext {
+                wrap_around: wrap_around.unwrap_or(false),
+                allow_same: allow_same.unwrap_or(false),
+            },
+            FindPrevious { wrap_around } => EditorCommand::FindPrevious {
+                wrap_around: wrap_around.unwrap_or(false),
+            },
+            ReplaceNext { chars } => EditorCommand::ReplaceNext(chars),
+            ReplaceAll { chars } => EditorCommand::ReplaceAll(chars),
+            ReplaceInSelection { chars } => EditorCommand::ReplaceInSelection(chars),
+            DebugRewrap => EditorCommand::DebugRewrap,
+            DebugPrintSpans => EditorCommand::DebugPrintSpans,
+            CancelOperation => EditorCommand::CancelOperation,
+        }
+    }
+}
+
+impl From<EditRequest> for EditorCommand {
+    fn from(src: EditRequest) -> EditorCommand {
+        match src {
+            EditRequest::Cut => EditorCommand::Cut,
+            EditRequest::Copy => EditorCommand::Copy,
+            EditRequest::Find { chars, case_sensitive, regex, whole_words, ignore_accents,
+                                normalize } => {
+                let case_matching = if ignore_accents.unwrap_or(false) {
+                    CaseMatching::IgnoreAccents
+                } else if !case_sensitive {
+                    CaseMatching::CaseInsensitive
+                } else if normalize.unwrap_or(false) {
+                    CaseMatching::Normalized
+                } else {
+                    CaseMatching::Exact
+                };
+                EditorCommand::Find {
+                    chars,
+                    case_matching,
+                    whole_words: whole_words.unwrap_or(false),
+                    regex: regex.unwrap_or(false),
+                }
+            }
+        }
+    }
+}
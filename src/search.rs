@@ -0,0 +1,530 @@
+// Copyright 2018 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Searching across all open buffers and the files of a project.
+//!
+//! `Documents::search_all` takes a snapshot of the text of every open buffer
+//! (ropes are cheap to clone), and searches it on a background thread,
+//! followed by the files under the project directory that aren't open.
+//! Matches are sent back as they are found, and are read from the returned
+//! `SearchHandle`.
+
+use std::cmp::min;
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+
+use regex_automata::meta;
+
+use rope::find::{find_progress, find_regex_progress, CaseMatching, FindResult, RegexError,
+                 RegexMatcher};
+use rope::rope::{BaseMetric, LinesMetric, Rope, RopeInfo};
+use rope::tree::Cursor;
+use tabs::BufferIdentifier;
+use word_boundaries::{is_whole_word, WordBoundaryMode};
+
+/// The number of steps of `find_progress` taken between checks of whether
+/// the search was cancelled.
+const STEPS_PER_CHECK: usize = 64;
+
+/// Files larger than this many bytes aren't searched.
+const MAX_FILE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// What to search for with `Documents::search_all`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchQuery {
+    pub pattern: String,
+    pub case_matching: CaseMatching,
+    /// Only find occurrences that neither start nor end in the middle of a word.
+    pub whole_words: bool,
+    /// How the text is split into words for `whole_words`.
+    pub word_boundaries: WordBoundaryMode,
+    /// Whether `pattern` is a regular expression.
+    pub regex: bool,
+    /// The number of lines before and after each match to include with it.
+    pub context_lines: usize,
+}
+
+/// Where `Documents::search_all` looks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchScope {
+    /// Every open buffer.
+    OpenBuffers,
+    /// Every open buffer, and the files under a directory that aren't open,
+    /// except those matching one of the `search_ignore_globs` setting.
+    /// Symbolic links aren't followed, and files larger than 16 MiB are
+    /// skipped.
+    Project(PathBuf),
+}
+
+/// Where a match was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchSource {
+    /// An open buffer, and its path if it has one. The match is in the text
+    /// of the buffer, which may not have been saved.
+    Buffer(BufferIdentifier, Option<PathBuf>),
+    /// A file that isn't open.
+    File(PathBuf),
+}
+
+/// An occurrence found by `Documents::search_all`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchMatch {
+    pub source: SearchSource,
+    /// The line containing the start of the match, counting from 0.
+    pub line: usize,
+    /// The offset in bytes of the start of the match within its line.
+    pub column: usize,
+    /// The length of the match in bytes; it may continue on the next lines.
+    pub len: usize,
+    /// The text of the line, without its line ending.
+    pub line_text: String,
+    /// Up to `context_lines` lines before the line.
+    pub context_before: Vec<String>,
+    /// Up to `context_lines` lines after the line.
+    pub context_after: Vec<String>,
+}
+
+/// A search started by `Documents::search_all`.
+///
+/// Open buffers are searched first, in the order they were opened, then the
+/// files of the project in path order. Iterating over the handle blocks
+/// until the next match is found; for use from an event loop, `poll` returns
+/// the matches found so far instead. Dropping the handle cancels the search.
+pub struct SearchHandle {
+    receiver: Receiver<SearchMatch>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl SearchQuery {
+    /// A case insensitive search for a literal string, without context.
+    pub fn new<S: Into<String>>(pattern: S) -> SearchQuery {
+        SearchQuery {
+            pattern: pattern.into(),
+            case_matching: CaseMatching::CaseInsensitive,
+            whole_words: false,
+            word_boundaries: WordBoundaryMode::default(),
+            regex: false,
+            context_lines: 0,
+        }
+    }
+}
+
+impl SearchHandle {
+    /// Stops the search. Matches that were already found can still be read.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns the matches found since the last call, and whether the search
+    /// is finished.
+    pub fn poll(&self) -> (Vec<SearchMatch>, bool) {
+        let mut matches = Vec::new();
+        loop {
+            match self.receiver.try_recv() {
+                Ok(m) => matches.push(m),
+                Err(TryRecvError::Empty) => return (matches, false),
+                Err(TryRecvError::Disconnected) => return (matches, true),
+            }
+        }
+    }
+}
+
+impl Iterator for SearchHandle {
+    type Item = SearchMatch;
+
+    fn next(&mut self) -> Option<SearchMatch> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl Drop for SearchHandle {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// Starts a search of the given buffers, then of the files under `root`
+/// that aren't among them, on a new thread.
+pub fn start_search(query: &SearchQuery,
+                    buffers: Vec<(BufferIdentifier, Option<PathBuf>, Rope)>,
+                    root: Option<PathBuf>, ignore_globs: Vec<String>)
+                    -> Result<SearchHandle, RegexError>
+{
+    let matcher = if query.regex {
+        Some(RegexMatcher::new(&query.pattern, query.case_matching)?)
+    } else {
+        None
+    };
+    let (sender, receiver) = mpsc::channel();
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let mut searcher = Searcher {
+        query: query.clone(),
+        matcher,
+        ignore_globs: ignore_globs.iter().filter_map(|glob| IgnoreGlob::new(glob)).collect(),
+        sender,
+        cancelled: cancelled.clone(),
+    };
+    thread::spawn(move || {
+        let mut open_paths = BTreeSet::new();
+        for (buffer_id, path, text) in buffers {
+            if let Some(ref path) = path {
+                open_paths.insert(fs::canonicalize(path).unwrap_or_else(|_| path.clone()));
+            }
+            if !searcher.search_text(&text, &SearchSource::Buffer(buffer_id, path)) {
+                return;
+            }
+        }
+        if let Some(root) = root {
+            if !searcher.query.pattern.is_empty() {
+                searcher.search_dir(&root, &root, &open_paths);
+            }
+        }
+    });
+    Ok(SearchHandle { receiver, cancelled })
+}
+
+struct Searcher {
+    query: SearchQuery,
+    matcher: Option<RegexMatcher>,
+    ignore_globs: Vec<IgnoreGlob>,
+    sender: Sender<SearchMatch>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Searcher {
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Sends the matches in `text`. Returns `false` if the search should stop.
+    fn search_text(&mut self, text: &Rope, source: &SearchSource) -> bool {
+        if self.query.pattern.is_empty() {
+            return true;
+        }
+        let mut cursor = Cursor::new(text, 0);
+        while let Some((start, end)) = self.next_match(&mut cursor) {
+            if self.is_cancelled() {
+                return false;
+            }
+            if start == end {
+                // empty matches aren't reported
+                if cursor.next::<BaseMetric>().is_none() {
+                    break;
+                }
+                continue;
+            }
+            let m = make_match(text, source.clone(), start, end, self.query.context_lines);
+            if self.sender.send(m).is_err() {
+                // the handle was dropped
+                return false;
+            }
+        }
+        !self.is_cancelled()
+    }
+
+    /// Finds the next match, in bounded steps so that a cancelled search
+    /// stops promptly. Returns `None` once the search is cancelled.
+    fn next_match(&mut self, cursor: &mut Cursor<RopeInfo>) -> Option<(usize, usize)> {
+        loop {
+            if let Some(ref mut matcher) = self.matcher {
+                matcher.reset();
+            }
+            let start = loop {
+                if self.is_cancelled() {
+                    return None;
+                }
+                let result = match self.matcher {
+                    Some(ref mut matcher) => find_regex_progress(cursor, matcher, STEPS_PER_CHECK),
+                    None => find_progress(cursor, self.query.case_matching, &self.query.pattern,
+                                          STEPS_PER_CHECK),
+                };
+                match result {
+                    FindResult::Found(start) => break start,
+                    FindResult::NotFound => return None,
+                    FindResult::TryAgain => (),
+                }
+            };
+            let end = cursor.pos();
+            let mode = self.query.word_boundaries;
+            if !self.query.whole_words || is_whole_word(cursor.root(), start, end, mode) {
+                return Some((start, end));
+            }
+            cursor.set(start);
+            cursor.next::<BaseMetric>()?;
+        }
+    }
+
+    /// Searches the files under `dir`, recursively. Returns `false` if the
+    /// search should stop.
+    fn search_dir(&mut self, root: &Path, dir: &Path, open_paths: &BTreeSet<PathBuf>) -> bool {
+        let mut paths = match fs::read_dir(dir) {
+            Ok(entries) => entries.filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .collect::<Vec<_>>(),
+            Err(_) => return true,
+        };
+        paths.sort();
+        for path in paths {
+            if self.is_cancelled() {
+                return false;
+            }
+            if self.is_ignored(root, &path) {
+                continue;
+            }
+            let file_type = match fs::symlink_metadata(&path) {
+                Ok(metadata) => metadata.file_type(),
+                Err(_) => continue,
+            };
+            if file_type.is_dir() {
+                if !self.search_dir(root, &path, open_paths) {
+                    return false;
+                }
+            } else if file_type.is_file() {
+                let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+                if open_paths.contains(&canonical) {
+                    continue;
+                }
+                if let Some(text) = read_text(&path) {
+                    if !self.search_text(&text, &SearchSource::File(path)) {
+                        return false;
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    fn is_ignored(&self, root: &Path, path: &Path) -> bool {
+        let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+        let relative = path.strip_prefix(root).ok()
+            .and_then(Path::to_str)
+            .map(|s| s.replace('\\', "/"))
+            .unwrap_or_default();
+        self.ignore_globs.iter().any(|glob| {
+            glob.regex.is_match(if glob.is_path { relative.as_str() } else { name })
+        })
+    }
+}
+
+/// A pattern of the `search_ignore_globs` setting, compiled to a regex.
+struct IgnoreGlob {
+    regex: meta::Regex,
+    /// Whether the pattern contains a `/`, and so is matched against the path relative
+    /// to the search root, instead of the file name.
+    is_path: bool,
+}
+
+impl IgnoreGlob {
+    fn new(glob: &str) -> Option<IgnoreGlob> {
+        let is_path = glob.contains('/');
+        let glob = glob.strip_prefix('/').unwrap_or(glob);
+        let regex = meta::Regex::new(&glob_to_regex(glob)).ok()?;
+        Some(IgnoreGlob { regex, is_path })
+    }
+}
+
+/// Reads a file to search, skipping files that are larger than
+/// `MAX_FILE_SIZE` or aren't UTF-8 text.
+fn read_text(path: &Path) -> Option<Rope> {
+    let mut file = File::open(path).ok()?;
+    if file.metadata().ok()?.len() > MAX_FILE_SIZE {
+        return None;
+    }
+    let mut s = String::new();
+    file.read_to_string(&mut s).ok()?;
+    if s.contains('\0') {
+        return None;
+    }
+    Some(Rope::from(s))
+}
+
+/// The text of `line`, without its line ending.
+fn line_text(text: &Rope, line: usize) -> String {
+    let start = text.offset_of_line(line);
+    let end = Cursor::new(text, start).next::<LinesMetric>().unwrap_or(text.len());
+    let mut s = text.slice_to_string(start, end);
+    let len = s.trim_end_matches(['\n', '\r']).len();
+    s.truncate(len);
+    s
+}
+
+fn make_match(text: &Rope, source: SearchSource, start: usize, end: usize,
+              context_lines: usize) -> SearchMatch
+{
+    let line = text.line_of_offset(start);
+    // the empty line after a final newline doesn't count
+    let mut last_line = text.line_of_offset(text.len());
+    if last_line > 0 && text.offset_of_line(last_line) == text.len() {
+        last_line -= 1;
+    }
+    SearchMatch {
+        source,
+        line,
+        column: start - text.offset_of_line(line),
+        len: end - start,
+        line_text: line_text(text, line),
+        context_before: (line.saturating_sub(context_lines)..line)
+            .map(|l| line_text(text, l))
+            .collect(),
+        context_after: (line + 1..min(line + context_lines, last_line) + 1)
+            .map(|l| line_text(text, l))
+            .collect(),
+    }
+}
+
+/// Translates a glob `pattern` into a regex matching the same strings, where
+/// `*` matches any run of characters except `/`, `**` matches any run of
+/// characters, and `?` matches any character except `/`. Unlike matching the
+/// glob by backtracking, the regex takes linear time however many stars the
+/// pattern has.
+fn glob_to_regex(pattern: &str) -> String {
+    let mut regex = String::from("(?s)^");
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                // "a/**/b" also matches "a/b"
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            _ => {
+                if "\\.+*?()|[]{}^$#&-~".contains(c) {
+                    regex.push('\\');
+                }
+                regex.push(c);
+            }
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::io::Write;
+    use super::*;
+
+    fn glob(pattern: &str, text: &str) -> bool {
+        IgnoreGlob::new(pattern).unwrap().regex.is_match(text)
+    }
+
+    #[test]
+    fn globs() {
+        assert!(glob(".git", ".git"));
+        assert!(!glob(".git", ".gitignore"));
+        assert!(glob("*.min.js", "app.min.js"));
+        assert!(!glob("*.js", "src/app.js"));
+        assert!(glob("src/*.js", "src/app.js"));
+        assert!(glob("**/*.js", "src/lib/app.js"));
+        assert!(glob("**/*.js", "app.js"));
+        assert!(glob("docs/**/*.md", "docs/a/b/c.md"));
+        assert!(glob("docs/**/*.md", "docs/c.md"));
+        assert!(glob("fil?.txt", "file.txt"));
+        assert!(!glob("a?b", "a/b"));
+        assert!(glob("a(1)+[b].c", "a(1)+[b].c"));
+        assert!(!glob("a.c", "abc"));
+        // many stars don't make matching slow
+        let text = "a".repeat(100);
+        assert!(!glob("*a*a*a*a*a*a*a*a*a*a*a*b", &text));
+        assert!(!glob("**a**a**a**a**a**a**a**a**a**b", &text));
+    }
+
+    #[test]
+    fn context() {
+        let text = Rope::from("one\ntwo\nthree\nfour\n");
+        let source = SearchSource::File(PathBuf::from("x"));
+        let m = make_match(&text, source.clone(), 9, 12, 2);
+        assert_eq!((2, 1, 3), (m.line, m.column, m.len));
+        assert_eq!("three", m.line_text);
+        assert_eq!(vec!["one", "two"], m.context_before);
+        assert_eq!(vec!["four"], m.context_after);
+        let m = make_match(&text, source, 0, 1, 0);
+        assert!(m.context_before.is_empty() && m.context_after.is_empty());
+    }
+
+    #[test]
+    fn search_project() {
+        let root = env::temp_dir().join(format!("xi-search-test-{}", ::std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        let write = |path: &str, contents: &[u8]| {
+            File::create(root.join(path)).unwrap().write_all(contents).unwrap();
+        };
+        write("src/a.txt", b"needle\nhay\n");
+        write("src/b.txt", b"hay\nNeedles\n");
+        write("open.txt", b"needle on disk\n");
+        write(".git/config", b"needle\n");
+        write("binary", b"needle\0");
+
+        let buffers = vec![
+            (BufferIdentifier::new(1), Some(root.join("open.txt")), Rope::from("hay\nneedle")),
+        ];
+        let mut query = SearchQuery::new("needle");
+        query.context_lines = 1;
+        let matches = start_search(&query, buffers.clone(), Some(root.clone()),
+                                   vec![".git".to_owned()])
+            .unwrap()
+            .map(|m| (m.source, m.line, m.context_before))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![
+            (SearchSource::Buffer(BufferIdentifier::new(1), Some(root.join("open.txt"))), 1,
+             vec!["hay".to_owned()]),
+            (SearchSource::File(root.join("src/a.txt")), 0, vec![]),
+            (SearchSource::File(root.join("src/b.txt")), 1, vec!["hay".to_owned()]),
+        ], matches);
+
+        query.whole_words = true;
+        let handle = start_search(&query, buffers.clone(), Some(root.clone()), vec![]).unwrap();
+        assert_eq!(3, handle.count());
+
+        assert!(start_search(&SearchQuery { regex: true, ..SearchQuery::new("(") },
+                             buffers, None, vec![]).is_err());
+
+        // once cancelled, nothing more is found
+        let (sender, receiver) = mpsc::channel();
+        let mut searcher = Searcher {
+            query,
+            matcher: None,
+            ignore_globs: Vec::new(),
+            sender,
+            cancelled: Arc::new(AtomicBool::new(false)),
+        };
+        let text = Rope::from("hay\n".repeat(10_000) + "needle\n");
+        let source = SearchSource::File(root.join("x"));
+        assert!(searcher.search_text(&text, &source));
+        assert_eq!(1, receiver.try_iter().count());
+        let handle = SearchHandle { receiver, cancelled: searcher.cancelled.clone() };
+        handle.cancel();
+        assert!(!searcher.search_text(&text, &source));
+        assert!(!searcher.search_dir(&root, &root, &BTreeSet::new()));
+        drop(searcher);
+        assert_eq!(0, handle.count());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -156,8 +156,9 @@ impl Selection {
             }
         }
 
-        // delete ambiguous regions to the right
-        let mut last = self.search(region.max());
+        // delete ambiguous regions to the right (not before `ix`, which may be past a region
+        // ending where an empty region starts)
+        let mut last = max(ix, self.search(region.max()));
         if last < self.regions.len() && self.regions[last].min() < region.max() {
             last += 1;
         }
@@ -203,15 +204,28 @@ impl Deref for Selection {
     }
 }
 
+/// A rectangular selection, from the corner where it was started to the one
+/// that moves when it's extended. Columns are visual: tabs extend to the
+/// next tab stop and wide characters take up two columns, and a column may
+/// lie past the end of a line.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BlockSelection {
+    pub anchor_line: usize,
+    pub anchor_col: usize,
+    pub active_line: usize,
+    pub active_col: usize,
+}
+
 /// The "affinity" of a cursor which is sitting exactly on a line break.
 /// 
 /// We say "cursor" here rather than "caret" because (depending on presentation)
 /// the front-end may draw a cursor even when the region is not a caret.
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
 pub enum Affinity {
     /// The cursor should be displayed downstream of the line break. For
     /// example, if the buffer is "abcd", and the cursor is on a line break
     /// after "ab", it should be displayed on the second line before "cd".
+    #[default]
     Downstream,
     /// The cursor should be displayed upstream of the line break. For
     /// example, if the buffer is "abcd", and the cursor is on a line break
@@ -219,12 +233,6 @@ pub enum Affinity {
     Upstream,
 }
 
-impl Default for Affinity {
-    fn default() -> Affinity {
-        Affinity::Downstream
-    }
-}
-
 /// A type representing a single contiguous region of a selection. We use the
 /// term "caret" (sometimes also "cursor", more loosely) to refer to a selection
 /// region with an empty interior. A "non-caret region" is one with a non-empty
@@ -283,8 +291,8 @@ impl SelRegion {
             (new_max, new_min)
         };
         SelRegion {
-            start: start,
-            end: end,
+            start,
+            end,
             // Could try to preserve horiz/affinity from one of the
             // sources, but very likely not worth it.
             horiz: None,
@@ -300,8 +308,8 @@ mod tests {
 
     fn r(start: usize, end: usize) -> SelRegion {
         SelRegion {
-            start: start,
-            end: end,
+            start,
+            end,
             horiz: None,
             affinity: Affinity::default(),
         }
@@ -13,14 +13,24 @@
 // limitations under the License.
 
 //! Management of styles.
+//!
+//! Themes are provided by syntect; without the `syntect` feature there are
+//! no themes, and every buffer uses the default style.
 
 use std::collections::HashMap;
 
+#[cfg(feature = "syntect")]
 use syntect::highlighting::StyleModifier as SynStyleModifier;
+#[cfg(feature = "syntect")]
 use syntect::highlighting::{Color, Theme, ThemeSet, ThemeSettings, Highlighter};
 
-const N_RESERVED_STYLES: usize = 2;
+/// The number of style ids reserved for styles the front-end defines itself:
+/// selections, find highlights and matching brackets, in that order. Theme
+/// styles are numbered from here.
+pub const N_RESERVED_STYLES: usize = 3;
+#[cfg(feature = "syntect")]
 const SYNTAX_PRIORITY_DEFAULT: u16 = 200;
+#[cfg(feature = "syntect")]
 const SYNTAX_PRIORITY_LOWEST: u16 = 0;
 
 #[derive(Clone, PartialEq, Eq, Default, Hash, Debug)]
@@ -47,6 +57,7 @@ pub struct Style {
 impl Style {
 
     /// Creates a new `Style` by converting from a `Syntect::StyleModifier`.
+    #[cfg(feature = "syntect")]
     pub fn from_syntect_style_mod(style: &SynStyleModifier) -> Self {
         let font_style = style.font_style.map(|s|s.bits()).unwrap_or_default();
         let weight = if (font_style & 1) != 0 { Some(700) } else { None };
@@ -73,7 +84,7 @@ impl Style {
     {
         assert!(priority <= 1000);
         Style {
-            priority: priority,
+            priority,
             fg_color: fg_color.into(),
             bg_color: bg_color.into(),
             weight: weight.into(),
@@ -83,6 +94,7 @@ impl Style {
     }
 
     /// Returns the default style for the given `Theme`.
+    #[cfg(feature = "syntect")]
     pub fn default_for_theme(theme: &Theme) -> Self {
         let fg = theme.settings.foreground.unwrap_or(Color::BLACK);
         Style::new(
@@ -117,6 +129,7 @@ impl Style {
             )
     }
 
+    #[cfg(feature = "syntect")]
     fn rgba_from_syntect_color(color: &Color) -> u32 {
         let &Color { r, g, b, a } = color;
         ((a as u32) << 24) | ((r as u32) << 16) | ((g as u32) << 8) | (b as u32)
@@ -125,8 +138,11 @@ impl Style {
 
 /// A map from styles to client identifiers for a given `Theme`.
 pub struct ThemeStyleMap {
+    #[cfg(feature = "syntect")]
     themes: ThemeSet,
+    #[cfg(feature = "syntect")]
     theme_name: String,
+    #[cfg(feature = "syntect")]
     theme: Theme,
     default_style: Style,
     map: HashMap<Style, usize>,
@@ -136,7 +152,14 @@ pub struct ThemeStyleMap {
     styles: Vec<Style>,
 }
 
+impl Default for ThemeStyleMap {
+    fn default() -> ThemeStyleMap {
+        ThemeStyleMap::new()
+    }
+}
+
 impl ThemeStyleMap {
+    #[cfg(feature = "syntect")]
     pub fn new() -> ThemeStyleMap {
         let themes = ThemeSet::load_defaults();
         let theme_name = "InspiredGitHub".to_owned();
@@ -144,10 +167,19 @@ impl ThemeStyleMap {
         let default_style = Style::default_for_theme(&theme);
 
         ThemeStyleMap {
-            themes: themes,
-            theme_name: theme_name,
-            theme: theme,
-            default_style: default_style,
+            themes,
+            theme_name,
+            theme,
+            default_style,
+            map: HashMap::new(),
+            styles: Vec::new(),
+        }
+    }
+
+    #[cfg(not(feature = "syntect"))]
+    pub fn new() -> ThemeStyleMap {
+        ThemeStyleMap {
+            default_style: Style::default(),
             map: HashMap::new(),
             styles: Vec::new(),
         }
@@ -157,22 +189,32 @@ impl ThemeStyleMap {
         &self.default_style
     }
 
+    #[cfg(feature = "syntect")]
     pub fn get_highlighter<'a>(&'a self) -> Highlighter<'a> {
         Highlighter::new(&self.theme)
     }
 
+    #[cfg(feature = "syntect")]
     pub fn get_theme_name(&self) -> &str {
         &self.theme_name
     }
 
-    pub fn get_theme_settings<'a>(&'a self) -> &ThemeSettings {
+    #[cfg(feature = "syntect")]
+    pub fn get_theme_settings(&self) -> &ThemeSettings {
         &self.theme.settings
     }
 
+    #[cfg(feature = "syntect")]
     pub fn get_theme_names(&self) -> Vec<String>  {
         self.themes.themes.keys().cloned().collect()
     }
 
+    #[cfg(not(feature = "syntect"))]
+    pub fn get_theme_names(&self) -> Vec<String>  {
+        Vec::new()
+    }
+
+    #[cfg(feature = "syntect")]
     pub fn set_theme(&mut self, theme_name: &str) -> Result<(), &'static str> {
         if theme_name == self.theme_name {
             return Ok(())
@@ -189,12 +231,17 @@ impl ThemeStyleMap {
         }
     }
 
+    #[cfg(not(feature = "syntect"))]
+    pub fn set_theme(&mut self, _theme_name: &str) -> Result<(), &'static str> {
+        Err("unknown theme")
+    }
+
     pub fn merge_with_default(&self, style: &Style) -> Style {
         self.default_style.merge(style)
     }
 
     pub fn lookup(&self, style: &Style) -> Option<usize> {
-        self.map.get(style).map(|&ix| ix)
+        self.map.get(style).copied()
     }
 
     pub fn add(&mut self, style: &Style) -> usize {
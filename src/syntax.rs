@@ -14,21 +14,14 @@
 
 //! Very basic syntax detection.
 
-use std::fmt;
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub enum SyntaxDefinition {
+    #[default]
     Plaintext, Markdown, Python, Rust, C, Go, Dart, Swift, Toml,
     Json, Yaml, Cpp, Objc, Shell, Ruby, Javascript, Java, Php,
     Perl, Makefile,
 }
 
-impl Default for SyntaxDefinition {
-    fn default() -> Self {
-        SyntaxDefinition::Plaintext
-    }
-}
-
 //FIXME: this should be Into<SyntaxDefinition> for AsRef<Path>, or something
 impl SyntaxDefinition {
     pub fn new<'a, S: Into<Option<&'a str>>>(s: S) -> Self {
@@ -36,7 +29,7 @@ impl SyntaxDefinition {
         let s = s.into().unwrap_or("").to_lowercase();
         if s == "makefile" { return Makefile }
 
-        match &*s.split('.').rev().nth(0).unwrap_or("") {
+        match s.split('.').rev().nth(0).unwrap_or("") {
             "rs" => Rust,
             "md" | "mdown" => Markdown,
             "py" => Python,
@@ -58,6 +51,36 @@ impl SyntaxDefinition {
             _ => Plaintext,
         }
     }
+
+    /// Returns the `SyntaxDefinition` with the given (case-insensitive)
+    /// name, such as "rust" or "makefile", if one exists.
+    pub fn try_from_name<S: AsRef<str>>(name: S) -> Option<Self> {
+        use self::SyntaxDefinition::*;
+        let r = match &*name.as_ref().to_lowercase() {
+            "plaintext" => Plaintext,
+            "markdown" => Markdown,
+            "python" => Python,
+            "rust" => Rust,
+            "c" => C,
+            "go" => Go,
+            "dart" => Dart,
+            "swift" => Swift,
+            "toml" => Toml,
+            "json" => Json,
+            "yaml" => Yaml,
+            "cpp" => Cpp,
+            "objc" => Objc,
+            "shell" => Shell,
+            "ruby" => Ruby,
+            "javascript" => Javascript,
+            "java" => Java,
+            "php" => Php,
+            "perl" => Perl,
+            "makefile" => Makefile,
+            _ => return None,
+        };
+        Some(r)
+    }
 }
 
 impl<S: AsRef<str>> From<S> for SyntaxDefinition {
@@ -79,4 +102,12 @@ mod tests {
         assert_eq!(SyntaxDefinition::from("build"), SyntaxDefinition::Plaintext);
         assert_eq!(SyntaxDefinition::from("build.test.sh"), SyntaxDefinition::Shell);
     }
+
+    #[test]
+    fn test_try_from_name() {
+        assert_eq!(SyntaxDefinition::try_from_name("rust"), Some(SyntaxDefinition::Rust));
+        assert_eq!(SyntaxDefinition::try_from_name("Makefile"),
+                   Some(SyntaxDefinition::Makefile));
+        assert_eq!(SyntaxDefinition::try_from_name("cobol"), None);
+    }
 }
@@ -12,43 +12,39 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-//! A container for all the documents being edited. Also functions as main dispatch for RPC.
+//! A container for all the documents being edited.
 
 use std::collections::BTreeMap;
+#[cfg(feature = "notify")]
 use std::ffi::OsStr;
 use std::fmt;
 use std::io::{self, Read};
 use std::path::{PathBuf, Path};
 use std::fs::File;
-use std::sync::{Arc, Mutex, MutexGuard, Weak, mpsc};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
 
 #[cfg(feature = "notify")]
 use notify::{RecursiveMode, DebouncedEvent};
 
+use rope::find::RegexError;
 use rope::rope::Rope;
 use editor::Editor;
 
-use rpc;
 use config;
 #[cfg(feature = "notify")]
 use watcher::{FsWatcher, EventToken};
 use styles::{Style, ThemeStyleMap};
+use observer::{NullObserver, ViewObserver, ViewUpdate};
 
+use search::{self, SearchHandle, SearchQuery, SearchScope};
 use syntax::SyntaxDefinition;
-use config::{ConfigManager, ConfigDomain};
-
-#[cfg(feature="ledger")]
-use apps_ledger_services_public::{Ledger_Proxy};
+use linewrap::WidthMeasurer;
+use config::{BufferConfig, ConfigManager, ConfigDomain, Table};
 
 /// Token for config-related file change events
 #[cfg(feature = "notify")]
 const CONFIG_EVENT_TOKEN: EventToken = EventToken(1);
 
-const NEW_VIEW_IDLE_TOKEN: usize = 1001;
-
-/// xi_rpc idle Token for watcher related idle scheduling.
-pub const WATCH_IDLE_TOKEN: usize = 1002;
-
 /// ViewIdentifiers are the primary means of routing messages between xi-core and a client view.
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ViewIdentifier(usize);
@@ -91,33 +87,32 @@ pub struct WeakBufferContainerRef(Weak<Mutex<BufferContainer>>);
 ///
 /// `Documents` is effectively the apex of the xi's model graph. It keeps references
 /// to all active `Editor ` instances (through a `BufferContainerRef` instance),
-/// and handles dispatch of RPC methods between client views and `Editor`
-/// instances, as well as between `Editor` instances and Plugins.
+/// and routes commands from client views to the appropriate `Editor`.
 pub struct Documents {
     /// keeps track of buffer/view state.
     buffers: BufferContainerRef,
     id_counter: usize,
     kill_ring: Arc<Mutex<Rope>>,
     style_map: Arc<Mutex<ThemeStyleMap>>,
-    // plugins: PluginManagerRef,
+    /// The receiver of view updates and other notifications for the front-end.
+    observer: Arc<ViewObserver>,
+    /// Measures text for line wrapping, if the front-end supplied a measurer.
+    width_measurer: Option<Arc<WidthMeasurer>>,
     config_manager: ConfigManager,
     #[cfg(feature = "notify")]
     file_watcher: FsWatcher,
-    /// A tx channel used to propagate plugin updates from all `Editor`s.
-    // update_channel: mpsc::Sender<(ViewIdentifier, PluginUpdate, usize)>,
-    /// A queue of closures to be executed on the next idle runloop pass.
+    /// A queue of closures to be executed on the next call to `handle_idle`.
     idle_queue: Vec<Box<IdleProc>>,
-    #[allow(dead_code)]
-    sync_repo: Option<SyncRepo>,
 }
 
 #[derive(Clone)]
 /// A container for state shared between `Editor` instances.
 pub struct DocumentCtx {
     pub kill_ring: Arc<Mutex<Rope>>,
-    // pub rpc_peer: MainPeer,
+    pub observer: Arc<ViewObserver>,
     pub style_map: Arc<Mutex<ThemeStyleMap>>,
-    // pub update_channel: mpsc::Sender<(ViewIdentifier, PluginUpdate, usize)>
+    /// The front-end's measurer for line wrapping, if it supplied one.
+    pub width_measurer: Option<Arc<WidthMeasurer>>,
 }
 
 /// A trait for closure types which are callable with a `Documents` instance.
@@ -174,6 +169,12 @@ impl BufferContainer {
     }
 }
 
+impl Default for BufferContainerRef {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl BufferContainerRef {
     pub fn new() -> Self {
         BufferContainerRef(Arc::new(Mutex::new(
@@ -255,10 +256,7 @@ impl WeakBufferContainerRef {
     ///
     /// Returns `None` if the inner value has been deallocated.
     pub fn upgrade(&self) -> Option<BufferContainerRef> {
-        match self.0.upgrade() {
-            Some(inner) => Some(BufferContainerRef(inner)),
-            None => None
-        }
+        self.0.upgrade().map(BufferContainerRef)
     }
 }
 
@@ -268,27 +266,35 @@ impl Clone for BufferContainerRef {
     }
 }
 
+impl Default for Documents {
+    fn default() -> Documents {
+        Documents::new()
+    }
+}
+
 impl Documents {
+    /// Creates a new `Documents` instance which discards all notifications.
     pub fn new() -> Documents {
+        Documents::with_observer(Arc::new(NullObserver))
+    }
+
+    /// Creates a new `Documents` instance, sending view updates and other
+    /// notifications to `observer`.
+    pub fn with_observer(observer: Arc<ViewObserver>) -> Documents {
         let buffers = BufferContainerRef::new();
         let config_manager = ConfigManager::default();
-        // let plugin_manager = PluginManagerRef::new(buffers.clone());
-        let (update_tx, update_rx) = mpsc::channel();
-
-        // plugins::start_update_thread(update_rx, &plugin_manager);
 
         Documents {
-            buffers: buffers,
+            buffers,
             id_counter: 0,
             kill_ring: Arc::new(Mutex::new(Rope::from(""))),
             style_map: Arc::new(Mutex::new(ThemeStyleMap::new())),
-            // plugins: plugin_manager,
-            config_manager: config_manager,
+            observer,
+            width_measurer: None,
+            config_manager,
             #[cfg(feature = "notify")]
             file_watcher: FsWatcher::default(),
-            update_channel: update_tx,
             idle_queue: Vec::new(),
-            sync_repo: None,
         }
     }
 
@@ -300,76 +306,32 @@ impl Documents {
     pub fn new_tab_ctx(&self) -> DocumentCtx {
         DocumentCtx {
             kill_ring: self.kill_ring.clone(),
-            // rpc_peer: peer.clone(),
+            observer: self.observer.clone(),
             style_map: self.style_map.clone(),
-            update_channel: self.update_channel.clone(),
+            width_measurer: self.width_measurer.clone(),
+        }
+    }
+
+    /// Sets the measurer lines are wrapped with, so that `wrap_width` is in
+    /// its units, such as pixels, rather than in columns. Open buffers are
+    /// wrapped again.
+    pub fn set_width_measurer(&mut self, measurer: Arc<WidthMeasurer>) {
+        self.width_measurer = Some(measurer.clone());
+        for ed in self.buffers.lock().iter_editors_mut() {
+            ed.set_width_measurer(measurer.clone());
         }
     }
 
     pub fn next_view_id(&mut self) -> ViewIdentifier {
         self.id_counter += 1;
-        format!("view-id-{}", self.id_counter).into()
+        ViewIdentifier(self.id_counter)
     }
 
     pub fn next_buffer_id(&mut self) -> BufferIdentifier {
         self.id_counter += 1;
         BufferIdentifier(self.id_counter)
     }
-/*
-    pub fn handle_notification(&mut self, cmd: rpc::CoreNotification,
-                               rpc_ctx: &RpcCtx) {
-        use rpc::CoreNotification::*;
-        match cmd {
-            ClientStarted { config_dir, client_extras_dir } =>
-                self.do_client_init(rpc_ctx.get_peer(), config_dir,
-                                    client_extras_dir),
-            SetTheme { theme_name } =>
-                self.do_set_theme(rpc_ctx.get_peer(), &theme_name),
-            Save { view_id, file_path } => self.do_save(view_id, file_path),
-            CloseView { view_id } => self.do_close_view(view_id),
-            Edit(rpc::EditCommand { view_id, cmd }) => {
-                self.buffers.lock().editor_for_view_mut(view_id)
-                    .map(|ed| ed.handle_notification(view_id, cmd));
-                }
-            Plugin(cmd) => self.do_plugin_cmd(cmd),
-            ModifyUserConfig { domain, changes } =>
-                self.do_modify_user_config(rpc_ctx.get_peer(), domain, changes)
-        }
-    }
-*/
-/*
-    pub fn handle_request(&mut self, cmd: rpc::CoreRequest) -> Result<Value, RemoteError> {
-        use rpc::CoreRequest::*;
-        match cmd {
-            NewView { file_path } => {
-                let result = self.do_new_view(rpc_ctx.get_peer(), file_path);
-                // schedule idle handler after creating views; this is used to
-                // send cursors for empty views, and to initialize plugins.
-                rpc_ctx.schedule_idle(NEW_VIEW_IDLE_TOKEN);
-                Ok(result)
-            }
-            Edit(rpc::EditCommand { view_id, cmd }) => {
-                let result = self.buffers.lock().editor_for_view_mut(view_id)
-                    .map(|ed| ed.handle_request(view_id, cmd));
-                match result {
-                    None => {
-                        let msg = format!("No editor for view_id: {}", view_id);
-                        Err(RemoteError::custom(2, msg, None))
-                    }
-                    Some(result) => result,
-                }
-            },
-            GetConfig { view_id } => {
-                self.do_get_config(view_id)
-                .map(|v| v.into())
-                .map_err(|_| {
-                    let msg = format!("No editor for view_id: {}", view_id);
-                    RemoteError::custom(2, msg, None)
-                })
-            }
-        }
-    }
-*/
+
     /// Creates a new view and associates it with a buffer.
     ///
     /// This function always creates a new view and associates it with a buffer
@@ -382,6 +344,9 @@ impl Documents {
     /// existing buffer. If `file_path` is given and that file _isn't_ open,
     /// we load that file into a new buffer. If `file_path` is not given,
     /// we create a new empty buffer.
+    ///
+    /// The new view's initial config and contents are sent to the observer
+    /// on the next call to `handle_idle`.
     pub fn do_new_view(&mut self, file_path: Option<String>) -> ViewIdentifier {
         // three code paths: new buffer, open file, and new view into existing buffer
         let view_id = self.next_view_id();
@@ -402,19 +367,14 @@ impl Documents {
             self.new_empty_view(view_id, buffer_id);
         }
 
-        // closure to handle post-creation work on next idle runloop
-        let init_info = self.buffers.lock().editor_for_view(view_id)
-            .unwrap().plugin_init_info();
-
+        // closure to handle post-creation work on next idle pass
         let on_idle = Box::new(move |self_ref: &mut Documents| {
-            self_ref.plugins.document_new(view_id, &init_info);
-            {
-                let mut editors = self_ref.buffers.lock();
-                editors.editor_for_view(view_id)
-                    .map(|ed| ed.send_config_init());
-                for editor in editors.iter_editors_mut() {
-                    editor.render();
-                }
+            let mut editors = self_ref.buffers.lock();
+            if let Some(ed) = editors.editor_for_view(view_id) {
+                ed.send_config_init();
+            }
+            for editor in editors.iter_editors_mut() {
+                editor.render();
             }
         });
         self.idle_queue.push(on_idle);
@@ -422,7 +382,6 @@ impl Documents {
     }
 
     pub fn do_close_view(&mut self, view_id: ViewIdentifier) {
-        self.plugins.document_close(view_id);
         self.buffers.close_view(view_id);
     }
 
@@ -436,7 +395,7 @@ impl Documents {
 
     pub fn new_view_with_file(&mut self, view_id: ViewIdentifier,
                               buffer_id: BufferIdentifier, path: &Path) {
-        match self.read_file(&path) {
+        match self.read_file(path) {
             Ok(contents) => {
                 let syntax = SyntaxDefinition::new(path.to_str());
                 let config = self.config_manager.get_buffer_config(syntax, view_id);
@@ -465,8 +424,7 @@ impl Documents {
     ///
     /// This is called once each time a new editor is created.
     pub fn add_editor(&mut self, view_id: ViewIdentifier, buffer_id: BufferIdentifier,
-                  mut editor: Editor, path: Option<&Path>) {
-        self.initialize_sync(&mut editor, path, buffer_id);
+                  editor: Editor, path: Option<&Path>) {
         self.buffers.add_editor(view_id, buffer_id, editor);
         if let Some(path) = path {
             self.buffers.set_path(path, view_id);
@@ -494,52 +452,24 @@ impl Documents {
         self.buffers.lock().editor_for_view_mut(view_id)
             .unwrap().do_save(file_path);
         self.buffers.set_path(file_path, view_id);
-        let init_info = self.buffers.lock().editor_for_view(view_id)
-            .unwrap().plugin_init_info();
 
         if prev_syntax != new_syntax {
-            self.plugins.document_syntax_changed(view_id, init_info);
             let new_config = self.config_manager.get_buffer_config(new_syntax,
                                                                    view_id);
             self.buffers.lock().editor_for_view_mut(view_id)
                 .unwrap().set_config(new_config);
         }
-        self.plugins.document_did_save(view_id, file_path);
-    }
-
-    /// Handles a plugin related command from a client
-    pub fn do_plugin_cmd(&mut self, cmd: rpc::PluginNotification) {
-        use rpc::PluginNotification::*;
-        match cmd {
-            Start { view_id, plugin_name } => {
-                //TODO: report this error to client?
-                let info = self.buffers.lock().editor_for_view(view_id)
-                    .map(|ed| ed.plugin_init_info());
-                match info {
-                    Some(info) => {
-                        let _ = self.plugins.start_plugin(view_id, &info, &plugin_name);
-                    },
-                    None => (),
-                }
-            }
-            Stop { view_id, plugin_name } => {
-                eprintln!("stop plugin rpc {}", plugin_name);
-                self.plugins.stop_plugin(view_id, &plugin_name);
-            }/*
-            PluginRpc  { view_id, receiver, rpc } => {
-                assert!(rpc.params_ref().is_object(), "params must be an object");
-                assert!(!rpc.is_request(), "client->plugin rpc is notification only");
-                self.plugins.dispatch_command(view_id, &receiver,
-                                              &rpc.method, &rpc.params);
-            }*/
-        }
     }
-/*
-    pub fn do_client_init(&mut self, rpc_peer: &MainPeer, config_dir: Option<PathBuf>,
-                      client_extras_dir: Option<PathBuf>) {
+
+    /// Handles the client's initial configuration.
+    ///
+    /// If `config_dir` is provided, config files found there are loaded
+    /// and, with the `notify` feature, watched for changes.
+    pub fn do_client_init(&mut self, config_dir: Option<PathBuf>,
+                          client_extras_dir: Option<PathBuf>) {
         if let Some(ref d) = config_dir {
-            self.config_manager.set_config_dir(&d);
-            if let Err(e) = self.init_file_based_configs(d, rpc_peer) {
+            self.config_manager.set_config_dir(d);
+            if let Err(e) = self.init_file_based_configs(d) {
                 eprintln!("Error reading config dir: {:?}", e);
             }
         }
@@ -548,34 +478,19 @@ impl Documents {
             //TODO: test setting this when config_dir.is_none()
             self.config_manager.set_extras_dir(d);
         }
+    }
 
-        let params = {
-            let style_map = self.style_map.lock().unwrap();
-            json!({
-                "themes": style_map.get_theme_names()
-            })
-        };
-
-        let plugin_paths = self.config_manager.plugin_search_path();
-        self.plugins.set_plugin_search_path(plugin_paths);
-        rpc_peer.send_rpc_notification("available_themes", &params);
+    /// Returns the names of the available themes.
+    pub fn available_themes(&self) -> Vec<String> {
+        self.style_map.lock().unwrap().get_theme_names()
     }
-*/
-/*
-    /// Handle a client set theme RPC
-    pub fn do_set_theme(&self, theme_name: &str) {
+
+    /// Sets the active theme, restyling all open buffers. Returns `false`
+    /// if no theme named `theme_name` exists.
+    pub fn do_set_theme(&self, theme_name: &str) -> bool {
         let success = self.style_map.lock().unwrap()
-            .set_theme(&theme_name).is_ok();
+            .set_theme(theme_name).is_ok();
         if success {
-            let params = {
-                let style_map = self.style_map.lock().unwrap();
-                json!({
-                    "name": style_map.get_theme_name(),
-                    "theme": style_map.get_theme_settings(),
-                })
-            };
-            rpc_peer.send_rpc_notification("theme_changed", &params);
-
             let mut buffers = self.buffers.lock();
             for ed in buffers.iter_editors_mut() {
                 ed.theme_changed();
@@ -583,36 +498,48 @@ impl Documents {
         } else {
             eprintln!("no theme named {}", theme_name);
         }
+        success
     }
-*/
-/*
-    pub fn do_get_config(&self, view_id: ViewIdentifier) -> Result<Table, RemoteError> {
-        let view_config = self.buffers.lock().editor_for_view(view_id)
-            .map(|ed| ed.get_config().to_table());
-        view_config.ok_or(
-            RemoteError::custom(2, &format!("No buffer for view {}", view_id), None))
+
+    /// Returns the complete config for the buffer shown in `view_id`.
+    pub fn do_get_config(&self, view_id: ViewIdentifier) -> Option<Table> {
+        self.buffers.lock().editor_for_view(view_id)
+            .map(|ed| ed.get_config().to_table())
     }
-*/
-/*
-    pub fn handle_idle(&mut self, ctx: &RpcCtx, token: usize) {
-        match token {
-            WATCH_IDLE_TOKEN => {
-                #[cfg(feature = "notify")]
-                self.handle_fs_events(ctx.get_peer())
-            }
-            NEW_VIEW_IDLE_TOKEN => {
-                while let Some(f) = self.idle_queue.pop() {
-                    f.call(self);
-                }
-            }
-            _ => (),
+
+    /// Starts searching for `query` in all open buffers, and with
+    /// `SearchScope::Project`, in the files of a directory that aren't open.
+    ///
+    /// The search runs on another thread, against the text of the buffers
+    /// at the time of the call; matches are read from the returned handle
+    /// as they are found. Returns an error if the query is an invalid regex.
+    pub fn search_all(&self, query: &SearchQuery, scope: SearchScope)
+                      -> Result<SearchHandle, RegexError> {
+        let buffers = self.buffers.lock().iter_editors()
+            .map(|ed| (ed.get_identifier(), ed.get_path().map(Path::to_owned), ed.text.clone()))
+            .collect();
+        let root = match scope {
+            SearchScope::OpenBuffers => None,
+            SearchScope::Project(root) => Some(root),
+        };
+        search::start_search(query, buffers, root, self.config_manager.search_ignore_globs())
+    }
+
+    /// Performs deferred work, such as the initial rendering of new views
+    /// and the processing of file system events.
+    ///
+    /// Embedders should call this periodically, and after creating views.
+    pub fn handle_idle(&mut self) {
+        while let Some(f) = self.idle_queue.pop() {
+            f.call(self);
         }
+        #[cfg(feature = "notify")]
+        self.handle_fs_events();
     }
-*/
-/*
+
     /// Process file system events, forwarding them to registrees.
     #[cfg(feature = "notify")]
-    fn handle_fs_events(&mut self, peer: &MainPeer) {
+    fn handle_fs_events(&mut self) {
         let mut events = self.file_watcher.take_events();
         let mut config_changed = false;
 
@@ -621,7 +548,7 @@ impl Documents {
                 CONFIG_EVENT_TOKEN => {
                     //TODO: we should(?) be more efficient about this update,
                     // with config_manager returning whether it's necessary.
-                    self.handle_config_fs_event(event, peer);
+                    self.handle_config_fs_event(event);
                     config_changed = true;
                 }
                 _ => eprintln!("unexpected fs event token {:?}", token),
@@ -631,40 +558,37 @@ impl Documents {
             self.after_config_change();
         }
     }
-*/
-/*
+
     /// Handles a config related file system event.
     #[cfg(feature = "notify")]
-    fn handle_config_fs_event(&mut self, event: DebouncedEvent, peer: &MainPeer) {
+    fn handle_config_fs_event(&mut self, event: DebouncedEvent) {
         use self::DebouncedEvent::*;
         match event {
             Create(ref path) | Write(ref path) => {
-                self.load_file_based_config(peer, path)
+                self.load_file_based_config(path)
             }
             Remove(ref path) => self.config_manager.remove_source(path),
             Rename(ref old, ref new) => {
                 self.config_manager.remove_source(old);
                 let should_load = self.config_manager.should_load_file(new);
-                if should_load { self.load_file_based_config(peer, new) }
+                if should_load { self.load_file_based_config(new) }
             }
             _ => (),
         }
     }
-*/
-/*
+
     /// Checks for existence of config dir, loading config files and registering
     /// for file system events if the directory exists and can be read.
-    fn init_file_based_configs(&mut self, config_dir: &Path,
-                               peer: &MainPeer) -> io::Result<()> {
+    fn init_file_based_configs(&mut self, config_dir: &Path) -> io::Result<()> {
         if !config_dir.exists() {
             config::init_config_dir(config_dir)?;
         }
         let config_files = config::iter_config_files(config_dir)?;
-        config_files.for_each(|p| self.load_file_based_config(peer, &p));
+        config_files.for_each(|p| self.load_file_based_config(&p));
 
         #[cfg(feature = "notify")]
         self.file_watcher.watch_filtered(config_dir, RecursiveMode::Recursive,
-                                         CONFIG_EVENT_TOKEN, peer,
+                                         CONFIG_EVENT_TOKEN,
                                          |p| {
                                              p.extension()
                                                  .and_then(OsStr::to_str)
@@ -674,100 +598,61 @@ impl Documents {
     }
 
     /// Attempt to load a config file.
-    fn load_file_based_config(&mut self, peer: &MainPeer, path: &Path) {
-        match config::try_load_from_file(&path) {
-            Ok((d, t)) => self.set_config(peer, d, t, Some(path.to_owned())),
-            Err(e) => {
-                let err_msg = format!("{}", &e);
-                peer.send_rpc_notification("alert", &json!({"msg": err_msg}));
-            }
+    fn load_file_based_config(&mut self, path: &Path) {
+        match config::try_load_from_file(path) {
+            Ok((d, t)) => self.set_config(d, t, Some(path.to_owned())),
+            Err(e) => self.observer.alert(&format!("{}", &e)),
         }
     }
 
     /// Sets (overwriting) the config for a given domain.
-    fn set_config<P>(&mut self, peer: &MainPeer, domain: ConfigDomain,
-                     table: Table, path: P)
+    fn set_config<P>(&mut self, domain: ConfigDomain, table: Table, path: P)
         where P: Into<Option<PathBuf>>
     {
         if let Err(e) = self.config_manager.set_user_config(domain, table, path) {
-            let err_msg = format!("{}", &e);
-            peer.send_rpc_notification("alert", &json!({"msg": err_msg}));
+            self.observer.alert(&format!("{}", &e));
         }
     }
 
-    // NOTE: this is coming in from a direct RPC; unlike `set_config`, missing
-    // keys here are left in their current state (`set_config` clears missing keys)
-    /// Updates the config for a given domain.
-    fn do_modify_user_config(&mut self, peer: &MainPeer, domain: ConfigDomain,
-                             changes: Table) {
+    /// Updates the config for a given domain. Unlike file-based configs,
+    /// keys missing from `changes` are left in their current state.
+    pub fn do_modify_user_config(&mut self, domain: ConfigDomain, changes: Table) {
         if let Err(e) = self.config_manager.update_user_config(domain, changes) {
-            let err_msg = format!("{}", &e);
-            peer.send_rpc_notification("alert", &json!({"msg": err_msg}));
+            self.observer.alert(&format!("{}", &e));
         }
         self.after_config_change();
     }
-*/
 
-    /// Notify editors/views/plugins of config changes.
+    /// Notify editors/views of config changes.
     fn after_config_change(&self) {
-        let mut to_notify = Vec::new();
-        {
-            let mut editors = self.buffers.lock();
-            for ed in editors.iter_editors_mut() {
-                let syntax = ed.get_syntax().to_owned();
-                let identifier = ed.get_main_view_id();
-                let new_config = self.config_manager.get_buffer_config(syntax,
-                                                                       identifier);
-                if let Some(changes) = ed.set_config(new_config) {
-                    to_notify.push((identifier, changes));
-                }
-            }
-        }
-        // update plugins after releasing the lock
-        for (view_id, changes) in to_notify.drain(..) {
-            self.plugins.document_config_changed(view_id, &changes);
-        }
-    }
-}
-
-#[cfg(feature = "ledger")]
-impl Drop for Documents {
-    fn drop(&mut self) {
-        use std::mem;
-        if let Some(repo) = mem::replace(&mut self.sync_repo, None) {
-            repo.tx.send(SyncMsg::Stop).unwrap();
-            repo.updater_handle.join().unwrap();
+        let mut editors = self.buffers.lock();
+        for ed in editors.iter_editors_mut() {
+            let syntax = ed.get_syntax().to_owned();
+            let identifier = ed.get_main_view_id();
+            let new_config = self.config_manager.get_buffer_config(syntax,
+                                                                   identifier);
+            ed.set_config(new_config);
         }
     }
 }
 
 impl DocumentCtx {
-/*
-    pub fn update_view(&self, view_id: ViewIdentifier, update: &Value) {
-        self.rpc_peer.send_rpc_notification("update",
-            &json!({
-                "view_id": view_id,
-                "update": update,
-            }));
+    pub fn update_view(&self, view_id: ViewIdentifier, update: ViewUpdate) {
+        self.observer.update(view_id, update);
     }
 
     pub fn scroll_to(&self, view_id: ViewIdentifier, line: usize, col: usize) {
-        self.rpc_peer.send_rpc_notification("scroll_to",
-            &json!({
-                "view_id": view_id,
-                "line": line,
-                "col": col,
-            }));
+        self.observer.scroll_to(view_id, line, col);
     }
 
-    pub fn config_changed(&self, view_id: &ViewIdentifier, changes: &Table) {
-        self.rpc_peer.send_rpc_notification("config_changed",
-                                            &json!({
-                                                "view_id": view_id,
-                                                "changes": changes,
-                                            }));
+    pub fn config_changed(&self, view_id: ViewIdentifier, config: &BufferConfig) {
+        self.observer.config_changed(view_id, &config.items);
     }
 
+    pub fn alert(&self, msg: &str) {
+        self.observer.alert(msg);
+    }
+/*
     /// Notify the client that a plugin ha started.
     pub fn plugin_started(&self, view_id: ViewIdentifier, plugin: &str) {
         self.rpc_peer.send_rpc_notification("plugin_started",
@@ -808,13 +693,6 @@ impl DocumentCtx {
                                                 "cmds": cmds,
                                             }));
     }
-
-    pub fn alert(&self, msg: &str) {
-        self.rpc_peer.send_rpc_notification("alert",
-            &json!({
-                "msg": msg,
-            }));
-    }
 */
     pub fn get_kill_ring(&self) -> Rope {
         self.kill_ring.lock().unwrap().clone()
@@ -830,7 +708,7 @@ impl DocumentCtx {
     }
 
     // Get the index for a given style. If the style is not in the existing
-    // style map, then issues a def_style request to the front end. Intended
+    // style map, then notifies the front end with `def_style`. Intended
     // to be reasonably efficient, but ideally callers would do their own
     // indexing.
     pub fn get_style_id(&self, style: &Style) -> usize {
@@ -840,7 +718,7 @@ impl DocumentCtx {
         }
         let ix = style_map.add(style);
         let style = style_map.merge_with_default(style);
-        // self.rpc_peer.send_rpc_notification("def_style", &style.to_json(ix));
+        self.observer.def_style(ix, &style);
         ix
     }
 /*
@@ -852,21 +730,6 @@ impl DocumentCtx {
 */
 }
 
-impl<'a> From<&'a str> for ViewIdentifier {
-    fn from(s: &'a str) -> Self {
-        let ord = s.trim_left_matches("view-id-");
-        let ident = usize::from_str_radix(ord, 10)
-            .expect("ViewIdentifier parsing should never fail");
-        ViewIdentifier(ident)
-    }
-}
-
-impl From<String> for ViewIdentifier {
-    fn from(s: String) -> Self {
-        s.as_str().into()
-    }
-}
-
 impl fmt::Display for ViewIdentifier {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "view-id-{}", self.0)
@@ -879,97 +742,103 @@ impl fmt::Display for BufferIdentifier {
     }
 }
 
-impl BufferIdentifier {
+impl ViewIdentifier {
     pub fn new(val: usize) -> Self {
-        BufferIdentifier(val)
+        ViewIdentifier(val)
     }
 }
 
-// =============== Fuchsia-specific synchronization plumbing
-// We can't move this elsewhere since it requires access to private fields
-
-#[cfg(not(feature = "ledger"))]
-pub struct SyncRepo;
-
-#[cfg(feature = "ledger")]
-use std::sync::mpsc::{channel, Sender};
-#[cfg(feature = "ledger")]
-use std::thread;
-#[cfg(feature = "ledger")]
-use fuchsia::sync::{SyncStore, SyncMsg, SyncUpdater, start_conflict_resolver_factory};
-
-#[cfg(feature = "ledger")]
-pub struct SyncRepo {
-    ledger: Ledger_Proxy,
-    tx: Sender<SyncMsg>,
-    updater_handle: thread::JoinHandle<()>,
-    session_id: (u64,u32),
-}
-
-#[cfg(feature = "ledger")]
-impl Documents {
-    pub fn setup_ledger(&mut self, mut ledger: Ledger_Proxy, session_id: (u64,u32)) {
-        let key = vec![0];
-        start_conflict_resolver_factory(&mut ledger, key);
-
-        let (tx, rx) = channel();
-        let updater = SyncUpdater::new(self.buffers.clone(), rx);
-        let updater_handle = thread::spawn(move|| updater.work().unwrap() );
-
-        self.sync_repo = Some(SyncRepo { ledger, tx, updater_handle, session_id });
-    }
-
-    fn initialize_sync(&mut self, editor: &mut Editor, path_opt: Option<&Path>, buffer_id: &BufferIdentifier) {
-        use apps_ledger_services_public::*;
-        use fuchsia::ledger::{ledger_crash_callback, gen_page_id};
-
-        if let (Some(path), Some(repo)) = (path_opt, self.sync_repo.as_mut()) {
-            // TODO this will panic when loading a file with initial contents.
-            // We haven't figured out what that even means in a multi-device
-            // context so it's not clear we can do anything better.
-            editor.set_session_id(repo.session_id);
-            // create the sync ID based on the path
-            // TODO: maybe make sync-id orthogonal to path
-            let path_str = path.to_string_lossy();
-            let path_bytes: &[u8] = path_str.as_bytes();
-            let sync_id = gen_page_id(path_bytes);
-            // get the page
-            let (page, page_request) = Page_new_pair();
-            repo.ledger.get_page(Some(sync_id.clone()), page_request).with(ledger_crash_callback);
-            // create the SyncStore
-            let sync_store = SyncStore::new(page, vec![0], repo.tx.clone(), buffer_id.clone());
-            // set the SyncStore for the Editor
-            editor.set_sync_store(sync_store);
-        }
+impl BufferIdentifier {
+    pub fn new(val: usize) -> Self {
+        BufferIdentifier(val)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use xi_rpc::{RpcLoop};
-    use std::env;
-    use std::fs::File;
-    use serde_json;
-
-    // a bit of gymnastics to let us instantiate an Editor instance
-    fn mock_doc_ctx(tempfile: &str) -> DocumentCtx {
-        let mut dir = env::temp_dir();
-        dir.push(tempfile);
-        let f = File::create(dir).unwrap();
-
-        let mock_loop = RpcLoop::new(f);
-        let mock_peer = mock_loop.get_raw_peer();
-        let (update_tx, _) = mpsc::channel();
-
+    use commands::{CommandError, EditorCommand, SelectionModifier};
+    use config::BufferItems;
+    use layers::PluginPid;
+    use movement::Movement;
+    use observer::{ChannelObserver, LineCacheOp, ViewEvent};
+    use rope::find::CaseMatching;
+    use rope::interval::Interval;
+    use rope::spans::SpansBuilder;
+    use rope::tree::Cursor;
+    use folding::FoldingMode;
+    use undo_tree::UndoStep;
+    use view::RenderedLine;
+
+    fn mock_doc_ctx() -> DocumentCtx {
         DocumentCtx {
             kill_ring: Arc::new(Mutex::new(Rope::from(""))),
-            rpc_peer: Box::new(mock_peer.clone()),
+            observer: Arc::new(NullObserver),
             style_map: Arc::new(Mutex::new(ThemeStyleMap::new())),
-            update_channel: update_tx,
+            width_measurer: None,
         }
     }
 
+    /// An editor with the default buffer config, changed by `configure`,
+    /// and with `text` typed into it.
+    fn mock_editor<F: FnOnce(&mut BufferItems)>(text: &str, configure: F) -> Editor {
+        let mut config = ConfigManager::default().default_buffer_config();
+        configure(&mut config.items);
+        let mut editor = Editor::new(mock_doc_ctx(), config,
+                                     BufferIdentifier(1), ViewIdentifier(1));
+        if !text.is_empty() {
+            editor.execute(EditorCommand::Insert(text.into())).unwrap();
+        }
+        editor
+    }
+
+    #[test]
+    fn test_channel_observer() {
+        let (observer, events) = ChannelObserver::new();
+        let doc_ctx = DocumentCtx { observer: Arc::new(observer), ..mock_doc_ctx() };
+        let config = ConfigManager::default().default_buffer_config();
+        let mut editor = Editor::new(doc_ctx, config, BufferIdentifier(1), ViewIdentifier(1));
+        let updates = || events.try_iter().filter_map(|event| match event {
+            ViewEvent::Update { update, .. } => Some(update.ops),
+            _ => None,
+        }).collect::<Vec<_>>();
+        let line = |text: &str, cursors: &[usize]| RenderedLine {
+            text: text.into(), styles: Vec::new(), cursors: cursors.to_vec(), folded: false,
+        };
+
+        editor.execute(EditorCommand::Scroll(0..2)).unwrap();
+        updates();
+        // the view follows the caret to the last line; the lines above it are out of
+        // view, and only invalidated
+        editor.execute(EditorCommand::Insert("a\nb\nc\nd\ne\nf".into())).unwrap();
+        assert_eq!(vec![vec![
+            LineCacheOp::Invalidate(2),
+            LineCacheOp::Insert(vec![line("c\n", &[]), line("d\n", &[]), line("e\n", &[]),
+                                     line("f", &[1])]),
+        ]], updates());
+
+        // lines that haven't changed are copied from the old cache
+        editor.execute(EditorCommand::GotoLine(1)).unwrap();
+        assert_eq!(vec![vec![
+            LineCacheOp::Insert(vec![line("a\n", &[]), line("b\n", &[0])]),
+            LineCacheOp::Skip(2),
+            LineCacheOp::Copy(3),
+            LineCacheOp::Invalidate(1),
+        ]], updates());
+
+        // a deleted line is skipped
+        editor.execute(EditorCommand::Move(Movement::Down, SelectionModifier::Extend)).unwrap();
+        updates();
+        editor.execute(EditorCommand::DeleteBackward).unwrap();
+        assert_eq!(vec![vec![
+            LineCacheOp::Copy(1),
+            LineCacheOp::Insert(vec![line("c\n", &[0])]),
+            LineCacheOp::Skip(2),
+            LineCacheOp::Copy(2),
+            LineCacheOp::Insert(vec![line("f", &[])]),
+        ]], updates());
+    }
+
     #[test]
     fn test_save_as() {
         let container_ref = BufferContainerRef::new();
@@ -979,7 +848,7 @@ mod tests {
         let buf_id_1 = BufferIdentifier(1);
         let path_1 = PathBuf::from("a_path");
         let path_2 = PathBuf::from("a_different_path");
-        let editor = Editor::new(mock_doc_ctx(&view_id_1.to_string()),
+        let editor = Editor::new(mock_doc_ctx(),
                                  config.clone(),
                                  buf_id_1, view_id_1);
         container_ref.add_editor(view_id_1, buf_id_1, editor);
@@ -987,7 +856,7 @@ mod tests {
 
         // set path (as if on save)
         container_ref.set_path(&path_1, view_id_1);
-        assert_eq!(container_ref.has_open_file(&path_1), true);
+        assert!(container_ref.has_open_file(&path_1));
         assert_eq!(
             container_ref.lock().editor_for_view(view_id_1).unwrap().get_path(),
             Some(path_1.as_ref()));
@@ -995,8 +864,8 @@ mod tests {
         // then save somewhere else:
         container_ref.set_path(&path_2, view_id_1);
         assert_eq!(container_ref.lock().editors.len(), 1);
-        assert_eq!(container_ref.has_open_file(&path_1), false);
-        assert_eq!(container_ref.has_open_file(&path_2), true);
+        assert!(!container_ref.has_open_file(&path_1));
+        assert!(container_ref.has_open_file(&path_2));
         assert_eq!(
             container_ref.lock().editor_for_view(view_id_1).unwrap().get_path(),
             Some(path_2.as_ref()));
@@ -1004,47 +873,640 @@ mod tests {
         // reopen the original file:
         let view_id_2 = ViewIdentifier(2);
         let buf_id_2 = BufferIdentifier(2);
-        let editor = Editor::new(mock_doc_ctx(&view_id_2.to_string()),
+        let editor = Editor::new(mock_doc_ctx(),
                                  config.clone(), buf_id_2, view_id_2);
         container_ref.add_editor(view_id_2, buf_id_2, editor);
         container_ref.set_path(&path_1, view_id_2);
         assert_eq!(container_ref.lock().editors.len(), 2);
-        assert_eq!(container_ref.has_open_file(&path_1), true);
-        assert_eq!(container_ref.has_open_file(&path_2), true);
+        assert!(container_ref.has_open_file(&path_1));
+        assert!(container_ref.has_open_file(&path_2));
 
         container_ref.close_view(view_id_1);
         assert_eq!(container_ref.lock().editors.len(), 1);
-        assert_eq!(container_ref.has_open_file(&path_2), false);
-        assert_eq!(container_ref.has_open_file(&path_1), true);
+        assert!(!container_ref.has_open_file(&path_2));
+        assert!(container_ref.has_open_file(&path_1));
 
         container_ref.close_view(view_id_2);
-        assert_eq!(container_ref.has_open_file(&path_2), false);
+        assert!(!container_ref.has_open_file(&path_2));
         assert_eq!(container_ref.lock().editors.len(), 0);
     }
 
     #[test]
-    fn test_id_serde() {
-        // check to see that struct with single string member serializes as string
-        let view_id = ViewIdentifier(8);
-        let as_val = serde_json::to_value(view_id).unwrap();
-        assert_eq!(as_val.to_string(), "\"view-id-8\"");
+    fn test_undo_tree() {
+        let mut editor = mock_editor("", |_| ());
+        editor.execute(EditorCommand::Insert("a".into())).unwrap();
+        editor.execute(EditorCommand::Undo).unwrap();
+        assert_eq!("", String::from(&editor.text));
+        // typing after an undo starts a branch, instead of dropping "a"
+        editor.execute(EditorCommand::Insert("b".into())).unwrap();
+        assert_eq!("b", String::from(&editor.text));
+        assert_eq!(vec![1, 2], editor.undo_tree.branches());
+
+        editor.execute(EditorCommand::GotoUndoState(1)).unwrap();
+        assert_eq!("a", String::from(&editor.text));
+        editor.execute(EditorCommand::UndoLater(UndoStep::Edits(1))).unwrap();
+        assert_eq!("b", String::from(&editor.text));
+        editor.execute(EditorCommand::UndoEarlier(UndoStep::Edits(2))).unwrap();
+        assert_eq!("", String::from(&editor.text));
+        // redo follows the branch that was visited last
+        editor.execute(EditorCommand::Redo).unwrap();
+        assert_eq!("b", String::from(&editor.text));
+
+        assert_eq!(Err(CommandError::UnknownUndoGroup(7)),
+                   editor.execute(EditorCommand::GotoUndoState(7)));
+    }
+
+    #[test]
+    fn test_undo_tree_pruned() {
+        let mut editor = mock_editor("", |_| ());
+        for _ in 0..1500 {
+            editor.execute(EditorCommand::Insert("a".into())).unwrap();
+            editor.execute(EditorCommand::Move(Movement::Left, SelectionModifier::None))
+                .unwrap();
+        }
+        assert_eq!(1500, editor.text.len());
+        // the oldest edits are dropped from the history, and can't be undone
+        assert!(editor.undo_tree.groups().len() <= 1000);
+        assert!(editor.engine.edit_undo_groups().len() <= 1000);
+        editor.execute(EditorCommand::UndoEarlier(UndoStep::Edits(2000))).unwrap();
+        assert!(editor.text.len() >= 500);
+        assert!(editor.text.len() < 1500);
+    }
+
+    #[test]
+    fn test_find_regex() {
+        let mut editor = mock_editor("foo1 bar22\nBaz333", |_| ());
+        editor.execute(EditorCommand::GotoLine(0)).unwrap();
+        let find = |chars: &str, regex| EditorCommand::Find {
+            chars: Some(chars.into()), case_matching: CaseMatching::CaseInsensitive,
+            whole_words: false, regex,
+        };
+        editor.execute(find("[a-z]+\\d{2,}", true)).unwrap();
+        editor.execute(EditorCommand::FindNext { wrap_around: false, allow_same: true }).unwrap();
+        let sel = &editor.view.sel_regions()[0];
+        assert_eq!((5, 10), (sel.min(), sel.max()));
+        editor.execute(EditorCommand::FindNext { wrap_around: false, allow_same: false }).unwrap();
+        let sel = &editor.view.sel_regions()[0];
+        assert_eq!((11, 17), (sel.min(), sel.max()));
+        // matches can span lines
+        editor.execute(EditorCommand::GotoLine(0)).unwrap();
+        editor.execute(find("\\d\\n\\w+", true)).unwrap();
+        editor.execute(EditorCommand::FindNext { wrap_around: false, allow_same: true }).unwrap();
+        let sel = &editor.view.sel_regions()[0];
+        assert_eq!((9, 17), (sel.min(), sel.max()));
+        // empty matches move the caret
+        editor.execute(EditorCommand::GotoLine(0)).unwrap();
+        editor.execute(find("^", true)).unwrap();
+        editor.execute(EditorCommand::FindNext { wrap_around: false, allow_same: false }).unwrap();
+        let sel = &editor.view.sel_regions()[0];
+        assert_eq!((11, 11), (sel.min(), sel.max()));
+        editor.execute(EditorCommand::FindNext { wrap_around: true, allow_same: false }).unwrap();
+        let sel = &editor.view.sel_regions()[0];
+        assert_eq!((0, 0), (sel.min(), sel.max()));
+
+        match editor.execute(find("[a-z", true)) {
+            Err(CommandError::InvalidRegex(_)) => (),
+            other => panic!("expected an invalid regex error, got {:?}", other),
+        }
+        // the same string is fine when searched literally
+        assert!(editor.execute(find("[a-z", false)).is_ok());
+    }
+
+    #[test]
+    fn test_find_whole_words() {
+        let mut editor = mock_editor("\u{dc}ber \u{fc}berall uber", |_| ());
+        editor.execute(EditorCommand::GotoLine(0)).unwrap();
+        editor.execute(EditorCommand::Find {
+            chars: Some("uber".into()), case_matching: CaseMatching::IgnoreAccents,
+            whole_words: true, regex: false,
+        }).unwrap();
+        let mut found = Vec::new();
+        for _ in 0..3 {
+            editor.execute(EditorCommand::FindNext { wrap_around: true, allow_same: false })
+                .unwrap();
+            let sel = &editor.view.sel_regions()[0];
+            found.push((sel.min(), sel.max()));
+        }
+        assert_eq!(vec![(0, 5), (15, 19), (0, 5)], found);
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut editor = mock_editor("a=1 b=2\nc=3", |_| ());
+        editor.execute(EditorCommand::GotoLine(0)).unwrap();
+        editor.execute(EditorCommand::Find {
+            chars: Some(r"(\w)=(\d)".into()), case_matching: CaseMatching::Exact,
+            whole_words: false, regex: true,
+        }).unwrap();
+
+        editor.execute(EditorCommand::ReplaceNext("$2=$1".into())).unwrap();
+        assert_eq!("1=a b=2\nc=3", String::from(&editor.text));
+        // the next occurrence is selected
+        let sel = &editor.view.sel_regions()[0];
+        assert_eq!((4, 7), (sel.min(), sel.max()));
+        editor.execute(EditorCommand::ReplaceNext("$2=$1".into())).unwrap();
+        editor.execute(EditorCommand::ReplaceNext("$2=$1".into())).unwrap();
+        assert_eq!("1=a 2=b\n3=c", String::from(&editor.text));
+        // with no occurrence left, the caret is put after the last replacement
+        let sel = &editor.view.sel_regions()[0];
+        assert_eq!((11, 11), (sel.min(), sel.max()));
+        editor.execute(EditorCommand::Undo).unwrap();
+        editor.execute(EditorCommand::Undo).unwrap();
+
+        editor.execute(EditorCommand::ReplaceAll("${1}_$2".into())).unwrap();
+        assert_eq!("1=a b_2\nc_3", String::from(&editor.text));
+        // a batch of replacements is undone at once
+        editor.execute(EditorCommand::Undo).unwrap();
+        assert_eq!("1=a b=2\nc=3", String::from(&editor.text));
+
+        editor.execute(EditorCommand::Find {
+            chars: Some("=".into()), case_matching: CaseMatching::Exact,
+            whole_words: false, regex: false,
+        }).unwrap();
+        editor.execute(EditorCommand::GotoLine(1)).unwrap();
+        editor.execute(EditorCommand::Move(Movement::EndOfDocument, SelectionModifier::Extend))
+            .unwrap();
+        editor.execute(EditorCommand::ReplaceInSelection(" := ".into())).unwrap();
+        assert_eq!("1=a b=2\nc := 3", String::from(&editor.text));
+
+        // empty matches are replaced too
+        let mut editor = mock_editor("abc\ndef", |_| ());
+        editor.execute(EditorCommand::Find {
+            chars: Some("$".into()), case_matching: CaseMatching::Exact,
+            whole_words: false, regex: true,
+        }).unwrap();
+        editor.execute(EditorCommand::ReplaceAll(";".into())).unwrap();
+        assert_eq!("abc;\ndef;", String::from(&editor.text));
+        editor.execute(EditorCommand::Undo).unwrap();
+        // an empty match right after another match is skipped, as with `regex::find_iter`
+        editor.execute(EditorCommand::Find {
+            chars: Some("[a-c]*".into()), case_matching: CaseMatching::Exact,
+            whole_words: false, regex: true,
+        }).unwrap();
+        editor.execute(EditorCommand::ReplaceAll("-".into())).unwrap();
+        assert_eq!("-\n-d-e-f-", String::from(&editor.text));
+    }
+
+    #[test]
+    fn test_block_selection() {
+        let mut editor = mock_editor("a\tbc\n\u{65e5}\u{672c}\u{8a9e}x\nab\nabcdefgh", |_| ());
+        let block_click = EditorCommand::Click {
+            line: 0, col: 2, modifier: SelectionModifier::Block, click_count: 1,
+        };
+        // columns 4 to 5: after the tab, and through the wide third character
+        editor.execute(block_click.clone()).unwrap();
+        editor.execute(EditorCommand::Drag { line: 3, col: 5 }).unwrap();
+        assert_eq!(4, editor.view.sel_regions().len());
+        assert_eq!(Ok(Some("b\n\u{8a9e}\n\ne".to_owned())), editor.execute(EditorCommand::Copy));
+
+        editor.execute(EditorCommand::Insert("X".into())).unwrap();
+        assert_eq!("a\tXc\n\u{65e5}\u{672c}Xx\nabX\nabcdXfgh", String::from(&editor.text));
+        // the block is kept, after the typed text
+        let cols = editor.view.block.map(|block| (block.anchor_col, block.active_col));
+        assert_eq!(Some((5, 5)), cols);
+        editor.execute(EditorCommand::Undo).unwrap();
+
+        // pasting the copied block puts one line in each region
+        editor.execute(block_click).unwrap();
+        editor.execute(EditorCommand::Drag { line: 3, col: 5 }).unwrap();
+        editor.execute(EditorCommand::Insert("1\n2\n3\n4\n".into())).unwrap();
+        assert_eq!("a\t1c\n\u{65e5}\u{672c}2x\nab3\nabcd4fgh", String::from(&editor.text));
+        editor.execute(EditorCommand::Undo).unwrap();
+
+        // a block pasted at a caret is padded, and extends the text
+        editor.execute(EditorCommand::GotoLine(2)).unwrap();
+        editor.execute(EditorCommand::Move(Movement::Right, SelectionModifier::None)).unwrap();
+        editor.execute(EditorCommand::InsertBlock("12\n34\n56".into())).unwrap();
+        assert_eq!("a\tbc\n\u{65e5}\u{672c}\u{8a9e}x\na12b\na34bcdefgh\n 56",
+                   String::from(&editor.text));
+        editor.execute(EditorCommand::Undo).unwrap();
+
+        // moving by a column steps over wide characters, and includes tabs
+        editor.execute(EditorCommand::GotoLine(1)).unwrap();
+        editor.execute(EditorCommand::Move(Movement::Right, SelectionModifier::Block)).unwrap();
+        editor.execute(EditorCommand::Move(Movement::Up, SelectionModifier::Block)).unwrap();
+        assert_eq!(Ok(Some("a\t\n\u{65e5}".to_owned())), editor.execute(EditorCommand::Cut));
+        assert_eq!("bc\n\u{672c}\u{8a9e}x\nab\nabcdefgh", String::from(&editor.text));
+    }
+
+    #[test]
+    fn test_add_next_occurrence() {
+        let mut editor = mock_editor("foo bar foo\nfoobar foo", |_| ());
+        editor.execute(EditorCommand::Click {
+            line: 0, col: 1, modifier: SelectionModifier::None, click_count: 1,
+        }).unwrap();
+        let regions = |editor: &Editor| editor.view.sel_regions().iter()
+            .map(|r| (r.min(), r.max()))
+            .collect::<Vec<_>>();
+
+        // the first one selects the word, then whole-word occurrences are added
+        editor.execute(EditorCommand::AddNextOccurrence).unwrap();
+        assert_eq!(vec![(0, 3)], regions(&editor));
+        editor.execute(EditorCommand::AddNextOccurrence).unwrap();
+        assert_eq!(vec![(0, 3), (8, 11)], regions(&editor));
+        editor.execute(EditorCommand::SkipOccurrence).unwrap();
+        assert_eq!(vec![(0, 3), (19, 22)], regions(&editor));
+        // wraps around, skipping what's already selected
+        editor.execute(EditorCommand::AddNextOccurrence).unwrap();
+        assert_eq!(vec![(0, 3), (8, 11), (19, 22)], regions(&editor));
+        editor.execute(EditorCommand::AddNextOccurrence).unwrap();
+        assert_eq!(3, editor.view.sel_regions().len());
+
+        editor.execute(EditorCommand::Insert("baz".into())).unwrap();
+        assert_eq!("baz bar baz\nfoobar baz", String::from(&editor.text));
+
+        // a selection that isn't a word matches anywhere
+        editor.execute(EditorCommand::Click {
+            line: 0, col: 0, modifier: SelectionModifier::None, click_count: 1,
+        }).unwrap();
+        editor.execute(EditorCommand::Move(Movement::Right, SelectionModifier::Extend)).unwrap();
+        editor.execute(EditorCommand::Move(Movement::Right, SelectionModifier::Extend)).unwrap();
+        editor.execute(EditorCommand::SelectAllOccurrences).unwrap();
+        assert_eq!(vec![(0, 2), (4, 6), (8, 10), (15, 17), (19, 21)], regions(&editor));
+    }
+
+    #[test]
+    fn test_auto_indent() {
+        let mut editor = mock_editor("fn f() {", |items| items.auto_indent = true);
+        editor.execute(EditorCommand::InsertNewline).unwrap();
+        editor.execute(EditorCommand::Insert("let x = (".into())).unwrap();
+        editor.execute(EditorCommand::InsertNewline).unwrap();
+        editor.execute(EditorCommand::Insert("1);;".into())).unwrap();
+        editor.execute(EditorCommand::DeleteBackward).unwrap();
+        editor.execute(EditorCommand::InsertNewline).unwrap();
+        assert_eq!("fn f() {\n    let x = (\n        1);\n        ", String::from(&editor.text));
+
+        // the newline and its indentation are undone together
+        editor.execute(EditorCommand::Undo).unwrap();
+        assert_eq!("fn f() {\n    let x = (\n        1);", String::from(&editor.text));
+
+        // a closing bracket after the cursor takes one level off
+        editor.execute(EditorCommand::Insert("}".into())).unwrap();
+        editor.execute(EditorCommand::Move(Movement::Left, SelectionModifier::None)).unwrap();
+        editor.execute(EditorCommand::InsertNewline).unwrap();
+        assert_eq!("fn f() {\n    let x = (\n        1);\n    }", String::from(&editor.text));
+        assert_eq!(editor.text.len() - 1, editor.view.sel_regions()[0].start);
+
+        // between an opener and its closer, the closer goes on its own line
+        let mut editor = mock_editor("  if a {}", |items| items.auto_indent = true);
+        editor.execute(EditorCommand::Move(Movement::Left, SelectionModifier::None)).unwrap();
+        editor.execute(EditorCommand::InsertNewline).unwrap();
+        assert_eq!("  if a {\n      \n  }", String::from(&editor.text));
+        assert_eq!(15, editor.view.sel_regions()[0].start);
+        editor.execute(EditorCommand::Insert("b();".into())).unwrap();
+        assert_eq!("  if a {\n      b();\n  }", String::from(&editor.text));
+
+        let manager = ConfigManager::default();
+        let mut config = manager.get_buffer_config(SyntaxDefinition::Python, None);
+        config.items.auto_indent = true;
+        let mut editor = Editor::new(mock_doc_ctx(), config,
+                                     BufferIdentifier(3), ViewIdentifier(3));
+        editor.execute(EditorCommand::Insert("if x:".into())).unwrap();
+        editor.execute(EditorCommand::InsertNewline).unwrap();
+        editor.execute(EditorCommand::Insert("return".into())).unwrap();
+        editor.execute(EditorCommand::InsertNewline).unwrap();
+        assert_eq!("if x:\n    return\n", String::from(&editor.text));
+    }
+
+    #[test]
+    fn test_indent_lines() {
+        let mut editor = mock_editor("a\n  b\n\nc\nd", |_| ());
+        // select from the middle of the first line to the start of the fourth
+        editor.execute(EditorCommand::Click {
+            line: 0, col: 1, modifier: SelectionModifier::None, click_count: 1,
+        }).unwrap();
+        editor.execute(EditorCommand::Drag { line: 3, col: 0 }).unwrap();
+
+        // indents to the next tab stop, skipping the empty line
+        editor.execute(EditorCommand::Indent).unwrap();
+        assert_eq!("    a\n    b\n\nc\nd", String::from(&editor.text));
+        let region = editor.view.sel_regions()[0].clone();
+        assert_eq!((5, 13), (region.start, region.end));
+        editor.execute(EditorCommand::Indent).unwrap();
+        assert_eq!("        a\n        b\n\nc\nd", String::from(&editor.text));
+        editor.execute(EditorCommand::Outdent).unwrap();
+        editor.execute(EditorCommand::Outdent).unwrap();
+        editor.execute(EditorCommand::Outdent).unwrap();
+        assert_eq!("a\nb\n\nc\nd", String::from(&editor.text));
+
+        // each one is a single undo group
+        editor.execute(EditorCommand::Undo).unwrap();
+        assert_eq!("    a\n    b\n\nc\nd", String::from(&editor.text));
+
+        editor.config.items.translate_tabs_to_spaces = false;
+        editor.config.items.use_tab_stops = false;
+        editor.execute(EditorCommand::Click {
+            line: 4, col: 1, modifier: SelectionModifier::None, click_count: 1,
+        }).unwrap();
+        editor.execute(EditorCommand::Indent).unwrap();
+        assert_eq!("    a\n    b\n\nc\n\td", String::from(&editor.text));
+        editor.execute(EditorCommand::Click {
+            line: 1, col: 0, modifier: SelectionModifier::None, click_count: 1,
+        }).unwrap();
+        editor.execute(EditorCommand::Indent).unwrap();
+        assert_eq!("    a\n\t\tb\n\nc\n\td", String::from(&editor.text));
+    }
+
+    #[test]
+    fn test_line_commands() {
+        let mut editor = mock_editor("one\ntwo\nthree", |_| ());
+        let click = |editor: &mut Editor, line, col| editor.execute(EditorCommand::Click {
+            line, col, modifier: SelectionModifier::None, click_count: 1,
+        }).unwrap();
+        let regions = |editor: &Editor| editor.view.sel_regions().iter()
+            .map(|r| (r.start, r.end))
+            .collect::<Vec<_>>();
+
+        click(&mut editor, 0, 1);
+        editor.execute(EditorCommand::DuplicateLine).unwrap();
+        assert_eq!("one\none\ntwo\nthree", String::from(&editor.text));
+        assert_eq!(vec![(5, 5)], regions(&editor));
+
+        // the last line has no line break, which stays at the end
+        click(&mut editor, 1, 2);
+        editor.execute(EditorCommand::Drag { line: 2, col: 1 }).unwrap();
+        editor.execute(EditorCommand::MoveLinesDown).unwrap();
+        assert_eq!("one\nthree\none\ntwo", String::from(&editor.text));
+        assert_eq!(vec![(12, 15)], regions(&editor));
+        editor.execute(EditorCommand::MoveLinesDown).unwrap();
+        assert_eq!("one\nthree\none\ntwo", String::from(&editor.text));
+        editor.execute(EditorCommand::MoveLinesUp).unwrap();
+        editor.execute(EditorCommand::MoveLinesUp).unwrap();
+        assert_eq!("one\ntwo\none\nthree", String::from(&editor.text));
+        assert_eq!(vec![(2, 5)], regions(&editor));
+
+        // undone in one step
+        editor.execute(EditorCommand::Undo).unwrap();
+        assert_eq!("one\none\ntwo\nthree", String::from(&editor.text));
+
+        click(&mut editor, 3, 2);
+        editor.execute(EditorCommand::MoveLinesUp).unwrap();
+        assert_eq!("one\none\nthree\ntwo", String::from(&editor.text));
+        assert_eq!(vec![(10, 10)], regions(&editor));
+
+        // the line break before the last line goes with it
+        click(&mut editor, 3, 1);
+        editor.execute(EditorCommand::DeleteLine).unwrap();
+        assert_eq!("one\none\nthree", String::from(&editor.text));
+        click(&mut editor, 0, 0);
+        editor.execute(EditorCommand::Drag { line: 1, col: 0 }).unwrap();
+        editor.execute(EditorCommand::DeleteLine).unwrap();
+        assert_eq!("one\nthree", String::from(&editor.text));
+
+        click(&mut editor, 1, 0);
+        editor.execute(EditorCommand::Insert("  \n\n   four  ".into())).unwrap();
+        assert_eq!("one\n  \n\n   four  three", String::from(&editor.text));
+        click(&mut editor, 0, 0);
+        editor.execute(EditorCommand::Drag { line: 3, col: 2 }).unwrap();
+        editor.execute(EditorCommand::JoinLines).unwrap();
+        assert_eq!("one four  three", String::from(&editor.text));
+    }
+
+    #[test]
+    fn test_auto_close_pairs() {
+        let mut editor = mock_editor("", |_| ());
+        let type_chars = |editor: &mut Editor, s: &str| for c in s.chars() {
+            editor.execute(EditorCommand::Insert(c.to_string())).unwrap();
+        };
+        type_chars(&mut editor, "f(");
+        assert_eq!("f()", String::from(&editor.text));
+        type_chars(&mut editor, "[\"a");
+        assert_eq!("f([\"a\"])", String::from(&editor.text));
+        // closers step over the ones inserted automatically
+        type_chars(&mut editor, "\"]");
+        assert_eq!("f([\"a\"])", String::from(&editor.text));
+        assert_eq!(7, editor.view.sel_regions()[0].start);
+        // no quote is added to a word
+        type_chars(&mut editor, ", don't");
+        assert_eq!("f([\"a\"], don't)", String::from(&editor.text));
+        type_chars(&mut editor, ")");
+        assert_eq!("f([\"a\"], don't)", String::from(&editor.text));
+        assert_eq!(editor.text.len(), editor.view.sel_regions()[0].start);
+
+        // backspace deletes an empty pair
+        type_chars(&mut editor, " {");
+        assert_eq!("f([\"a\"], don't) {}", String::from(&editor.text));
+        editor.execute(EditorCommand::DeleteBackward).unwrap();
+        assert_eq!("f([\"a\"], don't) ", String::from(&editor.text));
+
+        // an opener surrounds a selection
+        editor.execute(EditorCommand::Click {
+            line: 0, col: 4, modifier: SelectionModifier::None, click_count: 1,
+        }).unwrap();
+        editor.execute(EditorCommand::Drag { line: 0, col: 5 }).unwrap();
+        type_chars(&mut editor, "(");
+        assert_eq!("f([\"(a)\"], don't) ", String::from(&editor.text));
+        let region = editor.view.sel_regions()[0].clone();
+        assert_eq!((5, 6), (region.start, region.end));
+
+        // a closer that was typed isn't stepped over
+        editor.execute(EditorCommand::Click {
+            line: 0, col: 1, modifier: SelectionModifier::None, click_count: 1,
+        }).unwrap();
+        editor.execute(EditorCommand::Move(Movement::Right, SelectionModifier::None)).unwrap();
+        type_chars(&mut editor, "]");
+        assert_eq!("f(][\"(a)\"], don't) ", String::from(&editor.text));
+    }
+
+    #[test]
+    fn test_matching_bracket() {
+        let mut editor = mock_editor("f(a, \")\") {}", |_| ());
+        let text_len = editor.text.len();
+
+        // the bracket in the string is ignored
+        let mut spans = SpansBuilder::new(text_len);
+        spans.add_span(Interval::new_closed_open(5, 8), 0);
+        let layer = PluginPid::default();
+        editor.styles.add_scopes(layer, vec![vec!["string.quoted.double".into()]],
+                                 &editor.doc_ctx);
+        editor.styles.update_layer(layer, Interval::new_closed_open(0, text_len), spans.build());
+
+        editor.execute(EditorCommand::Click {
+            line: 0, col: 1, modifier: SelectionModifier::None, click_count: 1,
+        }).unwrap();
+        assert_eq!(vec![1, 8], editor.view.bracket_highlights);
+        editor.execute(EditorCommand::Move(Movement::MatchingBracket,
+                                           SelectionModifier::None)).unwrap();
+        assert_eq!(8, editor.view.sel_regions()[0].end);
+        editor.execute(EditorCommand::Move(Movement::MatchingBracket,
+                                           SelectionModifier::None)).unwrap();
+        assert_eq!(1, editor.view.sel_regions()[0].end);
+
+        // from after a bracket, to after its match, extending the selection
+        editor.execute(EditorCommand::Move(Movement::EndOfDocument,
+                                           SelectionModifier::None)).unwrap();
+        assert_eq!(vec![10, 11], editor.view.bracket_highlights);
+        editor.execute(EditorCommand::Move(Movement::MatchingBracket,
+                                           SelectionModifier::Extend)).unwrap();
+        let region = editor.view.sel_regions()[0].clone();
+        assert_eq!((12, 11), (region.start, region.end));
+        assert!(editor.view.bracket_highlights.is_empty());
+
+        // the index follows edits
+        editor.execute(EditorCommand::Click {
+            line: 0, col: 0, modifier: SelectionModifier::None, click_count: 1,
+        }).unwrap();
+        editor.execute(EditorCommand::Insert("[".into())).unwrap();
+        assert!(editor.view.bracket_highlights.is_empty());
+        editor.execute(EditorCommand::Move(Movement::EndOfDocument,
+                                           SelectionModifier::None)).unwrap();
+        editor.execute(EditorCommand::Insert("]".into())).unwrap();
+        assert_eq!(vec![0, 13], editor.view.bracket_highlights);
+    }
+
+    #[test]
+    fn test_toggle_comments() {
+        let mut editor = mock_editor("fn f() {\n    a();\n\n  b();\n}", |_| ());
+        editor.execute(EditorCommand::Click {
+            line: 1, col: 6, modifier: SelectionModifier::None, click_count: 1,
+        }).unwrap();
+        editor.execute(EditorCommand::Drag { line: 3, col: 2 }).unwrap();
+
+        // the markers line up at the smallest indentation, skipping blank lines
+        editor.execute(EditorCommand::ToggleLineComment).unwrap();
+        assert_eq!("fn f() {\n  //   a();\n\n  // b();\n}", String::from(&editor.text));
+        editor.execute(EditorCommand::ToggleLineComment).unwrap();
+        assert_eq!("fn f() {\n    a();\n\n  b();\n}", String::from(&editor.text));
+
+        // lines are only uncommented if they all are commented
+        editor.execute(EditorCommand::Click {
+            line: 3, col: 2, modifier: SelectionModifier::None, click_count: 1,
+        }).unwrap();
+        editor.execute(EditorCommand::ToggleLineComment).unwrap();
+        editor.execute(EditorCommand::Click {
+            line: 3, col: 0, modifier: SelectionModifier::None, click_count: 1,
+        }).unwrap();
+        editor.execute(EditorCommand::AddSelectionBelow).unwrap();
+        editor.execute(EditorCommand::ToggleLineComment).unwrap();
+        assert_eq!("fn f() {\n    a();\n\n//   // b();\n// }", String::from(&editor.text));
+        editor.execute(EditorCommand::ToggleLineComment).unwrap();
+        assert_eq!("fn f() {\n    a();\n\n  // b();\n}", String::from(&editor.text));
+
+        // a block comment wraps the selection, which stays on the same text
+        editor.execute(EditorCommand::Click {
+            line: 1, col: 4, modifier: SelectionModifier::None, click_count: 1,
+        }).unwrap();
+        editor.execute(EditorCommand::Drag { line: 1, col: 7 }).unwrap();
+        editor.execute(EditorCommand::ToggleBlockComment).unwrap();
+        assert_eq!("fn f() {\n    /* a() */;\n\n  // b();\n}", String::from(&editor.text));
+        let region = editor.view.sel_regions()[0].clone();
+        assert_eq!((16, 19), (region.start, region.end));
+        editor.execute(EditorCommand::ToggleBlockComment).unwrap();
+        assert_eq!("fn f() {\n    a();\n\n  // b();\n}", String::from(&editor.text));
+        let region = editor.view.sel_regions()[0].clone();
+        assert_eq!((13, 16), (region.start, region.end));
+
+        // syntaxes without line comments use block comments, around a caret's line
+        let markdown = ConfigManager::default().get_buffer_config(SyntaxDefinition::Markdown, None);
+        assert_eq!("", markdown.items.line_comment);
+        editor.config.items.line_comment = markdown.items.line_comment;
+        editor.config.items.block_comment = markdown.items.block_comment;
+        editor.execute(EditorCommand::Click {
+            line: 1, col: 5, modifier: SelectionModifier::None, click_count: 1,
+        }).unwrap();
+        editor.execute(EditorCommand::ToggleLineComment).unwrap();
+        assert_eq!("fn f() {\n    <!-- a(); -->\n\n  // b();\n}", String::from(&editor.text));
+        assert_eq!(19, editor.view.sel_regions()[0].end);
+    }
+
+    #[test]
+    fn test_folding() {
+        let mut editor = mock_editor(
+            "fn a() {\n    b();\n    c();\n}\nfn d() {\n    e();\n}", |_| ());
+        editor.execute(EditorCommand::Click {
+            line: 1, col: 6, modifier: SelectionModifier::None, click_count: 1,
+        }).unwrap();
+
+        // the caret moves out of the fold, and the hidden lines aren't counted
+        editor.execute(EditorCommand::Fold).unwrap();
+        assert_eq!(vec![(0, 2)], editor.view.folded_lines(&editor.text));
+        assert_eq!(8, editor.view.sel_regions()[0].end);
+        assert_eq!(4, editor.view.line_of_offset(&editor.text, editor.text.len()));
+        assert_eq!(27, editor.view.offset_of_line(&editor.text, 1));
+        let mut cursor = Cursor::new(&editor.text, 0);
+        let line = editor.view.render_line(&editor.doc_ctx, &editor.text, &mut cursor, None,
+                                           editor.styles.get_merged(), 0);
+        assert_eq!(("fn a() {\n", true), (line.text.as_str(), line.folded));
+        assert_eq!(27, cursor.pos());
+
+        // horizontal movement skips over the fold
+        editor.execute(EditorCommand::Move(Movement::Right, SelectionModifier::None)).unwrap();
+        assert_eq!(27, editor.view.sel_regions()[0].end);
+        editor.execute(EditorCommand::Move(Movement::Left, SelectionModifier::None)).unwrap();
+        assert_eq!(8, editor.view.sel_regions()[0].end);
+
+        // the fold is carried through edits, as long as it spans whole lines
+        editor.execute(EditorCommand::Click {
+            line: 0, col: 0, modifier: SelectionModifier::None, click_count: 1,
+        }).unwrap();
+        editor.execute(EditorCommand::Insert("// x\n".into())).unwrap();
+        assert_eq!(vec![(1, 3)], editor.view.folded_lines(&editor.text));
+        editor.execute(EditorCommand::Move(Movement::Left, SelectionModifier::None)).unwrap();
+        editor.execute(EditorCommand::DeleteForward).unwrap();
+        assert_eq!(vec![(0, 2)], editor.view.folded_lines(&editor.text));
+        editor.execute(EditorCommand::Insert("\n".into())).unwrap();
+
+        // folded text can be found, which reveals it
+        editor.execute(EditorCommand::Find {
+            chars: Some("c()".into()), case_matching: CaseMatching::Exact,
+            whole_words: false, regex: false,
+        }).unwrap();
+        editor.execute(EditorCommand::FindNext { wrap_around: true, allow_same: false })
+            .unwrap();
+        assert_eq!(27, editor.view.sel_regions()[0].min());
+        assert!(editor.view.folded_lines(&editor.text).is_empty());
+
+        // folding everything folds the outermost regions, and unfolding
+        // acts on the lines of the carets
+        editor.execute(EditorCommand::FoldAll).unwrap();
+        assert_eq!(vec![(1, 3), (5, 6)], editor.view.folded_lines(&editor.text));
+        editor.execute(EditorCommand::Click {
+            line: 3, col: 0, modifier: SelectionModifier::None, click_count: 1,
+        }).unwrap();
+        editor.execute(EditorCommand::Unfold).unwrap();
+        assert_eq!(vec![(1, 3)], editor.view.folded_lines(&editor.text));
+        editor.execute(EditorCommand::UnfoldAll).unwrap();
+        assert!(editor.view.folded_lines(&editor.text).is_empty());
+
+        // regions can be found by indentation, or supplied by a plugin
+        editor.config.items.folding = FoldingMode::Indentation;
+        assert_eq!(vec![(1, 3), (5, 6)], editor.fold_regions());
+        editor.set_fold_regions(Some(vec![(5, 16)]));
+        editor.execute(EditorCommand::Click {
+            line: 0, col: 0, modifier: SelectionModifier::None, click_count: 1,
+        }).unwrap();
+        editor.execute(EditorCommand::Insert("\n".into())).unwrap();
+        editor.execute(EditorCommand::FoldAll).unwrap();
+        assert_eq!(vec![(2, 3)], editor.view.folded_lines(&editor.text));
     }
 
     #[test]
-    fn test_struct_serde() {
-        #[derive(Serialize, Deserialize)]
-        struct TestStruct {
-            name: String,
-            view: ViewIdentifier,
-            flag: u64,
+    fn test_folding_many() {
+        let text = (0..100).map(|i| format!("fn f{}() {{\n    x();\n    y();\n}}\n", i))
+            .collect::<String>();
+        let mut editor = mock_editor(&text, |_| ());
+        editor.execute(EditorCommand::FoldAll).unwrap();
+        assert_eq!(100, editor.view.folds.count());
+        for i in 0..100 {
+            let header = editor.text.offset_of_line(i * 4);
+            let hidden = editor.text.offset_of_line(i * 4 + 2);
+            let close = editor.text.offset_of_line(i * 4 + 3);
+            assert_eq!(header, editor.view.offset_of_line(&editor.text, i * 2));
+            assert_eq!(close, editor.view.offset_of_line(&editor.text, i * 2 + 1));
+            assert_eq!(i * 2, editor.view.line_of_offset(&editor.text, header));
+            assert_eq!(i * 2, editor.view.line_of_offset(&editor.text, hidden));
+            assert_eq!(i * 2 + 1, editor.view.line_of_offset(&editor.text, close));
         }
-        let json = r#"
-        {"name": "victor",
-         "view": "view-id-6",
-         "flag": 42
-        }"#;
-
-        let result: TestStruct = serde_json::from_str(json).unwrap();
-        assert_eq!(result.view, ViewIdentifier(6));
+        assert_eq!(200, editor.view.line_of_offset(&editor.text, editor.text.len()));
+
+        // an edit between folds only moves the ones after it
+        editor.execute(EditorCommand::Click {
+            line: 100, col: 0, modifier: SelectionModifier::None, click_count: 1,
+        }).unwrap();
+        editor.execute(EditorCommand::Insert("// z\n".into())).unwrap();
+        assert_eq!(100, editor.view.folds.count());
+        assert_eq!((201, 203), editor.view.folded_lines(&editor.text)[50]);
+        assert_eq!(editor.text.offset_of_line(204),
+                   editor.view.offset_of_line(&editor.text, 102));
     }
 }
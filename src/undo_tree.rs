@@ -0,0 +1,484 @@
+// Copyright 2018 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A branching undo history.
+//!
+//! Every undo group recorded by the `Editor` becomes a node in an
+//! `UndoTree`, whose parent is the state the buffer was in when the group
+//! was started. Editing after an undo starts a new branch instead of
+//! discarding the undone groups, so every state the buffer has been in can
+//! be returned to.
+//!
+//! The tree doesn't touch the text itself: the groups on the path from the
+//! root to the current node are the ones that are applied, and all others
+//! are undone. `undone_groups` gives the set to pass to `Engine::undo`.
+//!
+//! To bound its size, `prune` drops the oldest branches off the path to the
+//! current state, and then the oldest groups on that path, whose edits can
+//! no longer be undone; the groups it returns should be passed to
+//! `Engine::gc`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
+
+/// The undo group of the initial text, which can't be undone.
+const ROOT: usize = 0;
+
+/// An amount to move through the history with `UndoTree::earlier` and
+/// `UndoTree::later`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UndoStep {
+    /// A number of undo groups, in the order they were created,
+    /// regardless of which branch they're on.
+    Edits(usize),
+    /// An amount of wall-clock time. The times of groups restored from a
+    /// saved history aren't known, so time steps don't move to them.
+    Time(Duration),
+}
+
+struct UndoNode {
+    parent: Option<usize>,
+    children: Vec<usize>,
+    /// The child that `redo` moves to; the one most recently visited.
+    redo_child: Option<usize>,
+    /// The position of this node in creation order.
+    seq: usize,
+    /// The time of the last edit in this group, if it was made since the
+    /// tree was created or restored.
+    time: Option<Instant>,
+}
+
+pub struct UndoTree {
+    nodes: BTreeMap<usize, UndoNode>,
+    /// Groups in creation order, indexed by `seq`.
+    by_seq: Vec<usize>,
+    current: usize,
+}
+
+impl UndoTree {
+    /// Creates a tree containing only the initial state.
+    pub fn new() -> UndoTree {
+        let root = UndoNode {
+            parent: None,
+            children: Vec::new(),
+            redo_child: None,
+            seq: 0,
+            time: Some(Instant::now()),
+        };
+        let mut nodes = BTreeMap::new();
+        nodes.insert(ROOT, root);
+        UndoTree { nodes, by_seq: vec![ROOT], current: ROOT }
+    }
+
+    /// Reconstructs a tree from the undo groups of a history, in the order
+    /// they were created, and the set of groups that are undone.
+    ///
+    /// Applied groups form the path to the current state. Which branch an
+    /// undone group belongs to isn't recorded, so each one is attached to
+    /// the group created just before it. When the groups were made isn't
+    /// recorded either, so they have no time.
+    pub fn from_groups(groups: &[usize], undone: &BTreeSet<usize>) -> UndoTree {
+        let mut tree = UndoTree::new();
+        tree.nodes.get_mut(&ROOT).unwrap().time = None;
+        let mut prev = ROOT;
+        let mut current = ROOT;
+        for &group in groups.iter().filter(|&&g| g != ROOT) {
+            let parent = if undone.contains(&group) { prev } else { current };
+            tree.add_node(group, parent, None);
+            if !undone.contains(&group) {
+                current = group;
+            }
+            prev = group;
+        }
+        tree.set_current(current);
+        tree
+    }
+
+    /// The undo group of the current state.
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn contains(&self, group: usize) -> bool {
+        self.nodes.contains_key(&group)
+    }
+
+    /// The state `group` was started from, or `None` for the root.
+    pub fn parent(&self, group: usize) -> Option<usize> {
+        self.nodes.get(&group).and_then(|node| node.parent)
+    }
+
+    /// The groups started from `group`, oldest first.
+    pub fn children(&self, group: usize) -> &[usize] {
+        self.nodes.get(&group).map(|node| &node.children[..]).unwrap_or(&[])
+    }
+
+    /// The time of the last edit in `group`, if it's known.
+    pub fn time(&self, group: usize) -> Option<Instant> {
+        self.nodes.get(&group).and_then(|node| node.time)
+    }
+
+    /// All groups in the tree, in the order they were created.
+    pub fn groups(&self) -> &[usize] {
+        &self.by_seq
+    }
+
+    /// The tips of all branches, in the order they were created.
+    pub fn branches(&self) -> Vec<usize> {
+        self.by_seq.iter()
+            .filter(|&&group| self.nodes[&group].children.is_empty())
+            .cloned()
+            .collect()
+    }
+
+    /// The groups from the root to `group`, inclusive.
+    pub fn path_to(&self, group: usize) -> Vec<usize> {
+        let mut path = Vec::new();
+        let mut next = if self.contains(group) { Some(group) } else { None };
+        while let Some(group) = next {
+            path.push(group);
+            next = self.nodes[&group].parent;
+        }
+        path.reverse();
+        path
+    }
+
+    /// The groups that are undone in the current state.
+    pub fn undone_groups(&self) -> BTreeSet<usize> {
+        let done = self.path_to(self.current).into_iter().collect::<BTreeSet<_>>();
+        self.nodes.keys().filter(|group| !done.contains(group)).cloned().collect()
+    }
+
+    /// Records an edit in `group`, which is either the current group, or a
+    /// new group that starts a branch from the current state.
+    pub fn record_edit(&mut self, group: usize) {
+        self.record_edit_at(group, Instant::now());
+    }
+
+    fn record_edit_at(&mut self, group: usize, time: Instant) {
+        if group == self.current {
+            self.nodes.get_mut(&group).unwrap().time = Some(time);
+        } else {
+            assert!(!self.contains(group), "undo group {} already recorded", group);
+            // the path to the current state already leads redo to it
+            let parent = self.current;
+            self.add_node(group, parent, Some(time));
+            self.nodes.get_mut(&parent).unwrap().redo_child = Some(group);
+            self.current = group;
+        }
+    }
+
+    /// Moves to the parent of the current state. Returns `false` if there
+    /// is nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        match self.nodes[&self.current].parent {
+            Some(parent) => self.jump_to(parent),
+            None => false,
+        }
+    }
+
+    /// Moves to the most recently visited child of the current state.
+    /// Returns `false` if there is nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        let node = &self.nodes[&self.current];
+        match node.redo_child.or_else(|| node.children.last().cloned()) {
+            Some(child) => self.jump_to(child),
+            None => false,
+        }
+    }
+
+    /// Moves to the state after `group`. Returns `false` if `group` isn't
+    /// in the tree or is already the current state.
+    pub fn jump_to(&mut self, group: usize) -> bool {
+        if group == self.current || !self.contains(group) {
+            return false;
+        }
+        self.set_current(group);
+        true
+    }
+
+    /// Moves back through the states in the order they were created, like
+    /// vim's `:earlier`. Returns `false` if the current state didn't change.
+    pub fn earlier(&mut self, step: UndoStep) -> bool {
+        let seq = self.nodes[&self.current].seq;
+        let target = match step {
+            UndoStep::Edits(n) => self.by_seq[seq.saturating_sub(n)],
+            UndoStep::Time(duration) => {
+                let time = match self.time(self.current) {
+                    Some(time) => time.checked_sub(duration),
+                    None => return false,
+                };
+                let timed = self.by_seq[..seq].iter().cloned()
+                    .filter(|&group| self.time(group).is_some());
+                // go back as far as the oldest state whose time is known
+                let oldest = timed.clone().next().unwrap_or(self.current);
+                timed.filter(|&group| time.is_some_and(|t| self.time(group) <= Some(t)))
+                    .max_by_key(|&group| self.time(group))
+                    .unwrap_or(oldest)
+            }
+        };
+        self.jump_to(target)
+    }
+
+    /// Moves forward through the states in the order they were created, like
+    /// vim's `:later`. Returns `false` if the current state didn't change.
+    pub fn later(&mut self, step: UndoStep) -> bool {
+        let seq = self.nodes[&self.current].seq;
+        let target = match step {
+            UndoStep::Edits(n) => {
+                let last = self.by_seq.len() - 1;
+                self.by_seq[seq.saturating_add(n).min(last)]
+            }
+            UndoStep::Time(duration) => {
+                let time = match self.time(self.current) {
+                    Some(time) => time + duration,
+                    None => return false,
+                };
+                self.by_seq[seq + 1..].iter().cloned()
+                    .filter(|&group| self.time(group).is_some_and(|t| t <= time))
+                    .max_by_key(|&group| self.time(group))
+                    .unwrap_or(self.current)
+            }
+        };
+        self.jump_to(target)
+    }
+
+    /// Removes groups until at most `max` are left, or only the current
+    /// state is. The oldest groups off the path to the current state go
+    /// first, a branch tip at a time; once only the path remains, its root
+    /// is removed and the next group on it becomes the root, so the oldest
+    /// edits can no longer be undone. Returns the removed groups.
+    pub fn prune(&mut self, max: usize) -> BTreeSet<usize> {
+        let mut removed = BTreeSet::new();
+        if self.nodes.len() <= max.max(1) {
+            return removed;
+        }
+        let mut path = self.path_to(self.current);
+        let on_path = path.iter().cloned().collect::<BTreeSet<_>>();
+        let mut tips = self.by_seq.iter().cloned()
+            .filter(|group| !on_path.contains(group) && self.nodes[group].children.is_empty())
+            .collect::<BTreeSet<_>>();
+        while self.nodes.len() > max.max(1) {
+            let tip = tips.iter().cloned().min_by_key(|group| self.nodes[group].seq);
+            if let Some(tip) = tip {
+                tips.remove(&tip);
+                let node = self.nodes.remove(&tip).unwrap();
+                let parent_group = node.parent.unwrap();
+                let parent = self.nodes.get_mut(&parent_group).unwrap();
+                parent.children.retain(|&child| child != tip);
+                if parent.redo_child == Some(tip) {
+                    parent.redo_child = None;
+                }
+                if parent.children.is_empty() && !on_path.contains(&parent_group) {
+                    tips.insert(parent_group);
+                }
+                removed.insert(tip);
+            } else {
+                let root = path.remove(0);
+                self.nodes.remove(&root);
+                self.nodes.get_mut(&path[0]).unwrap().parent = None;
+                removed.insert(root);
+            }
+        }
+        self.by_seq.retain(|group| !removed.contains(group));
+        for (seq, group) in self.by_seq.iter().enumerate() {
+            self.nodes.get_mut(group).unwrap().seq = seq;
+        }
+        removed
+    }
+
+    fn add_node(&mut self, group: usize, parent: usize, time: Option<Instant>) {
+        let node = UndoNode {
+            parent: Some(parent),
+            children: Vec::new(),
+            redo_child: None,
+            seq: self.by_seq.len(),
+            time,
+        };
+        self.nodes.get_mut(&parent).unwrap().children.push(group);
+        self.nodes.insert(group, node);
+        self.by_seq.push(group);
+    }
+
+    /// Makes `group` current, and remembers the path to it for `redo`.
+    fn set_current(&mut self, group: usize) {
+        let mut child = group;
+        while let Some(parent) = self.nodes[&child].parent {
+            self.nodes.get_mut(&parent).unwrap().redo_child = Some(child);
+            child = parent;
+        }
+        self.current = group;
+    }
+}
+
+impl Default for UndoTree {
+    fn default() -> UndoTree {
+        UndoTree::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(groups: &[usize]) -> BTreeSet<usize> {
+        groups.iter().cloned().collect()
+    }
+
+    #[test]
+    fn branch_after_undo() {
+        let mut tree = UndoTree::new();
+        tree.record_edit(1);
+        tree.record_edit(1);
+        tree.record_edit(2);
+        assert!(tree.undo());
+        tree.record_edit(3);
+        assert_eq!(3, tree.current());
+        assert_eq!(&[2, 3], tree.children(1));
+        assert_eq!(vec![2, 3], tree.branches());
+        assert_eq!(set(&[2]), tree.undone_groups());
+
+        // redo follows the branch that was visited last
+        assert!(tree.undo());
+        assert!(tree.undo());
+        assert!(!tree.undo());
+        assert_eq!(set(&[1, 2, 3]), tree.undone_groups());
+        assert!(tree.redo());
+        assert!(tree.redo());
+        assert_eq!(3, tree.current());
+        assert!(!tree.redo());
+
+        assert!(tree.jump_to(2));
+        assert_eq!(vec![0, 1, 2], tree.path_to(2));
+        assert_eq!(set(&[3]), tree.undone_groups());
+        assert!(!tree.jump_to(2));
+        assert!(!tree.jump_to(42));
+        tree.undo();
+        tree.redo();
+        assert_eq!(2, tree.current());
+    }
+
+    #[test]
+    fn earlier_later_edits() {
+        let mut tree = UndoTree::new();
+        tree.record_edit(1);
+        tree.record_edit(2);
+        tree.undo();
+        tree.record_edit(3);
+        // creation order is 0, 1, 2, 3, whatever the branch
+        assert!(tree.earlier(UndoStep::Edits(1)));
+        assert_eq!(2, tree.current());
+        assert!(tree.earlier(UndoStep::Edits(5)));
+        assert_eq!(0, tree.current());
+        assert!(!tree.earlier(UndoStep::Edits(1)));
+        assert!(tree.later(UndoStep::Edits(1)));
+        assert_eq!(1, tree.current());
+        assert!(tree.later(UndoStep::Edits(10)));
+        assert_eq!(3, tree.current());
+        assert!(!tree.later(UndoStep::Edits(1)));
+    }
+
+    #[test]
+    fn earlier_later_time() {
+        let start = Instant::now();
+        let minutes = |n: u64| start + Duration::from_secs(60 * n);
+        let mut tree = UndoTree::new();
+        tree.record_edit_at(1, minutes(1));
+        tree.record_edit_at(2, minutes(2));
+        tree.record_edit_at(3, minutes(10));
+        tree.record_edit_at(3, minutes(11));
+
+        assert!(tree.earlier(UndoStep::Time(Duration::from_secs(60 * 5))));
+        assert_eq!(2, tree.current());
+        assert!(!tree.later(UndoStep::Time(Duration::from_secs(60))));
+        assert!(tree.later(UndoStep::Time(Duration::from_secs(60 * 9))));
+        assert_eq!(3, tree.current());
+        assert!(tree.earlier(UndoStep::Time(Duration::from_secs(60 * 60))));
+        assert_eq!(0, tree.current());
+    }
+
+    #[test]
+    fn from_groups() {
+        let tree = UndoTree::from_groups(&[0, 1, 2, 3, 4], &set(&[2, 3]));
+        assert_eq!(4, tree.current());
+        assert_eq!(vec![0, 1, 4], tree.path_to(4));
+        assert_eq!(vec![0, 1, 2, 3], tree.path_to(3));
+        assert_eq!(set(&[2, 3]), tree.undone_groups());
+    }
+
+    #[test]
+    fn time_steps_after_restore() {
+        let start = Instant::now();
+        let minutes = |n: u64| start + Duration::from_secs(60 * n);
+        let mut tree = UndoTree::from_groups(&[0, 1, 2], &set(&[]));
+        assert_eq!(None, tree.time(2));
+        assert!(!tree.earlier(UndoStep::Time(Duration::from_secs(60))));
+        tree.record_edit_at(3, minutes(1));
+        tree.record_edit_at(4, minutes(2));
+
+        // restored groups are only reached by counting edits
+        assert!(tree.earlier(UndoStep::Time(Duration::from_secs(60 * 60))));
+        assert_eq!(3, tree.current());
+        assert!(!tree.earlier(UndoStep::Time(Duration::from_secs(60))));
+        assert!(tree.earlier(UndoStep::Edits(1)));
+        assert_eq!(2, tree.current());
+        assert!(!tree.later(UndoStep::Time(Duration::from_secs(60))));
+    }
+
+    #[test]
+    fn prune() {
+        let mut tree = UndoTree::new();
+        tree.record_edit(1);
+        tree.record_edit(2);
+        tree.undo();
+        tree.record_edit(3);
+        tree.record_edit(4);
+        tree.undo();
+        tree.undo();
+        tree.record_edit(5);
+        // 0 -> 1 -> 2, 0 -> 1 -> 3 -> 4 and 0 -> 1 -> 5
+        assert!(tree.prune(10).is_empty());
+        assert_eq!(set(&[2, 4]), tree.prune(4));
+        assert_eq!(vec![0, 1, 3, 5], tree.groups());
+        assert_eq!(set(&[3]), tree.undone_groups());
+        // once only the path to the current state is left, its oldest
+        // groups go, and can't be undone to any more
+        assert_eq!(set(&[0, 3]), tree.prune(2));
+        assert_eq!(vec![1, 5], tree.groups());
+        assert!(tree.undone_groups().is_empty());
+        assert!(tree.earlier(UndoStep::Edits(1)));
+        assert_eq!(1, tree.current());
+        assert!(!tree.undo());
+        assert!(tree.redo());
+        assert_eq!(5, tree.current());
+        // the current state is always kept
+        assert_eq!(set(&[1]), tree.prune(0));
+        assert_eq!(vec![5], tree.groups());
+        assert_eq!(None, tree.parent(5));
+    }
+
+    #[test]
+    fn prune_linear() {
+        let mut tree = UndoTree::new();
+        for group in 1..3000 {
+            tree.record_edit(group);
+            if tree.groups().len() > 1000 {
+                tree.prune(750);
+            }
+        }
+        assert!(tree.groups().len() <= 1000);
+        assert_eq!(2999, tree.current());
+        assert_eq!(tree.groups().to_vec(), tree.path_to(2999));
+    }
+}
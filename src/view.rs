@@ -14,23 +14,28 @@
 
 use std::cmp::{min,max};
 use std::mem;
+use std::sync::Arc;
 
-use rope::rope::{Rope, LinesMetric, RopeInfo};
+use rope::rope::{BaseMetric, Rope, LinesMetric, RopeInfo};
 use rope::delta::{Delta, DeltaRegion};
 use rope::tree::{Cursor, Metric};
 use rope::breaks::{Breaks, BreaksInfo, BreaksMetric, BreaksBaseMetric};
 use rope::interval::Interval;
 use rope::spans::Spans;
-use rope::find::{find, CaseMatching};
+use rope::folds::{Folds, FoldsBuilder};
+use rope::find::{find, find_regex_before, CaseMatching, RegexMatcher};
 
 use tabs::{ViewIdentifier, DocumentCtx};
+use observer::{LineCacheOp, ViewUpdate};
 use styles::Style;
 use index_set::IndexSet;
-use selection::{Affinity, Selection, SelRegion};
-use movement::{Movement, selection_movement};
+use selection::{Affinity, BlockSelection, Selection, SelRegion};
+use movement::{Movement, region_movement, selection_movement};
 use line_cache_shadow::{self, LineCacheShadow, RenderPlan, RenderTactic};
+use word_boundaries::{is_whole_word, WordBoundaryMode};
+use unicode::char_width;
 
-use linewrap;
+use linewrap::{self, WidthMeasurer};
 
 const BACKWARDS_FIND_CHUNK_SIZE: usize = 32_768;
 
@@ -39,12 +44,25 @@ pub struct View {
     /// The selection state for this view. Invariant: non-empty.
     pub selection: Selection,
     pub drag_state: Option<DragState>,
+    /// The rectangle the selection was made from, while it is a block
+    /// selection. Any other change to the selection clears it.
+    pub block: Option<BlockSelection>,
+    /// The region most recently added by `add_next_occurrence`, from which
+    /// the next occurrence is searched. Any other change to the selection
+    /// clears it.
+    pub last_added_occurrence: Option<SelRegion>,
     /// vertical scroll position
     pub first_line: usize,
     /// height of visible portion
     pub height: usize,  
     pub breaks: Option<Breaks>,
-    pub wrap_col: usize,
+    /// The width lines are wrapped at, in the units of `width_measurer`.
+    pub wrap_width: f64,
+    /// Measures text for line wrapping, while lines are wrapped.
+    pub width_measurer: Option<Arc<WidthMeasurer>>,
+    /// The folded regions of the text. Lines are counted as shown, so the
+    /// lines hidden in a fold don't have a line number of their own.
+    pub folds: Folds,
     /// Front end's line cache state for this view. See the `LineCacheShadow`
     /// description for the invariant.
     pub lc_shadow: LineCacheShadow,
@@ -56,16 +74,32 @@ pub struct View {
     pub search_string: Option<String>,
     /// The case matching setting for the currently active search
     pub case_matching: CaseMatching,
+    /// The compiled search string, if the active search is a regular expression
+    pub search_regex: Option<RegexMatcher>,
+    /// Whether the currently active search only matches whole words
+    pub whole_words: bool,
     /// The set of all known find occurrences (highlights)
     pub occurrences: Option<Selection>,
     /// Set of ranges that have already been searched for the currently active search string
     pub valid_search: IndexSet,
+    /// How word movements split the text into words.
+    pub word_boundaries: WordBoundaryMode,
+    /// The offsets of the brackets next to the carets and the brackets
+    /// matching them, which are highlighted.
+    pub bracket_highlights: Vec<usize>,
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct RenderedLine {
     pub text: String,
+    /// The styles of the line, as triples of the offset from the end of the
+    /// previous span, the length and the style id. The ids below
+    /// `N_RESERVED_STYLES` are never defined with `def_style`: 0 is the
+    /// selection, 1 find highlights and 2 matching brackets.
     pub styles: Vec<isize>,
     pub cursors: Vec<usize>,
+    /// Whether lines following this one are folded into it.
+    pub folded: bool,
 }
 
 /// State required to resolve a drag gesture into a selection.
@@ -92,19 +126,27 @@ impl View {
         });
         View {
             view_id: view_id.to_owned(),
-            selection: selection,
+            selection,
             drag_state: None,
+            block: None,
+            last_added_occurrence: None,
             first_line: 0,
             height: 10,
             breaks: None,
-            wrap_col: 0,
+            wrap_width: 0.0,
+            width_measurer: None,
+            folds: Folds::default(),
             lc_shadow: LineCacheShadow::default(),
             hls_dirty: true,
             pristine: true,
             search_string: None,
             case_matching: CaseMatching::CaseInsensitive,
+            search_regex: None,
+            whole_words: false,
             occurrences: None,
             valid_search: IndexSet::new(),
+            word_boundaries: WordBoundaryMode::default(),
+            bracket_highlights: Vec::new(),
         }
     }
 
@@ -140,7 +182,7 @@ impl View {
         }
         self.drag_state = Some(DragState {
             base_sel: selection.clone(),
-            offset: offset,
+            offset,
             min: offset,
             max: offset,
         });
@@ -183,6 +225,9 @@ impl View {
     /// the offset of a point that should be scrolled into view.
     fn set_selection_for_edit(&mut self, text: &Rope, sel: Selection) -> Option<usize> {
         self.selection = sel;
+        self.block = None;
+        self.last_added_occurrence = None;
+        self.reveal_selection(text);
         // We somewhat arbitrarily choose the last region for setting the old-style
         // selection state, and for scrolling it into view if needed. This choice can
         // likely be improved.
@@ -192,13 +237,32 @@ impl View {
     }
 
     /// Sets the selection to a new value, invalidating the line cache as needed. This
-    /// function does not perform any scrolling.
+    /// function does not perform any scrolling. Folds hiding an end of a region
+    /// are unfolded, so a search hit inside a fold reveals it.
     fn set_selection_raw(&mut self, text: &Rope, sel: Selection) {
         self.invalidate_selection(text);
         self.selection = sel;
+        self.block = None;
+        self.last_added_occurrence = None;
+        self.reveal_selection(text);
         self.invalidate_selection(text);
     }
 
+    /// Unfolds the folds hiding either end of a selection region.
+    fn reveal_selection(&mut self, text: &Rope) {
+        if self.folds.count() == 0 {
+            return;
+        }
+        let mut hiding = Vec::new();
+        for region in self.selection.iter() {
+            hiding.extend(self.folds.fold_containing(region.start));
+            hiding.extend(self.folds.fold_containing(region.end));
+        }
+        if !hiding.is_empty() {
+            self.unfold(text, |start, end| hiding.contains(&(start, end)));
+        }
+    }
+
     /// Invalidate the current selection. Note that we could be even more fine-grained
     /// in the case of multiple cursors, but we also want this method to be fast even
     /// when the selection is large.
@@ -215,6 +279,19 @@ impl View {
         self.lc_shadow.partial_invalidate(first_line, last_line, invalid);
     }
 
+    /// Sets the offsets of the brackets to highlight, invalidating the lines
+    /// of the old and new ones.
+    pub fn set_bracket_highlights(&mut self, text: &Rope, brackets: Vec<usize>) {
+        if brackets == self.bracket_highlights {
+            return;
+        }
+        let old = mem::replace(&mut self.bracket_highlights, brackets);
+        for offset in old.into_iter().chain(self.bracket_highlights.clone()) {
+            let offset = min(offset, text.len());
+            self.invalidate_styles(text, offset, min(offset + 1, text.len()));
+        }
+    }
+
     /// Invalidates the styles of the given range (start and end are offsets within
     /// the text).
     pub fn invalidate_styles(&mut self, text: &Rope, start: usize, end: usize) {
@@ -262,6 +339,126 @@ impl View {
         new_sel.and_then(|new_sel| self.set_selection(text, new_sel))
     }
 
+    /// Replaces the selection with a block selection: a region on each line of
+    /// `block`, spanning its columns, or a caret at the end of the lines that
+    /// don't reach its left edge. Characters that straddle an edge are
+    /// included. Return value is the offset of a point that should be
+    /// scrolled into view.
+    pub fn set_block_selection(&mut self, text: &Rope, block: BlockSelection, tab_size: usize)
+        -> Option<usize>
+    {
+        let first_line = min(block.anchor_line, block.active_line);
+        let last_line = max(block.anchor_line, block.active_line);
+        let left = min(block.anchor_col, block.active_col);
+        let right = max(block.anchor_col, block.active_col);
+        let mut sel = Selection::new();
+        for line in first_line..last_line + 1 {
+            let (min, _) = self.visual_col_to_offset(text, line, left, tab_size, false);
+            let max = if left == right {
+                min
+            } else {
+                self.visual_col_to_offset(text, line, right, tab_size, true).0
+            };
+            let (start, end) = if block.active_col < block.anchor_col {
+                (max, min)
+            } else {
+                (min, max)
+            };
+            sel.add_region(SelRegion { start, end, horiz: None, affinity: Affinity::default() });
+        }
+        let scroll_to = self.set_selection(text, sel);
+        self.block = Some(block);
+        scroll_to
+    }
+
+    /// Keeps the block selection `block` through an edit that left a region
+    /// on each of its lines, moving it with the regions. Its columns are the
+    /// rightmost ones reached by the ends of the regions, so that lines too
+    /// short to reach the block don't pull it left.
+    pub fn restore_block(&mut self, text: &Rope, block: BlockSelection, tab_size: usize) {
+        let old_first_line = min(block.anchor_line, block.active_line);
+        let n_lines = max(block.anchor_line, block.active_line) - old_first_line + 1;
+        let regions = self.sel_regions();
+        if regions.len() != n_lines {
+            return;
+        }
+        let first_line = self.line_of_offset(text, regions[0].min());
+        let mut anchor_col = 0;
+        let mut active_col = 0;
+        for (i, region) in regions.iter().enumerate() {
+            let (start_line, start_col) = self.offset_to_visual_col(text, region.start, tab_size);
+            let (end_line, end_col) = self.offset_to_visual_col(text, region.end, tab_size);
+            if start_line != first_line + i || end_line != first_line + i {
+                return;
+            }
+            anchor_col = max(anchor_col, start_col);
+            active_col = max(active_col, end_col);
+        }
+        self.block = Some(BlockSelection {
+            anchor_line: first_line + block.anchor_line - old_first_line,
+            anchor_col,
+            active_line: first_line + block.active_line - old_first_line,
+            active_col,
+        });
+    }
+
+    /// Moves the active corner of the block selection, starting one from the
+    /// last selection region if the selection isn't a block. Vertical
+    /// movements keep the column, even past the end of a line, and moving
+    /// right past the end of a line adds columns.
+    pub fn do_block_move(&mut self, text: &Rope, movement: Movement, tab_size: usize)
+        -> Option<usize>
+    {
+        self.drag_state = None;
+        let mut block = match self.block {
+            Some(block) => block,
+            None => {
+                let region = self.selection.last().unwrap().clone();
+                let (anchor_line, anchor_col) =
+                    self.offset_to_visual_col(text, region.start, tab_size);
+                let (active_line, active_col) =
+                    self.offset_to_visual_col(text, region.end, tab_size);
+                BlockSelection { anchor_line, anchor_col, active_line, active_col }
+            }
+        };
+        let line = block.active_line;
+        let col = block.active_col;
+        match movement {
+            Movement::Up => block.active_line = line.saturating_sub(1),
+            Movement::Down => {
+                block.active_line = min(line + 1, self.line_of_offset(text, text.len()));
+            }
+            Movement::Left if col > 0 => {
+                block.active_col = self.visual_col_to_offset(text, line, col - 1, tab_size,
+                                                             false).1;
+            }
+            Movement::Left => (),
+            Movement::Right => {
+                let (offset, reached) = self.visual_col_to_offset(text, line, col, tab_size,
+                                                                  false);
+                let end = self.line_content_end(text, line);
+                block.active_col = match text.slice_to_string(offset, end).chars().next() {
+                    Some(c) if reached == col => advance_col(col, c, tab_size),
+                    _ => col + 1,
+                };
+            }
+            _ => {
+                let offset = self.visual_col_to_offset(text, line, col, tab_size, false).0;
+                let caret = SelRegion {
+                    start: offset,
+                    end: offset,
+                    horiz: None,
+                    affinity: Affinity::default(),
+                };
+                let region = region_movement(movement, &caret, self, text, false);
+                let (line, col) = self.offset_to_visual_col(text, region.end, tab_size);
+                block.active_line = line;
+                block.active_col = col;
+            }
+        }
+        self.set_block_selection(text, block, tab_size)
+    }
+
     /// Returns the regions of the current selection.
     pub fn sel_regions(&self) -> &[SelRegion] {
         &self.selection
@@ -271,7 +468,7 @@ impl View {
     pub fn collapse_selections(&mut self, text: &Rope) {
         let mut sel = self.selection.clone();
         sel.collapse();
-        &self.set_selection(text, sel);
+        self.set_selection(text, sel);
     }
 
     /// Determines whether the offset is in any selection (counting carets and
@@ -282,18 +479,28 @@ impl View {
 
     // Render a single line, and advance cursors to next line.
     pub fn render_line(&self, tab_ctx: &DocumentCtx, text: &Rope,
-        start_of_line: &mut Cursor<RopeInfo>, soft_breaks: Option<&mut Cursor<BreaksInfo>>, style_spans: &Spans<Style>,
+        start_of_line: &mut Cursor<RopeInfo>, mut soft_breaks: Option<&mut Cursor<BreaksInfo>>, style_spans: &Spans<Style>,
         line_num: usize) -> RenderedLine
     {
         let start_pos = start_of_line.pos();
-        let pos = soft_breaks.map_or(start_of_line.next::<LinesMetric>(), |bc| {
-            let pos = bc.next::<BreaksMetric>();
-            // if using breaks update cursor
-            if let Some(pos) = pos { start_of_line.set(pos) }
-            pos
-        }).unwrap_or(text.len());
-
-        let l_str = text.slice_to_string(start_pos, pos);
+        let mut pos = next_line_start(text, start_of_line, soft_breaks.as_deref_mut());
+
+        // A folded line ends where its fold starts, followed by the line break
+        // of the last line it hides. The line after the fold is rendered next.
+        let fold = self.folds.iter_from(start_pos).next()
+            .filter(|&(fold_start, _)| fold_start < pos);
+        let mut line_break = String::new();
+        if let Some((fold_start, fold_end)) = fold {
+            start_of_line.set(fold_end);
+            if let Some(bc) = soft_breaks.as_mut() {
+                bc.set(fold_end);
+            }
+            let next = next_line_start(text, start_of_line, soft_breaks);
+            line_break = text.slice_to_string(fold_end, next);
+            pos = fold_start;
+        }
+
+        let l_str = text.slice_to_string(start_pos, pos) + &line_break;
         let mut cursors = Vec::new();
         let mut selections = Vec::new();
         for region in self.selection.regions_in_range(start_pos, pos) {
@@ -326,36 +533,43 @@ impl View {
             }
         }
 
-        let styles = self.render_styles(tab_ctx, start_pos, pos, &selections, &hls, style_spans);
+        let brackets = self.bracket_highlights.iter()
+            .filter(|&&offset| offset >= start_pos && offset < pos)
+            .map(|&offset| (offset - start_pos, offset - start_pos + 1))
+            .collect::<Vec<_>>();
+
+        let styles = self.render_styles(tab_ctx, start_pos, pos, &[selections, hls, brackets],
+                                        style_spans);
 
         RenderedLine {
             text: l_str,
-            styles: styles,
-            cursors: cursors,
+            styles,
+            cursors,
+            folded: fold.is_some(),
         }
     }
 
+    /// The styles of a line, as triples of the offset from the end of the
+    /// previous span, the length and the style id. `reserved` has the
+    /// ranges of each reserved style, in order of their ids: the selection,
+    /// find highlights and matching brackets.
     pub fn render_styles(&self, tab_ctx: &DocumentCtx, start: usize, end: usize,
-        sel: &[(usize, usize)], hls: &[(usize, usize)], style_spans: &Spans<Style>) -> Vec<isize>
+        reserved: &[Vec<(usize, usize)>], style_spans: &Spans<Style>) -> Vec<isize>
     {
         let mut rendered_styles = Vec::new();
         let style_spans = style_spans.subseq(Interval::new_closed_open(start, end));
 
         let mut ix = 0;
-        for &(sel_start, sel_end) in sel {
-            rendered_styles.push((sel_start as isize) - ix);
-            rendered_styles.push(sel_end as isize - sel_start as isize);
-            rendered_styles.push(0);
-            ix = sel_end as isize;
-        }
-        for &(sel_start, sel_end) in hls {
-            rendered_styles.push((sel_start as isize) - ix);
-            rendered_styles.push(sel_end as isize - sel_start as isize);
-            rendered_styles.push(1);
-            ix = sel_end as isize;
+        for (style_id, ranges) in reserved.iter().enumerate() {
+            for &(range_start, range_end) in ranges {
+                rendered_styles.push((range_start as isize) - ix);
+                rendered_styles.push(range_end as isize - range_start as isize);
+                rendered_styles.push(style_id as isize);
+                ix = range_end as isize;
+            }
         }
         for (iv, style) in style_spans.iter() {
-            let style_id = tab_ctx.get_style_id(&style);
+            let style_id = tab_ctx.get_style_id(style);
             rendered_styles.push((iv.start() as isize) - ix);
             rendered_styles.push(iv.end() as isize - iv.start() as isize);
             rendered_styles.push(style_id as isize);
@@ -363,21 +577,8 @@ impl View {
         }
         rendered_styles
     }
-/*
-    pub fn build_update_op(&self, op: &str, lines: Option<Vec<Value>>, n: usize) -> Value {
-        let mut update = json!({
-            "op": op,
-            "n": n,
-        });
-
-        if let Some(lines) = lines {
-            update["lines"] = json!(lines);
-        }
-
-        update
-    }
-*/
-/*
+    /// Renders the lines required by `plan`, and sends the resulting line
+    /// cache update to the front-end.
     pub fn send_update_for_plan(&mut self, text: &Rope, tab_ctx: &DocumentCtx,
         style_spans: &Spans<Style>, plan: &RenderPlan)
     {
@@ -389,11 +590,11 @@ impl View {
 
         // Note: if we weren't doing mutable update_find_for_lines in the loop, we
         // could just borrow self.lc_shadow instead of doing this.
-        let lc_shadow = mem::replace(&mut self.lc_shadow, LineCacheShadow::default());
+        let lc_shadow = mem::take(&mut self.lc_shadow);
         for seg in lc_shadow.iter_with_plan(plan) {
             match seg.tactic {
                 RenderTactic::Discard => {
-                    ops.push(self.build_update_op("invalidate", None, seg.n));
+                    ops.push(LineCacheOp::Invalidate(seg.n));
                     b.add_span(seg.n, 0, 0);
                 }
                 RenderTactic::Preserve => {
@@ -402,13 +603,13 @@ impl View {
                     if seg.validity == line_cache_shadow::ALL_VALID {
                         let n_skip = seg.their_line_num - line_num;
                         if n_skip > 0 {
-                            ops.push(self.build_update_op("skip", None, n_skip));
+                            ops.push(LineCacheOp::Skip(n_skip));
                         }
-                        ops.push(self.build_update_op("copy", None, seg.n));
+                        ops.push(LineCacheOp::Copy(seg.n));
                         b.add_span(seg.n, seg.our_line_num, line_cache_shadow::ALL_VALID);
                         line_num = seg.their_line_num + seg.n;
                     } else {
-                        ops.push(self.build_update_op("invalidate", None, seg.n));
+                        ops.push(LineCacheOp::Invalidate(seg.n));
                         b.add_span(seg.n, 0, 0);
                     }
                 }
@@ -417,9 +618,9 @@ impl View {
                     if seg.validity == line_cache_shadow::ALL_VALID {
                         let n_skip = seg.their_line_num - line_num;
                         if n_skip > 0 {
-                            ops.push(self.build_update_op("skip", None, n_skip));
+                            ops.push(LineCacheOp::Skip(n_skip));
                         }
-                        ops.push(self.build_update_op("copy", None, seg.n));
+                        ops.push(LineCacheOp::Copy(seg.n));
                         b.add_span(seg.n, seg.our_line_num, line_cache_shadow::ALL_VALID);
                         line_num = seg.their_line_num + seg.n;
                     } else {
@@ -437,22 +638,21 @@ impl View {
                             rendered_lines.push(self.render_line(tab_ctx, text,
                                 &mut line_cursor, soft_breaks.as_mut(), style_spans, line_num));
                         }
-                        ops.push(self.build_update_op("ins", Some(rendered_lines), seg.n));
+                        ops.push(LineCacheOp::Insert(rendered_lines));
                         b.add_span(seg.n, seg.our_line_num, line_cache_shadow::ALL_VALID);
                     }
                 }
             }
         }
-        let params = json!({
-            "ops": ops,
-            "pristine": self.pristine,
-        });
-        tab_ctx.update_view(self.view_id, &params);
+        let update = ViewUpdate {
+            ops,
+            pristine: self.pristine,
+        };
+        tab_ctx.update_view(self.view_id, update);
         self.lc_shadow = b.build();
         self.hls_dirty = false;
     }
-*/
-/*
+
     // Update front-end with any changes to view since the last time sent.
     pub fn render_if_dirty(&mut self, text: &Rope, tab_ctx: &DocumentCtx,
         style_spans: &Spans<Style>)
@@ -461,7 +661,6 @@ impl View {
         let plan = RenderPlan::create(height, self.first_line, self.height);
         self.send_update_for_plan(text, tab_ctx, style_spans, &plan);
     }
-*/
 
     // Send the requested lines even if they're outside the current scroll region.
     pub fn request_lines(&mut self, text: &Rope, tab_ctx: &DocumentCtx, style_spans: &Spans<Style>,
@@ -498,6 +697,70 @@ impl View {
         (line, offset - self.offset_of_line(text, line))
     }
 
+    /// Returns the line of `offset`, and its visual column, where a tab
+    /// extends to the next multiple of `tab_size` and wide characters take up
+    /// two columns.
+    pub fn offset_to_visual_col(&self, text: &Rope, offset: usize, tab_size: usize)
+        -> (usize, usize)
+    {
+        let line = self.line_of_offset(text, offset);
+        let start = self.offset_of_line(text, line);
+        let col = text.slice_to_string(start, offset).chars()
+            .fold(0, |col, c| advance_col(col, c, tab_size));
+        (line, col)
+    }
+
+    /// Returns the offset of visual column `col` on `line`, and the column
+    /// actually reached, which is less than `col` if the line is too short.
+    /// A character straddling `col` is included if `round_up` is set.
+    pub fn visual_col_to_offset(&self, text: &Rope, line: usize, col: usize, tab_size: usize,
+                                round_up: bool) -> (usize, usize)
+    {
+        let start = self.offset_of_line(text, line);
+        let end = self.line_content_end(text, line);
+        let mut offset = start;
+        let mut reached = 0;
+        for c in text.slice_to_string(start, end).chars() {
+            let next = advance_col(reached, c, tab_size);
+            if next > col && (reached >= col || !round_up) {
+                break;
+            }
+            offset += c.len_utf8();
+            reached = next;
+        }
+        (offset, reached)
+    }
+
+    /// Converts a line and a column in UTF-8 code units, as used by clicks,
+    /// to a line and a visual column. A column past the end of the line
+    /// extends it with virtual spaces.
+    pub fn line_col_to_visual_col(&self, text: &Rope, line: usize, col: usize, tab_size: usize)
+        -> (usize, usize)
+    {
+        let line = min(line, self.line_of_offset(text, text.len()));
+        let offset = self.line_col_to_offset(text, line, col);
+        let (_, visual_col) = self.offset_to_visual_col(text, offset, tab_size);
+        let line_len = self.line_content_end(text, line) - self.offset_of_line(text, line);
+        (line, visual_col + col.saturating_sub(line_len))
+    }
+
+    /// The offset of the end of `line`, before its line ending, or before
+    /// the lines folded into it.
+    fn line_content_end(&self, text: &Rope, line: usize) -> usize {
+        if line >= self.line_of_offset(text, text.len()) {
+            return self.skip_folded(text, text.len(), false);
+        }
+        let start = self.offset_of_line(text, line);
+        let mut end = self.offset_of_line(text, line + 1);
+        if end > start && text.byte_at(end - 1) == b'\n' {
+            end -= 1;
+            if end > start && text.byte_at(end - 1) == b'\r' {
+                end -= 1;
+            }
+        }
+        self.skip_folded(text, end, false)
+    }
+
     pub fn line_col_to_offset(&self, text: &Rope, line: usize, col: usize) -> usize {
         let mut offset = self.offset_of_line(text, line).saturating_add(col);
         if offset >= text.len() {
@@ -517,13 +780,36 @@ impl View {
                 offset = prev;
             }
         }
-        offset
+        self.skip_folded(text, offset, false)
+    }
+
+    /// Returns the visible line number containing the given offset. An
+    /// offset hidden in a fold is on the line the fold starts on.
+    pub fn line_of_offset(&self, text: &Rope, offset: usize) -> usize {
+        let offset = self.folds.fold_containing(offset).map_or(offset, |(start, _)| start);
+        self.unfolded_line_of_offset(text, offset) - self.folds.hidden_lines_before(offset)
+    }
+
+    /// Returns the byte offset corresponding to the line `line`.
+    pub fn offset_of_line(&self, text: &Rope, line: usize) -> usize {
+        // the first unfolded line shown as `line`; the lines hidden before
+        // it are between none and all of them
+        let (mut lo, mut hi) = (line, line + self.folds.hidden_lines());
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if self.line_of_offset(text, self.unfolded_offset_of_line(text, mid)) < line {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        self.unfolded_offset_of_line(text, lo)
     }
 
     // use own breaks if present, or text if not (no line wrapping)
 
-    /// Returns the visible line number containing the given offset.
-    pub fn line_of_offset(&self, text: &Rope, offset: usize) -> usize {
+    /// The line number of `offset`, counting the lines hidden in folds.
+    fn unfolded_line_of_offset(&self, text: &Rope, offset: usize) -> usize {
         match self.breaks {
             Some(ref breaks) => {
                 breaks.convert_metrics::<BreaksBaseMetric, BreaksMetric>(offset)
@@ -532,8 +818,8 @@ impl View {
         }
     }
 
-    /// Returns the byte offset corresponding to the line `line`.
-    pub fn offset_of_line(&self, text: &Rope, line: usize) -> usize {
+    /// The offset of `line`, counting the lines hidden in folds.
+    fn unfolded_offset_of_line(&self, text: &Rope, line: usize) -> usize {
         match self.breaks {
             Some(ref breaks) => {
                 breaks.convert_metrics::<BreaksMetric, BreaksBaseMetric>(line)
@@ -542,13 +828,107 @@ impl View {
         }
     }
 
-    pub fn rewrap(&mut self, text: &Rope, wrap_col: usize) {
-        if wrap_col > 0 {
-            self.breaks = Some(linewrap::linewrap(text, wrap_col));
-            self.wrap_col = wrap_col;
+    /// Returns `offset`, or if it's hidden in a fold, the offset the fold
+    /// starts at, or the one after the fold if `forward` is set and there
+    /// is one.
+    pub fn skip_folded(&self, text: &Rope, offset: usize, forward: bool) -> usize {
+        match self.folds.fold_containing(offset) {
+            Some((start, end)) if forward => text.next_grapheme_offset(end).unwrap_or(start),
+            Some((start, _)) => start,
+            None => offset,
+        }
+    }
+
+    /// Folds the text lines after each `header`, up to and including its
+    /// `last`, so that they are hidden, and `header` is shown in their place.
+    /// Overlapping folds are merged, and selection regions ending in a fold
+    /// are moved to its start.
+    pub fn fold_lines(&mut self, text: &Rope, lines: &[(usize, usize)]) {
+        let n_lines = text.measure::<LinesMetric>();
+        let mut folds = lines.iter()
+            .filter(|&&(header, last)| header < last && last <= n_lines)
+            .map(|&(header, last)| (text_line_end(text, header), text_line_end(text, last)))
+            .collect::<Vec<_>>();
+        if folds.is_empty() {
+            return;
+        }
+        folds.extend(self.folds.iter());
+        folds.sort();
+        let mut merged: Vec<(usize, usize)> = Vec::with_capacity(folds.len());
+        for (start, end) in folds {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = max(last.1, end),
+                _ => merged.push((start, end)),
+            }
+        }
+        self.folds = self.build_folds(text, merged.into_iter());
+        self.set_dirty(text);
+
+        let mut sel = Selection::new();
+        for region in self.selection.iter() {
+            sel.add_region(SelRegion {
+                start: self.skip_folded(text, region.start, false),
+                end: self.skip_folded(text, region.end, false),
+                horiz: None,
+                affinity: region.affinity,
+            });
+        }
+        self.set_selection_raw(text, sel);
+    }
+
+    /// Removes the folds for which `f`, called with the start and end of a
+    /// fold, returns `true`. Returns whether any were removed.
+    pub fn unfold<F>(&mut self, text: &Rope, f: F) -> bool
+        where F: Fn(usize, usize) -> bool
+    {
+        let count = self.folds.count();
+        let folds = self.build_folds(text, self.folds.iter().filter(|&(start, end)| !f(start, end)));
+        let removed = folds.count() < count;
+        if removed {
+            self.folds = folds;
+            self.set_dirty(text);
+        }
+        removed
+    }
+
+    /// Builds the folds with the given starts and ends, which must be in
+    /// order and disjoint.
+    fn build_folds<I>(&self, text: &Rope, folds: I) -> Folds
+        where I: Iterator<Item=(usize, usize)>
+    {
+        let mut builder = FoldsBuilder::new(text.len());
+        for (start, end) in folds {
+            builder.add_fold(start, end, self.hidden_lines(text, start, end));
+        }
+        builder.build()
+    }
+
+    /// The number of lines hidden by a fold from `start` to `end`.
+    fn hidden_lines(&self, text: &Rope, start: usize, end: usize) -> usize {
+        self.unfolded_line_of_offset(text, end) - self.unfolded_line_of_offset(text, start)
+    }
+
+    /// The folds, as the text line shown in place of each and the last
+    /// text line it hides.
+    pub fn folded_lines(&self, text: &Rope) -> Vec<(usize, usize)> {
+        self.folds.iter()
+            .map(|(start, end)| (text.line_of_offset(start), text.line_of_offset(end)))
+            .collect()
+    }
+
+    /// Wraps lines at `wrap_width`, as measured by `measurer`, or stops
+    /// wrapping if it's zero.
+    pub fn rewrap(&mut self, text: &Rope, wrap_width: f64, measurer: Arc<WidthMeasurer>) {
+        if wrap_width > 0.0 {
+            self.breaks = Some(linewrap::linewrap(text, wrap_width, &*measurer));
+            self.wrap_width = wrap_width;
+            self.width_measurer = Some(measurer);
         } else {
-            self.breaks = None
+            self.breaks = None;
+            self.width_measurer = None;
         }
+        // the number of lines hidden by each fold depends on the wrapping
+        self.folds = self.build_folds(text, self.folds.iter());
     }
 
     /// Updates the view after the text has been modified by the given `delta`.
@@ -560,16 +940,29 @@ impl View {
         pristine: bool) -> Option<usize>
     {
         let (iv, new_len) = delta.summary();
-        if let Some(breaks) = self.breaks.as_mut() {
-            linewrap::rewrap(breaks, text, iv, new_len, self.wrap_col);
+        if let (Some(breaks), Some(measurer)) = (self.breaks.as_mut(), self.width_measurer.as_ref()) {
+            linewrap::rewrap(breaks, text, iv, new_len, self.wrap_width, &**measurer);
         }
-        if self.breaks.is_some() {
+        // a fold is dropped once it no longer spans whole lines
+        let folds = self.folds.transform(delta, |start, end| {
+            let (first, last) = (text.line_of_offset(start), text.line_of_offset(end));
+            if first < last && start == text_line_end(text, first)
+                && end == text_line_end(text, last)
+            {
+                Some(self.hidden_lines(text, start, end))
+            } else {
+                None
+            }
+        });
+        if self.breaks.is_some() || folds.count() < self.folds.count() {
             // TODO: finer grain invalidation for the line wrapping, needs info
             // about what wrapped.
+            self.folds = folds;
             self.set_dirty(text);
         } else {
             let start = self.line_of_offset(last_text, iv.start());
             let end = self.line_of_offset(last_text, iv.end()) + 1;
+            self.folds = folds;
             let new_end = self.line_of_offset(text, iv.start() + new_len) + 1;
             self.lc_shadow.edit(start, end, new_end - start);
         }
@@ -581,7 +974,7 @@ impl View {
         // Update search highlights for changed regions
         if self.search_string.is_some() {
             self.valid_search = self.valid_search.apply_delta(delta);
-            let mut occurrences = self.occurrences.take().unwrap_or_else(|| Selection::new());
+            let mut occurrences = self.occurrences.take().unwrap_or_default();
 
             // invalidate occurrences around deletion positions
             for DeltaRegion{ old_offset, new_offset, len } in delta.iter_deletions() {
@@ -618,6 +1011,7 @@ impl View {
     /// Unsets the search and removes all highlights from the view.
     pub fn unset_find(&mut self, text: &Rope) {
         self.search_string = None;
+        self.search_regex = None;
         self.occurrences = None;
         self.hls_dirty = true;
         // TODO: finer grained invalidation
@@ -625,17 +1019,32 @@ impl View {
         self.valid_search.clear();
     }
 
-    /// Sets find for the view, highlights occurrences in the current viewport and selects the first
-    /// occurrence relative to the last cursor.
-    pub fn set_find(&mut self, text: &Rope, search_string: &str, case_sensitive: bool) {
-        let case_matching = if case_sensitive {
-            CaseMatching::Exact
-        } else {
-            CaseMatching::CaseInsensitive
-        };
+    /// Sets how the text is split into words. A whole word search is redone
+    /// under the new mode.
+    pub fn set_word_boundaries(&mut self, text: &Rope, mode: WordBoundaryMode) {
+        if mode == self.word_boundaries {
+            return;
+        }
+        self.word_boundaries = mode;
+        if self.whole_words && self.search_string.is_some() {
+            self.occurrences = None;
+            self.hls_dirty = true;
+            self.set_dirty(text);
+            self.valid_search.clear();
+        }
+    }
 
+    /// Sets find for the view, highlights occurrences in the current viewport and selects the first
+    /// occurrence relative to the last cursor. If `whole_words` is set, only occurrences that
+    /// neither start nor end in the middle of a word are found. If `search_regex` is given, it is
+    /// the search string compiled as a regular expression, which is searched for instead.
+    pub fn set_find(&mut self, text: &Rope, search_string: &str, case_matching: CaseMatching,
+                    whole_words: bool, search_regex: Option<RegexMatcher>)
+    {
         if let Some(ref s) = self.search_string {
-            if s == search_string && case_matching == self.case_matching {
+            if s == search_string && case_matching == self.case_matching
+                && whole_words == self.whole_words
+                && search_regex.is_some() == self.search_regex.is_some() {
                 // search parameters did not change
                 return;
             }
@@ -645,6 +1054,8 @@ impl View {
 
         self.search_string = Some(search_string.to_string());
         self.case_matching = case_matching;
+        self.whole_words = whole_words;
+        self.search_regex = search_regex;
     }
 
     fn update_find_for_lines(&mut self, text: &Rope, first_line: usize, last_line: usize) {
@@ -667,31 +1078,59 @@ impl View {
         // extend the search by twice the string length (twice, because case matching may increase
         // the length of an occurrence)
         let slop = if include_slop { self.search_string.as_ref().unwrap().len() * 2 } else { 0 };
-        let mut occurrences = self.occurrences.take().unwrap_or_else(|| Selection::new());
+        let mut occurrences = self.occurrences.take().unwrap_or_default();
         let mut searched_until = end;
         let mut invalidate_from = None;
 
-        for (start, end) in self.valid_search.minus_one_range(start, end) {
-            let search_string = self.search_string.as_ref().unwrap();
-            let len = search_string.len();
-
-            // expand region to be able to find occurrences around the region's edges
-            let from = max(start, slop) - slop;
-            let to = min(end + slop, text.len());
+        let unsearched = self.valid_search.minus_one_range(start, end).collect::<Vec<_>>();
+        for (start, end) in unsearched {
+            // expand region to be able to find occurrences around the region's edges; regex
+            // occurrences can have any length, and whole words depend on the text around them,
+            // so whole lines are searched instead
+            let (from, to) = if self.search_regex.is_some() || self.whole_words {
+                let from = text.offset_of_line(text.line_of_offset(start));
+                let to = Cursor::new(text, end).next::<LinesMetric>().unwrap_or(text.len());
+                (from, to)
+            } else {
+                (max(start, slop) - slop, min(end + slop, text.len()))
+            };
 
+            // a regex match starting in the region may end past it, so only literal
+            // searches are confined to the region
             // TODO: this interval might cut a unicode codepoint, make sure it is
             // aligned to codepoint boundaries.
-            let text = text.subseq(Interval::new_closed_open(0, to));
+            let text = if self.search_regex.is_some() {
+                text.clone()
+            } else {
+                text.subseq(Interval::new_closed_open(0, to))
+            };
             let mut cursor = Cursor::new(&text, from);
 
+            let mut last_end = None;
             loop {
-                match find(&mut cursor, self.case_matching, &search_string) {
-                    Some(start) => {
-                        let end = start + len;
+                match self.next_match(&mut cursor, to) {
+                    // a match starting at `to` is found when the text after it is searched,
+                    // unless it is an empty match at the end of the text
+                    Some((start, _)) if start >= to && to < text_len => break,
+                    Some((start, end)) => {
+                        if start == end {
+                            // like `regex::find_iter`, step past an empty match, and skip one
+                            // that directly follows the previous match
+                            let skip = last_end == Some(end);
+                            match text.next_codepoint_offset(end) {
+                                Some(next) => cursor.set(next),
+                                None if skip => break,
+                                None => (),
+                            }
+                            if skip {
+                                continue;
+                            }
+                        }
+                        last_end = Some(end);
 
                         let region = SelRegion {
-                            start: start,
-                            end: end,
+                            start,
+                            end,
                             horiz: None,
                             affinity: Affinity::default(),
                         };
@@ -706,6 +1145,11 @@ impl View {
                             cursor.set(e);
                             continue;
                         }
+                        if occurrences.len() == prev_len {
+                            // the occurrence was already known (regex searches go over
+                            // whole lines, which may have been searched in part)
+                            continue;
+                        }
 
                         // add_range_distinct() above removes ambiguous regions after the added
                         // region, if something has been deleted, everything thereafter is
@@ -730,13 +1174,14 @@ impl View {
             self.valid_search.union_one_range(start, invalidate_from);
 
             // invalidate all search results from the point of the ambiguous search result until ...
-            let is_multi_line = LinesMetric::next(self.search_string.as_ref().unwrap(), 0).is_some();
+            let is_multi_line = self.search_regex.is_some()
+                || LinesMetric::next(self.search_string.as_ref().unwrap(), 0).is_some();
             if is_multi_line {
                 // ... the end of the file
                 self.valid_search.delete_range(invalidate_from, text_len);
             } else {
                 // ... the end of the line
-                let mut cursor = Cursor::new(&text, invalidate_from);
+                let mut cursor = Cursor::new(text, invalidate_from);
                 if let Some(end_of_line) = cursor.next::<LinesMetric>() {
                     self.valid_search.delete_range(invalidate_from, end_of_line);
                 }
@@ -750,6 +1195,119 @@ impl View {
         }
     }
 
+    /// Adds the next occurrence of the active search that isn't selected
+    /// yet, after the region most recently added (or the last region),
+    /// wrapping around the end of the text, as a new selection region.
+    ///
+    /// If `skip` is set, the most recently added region is replaced by the
+    /// new one instead, unless it's the only region. Return value is the
+    /// offset of a point that should be scrolled into view.
+    pub fn add_next_occurrence(&mut self, text: &Rope, skip: bool) -> Option<usize> {
+        let last = self.last_added_occurrence.clone()
+            .unwrap_or_else(|| self.selection.last().unwrap().clone());
+        let occurrence = self.next_unselected_occurrence(text, last.max())?;
+        let mut selection = self.selection.clone();
+        if skip && selection.len() > 1 {
+            selection.delete_range(last.min(), last.max(), false);
+        } else if skip {
+            selection.clear();
+        }
+        selection.add_region(occurrence.clone());
+        self.set_selection_raw(text, selection);
+        self.last_added_occurrence = Some(occurrence.clone());
+        self.scroll_to_cursor(text);
+        Some(occurrence.end)
+    }
+
+    /// Selects every occurrence of the active search, keeping the existing
+    /// regions.
+    pub fn select_all_occurrences(&mut self, text: &Rope) -> Option<usize> {
+        self.update_find(text, 0, text.len(), false, false);
+        let mut selection = self.selection.clone();
+        for occurrence in self.occurrences.as_ref()?.iter() {
+            selection.add_region(occurrence.clone());
+        }
+        self.set_selection(text, selection)
+    }
+
+    /// The first occurrence of the active search starting at or after
+    /// `offset` that isn't selected, wrapping around the end of the text.
+    fn next_unselected_occurrence(&mut self, text: &Rope, offset: usize) -> Option<SelRegion> {
+        // searched ranges are remembered, so this only searches the text once
+        self.update_find(text, 0, text.len(), false, false);
+        let occurrences = self.occurrences.as_ref()?;
+        let selection = &self.selection;
+        let is_selected = |occurrence: &SelRegion| {
+            selection.regions_in_range(occurrence.min(), occurrence.max()).iter()
+                .any(|region| region.min() == occurrence.min() && region.max() == occurrence.max())
+        };
+        let ix = occurrences.iter().position(|o| o.min() >= offset).unwrap_or(occurrences.len());
+        occurrences[ix..].iter().chain(occurrences[..ix].iter())
+            .find(|occurrence| !is_selected(occurrence))
+            .cloned()
+    }
+
+    /// Finds the next match of the active search, starting at the cursor, and returns its
+    /// start and end. The cursor is left at the end of the match.
+    ///
+    /// A regex search gives up once it is past `limit` without having started a match, so that
+    /// it doesn't scan the rest of the text for matches that would be ignored.
+    fn next_match(&mut self, cursor: &mut Cursor<RopeInfo>, limit: usize)
+        -> Option<(usize, usize)>
+    {
+        let search_string = self.search_string.as_ref()?;
+        loop {
+            let start = match self.search_regex {
+                Some(ref mut matcher) => find_regex_before(cursor, matcher, limit)?,
+                None => find(cursor, self.case_matching, search_string)?,
+            };
+            let end = cursor.pos();
+            if !self.whole_words
+                || is_whole_word(cursor.root(), start, end, self.word_boundaries)
+            {
+                return Some((start, end));
+            }
+            cursor.set(start);
+            cursor.next::<BaseMetric>()?;
+        }
+    }
+
+    /// Finds up to `limit` occurrences of the active search that lie within `start..end`, and
+    /// pairs each with the text to replace it with. For a regex search, `$1` or `${name}` in
+    /// `replacement` refer to capture groups. As with `regex::find_iter`, an empty match is
+    /// skipped if it directly follows the previous match.
+    pub fn find_replacements(&mut self, text: &Rope, start: usize, end: usize,
+                             replacement: &str, limit: usize) -> Vec<(Interval, String)>
+    {
+        let mut result = Vec::new();
+        let mut cursor = Cursor::new(text, start);
+        let mut last_end = None;
+        while result.len() < limit {
+            let (start, end) = match self.next_match(&mut cursor, end) {
+                Some((s, e)) if e <= end => (s, e),
+                _ => break,
+            };
+            if start == end {
+                let skip = last_end == Some(end);
+                match text.next_codepoint_offset(end) {
+                    Some(next) => cursor.set(next),
+                    None if skip => break,
+                    None => (),
+                }
+                if skip {
+                    continue;
+                }
+            }
+            last_end = Some(end);
+            let replaced = match self.search_regex {
+                Some(ref matcher) => matcher.expand(text, start, end, replacement),
+                None => replacement.to_string(),
+            };
+            result.push((Interval::new_closed_open(start, end), replaced));
+        }
+        result
+    }
+
     /// Select the next occurrence relative to the last cursor. `reverse` determines whether the
     /// next occurrence before (`true`) or after (`false`) the last cursor is selected. `wrapped`
     /// indicates a search for the next occurrence past the end of the file. `stop_on_found`
@@ -759,9 +1317,7 @@ impl View {
     pub fn select_next_occurrence(&mut self, text: &Rope, reverse: bool, wrapped: bool,
                                   stop_on_found: bool, allow_same: bool) -> Option<usize>
     {
-        if self.search_string.is_none() {
-            return None;
-        }
+        self.search_string.as_ref()?;
 
         let sel = match self.sel_regions().last() {
             Some(sel) => (sel.min(), sel.max()),
@@ -773,7 +1329,7 @@ impl View {
 
         loop {
             next_occurrence = self.occurrences.as_ref().and_then(|occurrences| {
-                if occurrences.len() == 0 {
+                if occurrences.is_empty() {
                     return None;
                 }
                 if wrapped { // wrap around file boundaries
@@ -798,7 +1354,7 @@ impl View {
                         })
                     }
                 }
-            }).map(|o| o.clone());
+            }).cloned();
 
             let region = {
                 let mut unsearched = self.valid_search.minus_one_range(from, to);
@@ -838,6 +1394,41 @@ impl View {
     }
 }
 
+/// The visual column after `c`, if it starts at `col`.
+pub fn advance_col(col: usize, c: char, tab_size: usize) -> usize {
+    if c == '\t' {
+        let tab_size = max(tab_size, 1);
+        col + tab_size - col % tab_size
+    } else {
+        col + char_width(c)
+    }
+}
+
+/// Advances the cursors to the start of the next line, returning its offset.
+fn next_line_start(text: &Rope, start_of_line: &mut Cursor<RopeInfo>,
+    soft_breaks: Option<&mut Cursor<BreaksInfo>>) -> usize
+{
+    soft_breaks.map_or(start_of_line.next::<LinesMetric>(), |bc| {
+        let pos = bc.next::<BreaksMetric>();
+        // if using breaks update cursor
+        if let Some(pos) = pos { start_of_line.set(pos) }
+        pos
+    }).unwrap_or(text.len())
+}
+
+/// The offset of the end of text line `line`, before its line ending.
+fn text_line_end(text: &Rope, line: usize) -> usize {
+    if line >= text.measure::<LinesMetric>() {
+        return text.len();
+    }
+    let start = text.offset_of_line(line);
+    let mut end = text.offset_of_line(line + 1) - 1;
+    if end > start && text.byte_at(end - 1) == b'\r' {
+        end -= 1;
+    }
+    end
+}
+
 // utility function to clamp a value within the given range
 fn clamp(x: usize, min: usize, max: usize) -> usize {
     if x < min {
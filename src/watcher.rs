@@ -21,8 +21,6 @@ use std::thread;
 use std::mem;
 use std::collections::VecDeque;
 
-use xi_rpc::RpcPeer;
-
 /// Delay for aggregating related file system events.
 pub const DEBOUNCE_WAIT_MILLIS: u64 = 50;
 
@@ -30,7 +28,8 @@ pub const DEBOUNCE_WAIT_MILLIS: u64 = 50;
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct EventToken(pub usize);
 
-/// Wrapper around `notify::Watcher`, adding support for the xi_rpc runloop.
+/// Wrapper around `notify::Watcher`, queueing events until the embedder
+/// asks for them.
 #[derive(Debug, Clone, Default)]
 pub struct FsWatcher {
     pub events: Arc<Mutex<VecDeque<(EventToken, DebouncedEvent)>>>,
@@ -38,27 +37,25 @@ pub struct FsWatcher {
 
 impl FsWatcher {
     /// Begin watching `path`. As `DebouncedEvent`s (documented in the [notify](https://docs.rs/notify/4.0.2/notify/) crate)
-    /// arrive, they are stored with the associated `token` and a task is
-    /// added to the runloop's idle queue.
+    /// arrive, they are stored with the associated `token`.
     ///
-    /// Delivery of events then requires that the runloop's handler
-    /// correctly forward the `handle_idle` call to the interested party.
+    /// Delivery of events then requires that the embedder periodically
+    /// drain the queue with `take_events`, or `Documents::handle_fs_events`.
     pub fn watch<P>(&mut self, path: P, recursive_mode: RecursiveMode,
-                token: EventToken, peer: &RpcPeer)
+                token: EventToken)
         where P: AsRef<Path>,
     {
-        self.watch_filtered(path, recursive_mode, token, peer, |_| { true });
+        self.watch_filtered(path, recursive_mode, token, |_| { true });
     }
 
     /// Like `watch`, but taking a predicate function that filters delivery
     /// of events based on their path.
     pub fn watch_filtered<P, F>(&mut self, path: P, recursive_mode: RecursiveMode,
-                                token: EventToken, peer: &RpcPeer, predicate: F)
+                                token: EventToken, predicate: F)
         where P: AsRef<Path>,
               F: Fn(&Path) -> bool + Send + 'static,
     {
         let path = path.as_ref().to_owned();
-        let peer = peer.clone();
         let events = self.events.clone();
         thread::spawn(move || {
 
@@ -73,7 +70,6 @@ impl FsWatcher {
                     Ok(event) =>  {
                         if apply_filter(&predicate, &event) {
                             events.lock().unwrap().push_back((token, event));
-                            peer.schedule_idle(::tabs::WATCH_IDLE_TOKEN);
                         }
                     },
                     Err(e) => {
@@ -90,7 +86,7 @@ impl FsWatcher {
     /// Takes ownership of this `Watcher`'s current event queue.
     pub fn take_events(&mut self) -> VecDeque<(EventToken, DebouncedEvent)> {
         let mut events = self.events.lock().unwrap();
-        mem::replace(&mut events, VecDeque::new())
+        mem::take(&mut events)
     }
 
     //TODO impl unwatch, when we add in watching of opened files
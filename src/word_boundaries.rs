@@ -12,26 +12,63 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-//! Segmentation of word boundaries. Note: this current implementation
-//! is intended to work for code. Future work is to make it Unicode aware.
+//! Segmentation of word boundaries. There are two modes: the default one
+//! is intended to work for code, treating runs of ASCII punctuation as
+//! words and everything else that isn't whitespace as part of a word. The
+//! other follows the Unicode word boundary rules (UAX #29), which handle
+//! prose in most scripts better.
 
 use rope::rope::{Rope, RopeInfo};
 use rope::tree::Cursor;
+use unicode::word_boundaries;
+
+/// How a buffer is split into words for word-wise movement, deletion and
+/// selection. Set with the `word_boundaries` config key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WordBoundaryMode {
+    /// Words are runs of non-whitespace, split at ASCII punctuation.
+    #[default]
+    Code,
+    /// Words are as found by the UAX #29 word boundary rules.
+    Unicode,
+}
+
+impl WordBoundaryMode {
+    /// Returns the mode with the given config name, "code" or "unicode".
+    pub fn try_from_name(name: &str) -> Option<Self> {
+        match name {
+            "code" => Some(WordBoundaryMode::Code),
+            "unicode" => Some(WordBoundaryMode::Unicode),
+            _ => None,
+        }
+    }
+}
 
 pub struct WordCursor<'a> {
+    text: &'a Rope,
     inner: Cursor<'a, RopeInfo>,
+    mode: WordBoundaryMode,
 }
 
 impl<'a> WordCursor<'a> {
     pub fn new(text: &'a Rope, pos: usize) -> WordCursor<'a> {
+        WordCursor::with_mode(text, pos, WordBoundaryMode::default())
+    }
+
+    pub fn with_mode(text: &'a Rope, pos: usize, mode: WordBoundaryMode) -> WordCursor<'a> {
         let inner = Cursor::new(text, pos);
         WordCursor {
-            inner: inner,
+            text,
+            inner,
+            mode,
         }
     }
 
     /// Get previous boundary, and set the cursor at the boundary found.
     pub fn prev_boundary(&mut self) -> Option<usize> {
+        if self.mode == WordBoundaryMode::Unicode {
+            return self.prev_unicode_boundary();
+        }
         if let Some(ch) = self.inner.prev_codepoint() {
             let mut prop = get_word_property(ch);
             let mut candidate = self.inner.pos();
@@ -51,6 +88,9 @@ impl<'a> WordCursor<'a> {
 
     /// Get next boundary, and set the cursor at the boundary found.
     pub fn next_boundary(&mut self) -> Option<usize> {
+        if self.mode == WordBoundaryMode::Unicode {
+            return self.next_unicode_boundary();
+        }
         if let Some(ch) = self.inner.next_codepoint() {
             let mut prop = get_word_property(ch);
             let mut candidate = self.inner.pos();
@@ -71,6 +111,9 @@ impl<'a> WordCursor<'a> {
     /// Return the selection for the word containing the current cursor. The
     /// cursor is moved to the end of that selection.
     pub fn select_word(&mut self) -> (usize, usize) {
+        if self.mode == WordBoundaryMode::Unicode {
+            return self.select_unicode_word();
+        }
         let initial = self.inner.pos();
         let init_prop_after = self.inner.next_codepoint().map(get_word_property);
         self.inner.set(initial);
@@ -132,6 +175,171 @@ impl<'a> WordCursor<'a> {
         self.inner.set(end);
         (start, end)
     }
+
+    /// Whether the cursor is in the middle of a word, so that text starting
+    /// or ending here would be part of a larger word. A run of punctuation
+    /// counts as a word in `Code` mode.
+    pub fn is_inside_word(&mut self) -> bool {
+        let pos = self.inner.pos();
+        if self.mode == WordBoundaryMode::Unicode {
+            return segments_around(self.text, pos).iter()
+                .any(|seg| seg.start < pos && pos < seg.end && !seg.is_blank());
+        }
+        let next = self.inner.next_codepoint().map(get_word_property);
+        self.inner.set(pos);
+        let prev = self.inner.prev_codepoint().map(get_word_property);
+        self.inner.set(pos);
+        matches!((prev, next),
+                 (Some(WordProperty::Other), Some(WordProperty::Other))
+                 | (Some(WordProperty::Punctuation), Some(WordProperty::Punctuation)))
+    }
+
+    // In Unicode mode, a word starts at a boundary followed by a segment
+    // that isn't blank, and ends at a boundary preceded by one. Only the
+    // text around the cursor is segmented; see `segments_around`.
+
+    fn prev_unicode_boundary(&mut self) -> Option<usize> {
+        let pos = self.inner.pos();
+        if pos == 0 {
+            return None;
+        }
+        let prev = segments_around(self.text, pos).iter().rev()
+            .find(|seg| seg.start < pos && !seg.is_blank())
+            .map_or(0, |seg| seg.start);
+        self.inner.set(prev);
+        Some(prev)
+    }
+
+    fn next_unicode_boundary(&mut self) -> Option<usize> {
+        let pos = self.inner.pos();
+        if pos >= self.text.len() {
+            return None;
+        }
+        let next = segments_around(self.text, pos).iter()
+            .find(|seg| seg.end > pos && !seg.is_blank())
+            .map_or(self.text.len(), |seg| seg.end);
+        self.inner.set(next);
+        Some(next)
+    }
+
+    fn select_unicode_word(&mut self) -> (usize, usize) {
+        let pos = self.inner.pos();
+        let segments = segments_around(self.text, pos);
+        let after = segments.iter().position(|seg| seg.start <= pos && pos < seg.end);
+        let before = segments.iter().position(|seg| seg.start < pos && pos <= seg.end);
+        let target = if after.is_some() && after == before {
+            after
+        } else {
+            // between two segments, prefer words, then punctuation, then
+            // whitespace; ties go to the segment after the cursor
+            let kind = |ix: Option<usize>| ix.map(|ix| segments[ix].kind);
+            let best = if kind(before) > kind(after) { before } else { after };
+            best.filter(|&ix| segments[ix].kind != SegmentKind::LineBreak)
+        };
+        let (start, end) = match target {
+            Some(ix) if segments[ix].is_blank() => {
+                // select the whole run of whitespace
+                let mut first = ix;
+                while first > 0 && segments[first - 1].is_blank() {
+                    first -= 1;
+                }
+                let mut last = ix;
+                while last + 1 < segments.len() && segments[last + 1].is_blank() {
+                    last += 1;
+                }
+                (segments[first].start, segments[last].end)
+            }
+            Some(ix) => (segments[ix].start, segments[ix].end),
+            None => (pos, pos),
+        };
+        self.inner.set(end);
+        (start, end)
+    }
+}
+
+/// Whether `start..end` of `text` is made of whole words, that is, neither
+/// starts nor ends in the middle of a word, as split by `mode`.
+pub fn is_whole_word(text: &Rope, start: usize, end: usize, mode: WordBoundaryMode) -> bool {
+    !WordCursor::with_mode(text, start, mode).is_inside_word()
+        && !WordCursor::with_mode(text, end, mode).is_inside_word()
+}
+
+/// What a segment is made of, in increasing order of preference for
+/// double-click selection.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum SegmentKind {
+    LineBreak,
+    /// Whitespace that isn't a line break.
+    Blank,
+    /// Punctuation and symbols.
+    Other,
+    Word,
+}
+
+/// A segment between two adjacent UAX #29 word boundaries.
+struct Segment {
+    start: usize,
+    end: usize,
+    kind: SegmentKind,
+}
+
+impl Segment {
+    fn is_blank(&self) -> bool {
+        self.kind == SegmentKind::Blank
+    }
+}
+
+fn is_newline(c: char) -> bool {
+    matches!(c, '\n' | '\r' | '\u{0B}' | '\u{0C}' | '\u{85}' | '\u{2028}' | '\u{2029}')
+}
+
+fn segment_kind(s: &str) -> SegmentKind {
+    if s.chars().any(is_newline) {
+        SegmentKind::LineBreak
+    } else if s.chars().all(char::is_whitespace) {
+        SegmentKind::Blank
+    } else if s.chars().any(char::is_alphanumeric) {
+        SegmentKind::Word
+    } else {
+        SegmentKind::Other
+    }
+}
+
+/// The segments of the text around `pos`: back over any whitespace and the
+/// word before it, and forward over any whitespace and the word after it,
+/// along with one more whitespace character at each end. Whitespace always
+/// separates words, so the words in this range are segmented as they are
+/// in the whole text, while its extent stays small.
+fn segments_around(text: &Rope, pos: usize) -> Vec<Segment> {
+    let mut cursor = Cursor::new(text, pos);
+    let mut start = pos;
+    let mut in_word = false;
+    while let Some(c) = cursor.prev_codepoint() {
+        start = cursor.pos();
+        if c.is_whitespace() && in_word {
+            break;
+        }
+        in_word |= !c.is_whitespace();
+    }
+    cursor.set(pos);
+    let mut end = pos;
+    in_word = false;
+    while let Some(c) = cursor.next_codepoint() {
+        end = cursor.pos();
+        if c.is_whitespace() && in_word {
+            break;
+        }
+        in_word |= !c.is_whitespace();
+    }
+
+    let s = text.slice_to_string(start, end);
+    word_boundaries(&s).windows(2)
+        .map(|w| Segment {
+            start: start + w[0],
+            end: start + w[1],
+            kind: segment_kind(&s[w[0]..w[1]]),
+        })
+        .collect()
 }
 
 #[derive(PartialEq, Eq)]
@@ -215,4 +423,79 @@ fn get_word_property(codepoint: char) -> WordProperty {
         }
     }
     WordProperty::Other
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use rope::rope::Rope;
+    use super::*;
+
+    fn unicode_moves(text: &Rope, forward: bool) -> Vec<usize> {
+        let mut result = Vec::new();
+        let start = if forward { 0 } else { text.len() };
+        let mut cursor = WordCursor::with_mode(text, start, WordBoundaryMode::Unicode);
+        loop {
+            let next = if forward { cursor.next_boundary() } else { cursor.prev_boundary() };
+            match next {
+                Some(pos) => result.push(pos),
+                None => return result,
+            }
+        }
+    }
+
+    #[test]
+    fn unicode_movement() {
+        let text = Rope::from("can't  stop 3,141.59\nnext");
+        assert_eq!(vec![5, 11, 20, 21, 25], unicode_moves(&text, true));
+        assert_eq!(vec![21, 20, 12, 7, 0], unicode_moves(&text, false));
+
+        // the code mode splits at the apostrophe and the number separators
+        let mut cursor = WordCursor::new(&text, 0);
+        assert_eq!(Some(3), cursor.next_boundary());
+    }
+
+    #[test]
+    fn unicode_movement_cjk() {
+        // katakana stay together, ideographs are broken individually
+        let text = Rope::from("\u{30C6}\u{30B9}\u{30C8}\u{6F22}\u{5B57}");
+        assert_eq!(vec![9, 12, 15], unicode_moves(&text, true));
+    }
+
+    #[test]
+    fn unicode_select_word() {
+        let text = Rope::from("hello, world  \nagain");
+        let select = |pos| {
+            WordCursor::with_mode(&text, pos, WordBoundaryMode::Unicode).select_word()
+        };
+        assert_eq!((0, 5), select(0));
+        assert_eq!((0, 5), select(3));
+        assert_eq!((0, 5), select(5));
+        assert_eq!((7, 12), select(7));
+        assert_eq!((12, 14), select(13));
+        assert_eq!((12, 14), select(14));
+        assert_eq!((15, 20), select(15));
+        assert_eq!((15, 20), select(20));
+    }
+
+    #[test]
+    fn whole_words() {
+        let text = Rope::from("cat concat cat_ a+=b cafe\u{301} do-n't");
+        let code = |start, end| is_whole_word(&text, start, end, WordBoundaryMode::Code);
+        assert!(code(0, 3));
+        assert!(!code(7, 10));
+        assert!(!code(11, 14));
+        assert!(code(16, 17));
+        // a run of punctuation is a word, and a combining mark continues one
+        assert!(!code(17, 18));
+        assert!(!code(21, 25));
+        assert!(code(21, 27));
+
+        let unicode = |start, end| is_whole_word(&text, start, end, WordBoundaryMode::Unicode);
+        assert!(unicode(17, 18));
+        assert!(!unicode(21, 25));
+        assert!(unicode(28, 30));
+        // an apostrophe doesn't end a word
+        assert!(!unicode(31, 32));
+        assert!(code(31, 32));
+    }
+}